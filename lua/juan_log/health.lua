@@ -0,0 +1,55 @@
+-- :checkhealth juan_log. whether the rust library loaded, then the engine's own report for
+-- every open log buffer: the first thing to ask for when somebody reports a problem.
+local M = {}
+
+-- what's worth a warning in a report, everything else is just listed
+local function warnings(report)
+    local found = {}
+    if report.madvise == "failed" then
+        table.insert(found, "madvise(MADV_RANDOM) failed on the mapping, scrolling may read ahead more than it needs")
+    end
+    if report.indexed_percent < 100 and not report.paused then
+        table.insert(found, string.format("only %.1f%% of the file is indexed, it grew and wasn't refreshed", report.indexed_percent))
+    end
+    if report.misuses > 0 then
+        table.insert(found, string.format("%d calls into the library were wrong, last: %s", report.misuses, report.last_misuse))
+    end
+    if report.io_retries > 0 then
+        table.insert(found, string.format("%d reads had to be retried, the filesystem is flaky", report.io_retries))
+    end
+    return found
+end
+
+function M.check()
+    local juan_log = require("juan_log")
+    vim.health.start("juan_log")
+    local library = juan_log.library()
+    if not library.loaded then
+        vim.health.error("the library isn't loaded: " .. library.path,
+            { "run `cargo build --release` in the plugin directory" })
+        return
+    end
+    vim.health.ok(string.format("library %s (ABI %d)", library.path, library.abi))
+
+    local open = 0
+    for bufnr in pairs(_G.JuanLogStates) do
+        local report = juan_log.diagnostics(bufnr)
+        if report then
+            open = open + 1
+            vim.health.start("juan_log: " .. vim.api.nvim_buf_get_name(bufnr))
+            local found = warnings(report)
+            for _, warning in ipairs(found) do vim.health.warn(warning) end
+            if #found == 0 then vim.health.ok("nothing looks wrong") end
+            local keys = vim.tbl_keys(report)
+            table.sort(keys)
+            for _, key in ipairs(keys) do
+                vim.health.info(key .. ": " .. tostring(report[key]))
+            end
+        end
+    end
+    if open == 0 then
+        vim.health.info("no log buffers open, open one for its engine's report")
+    end
+end
+
+return M
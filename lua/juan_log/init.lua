@@ -8,22 +8,240 @@ local config = {
     dynamic_margin = 2000, -- reload when we get this close to the edge
     patterns = { "*" },
     enable_custom_statuscol = true,
-    syntax = false
+    syntax = false,
+    cache_ttl = 600, -- seconds before an unused search cache is dropped
+    highlight_matches = true, -- highlight the last search inside the loaded window
+    highlight_levels = true, -- color ERROR/WARN/... words and level fields (JuanLogError, JuanLogWarn... groups)
+    level_counts = false, -- on open, say how many lines there are at each level ("2 FATAL, 143 ERROR, 10k WARN")
+    head_tail_lines = 100, -- default size of each half in :LogHeadTail
+    overview_rows = 200, -- samples in :LogOverview
+    max_templates = 200, -- rows in :LogTemplates, the most common message templates
+    max_values = 100, -- rows in :LogTop, the most common values of a field
+    min_highlight_query = 2, -- shorter searches still jump, they just aren't highlighted
+    max_highlights = 100000, -- per loaded window, past this the rest stays unpainted
+    strip_ansi = false, -- drop terminal color/escape codes (\x1b[31m...) from displayed lines (:LogAnsi toggles)
+    hex_binary = true, -- show binary lines (a blob written into the log) as a short hex preview (:LogBinary toggles)
+    hex_dump_max = 65536, -- bytes of a blob :LogHex dumps at most
+    ansi_colors = false, -- and draw the text in the colors they asked for (:LogAnsi! toggles)
+    expand_escaped_newlines = false, -- draw literal \n in a line as separate rows (:LogExpand toggles)
+    fold_entries = false, -- fold stack traces and wrapped lines under the line they belong to (:LogFold toggles)
+    fold_below = nil, -- e.g. "warn": fold runs of lines less severe than that instead (:LogFold <level>)
+    collapse_repeats = false, -- fold runs of the same line over and over into one with a count, "template" for lines differing only in numbers (:LogRepeats toggles)
+    records = false, -- n/N go a record (a line and its stack trace) at a time, filters show whole records (:LogRecords toggles)
+    time_deltas = false, -- show how long after the previous stamped line each line came (:LogDeltas toggles)
+    slow_delta_s = 1, -- deltas at least this long stand out, to spot where things got slow
+    gap_s = 60, -- :LogGaps lists the places the log went quiet for at least this long
+    columns = nil, -- e.g. { "ts", "level", "msg" }: start with only those fields shown (:LogColumns changes it)
+    delimited = true, -- open .csv/.tsv files as tables, the header naming the columns (:LogCsv toggles)
+    source_path = nil, -- where :LogSource looks for the files lines were logged from (defaults to 'path')
+    formats = nil, -- e.g. { acme = "%{ts} [%{level}] %{logger} - %{msg}" }: in-house line formats, see register_format
+    time_zone = nil, -- e.g. "local", "utc" or "+05:30": show timestamps in that zone (:LogZone changes it)
+    assume_time_zone = "utc", -- the zone of timestamps that don't say theirs
+    watch = false, -- report growth/rotation of the file on disk (:LogWatch toggles)
+    watch_debounce_ms = 500, -- writes landing within this window are reported together
+    follow = false, -- start in follow mode, like tail -f (:LogFollow toggles)
+    throughput_window_s = 10, -- require("juan_log").throughput() averages over this long
+    follow_warn_rate = 5000, -- lines/s while following past which you're told the screen can't keep up, 0 = never
+    keep_rotated_mb = 0, -- after a rotation keep this much of the rotated out file above the new one
+    stream_max_lines = 0, -- pipes and :LogRun keep at most this many lines, dropping the oldest, 0 = all
+    stream_max_mb = 0, -- same, by size
+    max_parked_filters = 10, -- closed filter splits whose results are kept for :LogFilters
+    filter_context = 0, -- lines shown around every filter match (:LogContext changes it per filter)
+    filter_slice_mb = 64, -- filters on bigger files scan this much at a time and show progress, 0 = never
+    open_timeout_ms = 10000, -- give up opening after this long (dead mounts, huge sparse files), 0 = wait
+    query_timeout_ms = 30000, -- give up on a search, filter or level count still running after this long, 0 = wait
+    io_retries = 3, -- times a read failing on a flaky (network) filesystem is retried
+    io_retry_delay_ms = 50, -- wait before the first retry, doubled for each one after
+    threads = 0, -- threads a search or filter scans with, 0 = all cores (shared with other plugins), 1 = one
+    read_only = false, -- open log files as pure viewers: edits are put back, :w refuses
+    write_audit = false, -- on :w also write the session's audit trail to <file>.audit
+    notes_author = nil -- what your :LogNote notes in <file>.notes are signed with, $USER by default
 }
 
 -- keep this in sync with the rust struct/externs or segfaults will happen.
+-- isize is ptrdiff_t, not long: long is 32 bits on windows and would chop line numbers.
+-- engines are numbers, not pointers: using a freed one is an error (see last_error), not a crash.
 ffi.cdef [[
-    typedef struct LogEngine LogEngine;
-    LogEngine* log_engine_new(const char* path);
-    size_t log_engine_total_lines(LogEngine* engine);
-    const char* log_engine_get_block(LogEngine* engine, size_t start_line, size_t num_lines, size_t* out_len);
-    void log_engine_apply_edit(LogEngine* engine, size_t start_line, size_t num_deleted, const char* new_text);
-    bool log_engine_save(LogEngine* engine, const char* path);
-    long log_engine_search(LogEngine* engine, const char* query, size_t start_line);
-    long log_engine_search_backward(LogEngine* engine, const char* query, size_t start_line);
-    void log_engine_free(LogEngine* engine);
+    typedef uint64_t LogEngine;
+    typedef struct {
+        uint32_t version;
+        uint32_t threads;
+        uint64_t chunk_size;
+        uint64_t max_memory;
+        uint32_t index;
+        bool follow;
+        bool read_only;
+        uint32_t io_retries;
+        uint64_t io_retry_delay_ms;
+    } LogEngineOptions;
+    LogEngine log_engine_new(const char* path);
+    LogEngine log_engine_open(const char* path, uint64_t timeout_ms, const LogEngineOptions* options, int32_t* out_error);
+    LogEngine log_engine_new_with_options(const char* path, const LogEngineOptions* options);
+    void log_engine_set_read_only(LogEngine engine);
+    bool log_engine_is_read_only(LogEngine engine);
+    void log_engine_set_strip_ansi(LogEngine engine, bool strip);
+    LogEngine log_engine_new_from_fd(int32_t fd);
+    LogEngine log_engine_new_from_bytes(const char* data, size_t len);
+    LogEngine log_engine_open_w(const uint16_t* path, uint64_t timeout_ms, const LogEngineOptions* options, int32_t* out_error);
+    size_t log_engine_total_lines(LogEngine engine);
+    uint64_t log_engine_line_to_byte(LogEngine engine, uint64_t line);
+    uint64_t log_engine_byte_to_line(LogEngine engine, uint64_t offset);
+    double log_engine_line_time(LogEngine engine, uint64_t line);
+    int32_t log_engine_time_range(LogEngine engine, double* out_first, double* out_last);
+    double log_engine_parse_time(const char* text, size_t len);
+    double log_engine_read_time(LogEngine engine, const char* text, size_t len);
+    int64_t log_engine_seek_time(LogEngine engine, double secs);
+    const char* log_engine_line_fields(LogEngine engine, uint64_t line, size_t* out_len);
+    const char* log_engine_value_counts(LogEngine engine, uint64_t start_line, uint64_t num_lines, const char* field, bool regex, uint64_t max, size_t* out_len);
+    const char* log_engine_templates(LogEngine engine, uint64_t start_line, uint64_t num_lines, uint64_t max, size_t* out_len);
+    const char* log_engine_field_counts(LogEngine engine, uint64_t start_line, uint64_t num_lines, const char* key, size_t* out_len);
+    const char* log_engine_project(LogEngine engine, uint64_t start_line, uint64_t num_lines, const char* fields, size_t* out_len);
+    bool log_engine_is_json_lines(LogEngine engine);
+    int64_t log_engine_json_expand(LogEngine engine, uint64_t line);
+    int64_t log_engine_json_collapse(LogEngine engine, uint64_t line);
+    bool log_engine_set_time_zone(LogEngine engine, const char* target, const char* assume);
+    const char* log_engine_zoned_times(LogEngine engine, uint64_t start_line, uint64_t num_lines, size_t* out_len);
+    const uint64_t* log_engine_ansi_colors(LogEngine engine, uint64_t start_line, uint64_t num_lines, uint64_t* out_count);
+    const uint64_t* log_engine_level_spans(LogEngine engine, uint64_t start_line, uint64_t num_lines, uint64_t* out_count);
+    const uint8_t* log_engine_line_levels(LogEngine engine, uint64_t start_line, uint64_t num_lines, uint64_t* out_count);
+    bool log_engine_level_counts(LogEngine engine, uint64_t* out_counts);
+    const uint64_t* log_engine_time_histogram(LogEngine engine, double from, double to, double bucket_secs, uint64_t* out_count);
+    const uint64_t* log_engine_time_gaps(LogEngine engine, uint64_t start_line, uint64_t num_lines, double min_secs, uint64_t* out_count);
+    const double* log_engine_time_deltas(LogEngine engine, uint64_t start_line, uint64_t num_lines, int64_t anchor_line, uint64_t* out_count);
+    typedef struct {
+        uint32_t version;
+        uint64_t file_size;
+        uint64_t total_lines;
+        uint64_t original_lines;
+        uint64_t memory_lines;
+        uint64_t pieces;
+        double indexed_percent;
+    } LogEngineStats;
+    bool log_engine_stats(LogEngine engine, LogEngineStats* out);
+    uint64_t log_engine_generation(LogEngine engine);
+    bool log_engine_block_still_valid(LogEngine engine, uint64_t generation);
+    const char* log_engine_get_block(LogEngine engine, size_t start_line, size_t num_lines, size_t* out_len);
+    const char* log_engine_get_block_lines(LogEngine engine, uint64_t start_line, uint64_t num_lines, size_t* out_len, const uint64_t** out_offsets, uint64_t* out_count);
+    const char* log_engine_get_line(LogEngine engine, uint64_t line, size_t* out_len);
+    const char* log_engine_get_lines(LogEngine engine, const uint64_t* lines, uint64_t count, size_t* out_len);
+    const char* log_engine_head_tail(LogEngine engine, uint64_t head_lines, uint64_t tail_lines, size_t* out_len);
+    int64_t log_engine_head_tail_line(LogEngine engine, uint64_t head_lines, uint64_t tail_lines, uint64_t row);
+    const char* log_engine_sample(LogEngine engine, uint32_t mode, uint64_t step, uint64_t max_rows, size_t* out_len);
+    int64_t log_engine_sample_line(LogEngine engine, uint32_t mode, uint64_t step, uint64_t max_rows, uint64_t row);
+    void log_engine_apply_edit(LogEngine engine, size_t start_line, size_t num_deleted, const char* new_text);
+    uint64_t log_engine_append(LogEngine engine, const char* text);
+    bool log_engine_save(LogEngine engine, const char* path);
+    const char* log_engine_audit(LogEngine engine, size_t* out_len);
+    bool log_engine_note_set(LogEngine engine, uint64_t line, const char* author, const char* text);
+    const char* log_engine_notes(LogEngine engine, uint64_t start_line, uint64_t num_lines, size_t* out_len);
+    int64_t log_engine_notes_sync(LogEngine engine);
+    const char* log_engine_notes_conflicts(LogEngine engine, size_t* out_len);
+    const char* log_engine_diagnostics(LogEngine engine, size_t* out_len);
+    ptrdiff_t log_engine_search(LogEngine engine, const char* query, size_t start_line);
+    ptrdiff_t log_engine_search_backward(LogEngine engine, const char* query, size_t start_line);
+    int64_t log_engine_search_mode(LogEngine engine, const char* query, uint64_t start_line, uint32_t mode, bool backward);
+    int64_t log_engine_search_mode_n(LogEngine engine, const char* query, size_t query_len, uint64_t start_line, uint32_t mode, bool backward);
+    typedef struct {
+        uint64_t start_line;
+        uint64_t num_deleted;
+        const char* text;
+        size_t text_len;
+    } LogEngineEdit;
+    bool log_engine_apply_edits(LogEngine engine, const LogEngineEdit* edits, size_t count);
+    bool log_engine_save_n(LogEngine engine, const char* path, size_t path_len);
+    const uint64_t* log_engine_match_spans_tagged(LogEngine engine, const char* query, uint64_t start_line, uint64_t num_lines, uint32_t mode, uint64_t* out_count);
+    void log_engine_set_match_limits(LogEngine engine, uint64_t min_list_query, uint64_t max_spans);
+    bool log_engine_spans_capped(LogEngine engine);
+    const uint64_t* log_engine_escaped_newlines(LogEngine engine, uint64_t start_line, uint64_t num_lines, uint64_t* out_count);
+    const uint64_t* log_engine_fold_ranges(LogEngine engine, uint64_t start_line, uint64_t num_lines, uint64_t* out_count);
+    const uint64_t* log_engine_repeat_runs(LogEngine engine, uint64_t start_line, uint64_t num_lines, bool templated, uint64_t* out_count);
+    bool log_engine_repeat_run(LogEngine engine, uint64_t line, bool templated, uint64_t* out_first, uint64_t* out_last);
+    bool log_engine_record_bounds(LogEngine engine, uint64_t line, uint64_t* out_first, uint64_t* out_last);
+    const char* log_engine_source_location(LogEngine engine, uint64_t line, size_t* out_len, uint64_t* out_line);
+    bool log_engine_set_delimited(LogEngine engine, bool enable, uint8_t delimiter);
+    const char* log_engine_columns(LogEngine engine, size_t* out_len);
+    void log_engine_set_hex_binary(LogEngine engine, bool hex);
+    const uint64_t* log_engine_binary_regions(LogEngine engine, uint64_t start_line, uint64_t num_lines, uint64_t* out_count);
+    const uint8_t* log_engine_raw_bytes(LogEngine engine, uint64_t start_line, uint64_t num_lines, size_t* out_len);
+    const uint64_t* log_engine_severity_folds(LogEngine engine, uint64_t start_line, uint64_t num_lines, uint32_t min_level, uint64_t* out_count);
+    void log_engine_set_io_retries(LogEngine engine, uint32_t attempts, uint64_t base_delay_ms);
+    uint64_t log_engine_io_retries(LogEngine engine);
+    uint64_t log_engine_io_retry_after_ms(LogEngine engine);
+    void log_engine_set_cache_ttl(LogEngine engine, uint64_t ttl_secs);
+    uint64_t log_engine_trim_caches(LogEngine engine, uint64_t max_idle_secs);
+    size_t log_engine_history_len(LogEngine engine);
+    const char* log_engine_history_get(LogEngine engine, size_t idx, size_t* out_len, uint64_t* out_hits, uint32_t* out_mode);
+    int32_t log_engine_search_step(LogEngine engine, const char* query, size_t query_len, uint32_t mode, uint64_t* out_scanned, uint64_t* out_total);
+    const uint64_t* log_engine_checkpoints(LogEngine engine, uint64_t block_lines, uint64_t first_block, uint64_t num_blocks, uint64_t* out_count);
+    const char* log_engine_summarize(LogEngine engine, uint64_t start_line, uint64_t num_lines, size_t* out_len);
+    bool log_engine_split_line(LogEngine engine, uint64_t line, uint64_t col);
+    bool log_engine_join_lines(LogEngine engine, uint64_t start_line, uint64_t count, const char* separator);
+    int64_t log_engine_filter_create(LogEngine engine, const char* query, uint32_t mode);
+    int64_t log_engine_filter_refine(LogEngine engine, uint64_t filter, const char* query, uint32_t mode);
+    int64_t log_engine_filter_level(LogEngine engine, uint32_t min_level);
+    int64_t log_engine_filter_exclude(LogEngine engine, uint64_t filter, const char* query, uint32_t mode);
+    bool log_engine_filter_park(LogEngine engine, uint64_t filter);
+    bool log_engine_filter_unpark(LogEngine engine, uint64_t filter);
+    int64_t log_engine_filter_combine(LogEngine engine, uint64_t a, uint64_t b, uint32_t op);
+    int64_t log_engine_filter_time(LogEngine engine, uint64_t filter, const char* from, const char* to);
+    uint64_t log_engine_filter_total_lines(LogEngine engine, uint64_t filter);
+    const char* log_engine_filter_get_block(LogEngine engine, uint64_t filter, uint64_t start_row, uint64_t num_rows, size_t* out_len);
+    int64_t log_engine_filter_line(LogEngine engine, uint64_t filter, uint64_t row);
+    int64_t log_engine_filter_row(LogEngine engine, uint64_t filter, uint64_t line, bool nearest);
+    int64_t log_engine_filter_search(LogEngine engine, uint64_t filter, const char* query, uint64_t start_row, uint32_t mode, bool backward);
+    bool log_engine_filter_context(LogEngine engine, uint64_t filter, uint64_t before, uint64_t after);
+    bool log_engine_filter_records(LogEngine engine, uint64_t filter, bool records);
+    bool log_engine_filter_origin(LogEngine engine, uint64_t filter, uint32_t origin);
+    const char* log_engine_filter_export(LogEngine engine, uint64_t filter, size_t* out_len);
+    int64_t log_engine_filter_import(LogEngine engine, const char* setup);
+    void log_engine_set_filter_slice(LogEngine engine, uint64_t bytes);
+    int32_t log_engine_filter_step(LogEngine engine, uint64_t filter, uint64_t* out_scanned, uint64_t* out_total, uint64_t* out_matches);
+    void log_engine_filter_free(LogEngine engine, uint64_t filter);
+    uint64_t log_engine_misuse_count(void);
+    const char* log_engine_misuse_last(size_t* out_len);
+    uint32_t log_engine_abi_version(void);
+    uint64_t log_engine_op_reserve(void);
+    bool log_engine_cancel(uint64_t op);
+    bool log_engine_op_timeout(uint64_t op, uint64_t ms);
+    bool log_engine_register_format(LogEngine engine, const char* name, const char* template);
+    bool log_engine_unregister_format(LogEngine engine, const char* name);
+    void log_engine_set_callback(uint32_t event_mask, void (*callback)(LogEngine, uint32_t, uint64_t, uint64_t, void*), void* userdata);
+    void log_engine_set_wakeup(uint32_t event_mask, int (*wake)(void*), void* handle);
+    bool log_engine_next_event(uint64_t* out_engine, uint32_t* out_event, uint64_t* out_a, uint64_t* out_b);
+    bool log_engine_same(LogEngine a, LogEngine b);
+    int uv_async_send(void* async);
+    int32_t log_engine_last_error_code(void);
+    const char* log_engine_last_error_message(size_t* out_len);
+    bool log_engine_watch_native(LogEngine engine);
+    void log_engine_unwatch_native(LogEngine engine);
+    int64_t log_engine_refresh(LogEngine engine);
+    int32_t log_engine_refresh_delta(LogEngine engine, uint64_t* out_total, uint64_t* out_first_changed);
+    int32_t log_engine_reopen(LogEngine engine);
+    int32_t log_engine_pipe_status(LogEngine engine);
+    void log_engine_set_retention(LogEngine engine, uint64_t max_lines, uint64_t max_bytes);
+    uint64_t log_engine_evicted_lines(LogEngine engine);
+    uint64_t log_engine_snapshot(LogEngine engine);
+    LogEngine log_engine_freeze(LogEngine engine);
+    uint64_t log_engine_snapshot_total_lines(LogEngine engine, uint64_t snapshot);
+    const char* log_engine_snapshot_get_block(LogEngine engine, uint64_t snapshot, uint64_t start_line, uint64_t num_lines, size_t* out_len);
+    bool log_engine_snapshot_save(LogEngine engine, uint64_t snapshot, const char* path);
+    void log_engine_snapshot_free(LogEngine engine, uint64_t snapshot);
+    LogEngine log_engine_snapshot_freeze(LogEngine engine, uint64_t snapshot);
+    void log_engine_pause(LogEngine engine);
+    int64_t log_engine_resume(LogEngine engine);
+    uint64_t log_engine_pending_bytes(LogEngine engine);
+    void log_engine_throughput(LogEngine engine, double* out_lines_per_sec, double* out_bytes_per_sec);
+    void log_engine_set_throughput_window(LogEngine engine, uint64_t window_ms);
+    void log_engine_set_keep_rotated(LogEngine engine, uint64_t max_bytes);
+    uint64_t log_engine_rotated_lines(LogEngine engine);
+    bool log_engine_poll_changes(LogEngine engine, uint64_t* out_bytes, uint64_t* out_lines, bool* out_rotated);
+    LogEngine log_engine_retain(LogEngine engine);
+    bool log_engine_release(LogEngine engine);
+    void log_engine_free(LogEngine engine);
 ]]
 
+local is_windows = vim.loop.os_uname().sysname == "Windows_NT"
+
 local function get_lib_path()
     local sysname = vim.loop.os_uname().sysname
     local ext = sysname == "Windows_NT" and "dll" or (sysname == "Darwin" and "dylib" or "so")
@@ -51,8 +269,119 @@ if not ok then
     lib = nil
 end
 
+-- the ABI_VERSION this file's cdef was written against. an older .so (plugin updated, not
+-- rebuilt) doesn't even have the symbol, calling into one would crash on a changed signature.
+local ABI_VERSION = 68
+if lib then
+    local found, version = pcall(function() return lib.log_engine_abi_version() end)
+    if not found or version ~= ABI_VERSION then
+        vim.schedule(function()
+            vim.notify(string.format("[JuanLog] %s is out of date (ABI %s, the plugin needs %d), run `cargo build --release` in the plugin directory. The viewer is disabled.",
+                so_path, found and tostring(version) or "unknown", ABI_VERSION), vim.log.levels.WARN)
+        end)
+        lib = nil
+    end
+end
+
+-- edits nvim reported that the engine hasn't got yet, by engine number. a burst of on_lines
+-- going down the buffer (one :s, most undos) goes over as one log_engine_apply_edits once it's
+-- done, or as soon as anything calls on the engine. { start, deleted, text } each, plus
+-- `shift` (lines added minus removed) and `frontier` (the line after the last one's new lines)
+local unsent = {}
+-- the library as loaded, for the calls that mustn't send them first (on_lines itself)
+local raw_lib = lib
+
+local function send_edits(engine)
+    local key = tonumber(engine)
+    local edits = key and unsent[key]
+    if not edits then return end
+    unsent[key] = nil
+    local batch = ffi.new("LogEngineEdit[?]", #edits)
+    for i, edit in ipairs(edits) do
+        batch[i - 1].start_line = edit[1]
+        batch[i - 1].num_deleted = edit[2]
+        batch[i - 1].text = edit[3]
+        batch[i - 1].text_len = #edit[3]
+    end
+    raw_lib.log_engine_apply_edits(engine, batch, #edits)
+end
+
+-- every call's first argument is the engine when it has one: whatever is held back for it
+-- goes first, nothing reads lines the buffer has already changed
+if lib then
+    lib = setmetatable({}, {
+        __index = function(wrapped, name)
+            local call = raw_lib[name]
+            local function sending(first, ...)
+                if type(first) ~= "string" then send_edits(first) end
+                return call(first, ...)
+            end
+            wrapped[name] = sending
+            return sending
+        end
+    })
+end
+
+-- search modes, must match SearchMode on the rust side
+local SEARCH_LITERAL = 0
+local SEARCH_QUERY = 1
+local SEARCH_GLOB = 2
+local SEARCH_REGEX = 3
+
+-- bits of what log_engine_reopen returns
+local REOPEN_KEPT_EDITS = 1
+local REOPEN_CLOSED_SNAPSHOTS = 4
+local SEARCH_INVALID = -2 -- returned when the query doesn't compile (or was given up on)
+local ERROR_CANCELLED = 10 -- log_engine_last_error_code: query_timeout_ms ran out
+local ERROR_LOCKED = 13 -- log_engine_last_error_code: another session holds the file's lock
+local FILTER_TIME = "time" -- plugin side only: open_filter's query is then "from .. to"
+
+local match_ns = vim.api.nvim_create_namespace("juan_log_matches")
+local expand_ns = vim.api.nvim_create_namespace("juan_log_expand")
+local delta_ns = vim.api.nvim_create_namespace("juan_log_deltas")
+local zone_ns = vim.api.nvim_create_namespace("juan_log_zones")
+local columns_ns = vim.api.nvim_create_namespace("juan_log_columns")
+local level_ns = vim.api.nvim_create_namespace("juan_log_levels")
+local ansi_ns = vim.api.nvim_create_namespace("juan_log_ansi")
+local binary_ns = vim.api.nvim_create_namespace("juan_log_binary")
+local notes_ns = vim.api.nvim_create_namespace("juan_log_notes")
+
+-- one group per severity, trace first like rust ranks them. linked by default so
+-- colorschemes (and you) can set them
+local LEVEL_GROUPS = { "JuanLogTrace", "JuanLogDebug", "JuanLogInfo", "JuanLogWarn", "JuanLogError", "JuanLogFatal" }
+-- groups made up for terminal colors, "fg_bg_attrs" -> name, see ansi_group
+local ansi_groups = {}
+
+local function define_highlights()
+    for group, link in pairs({
+        JuanLogTrace = "Comment",
+        JuanLogDebug = "DiagnosticHint",
+        JuanLogInfo = "DiagnosticInfo",
+        JuanLogWarn = "DiagnosticWarn",
+        JuanLogError = "DiagnosticError",
+        JuanLogFatal = "ErrorMsg",
+        JuanLogBinary = "NonText",
+        JuanLogNote = "Todo",
+    }) do
+        vim.api.nvim_set_hl(0, group, { link = link, default = true })
+    end
+    ansi_groups = {}
+end
+define_highlights()
+-- :colorscheme clears every group, ours included
+vim.api.nvim_create_autocmd("ColorScheme", { callback = define_highlights })
+
 -- global state to map buffers to rust engines
 _G.JuanLogStates = _G.JuanLogStates or {}
+-- read-only scratch views derived from an engine buffer. numbers[row] = absolute line
+-- (0-based), -1 = none
+_G.JuanLogViews = _G.JuanLogViews or {}
+-- real path -> { [engine number] = true } of the buffers holding that file's engine. a second
+-- buffer on the same file (through a symlink, say) retains it instead of opening it again
+_G.JuanLogEngines = _G.JuanLogEngines or {}
+-- {name, template} for the line formats from config.formats and register_format, in order:
+-- every engine gets them when it's opened, see M.register_format
+local formats = {}
 
 -- custom status column to fake absolute line numbers.
 -- since the buffer only holds a small chunk, 'lnum' is wrong relative to the file.
@@ -62,17 +391,38 @@ _G._juan_log_statuscol = function()
     local st = _G.JuanLogStates[b]
     
     if st and config.mode == "dynamic" then
-        return string.format("%%=%d ", st.offset + vim.v.lnum)
+        return string.format("%%=%d ", st.evicted + st.offset + vim.v.lnum)
+    end
+
+    local view = _G.JuanLogViews[b]
+    if view then
+        local line = view.numbers[vim.v.lnum]
+        if not line or line < 0 then return "%= " end
+        return string.format("%%=%d ", line + 1)
     end
     return "%=%l "
 end
 
-local function fetch_lines(engine, start, count)
-    local len_ptr = ffi.new("size_t[1]")
-    -- this pointer is only valid until the next call to rust. copy immediately.
-    local block_ptr = lib.log_engine_get_block(engine, start, count, len_ptr)
-    
+-- foldexpr for the main buffer, reads what fold_entries left in the state
+_G._juan_log_foldexpr = function()
+    local st = _G.JuanLogStates[vim.api.nvim_get_current_buf()]
+    return st and st.folds and st.folds[vim.v.lnum] or "0"
+end
+
+-- a collapsed run of repeats: its first line and how many there are, the whole run's
+-- count when it goes on past the loaded window
+_G._juan_log_foldtext = function()
+    local st = _G.JuanLogStates[vim.api.nvim_get_current_buf()]
+    local count = st and st.run_sizes and st.run_sizes[vim.v.foldstart] or vim.v.foldend - vim.v.foldstart + 1
+    return string.format("%s  ×%d", vim.fn.getline(vim.v.foldstart), count)
+end
+
+-- this pointer is only valid until the next call to rust. copy immediately.
+-- with `engine`, `generation` is what log_engine_generation said right after the
+-- pointer came back: if anything touched the engine since, nothing is read through it.
+local function block_to_lines(block_ptr, len_ptr, engine, generation)
     if block_ptr == nil then return {} end
+    if engine and not lib.log_engine_block_still_valid(engine, generation) then return {} end
     
     local length = tonumber(len_ptr[0])
     if length == 0 then return {} end
@@ -86,6 +436,71 @@ local function fetch_lines(engine, start, count)
     return vim.split(raw_text, "\n", { plain = true })
 end
 
+-- why the call that just failed did, as the engine words it ("Permission denied (os error
+-- 13)", the regex error...). only means something right after a failure.
+local function last_error()
+    local len = ffi.new("size_t[1]")
+    local msg = lib.log_engine_last_error_message(len)
+    if msg == nil or len[0] == 0 then return "unknown error" end
+    return ffi.string(msg, len[0])
+end
+
+-- calls `call` with the rest as a slow call the engine gives up on after query_timeout_ms.
+-- nothing here can call log_engine_cancel while the call blocks the editor, the deadline
+-- is waited out on a thread of the engine's own
+local function bounded(call, ...)
+    if config.query_timeout_ms > 0 then
+        lib.log_engine_op_timeout(lib.log_engine_op_reserve(), config.query_timeout_ms)
+    end
+    return call(...)
+end
+
+-- a query that came back SEARCH_INVALID: it didn't compile, or took too long
+local function query_failed(what, query)
+    if lib.log_engine_last_error_code() == ERROR_CANCELLED then
+        vim.notify(string.format("[JuanLog] Gave up on %s after %ds: %s", what, config.query_timeout_ms / 1000, query),
+            vim.log.levels.WARN)
+        return
+    end
+    vim.notify("[JuanLog] Invalid " .. what .. ": " .. query .. " (" .. last_error() .. ")", vim.log.levels.ERROR)
+end
+
+-- the engine says where each line starts, every line is cut straight out of the block
+-- instead of splitting one big string. same pointer rules as block_to_lines
+local function fetch_lines(engine, start, count)
+    local len_ptr = ffi.new("size_t[1]")
+    local offsets_ptr = ffi.new("const uint64_t*[1]")
+    local count_ptr = ffi.new("uint64_t[1]")
+    local block = lib.log_engine_get_block_lines(engine, start, count, len_ptr, offsets_ptr, count_ptr)
+    if block == nil or count_ptr[0] == 0 then return {} end
+
+    local offsets = offsets_ptr[0]
+    local lines = {}
+    for i = 0, tonumber(count_ptr[0]) - 1 do
+        local from, to = tonumber(offsets[i]), tonumber(offsets[i + 1]) - 1
+        if to > from and block[to - 1] == 13 then to = to - 1 end -- \r of a \r\n
+        lines[i + 1] = ffi.string(block + from, to - from)
+    end
+    return lines
+end
+
+-- one line as a string, nil past the end. same pointer rules as fetch_lines
+local function fetch_line(engine, line)
+    local len_ptr = ffi.new("size_t[1]")
+    local text = lib.log_engine_get_line(engine, line, len_ptr)
+    if text == nil or not lib.log_engine_block_still_valid(engine, lib.log_engine_generation(engine)) then return nil end
+    return ffi.string(text, len_ptr[0])
+end
+
+-- the lines at the given 0-based numbers, in that order, in one call. "" for one past the end
+local function fetch_lines_at(engine, numbers)
+    if #numbers == 0 then return {} end
+    local wanted = ffi.new("uint64_t[?]", #numbers, numbers)
+    local len_ptr = ffi.new("size_t[1]")
+    local block = lib.log_engine_get_lines(engine, wanted, #numbers, len_ptr)
+    return block_to_lines(block, len_ptr, engine, lib.log_engine_generation(engine))
+end
+
 local function load_all_lines(bufnr, engine, total_lines)
     local chunk_size = 50000 
     local loaded = 0
@@ -112,7 +527,450 @@ local function load_all_lines(bufnr, engine, total_lines)
     vim.api.nvim_buf_set_option(bufnr, 'modified', false)
 end
 
+-- paint the last search inside whatever chunk is loaded right now.
+-- rust finds the spans, we just place extmarks.
+local function highlight_matches(bufnr, state)
+    if not config.highlight_matches then return end
+    vim.api.nvim_buf_clear_namespace(bufnr, match_ns, 0, -1)
+    if not state.last_query then return end
+
+    local count_ptr = ffi.new("uint64_t[1]")
+    local buf_lines = vim.api.nvim_buf_line_count(bufnr)
+    local spans = lib.log_engine_match_spans_tagged(state.engine, state.last_query, state.offset, buf_lines, state.last_mode, count_ptr)
+    if spans == nil then return end
+
+    for i = 0, tonumber(count_ptr[0]) - 1 do
+        local row = tonumber(spans[i * 4]) - state.offset
+        local col = tonumber(spans[i * 4 + 1])
+        local len = tonumber(spans[i * 4 + 2])
+        -- hits in lines you typed yourself look different from hits in the log
+        local hl = spans[i * 4 + 3] ~= 0 and "Substitute" or "Search"
+        -- buffer may have drifted from the engine mid-edit, a stale span is not worth an error
+        pcall(vim.api.nvim_buf_set_extmark, bufnr, match_ns, row, col, { end_col = col + len, hl_group = hl })
+    end
+
+    -- say it once per query, not on every scroll
+    if lib.log_engine_spans_capped(state.engine) and state.capped_query ~= state.last_query then
+        state.capped_query = state.last_query
+        vim.api.nvim_echo({ { "[JuanLog] Too many matches to highlight them all", "WarningMsg" } }, false, {})
+    end
+end
+
+-- xterm's first 16 colors, for when the terminal_color_N variables aren't set
+local ANSI_BASE = {
+    "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+    "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+}
+
+-- a color as rust encodes it (0 default, 1 + palette index, 0x1000000 + rgb) -> "#rrggbb"
+-- and the palette index for cterm, nil for the default
+local function ansi_color(code)
+    if code == 0 then return nil end
+    if code >= 0x1000000 then
+        return string.format("#%06x", code - 0x1000000), nil
+    end
+    local n = code - 1
+    if n < 16 then
+        return vim.g["terminal_color_" .. n] or ANSI_BASE[n + 1], n
+    elseif n < 232 then
+        local steps = { 0, 95, 135, 175, 215, 255 }
+        local i = n - 16
+        return string.format("#%02x%02x%02x", steps[math.floor(i / 36) + 1], steps[math.floor(i / 6) % 6 + 1], steps[i % 6 + 1]), n
+    end
+    local gray = 8 + (n - 232) * 10
+    return string.format("#%02x%02x%02x", gray, gray, gray), n
+end
+
+-- the highlight group for one combination of colors and attributes, made the first time
+local function ansi_group(fg, bg, attrs)
+    local key = fg .. "_" .. bg .. "_" .. attrs
+    if ansi_groups[key] then return ansi_groups[key] end
+    local name = "JuanLogAnsi_" .. key
+    local spec = {
+        bold = bit.band(attrs, 1) ~= 0,
+        italic = bit.band(attrs, 4) ~= 0,
+        underline = bit.band(attrs, 8) ~= 0,
+        reverse = bit.band(attrs, 16) ~= 0,
+        strikethrough = bit.band(attrs, 32) ~= 0,
+    }
+    spec.fg, spec.ctermfg = ansi_color(fg)
+    spec.bg, spec.ctermbg = ansi_color(bg)
+    if bit.band(attrs, 2) ~= 0 and not spec.fg then spec.link = "Comment" end -- dim, as best we can
+    vim.api.nvim_set_hl(0, name, spec)
+    ansi_groups[key] = name
+    return name
+end
+
+-- draw the loaded window in the colors its terminal escapes ask for. rust decodes them into
+-- runs with their colors, we make a group per combination and place extmarks.
+local function show_ansi_colors(bufnr, state)
+    vim.api.nvim_buf_clear_namespace(bufnr, ansi_ns, 0, -1)
+    if not state.ansi_colors then return end
+
+    local count_ptr = ffi.new("uint64_t[1]")
+    local buf_lines = vim.api.nvim_buf_line_count(bufnr)
+    local spans = lib.log_engine_ansi_colors(state.engine, state.offset, buf_lines, count_ptr)
+    if spans == nil then return end
+
+    for i = 0, tonumber(count_ptr[0]) - 1 do
+        local row = tonumber(spans[i * 6]) - state.offset
+        local col = tonumber(spans[i * 6 + 1])
+        local len = tonumber(spans[i * 6 + 2])
+        local hl = ansi_group(tonumber(spans[i * 6 + 3]), tonumber(spans[i * 6 + 4]), tonumber(spans[i * 6 + 5]))
+        -- under level colors and search hits
+        pcall(vim.api.nvim_buf_set_extmark, bufnr, ansi_ns, row, col, { end_col = col + len, hl_group = hl, priority = 100 })
+    end
+end
+
+-- color where every line of the loaded window says its severity. rust finds the level
+-- words, fields and syslog priorities, we just place extmarks.
+local function highlight_levels(bufnr, state)
+    vim.api.nvim_buf_clear_namespace(bufnr, level_ns, 0, -1)
+    if not config.highlight_levels then return end
+
+    local count_ptr = ffi.new("uint64_t[1]")
+    local buf_lines = vim.api.nvim_buf_line_count(bufnr)
+    local spans = lib.log_engine_level_spans(state.engine, state.offset, buf_lines, count_ptr)
+    if spans == nil then return end
+
+    for i = 0, tonumber(count_ptr[0]) - 1 do
+        local row = tonumber(spans[i * 4]) - state.offset
+        local col = tonumber(spans[i * 4 + 1])
+        local len = tonumber(spans[i * 4 + 2])
+        local hl = LEVEL_GROUPS[tonumber(spans[i * 4 + 3]) + 1]
+        -- below search hits, a match inside a level word still shows
+        pcall(vim.api.nvim_buf_set_extmark, bufnr, level_ns, row, col, { end_col = col + len, hl_group = hl, priority = 150 })
+    end
+end
+
+-- the binary lines of the loaded window, which the engine hands out as hex previews, dimmed
+-- so they read as a placeholder and not as log text
+local function show_binary(bufnr, state)
+    vim.api.nvim_buf_clear_namespace(bufnr, binary_ns, 0, -1)
+    if not state.hex_binary then return end
+
+    local count_ptr = ffi.new("uint64_t[1]")
+    local buf_lines = vim.api.nvim_buf_line_count(bufnr)
+    local pairs_ptr = lib.log_engine_binary_regions(state.engine, state.offset, buf_lines, count_ptr)
+    if pairs_ptr == nil then return end
+
+    for i = 0, tonumber(count_ptr[0]) - 1 do
+        local first = tonumber(pairs_ptr[i * 2]) - state.offset
+        local last = tonumber(pairs_ptr[i * 2 + 1]) - state.offset
+        pcall(vim.api.nvim_buf_set_extmark, bufnr, binary_ns, first, 0, { end_row = last + 1, hl_group = "JuanLogBinary", priority = 90 })
+    end
+end
+
+-- the engine escapes tabs and line breaks in notes so every one stays on its row
+local function unescape_note(text)
+    return (text:gsub("\\(.)", { t = "\t", n = "\n", r = "\r", ["\\"] = "\\" }))
+end
+
+-- notes on the loaded window's lines (see :LogNote), at the end of the line they're on
+local function show_notes(bufnr, state)
+    vim.api.nvim_buf_clear_namespace(bufnr, notes_ns, 0, -1)
+    local len_ptr = ffi.new("size_t[1]")
+    local ptr = lib.log_engine_notes(state.engine, state.offset, vim.api.nvim_buf_line_count(bufnr), len_ptr)
+    if ptr == nil then return end
+    for row in ffi.string(ptr, len_ptr[0]):gmatch("[^\n]+") do
+        local line, _, author, text = row:match("^(%d+)\t(%d+)\t([^\t]*)\t(.*)$")
+        if line then
+            local note = unescape_note(author) .. ": " .. unescape_note(text):gsub("\n", " ")
+            pcall(vim.api.nvim_buf_set_extmark, bufnr, notes_ns, tonumber(line) - state.offset, 0, {
+                virt_text = { { "  ✎ " .. note, "JuanLogNote" } },
+                virt_text_pos = "eol",
+            })
+        end
+    end
+end
+
+-- `00000000: 7f45 4c46 0201 0100 0000 0000 0000 0000  .ELF............`, like xxd
+local function hex_dump(bytes)
+    local rows = {}
+    for at = 1, #bytes, 16 do
+        local chunk = bytes:sub(at, at + 15)
+        local hex = chunk:gsub("..?", function(pair)
+            return (pair:gsub(".", function(c) return string.format("%02x", c:byte()) end)) .. " "
+        end)
+        local text = chunk:gsub("[^\32-\126]", ".")
+        rows[#rows + 1] = string.format("%08x: %-40s %s", at - 1, hex, text)
+    end
+    return rows
+end
+
+-- flattened stack traces (literal \n inside one line) drawn as several rows: the rest of the
+-- line after the first \n is concealed and every piece comes back as a virtual line below.
+-- the buffer line itself is untouched, so edits and search still see one line.
+local function expand_escapes(bufnr, state)
+    vim.api.nvim_buf_clear_namespace(bufnr, expand_ns, 0, -1)
+    if not state.expand then return end
+
+    local count_ptr = ffi.new("uint64_t[1]")
+    local buf_lines = vim.api.nvim_buf_line_count(bufnr)
+    local breaks_ptr = lib.log_engine_escaped_newlines(state.engine, state.offset, buf_lines, count_ptr)
+    if breaks_ptr == nil then return end
+
+    local breaks = {}
+    for i = 0, tonumber(count_ptr[0]) - 1 do
+        local row = tonumber(breaks_ptr[i * 2]) - state.offset
+        breaks[row] = breaks[row] or {}
+        table.insert(breaks[row], tonumber(breaks_ptr[i * 2 + 1]))
+    end
+
+    for row, cols in pairs(breaks) do
+        local text = vim.api.nvim_buf_get_lines(bufnr, row, row + 1, false)[1]
+        if text then
+            local virt = {}
+            for i, col in ipairs(cols) do
+                -- col is the 0-based byte of the backslash, the piece starts after the n
+                local piece = text:sub(col + 3, cols[i + 1] or #text):gsub("\\t", "    ")
+                table.insert(virt, { { "    " .. piece, "Normal" } })
+            end
+            pcall(vim.api.nvim_buf_set_extmark, bufnr, expand_ns, row, cols[1], {
+                end_col = #text,
+                conceal = "",
+                virt_lines = virt
+            })
+        end
+    end
+end
+
+-- "+850ms", "+3.2s", "+2m05s", "+1h10m"
+local function format_delta(secs)
+    local sign = secs < 0 and "-" or "+"
+    secs = math.abs(secs)
+    local floor = math.floor
+    if secs < 1 then return string.format("%s%dms", sign, floor(secs * 1000)) end
+    if secs < 60 then return string.format("%s%.1fs", sign, secs) end
+    if secs < 3600 then return string.format("%s%dm%02ds", sign, floor(secs / 60), floor(secs % 60)) end
+    return string.format("%s%dh%02dm", sign, floor(secs / 3600), floor(secs % 3600 / 60))
+end
+
+-- "90", "90s", "5m", "1.5h", "2d" -> seconds, nil for anything else
+local function duration_secs(text)
+    local n, unit = text:match("^(%d+%.?%d*)([smhd]?)$")
+    if not n then return nil end
+    return tonumber(n) * ({ [""] = 1, s = 1, m = 60, h = 3600, d = 86400 })[unit]
+end
+
+-- { { line = 0-based line, secs = how long it came after the stamps before it }, ... } for
+-- the lines of [start, start + count) written at least min_secs after the ones above them
+local function time_gaps(state, start, count, min_secs)
+    local count_ptr = ffi.new("uint64_t[1]")
+    local gaps = lib.log_engine_time_gaps(state.engine, start, count, min_secs, count_ptr)
+    if gaps == nil then return nil end
+    local out = {}
+    for i = 0, tonumber(count_ptr[0]) - 1 do
+        out[i + 1] = { line = tonumber(gaps[2 * i]), secs = tonumber(gaps[2 * i + 1]) / 1000 }
+    end
+    return out
+end
+
+-- lines per bucket_secs from `from` up to `to`, a count per bucket. nil when the engine
+-- refused (to before from, too many buckets)
+local function time_histogram(state, from, to, bucket_secs)
+    local count_ptr = ffi.new("uint64_t[1]")
+    local counts = lib.log_engine_time_histogram(state.engine, from, to, bucket_secs, count_ptr)
+    if counts == nil then return nil end
+    local out = {}
+    for i = 0, tonumber(count_ptr[0]) - 1 do out[i + 1] = tonumber(counts[i]) end
+    return out
+end
+
+local BUCKETS = { second = 1, minute = 60, hour = 3600, day = 86400 }
+local SPARKS = { " ", "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█" }
+
+-- how long after the stamped line before it every line of the loaded window was written, as
+-- virtual text at the end of the line, and how long after the anchor when :LogAnchor set one
+local function show_deltas(bufnr, state)
+    vim.api.nvim_buf_clear_namespace(bufnr, delta_ns, 0, -1)
+    if not state.deltas then return end
+
+    local count_ptr = ffi.new("uint64_t[1]")
+    local buf_lines = vim.api.nvim_buf_line_count(bufnr)
+    local deltas = lib.log_engine_time_deltas(state.engine, state.offset, buf_lines, state.anchor or -1, count_ptr)
+    if deltas == nil then return end
+
+    for row = 0, tonumber(count_ptr[0]) - 1 do
+        local previous, anchor = deltas[row * 2], deltas[row * 2 + 1]
+        local virt = {}
+        -- NaN ~= NaN: no stamp on this line, or nothing to compare it with
+        if previous == previous then
+            local hl = math.abs(previous) >= config.slow_delta_s and "WarningMsg" or "Comment"
+            table.insert(virt, { format_delta(previous), hl })
+        end
+        if anchor == anchor then
+            table.insert(virt, { "  " .. format_delta(anchor) .. " from anchor", "Comment" })
+        end
+        if #virt > 0 then
+            pcall(vim.api.nvim_buf_set_extmark, bufnr, delta_ns, row, 0, { virt_text = virt })
+        end
+    end
+end
+
+-- timestamps of the loaded window shown in the zone from :LogZone. the stamp is concealed and
+-- the converted one drawn in its place (at the end of the line before nvim 0.10, which
+-- can't put virtual text inside one). the buffer text stays as it was.
+local function show_zones(bufnr, state)
+    vim.api.nvim_buf_clear_namespace(bufnr, zone_ns, 0, -1)
+    if not state.zone then return end
+
+    local len_ptr = ffi.new("size_t[1]")
+    local buf_lines = vim.api.nvim_buf_line_count(bufnr)
+    local ptr = lib.log_engine_zoned_times(state.engine, state.offset, buf_lines, len_ptr)
+    if ptr == nil then return end
+
+    local inline = vim.fn.has("nvim-0.10") == 1
+    local row = 0
+    for entry in ffi.string(ptr, tonumber(len_ptr[0])):gmatch("([^\n]*)\n") do
+        local col, len, stamp = entry:match("^(%d+)\t(%d+)\t(.*)$")
+        if col then
+            col, len = tonumber(col), tonumber(len)
+            local mark = inline
+                and { end_col = col + len, conceal = "", virt_text = { { stamp, "Special" } }, virt_text_pos = "inline" }
+                or { virt_text = { { stamp, "Special" } } }
+            pcall(vim.api.nvim_buf_set_extmark, bufnr, zone_ns, row, col, mark)
+        end
+        row = row + 1
+    end
+end
+
+-- the column view: every line of the loaded window drawn as only the fields in
+-- state.columns, over the line itself. the buffer keeps the full lines, so yanking, editing
+-- and jumping work on them as usual and turning the view off shows them again.
+local function show_columns(bufnr, state)
+    vim.api.nvim_buf_clear_namespace(bufnr, columns_ns, 0, -1)
+    if not state.columns then return end
+
+    local len_ptr = ffi.new("size_t[1]")
+    local buf_lines = vim.api.nvim_buf_line_count(bufnr)
+    local ptr = lib.log_engine_project(state.engine, state.offset, buf_lines, state.columns, len_ptr)
+    if ptr == nil then return end
+
+    local lines = vim.api.nvim_buf_get_lines(bufnr, 0, -1, false)
+    local row = 0
+    for projected in ffi.string(ptr, tonumber(len_ptr[0])):gmatch("([^\n]*)\n") do
+        -- an overlay only hides what it covers, pad it past the end of the real line
+        local pad = vim.fn.strdisplaywidth(lines[row + 1] or "") - vim.fn.strdisplaywidth(projected)
+        local text = pad > 0 and projected .. string.rep(" ", pad) or projected
+        pcall(vim.api.nvim_buf_set_extmark, bufnr, columns_ns, row, 0,
+            { virt_text = { { text, "Normal" } }, virt_text_pos = "overlay" })
+        row = row + 1
+    end
+end
+
+-- severities in the order rust ranks them, for :LogFold <level>
+local LEVELS = { "trace", "debug", "info", "warn", "error", "fatal" }
+
+-- "warn" -> 3, nil when it's not a level
+local function level_rank(name)
+    local idx = name and vim.fn.index(LEVELS, name:lower()) or -1
+    return idx >= 0 and idx or nil
+end
+
+-- { trace = n, ..., fatal = n, none = n }: lines at every level in the whole log. the first
+-- call reads the whole file, unless a level filter already did
+local function level_counts(state)
+    local counts = ffi.new("uint64_t[7]")
+    if not bounded(lib.log_engine_level_counts, state.engine, counts) then return nil end
+    local out = { none = tonumber(counts[6]) }
+    for rank, name in ipairs(LEVELS) do out[name] = tonumber(counts[rank - 1]) end
+    return out
+end
+
+-- 10k, 1.2M
+local function short_count(n)
+    if n < 1000 then return tostring(n) end
+    if n < 1e6 then return (string.format("%.1fk", n / 1e3):gsub("%.0k$", "k")) end
+    return (string.format("%.1fM", n / 1e6):gsub("%.0M$", "M"))
+end
+
+-- "2 FATAL, 143 ERROR, 10k WARN, 1.2M INFO", most severe first, levels nobody used left out
+local function format_level_counts(counts)
+    local parts = {}
+    for rank = #LEVELS, 1, -1 do
+        local n = counts[LEVELS[rank]]
+        if n > 0 then table.insert(parts, short_count(n) .. " " .. LEVELS[rank]:upper()) end
+    end
+    return #parts > 0 and table.concat(parts, ", ") or "no levels"
+end
+
+-- multi-line entries (tracebacks, wrapped lines) of the loaded window as closed folds, or
+-- with fold_below set, runs of lines less severe than that level.
+-- rust works out where each fold ends, the foldexpr just looks the row up.
+local function fold_entries(bufnr, state)
+    local winid = vim.fn.bufwinid(bufnr)
+    if winid == -1 then return end
+    if not state.fold and not state.fold_below and not state.repeats then
+        if state.folds then
+            state.folds, state.run_sizes = nil, nil
+            vim.wo[winid].foldmethod = "manual"
+            vim.wo[winid].foldtext = "foldtext()"
+            vim.api.nvim_win_call(winid, function() vim.cmd("normal! zE") end)
+        end
+        return
+    end
+
+    local count_ptr = ffi.new("uint64_t[1]")
+    local buf_lines = vim.api.nvim_buf_line_count(bufnr)
+    local ranges
+    if state.repeats then
+        ranges = lib.log_engine_repeat_runs(state.engine, state.offset, buf_lines, state.repeats == "template", count_ptr)
+    elseif state.fold_below then
+        ranges = lib.log_engine_severity_folds(state.engine, state.offset, buf_lines, state.fold_below, count_ptr)
+    else
+        ranges = lib.log_engine_fold_ranges(state.engine, state.offset, buf_lines, count_ptr)
+    end
+    if ranges == nil then return end
+
+    local folds, edges = {}, {}
+    for i = 0, tonumber(count_ptr[0]) - 1 do
+        local first = tonumber(ranges[i * 2]) - state.offset + 1
+        local last = tonumber(ranges[i * 2 + 1]) - state.offset + 1
+        folds[first] = ">1"
+        for row = first + 1, last do folds[row] = "1" end
+        if first == 1 or last == buf_lines then table.insert(edges, first) end
+    end
+    state.folds = folds
+
+    -- runs cut by the window's edges are counted whole
+    state.run_sizes = nil
+    if state.repeats then
+        state.run_sizes = {}
+        local first, last = ffi.new("uint64_t[1]"), ffi.new("uint64_t[1]")
+        for _, row in ipairs(edges) do
+            if lib.log_engine_repeat_run(state.engine, state.offset + row - 1, state.repeats == "template", first, last) then
+                state.run_sizes[row] = tonumber(last[0] - first[0]) + 1
+            end
+        end
+    end
+    vim.wo[winid].foldtext = state.repeats and "v:lua._juan_log_foldtext()" or "foldtext()"
+
+    vim.wo[winid].foldexpr = "v:lua._juan_log_foldexpr()"
+    vim.wo[winid].foldmethod = "expr"
+    vim.wo[winid].foldlevel = 0
+    -- foldexpr results are cached per line, make vim ask again
+    vim.api.nvim_win_call(winid, function() vim.cmd("normal! zx") end)
+end
+
 -- "teleport" the visible window to a new location in the huge file
+-- seconds since the epoch for what was typed after :LogSeek, read like the log's own stamps.
+-- "HH:MM" or "HH:MM:SS" alone takes its day from the line under the cursor. nil if it's
+-- not a time.
+local function seek_secs(state, text)
+    local secs = lib.log_engine_read_time(state.engine, text, #text)
+    if secs == secs then return secs end
+    local hour, minute, second = text:match("^(%d%d?):(%d%d):?(%d*%.?%d*)$")
+    if not hour then return nil end
+    local cursor = state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1
+    local now = lib.log_engine_line_time(state.engine, cursor)
+    if now ~= now then return nil end
+    -- the day as the log's clock reads it, in the zone stamps without an offset are in
+    local day = os.date("!%Y-%m-%d", math.floor(now / 86400) * 86400)
+    local offset = lib.log_engine_parse_time(day, #day) - lib.log_engine_read_time(state.engine, day, #day)
+    return math.floor((now + offset) / 86400) * 86400 - offset + hour * 3600 + minute * 60 + (tonumber(second) or 0)
+end
+
 local function jump_to_line(bufnr, state, found_line)
     local half_chunk = math.floor(config.dynamic_chunk_size / 2)
     local new_offset = math.max(0, found_line - half_chunk)
@@ -136,107 +994,1139 @@ local function jump_to_line(bufnr, state, found_line)
     state.offset = new_offset
     vim.api.nvim_buf_set_option(bufnr, 'modified', was_modified)
     state.updating = false
+    show_ansi_colors(bufnr, state)
+    highlight_levels(bufnr, state)
+    show_binary(bufnr, state)
+    show_notes(bufnr, state)
+    highlight_matches(bufnr, state)
+    expand_escapes(bufnr, state)
+    show_deltas(bufnr, state)
+    show_zones(bufnr, state)
+    show_columns(bufnr, state)
+    fold_entries(bufnr, state)
     
     vim.cmd("normal! zz")
 end
 
-local function setup_dynamic_window(bufnr, engine, total_lines, filepath)
-    local state = {
-        offset = 0,
-        total = total_lines,
-        bufnr = bufnr,
-        engine = engine,
-        updating = false, -- semaphore to prevent recursion loops
-        last_query = nil,
-        timer = vim.loop.new_timer()
-    }
-    _G.JuanLogStates[bufnr] = state
+-- the json on the cursor line pretty-printed over several lines, or put back on one when
+-- it already is. only what's shown changes, :w writes it the way it was.
+local function toggle_json(bufnr, state)
+    local line = state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1
+    local at = tonumber(lib.log_engine_json_collapse(state.engine, line))
+    if at < 0 then
+        if tonumber(lib.log_engine_json_expand(state.engine, line)) < 0 then
+            vim.notify("[JuanLog] No JSON on this line", vim.log.levels.WARN)
+            return
+        end
+        at = line
+    end
+    state.total = tonumber(lib.log_engine_total_lines(state.engine))
+    jump_to_line(bufnr, state, at)
+end
 
-    state.updating = true
-    local initial_lines = fetch_lines(engine, 0, config.dynamic_chunk_size)
-    vim.api.nvim_buf_set_lines(bufnr, 0, -1, false, initial_lines)
-    vim.api.nvim_buf_set_option(bufnr, 'modified', false)
-    state.updating = false
+local function search(state, query, mode, start_line, backward)
+    -- with its length, a NUL pasted into the query is searched for instead of ending it
+    return tonumber(bounded(lib.log_engine_search_mode_n, state.engine, query, #query, start_line, mode, backward))
+end
 
-    local winid = vim.fn.bufwinid(bufnr)
-    if winid ~= -1 and config.enable_custom_statuscol then
-        vim.wo[winid].statuscolumn = "%!v:lua._juan_log_statuscol()"
-        vim.wo[winid].number = true
+-- first and last line of the record `line` is part of: the line starting it and its stack
+-- trace or wrapped text. the line alone past the end
+local function record_bounds(state, line)
+    local first, last = ffi.new("uint64_t[1]"), ffi.new("uint64_t[1]")
+    if not lib.log_engine_record_bounds(state.engine, line, first, last) then return line, line end
+    return tonumber(first[0]), tonumber(last[0])
+end
+
+-- on a big file a search only scans as far as its match, so n/N and the history count
+-- are on the rest of the file. scan the rest a slice at a time from the event loop, quietly,
+-- until the index is complete or the buffer moves on to another query.
+local function finish_search(bufnr)
+    local state = _G.JuanLogStates[bufnr]
+    local query, mode = state.last_query, state.last_mode
+    local function step()
+        if _G.JuanLogStates[bufnr] ~= state then return end
+        if state.last_query ~= query or state.last_mode ~= mode then return end
+        local status = bounded(lib.log_engine_search_step, state.engine, query, #query, mode, nil, nil)
+        if status == 0 then vim.defer_fn(step, 0) end
     end
+    vim.defer_fn(step, 0)
+end
 
-    -- listen for edits and send them to the rust piece table
-    vim.api.nvim_buf_attach(bufnr, false, {
-        on_lines = function(_, _, _, firstline, lastline, new_lastline)
-            if state.updating then return end
-            
-            local start_line = state.offset + firstline
-            local num_deleted = lastline - firstline
-            
-            local new_lines = vim.api.nvim_buf_get_lines(bufnr, firstline, new_lastline, false)
-            local new_text = table.concat(new_lines, "\n")
+-- jump to whichever match (up or down) is closest to the cursor
+local function find_closest(bufnr, query, mode)
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return end
+    if query == "" then return end
 
-            lib.log_engine_apply_edit(state.engine, start_line, num_deleted, new_text)
-            state.total = tonumber(lib.log_engine_total_lines(state.engine))
-        end
-    })
+    local cursor = vim.api.nvim_win_get_cursor(0)
+    local current_line_idx = state.offset + cursor[1] - 1
 
-    -- hijack save command
-    vim.api.nvim_create_autocmd("BufWriteCmd", {
-        buffer = bufnr,
-        callback = function()
-            local success = lib.log_engine_save(state.engine, filepath)
-            if success then
-                vim.api.nvim_buf_set_option(bufnr, 'modified', false)
-            end
+    local found_down = search(state, query, mode, current_line_idx + 1, false)
+    if found_down == SEARCH_INVALID then
+        query_failed("query", query)
+        return
+    end
+
+    local fresh = state.last_query ~= query or state.last_mode ~= mode
+    state.last_query = query
+    state.last_mode = mode
+    if fresh then finish_search(bufnr) end
+
+    local found_up = -1
+    if current_line_idx > 0 then
+        found_up = search(state, query, mode, current_line_idx - 1, true)
+    end
+
+    local target_line = -1
+
+    if found_down >= 0 and found_up >= 0 then
+        local dist_down = found_down - current_line_idx
+        local dist_up = current_line_idx - found_up
+        if dist_up < dist_down then
+            target_line = found_up
+        else
+            target_line = found_down
         end
-    })
+    elseif found_down >= 0 then
+        target_line = found_down
+    elseif found_up >= 0 then
+        target_line = found_up
+    end
 
-    -- infinite scrolling magic. 
-    -- if cursor hits the margin, fetch next/prev chunk and shift everything.
-    vim.api.nvim_create_autocmd({"CursorMoved", "CursorMovedI"}, {
-        buffer = bufnr,
-        callback = function()
-            if state.updating then return end
-            
-            state.timer:stop()
-            state.timer:start(15, 0, vim.schedule_wrap(function()
-                if state.updating or not vim.api.nvim_buf_is_valid(bufnr) then return end
+    if target_line >= 0 then
+        jump_to_line(bufnr, state, target_line)
+    end
+end
 
-                local cursor = vim.api.nvim_win_get_cursor(0)
-                local row = cursor[1]
-                local buf_lines = vim.api.nvim_buf_line_count(bufnr)
-                
-                local shift_needed = false
-                local new_offset = state.offset
+-- open a read-only split showing `lines`, numbered with the real line numbers.
+-- <CR> on a row teleports the source buffer there.
+local function open_view(bufnr, name, lines, numbers)
+    vim.cmd("split")
+    local view_buf = vim.api.nvim_create_buf(false, true)
+    vim.api.nvim_win_set_buf(0, view_buf)
+    vim.api.nvim_buf_set_lines(view_buf, 0, -1, false, lines)
+    vim.api.nvim_buf_set_name(view_buf, name)
+    vim.bo[view_buf].modifiable = false
+    vim.bo[view_buf].bufhidden = "wipe"
 
-                -- hit bottom margin?
-                if row > (buf_lines - config.dynamic_margin) and (state.offset + buf_lines < state.total) then
-                    local shift_amount = math.floor(config.dynamic_chunk_size / 2)
-                    new_offset = state.offset + shift_amount
-                    
-                    if new_offset + config.dynamic_chunk_size > state.total then
-                        new_offset = state.total - config.dynamic_chunk_size
-                    end
-                    shift_needed = true
-                end
+    _G.JuanLogViews[view_buf] = { source = bufnr, numbers = numbers }
+    if config.enable_custom_statuscol then
+        vim.wo.statuscolumn = "%!v:lua._juan_log_statuscol()"
+        vim.wo.number = true
+    end
 
-                -- hit top margin?
-                if row < config.dynamic_margin and state.offset > 0 then
-                    local shift_amount = math.floor(config.dynamic_chunk_size / 2)
-                    new_offset = math.max(0, state.offset - shift_amount)
-                    shift_needed = true
-                end
+    vim.keymap.set("n", "<CR>", function()
+        local line = numbers[vim.api.nvim_win_get_cursor(0)[1]]
+        local state = _G.JuanLogStates[bufnr]
+        local winid = vim.fn.bufwinid(bufnr)
+        if not line or line < 0 or not state or winid == -1 then return end
+        vim.api.nvim_set_current_win(winid)
+        jump_to_line(bufnr, state, line)
+    end, { buffer = view_buf, silent = true })
 
-                if shift_needed and new_offset ~= state.offset then
-                    state.updating = true
-                    local was_modified = vim.api.nvim_buf_get_option(bufnr, 'modified')
-                    
-                    local new_lines = fetch_lines(engine, new_offset, config.dynamic_chunk_size)
-                    
-                    -- swap buffer content seamlessly
-                    vim.api.nvim_buf_set_lines(bufnr, 0, -1, false, new_lines)
-                    
-                    -- adjust cursor relative to the new window
+    vim.api.nvim_create_autocmd("BufWipeout", {
+        buffer = view_buf,
+        callback = function() _G.JuanLogViews[view_buf] = nil end
+    })
+    return view_buf
+end
+
+-- a block of "a\tb" rows as { { a, b }, ... }
+local function tab_rows(ptr, len)
+    local rows = {}
+    if ptr == nil then return rows end
+    for row in ffi.string(ptr, len):gmatch("([^\n]*)\n") do
+        local a, b = row:match("^(.*)\t(.*)$")
+        if a then table.insert(rows, { a, b }) end
+    end
+    return rows
+end
+
+-- { { value = "/api/users", count = n }, ... } for a field (or with regex, what the pattern's
+-- first group caught) in lines [start, start + count), most common first, at most max (0 =
+-- all). nil and the reason for a pattern that doesn't compile
+local function value_counts(state, start, count, field, regex, max)
+    local len_ptr = ffi.new("size_t[1]")
+    local ptr = lib.log_engine_value_counts(state.engine, start, count, field, regex, max, len_ptr)
+    if ptr == nil then return nil, last_error() end
+    local out = {}
+    for _, row in ipairs(tab_rows(ptr, tonumber(len_ptr[0]))) do
+        table.insert(out, { value = row[1], count = tonumber(row[2]) })
+    end
+    return out
+end
+
+-- { { template = "Connection to * timed out", count = n, line = first line (0-based) }, ... }
+-- for lines [start, start + count), most common first, at most max (0 = all)
+local function templates(state, start, count, max)
+    local len_ptr = ffi.new("size_t[1]")
+    local ptr = lib.log_engine_templates(state.engine, start, count, max, len_ptr)
+    if ptr == nil then return nil end
+    local out = {}
+    for row in ffi.string(ptr, tonumber(len_ptr[0])):gmatch("([^\n]*)\n") do
+        local n, line, template = row:match("^(%d+)\t(%d+)\t(.*)$")
+        if n then table.insert(out, { template = template, count = tonumber(n), line = tonumber(line) }) end
+    end
+    return out
+end
+
+-- the columns of a log read as csv/tsv: { { name = ..., kind = ..., width = ... }, ... }, nil
+-- when it isn't read as one
+local function table_columns(engine)
+    local len_ptr = ffi.new("size_t[1]")
+    local ptr = lib.log_engine_columns(engine, len_ptr)
+    if ptr == nil then return nil end
+    local columns = {}
+    for name, kind, width in ffi.string(ptr, len_ptr[0]):gmatch("([^\t\n]*)\t([^\t\n]*)\t(%d+)\n") do
+        columns[#columns + 1] = { name = name, kind = kind, width = tonumber(width) }
+    end
+    return columns
+end
+
+-- every column of a table, for state.columns: the column view is how a table is shown
+local function table_column_names(engine)
+    local columns = table_columns(engine)
+    if not columns then return nil end
+    return table.concat(vim.tbl_map(function(column) return column.name end, columns), " ")
+end
+
+-- field names, or the values of one, in the loaded window, most common first
+local function window_fields(bufnr, state, key)
+    local len_ptr = ffi.new("size_t[1]")
+    local buf_lines = vim.api.nvim_buf_line_count(bufnr)
+    local ptr = lib.log_engine_field_counts(state.engine, state.offset, buf_lines, key, len_ptr)
+    local found = {}
+    for _, row in ipairs(tab_rows(ptr, tonumber(len_ptr[0]))) do table.insert(found, row[1]) end
+    return found
+end
+
+-- a value the way the query language takes it, quoted when it has to be
+local function query_value(value)
+    if value ~= "" and not value:find('[%s"()]') then return value end
+    return '"' .. value:gsub('[\\"]', "\\%0") .. '"'
+end
+
+-- completion for query syntax (:LogQuery, :LogFilter!): field names from the loaded
+-- window, and after "name:" (or ~, =, <, >) the values that field takes there
+local function complete_query(bufnr, arglead)
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return {} end
+    local before, word = arglead:match("^(.-)([^%s(]*)$")
+    local key, op, partial = word:match("^([%w_.@-]+)([:~=!<>]+)(.*)$")
+    local found = {}
+    if key then
+        for _, value in ipairs(window_fields(bufnr, state, key)) do
+            local quoted = query_value(value)
+            if vim.startswith(quoted, partial) then table.insert(found, before .. key .. op .. quoted) end
+        end
+    else
+        for _, name in ipairs(window_fields(bufnr, state, nil)) do
+            if vim.startswith(name, word) then table.insert(found, before .. name .. ":") end
+        end
+    end
+    return found
+end
+
+-- :LogFilter text, :LogFilter! query syntax, :LogFilter re:pattern (same for :LogExclude).
+-- a regex has to say so: a path like /var/log/ is text to look for, not a pattern in slashes
+local function filter_args(opts)
+    local re = opts.args:match("^re:(.+)$")
+    if re then return re, SEARCH_REGEX end
+    return opts.args, opts.bang and SEARCH_QUERY or SEARCH_LITERAL
+end
+
+-- the split showing a filter, defined below open_filter which both creates filters and is
+-- called from inside the split
+local show_filter
+
+-- config.filter_context for a filter that was just made, and whole records with :LogRecords
+-- on. reopened and restored ones keep whatever context they had.
+local function default_context(state, filter)
+    if config.filter_context > 0 then
+        lib.log_engine_filter_context(state.engine, filter, config.filter_context, config.filter_context)
+    end
+    if state.records and lib.log_engine_filter_records(state.engine, filter, true) then
+        state.record_filters[filter] = true
+    end
+end
+
+-- 1234567 -> "1,234,567"
+local function group_digits(n)
+    local s = tostring(n)
+    local grouped = s:reverse():gsub("(%d%d%d)", "%1,"):reverse()
+    return (grouped:gsub("^,", ""))
+end
+
+-- filters on big files come back empty and get scanned a slice per call. keep stepping from
+-- the event loop so the editor stays usable, show how far along it is, and call `done` at the end.
+local function when_scanned(bufnr, filter, done)
+    local state = _G.JuanLogStates[bufnr]
+    local scanned, total, matches = ffi.new("uint64_t[1]"), ffi.new("uint64_t[1]"), ffi.new("uint64_t[1]")
+    local shown = false
+    local function step()
+        -- buffer closed halfway, the engine (and the filter) went with it
+        if _G.JuanLogStates[bufnr] ~= state then return end
+        local status = bounded(lib.log_engine_filter_step, state.engine, filter, scanned, total, matches)
+        if status < 0 then
+            -- a slice that took too long is given up on, the filter stays unfinished
+            if lib.log_engine_last_error_code() == ERROR_CANCELLED then
+                vim.notify("[JuanLog] Gave up filtering, a slice took too long", vim.log.levels.WARN)
+            end
+            return
+        end
+        if status == 1 then
+            if shown then vim.api.nvim_echo({ { "" } }, false, {}) end
+            done()
+            return
+        end
+        local pct = math.floor(tonumber(scanned[0]) * 100 / math.max(1, tonumber(total[0])))
+        local msg = string.format("[JuanLog] Filtering... %d%% (%s matches)", pct, group_digits(tonumber(matches[0])))
+        vim.api.nvim_echo({ { msg } }, false, {})
+        shown = true
+        vim.defer_fn(step, 0)
+    end
+    step()
+end
+
+-- only the lines matching `query`, as a split backed by a rust filter view.
+-- scrolls like the main buffer: a window of rows is loaded and slides near the edges.
+-- with a parent filter it narrows that one instead of the whole file.
+-- `exclude` flips it around: everything except the matching lines.
+local function open_filter(bufnr, query, mode, parent, exclude)
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return end
+    local filter
+    if mode == FILTER_TIME then
+        -- "2024-01-01 12:00 .. 2024-01-01 12:05", either side may be left out
+        local from, to = query:match("^(.-)%s*%.%.%s*(.*)$")
+        filter = tonumber(bounded(lib.log_engine_filter_time, state.engine, parent or 0, vim.trim(from or query), to or ""))
+    elseif exclude then
+        filter = tonumber(bounded(lib.log_engine_filter_exclude, state.engine, parent or 0, query, mode))
+    elseif parent then
+        filter = tonumber(bounded(lib.log_engine_filter_refine, state.engine, parent, query, mode))
+    else
+        filter = tonumber(bounded(lib.log_engine_filter_create, state.engine, query, mode))
+    end
+    if filter == SEARCH_INVALID then
+        query_failed(mode == FILTER_TIME and "time range" or "query", query)
+        return
+    end
+    if filter < 0 then return end
+
+    local shown = query
+    if mode == SEARCH_REGEX then shown = "re:" .. query end
+    if mode == FILTER_TIME then shown = "time " .. query end
+    local title = (exclude and "not " or "") .. shown
+    if parent then title = (state.filter_titles[parent] or "?") .. " > " .. title end
+    state.filter_titles[filter] = title
+    when_scanned(bufnr, filter, function()
+        default_context(state, filter)
+        show_filter(bufnr, filter)
+    end)
+end
+
+-- closing a filter split parks the filter: its results stay packed in the engine and
+-- :LogFilters brings it back without a rescan. only the most recent few are kept.
+local function park_filter(state, filter)
+    if not lib.log_engine_filter_park(state.engine, filter) then return end
+    table.insert(state.parked, 1, filter)
+    while #state.parked > config.max_parked_filters do
+        local oldest = table.remove(state.parked)
+        lib.log_engine_filter_free(state.engine, oldest)
+        state.filter_titles[oldest] = nil
+    end
+end
+
+function show_filter(bufnr, filter)
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return end
+    state.open_filters[filter] = true
+
+    -- the line under the cursor where we came from, so the filter opens next to it
+    local cur_buf = vim.api.nvim_get_current_buf()
+    local cur_row = vim.api.nvim_win_get_cursor(0)[1]
+    local from_line = -1
+    if cur_buf == bufnr then
+        from_line = state.offset + cur_row - 1
+    elseif _G.JuanLogViews[cur_buf] then
+        from_line = _G.JuanLogViews[cur_buf].numbers[cur_row] or -1
+    end
+
+    local offset = 0
+    local numbers = {}
+    local known_total = 0
+
+    -- rows [first, first + chunk) of the filter, numbered with their real lines
+    local function load(first)
+        local total = tonumber(lib.log_engine_filter_total_lines(state.engine, filter))
+        known_total = total
+        first = math.max(0, math.min(first, total - config.dynamic_chunk_size))
+        local len_ptr = ffi.new("size_t[1]")
+        local block = lib.log_engine_filter_get_block(state.engine, filter, first, config.dynamic_chunk_size, len_ptr)
+        local lines = block_to_lines(block, len_ptr, state.engine, lib.log_engine_generation(state.engine))
+        for k in pairs(numbers) do numbers[k] = nil end
+        for row = 1, #lines do
+            numbers[row] = tonumber(lib.log_engine_filter_line(state.engine, filter, first + row - 1))
+        end
+        offset = first
+        return lines
+    end
+
+    local view_buf = open_view(bufnr, "juanlog://" .. bufnr .. "/filter/" .. filter, load(0), numbers)
+
+    local function reload(first, cursor_row)
+        local lines = load(first)
+        vim.bo[view_buf].modifiable = true
+        vim.api.nvim_buf_set_lines(view_buf, 0, -1, false, lines)
+        vim.bo[view_buf].modifiable = false
+        vim.api.nvim_win_set_cursor(0, { math.max(1, math.min(cursor_row - offset + 1, #lines)), 0 })
+    end
+
+    if from_line >= 0 then
+        local row = tonumber(lib.log_engine_filter_row(state.engine, filter, from_line, true))
+        if row >= 0 then
+            reload(row - math.floor(config.dynamic_chunk_size / 2), row)
+        end
+    end
+
+    vim.api.nvim_create_autocmd("CursorMoved", {
+        buffer = view_buf,
+        callback = function()
+            local row = vim.api.nvim_win_get_cursor(0)[1]
+            local buf_lines = vim.api.nvim_buf_line_count(view_buf)
+            local total = tonumber(lib.log_engine_filter_total_lines(state.engine, filter))
+            local half = math.floor(config.dynamic_chunk_size / 2)
+            if row > buf_lines - config.dynamic_margin and offset + buf_lines < total then
+                reload(offset + half, offset + row - 1)
+            elseif row < config.dynamic_margin and offset > 0 then
+                reload(offset - half, offset + row - 1)
+            end
+        end
+    })
+
+    -- n/N walk the source buffer's last search, but only through the filtered rows
+    local function jump(backward)
+        if not state.last_query then return end
+        local current = offset + vim.api.nvim_win_get_cursor(0)[1] - 1
+        local start = backward and current - 1 or current + 1
+        if start < 0 then return end
+        local found = tonumber(lib.log_engine_filter_search(state.engine, filter, state.last_query, start, state.last_mode, backward))
+        if found >= 0 then
+            reload(found - math.floor(config.dynamic_chunk_size / 2), found)
+        end
+    end
+    vim.keymap.set("n", "n", function() jump(false) end, { buffer = view_buf, silent = true })
+    vim.keymap.set("n", "N", function() jump(true) end, { buffer = view_buf, silent = true })
+
+    -- :LogFilter inside a filter drills down further, :q pops back to the previous one
+    vim.api.nvim_buf_create_user_command(view_buf, "LogFilter", function(opts)
+        local query, mode = filter_args(opts)
+        open_filter(bufnr, query, mode, filter)
+    end, { nargs = 1, bang = true })
+    vim.api.nvim_buf_create_user_command(view_buf, "LogExclude", function(opts)
+        local query, mode = filter_args(opts)
+        open_filter(bufnr, query, mode, filter, true)
+    end, { nargs = 1, bang = true })
+    vim.api.nvim_buf_create_user_command(view_buf, "LogTime", function(opts)
+        open_filter(bufnr, opts.args, FILTER_TIME, filter)
+    end, { nargs = 1 })
+
+    -- :LogOrigin original hides hits in lines edited this session (your own notes), edited
+    -- shows only those, rotated only the lines kept from the rotated-out file, all goes back
+    -- to everything. drill-downs from here keep the setting.
+    vim.api.nvim_buf_create_user_command(view_buf, "LogOrigin", function(opts)
+        local origins = { all = 0, original = 1, edited = 2, rotated = 3 }
+        local origin = origins[opts.args]
+        if not origin then return end
+        if not lib.log_engine_filter_origin(state.engine, filter, origin) then return end
+        reload(offset, offset + vim.api.nvim_win_get_cursor(0)[1] - 1)
+    end, {
+        nargs = 1,
+        complete = function() return { "all", "original", "edited", "rotated" } end
+    })
+
+    -- grep -C: ":LogContext 3" for both sides, ":LogContext 2 5" for before/after, 0 turns it off.
+    -- non-adjacent groups are split by a "--" row
+    vim.api.nvim_buf_create_user_command(view_buf, "LogContext", function(opts)
+        local before, after = opts.args:match("^(%d+)%s*(%d*)$")
+        if not before then return end
+        before = tonumber(before)
+        after = tonumber(after) or before
+        -- keep the cursor on the same line (or the closest one left) across the change
+        local line = numbers[vim.api.nvim_win_get_cursor(0)[1]] or -1
+        if line < 0 then line = numbers[vim.api.nvim_win_get_cursor(0)[1] + 1] or 0 end
+        if not lib.log_engine_filter_context(state.engine, filter, before, after) then return end
+        local row = math.max(0, tonumber(lib.log_engine_filter_row(state.engine, filter, line, true)))
+        reload(row - math.floor(config.dynamic_chunk_size / 2), row)
+    end, { nargs = 1 })
+
+    -- every hit with the rest of its record (the line it continues, the stack trace under it),
+    -- or back to the hits alone. records that don't touch are split by a "--" row
+    vim.api.nvim_buf_create_user_command(view_buf, "LogRecords", function()
+        local line = numbers[vim.api.nvim_win_get_cursor(0)[1]] or -1
+        if line < 0 then line = numbers[vim.api.nvim_win_get_cursor(0)[1] + 1] or 0 end
+        local records = not state.record_filters[filter]
+        if not lib.log_engine_filter_records(state.engine, filter, records) then return end
+        state.record_filters[filter] = records or nil
+        local row = math.max(0, tonumber(lib.log_engine_filter_row(state.engine, filter, line, true)))
+        reload(row - math.floor(config.dynamic_chunk_size / 2), row)
+    end, {})
+
+    -- merge with another open filter straight from their results: and, or, not (this minus that)
+    vim.api.nvim_buf_create_user_command(view_buf, "LogCombine", function(opts)
+        local ops = { ["and"] = { 0, " & " }, ["or"] = { 1, " | " }, ["not"] = { 2, " - " } }
+        local op = ops[opts.args]
+        if not op then return end
+        local others = {}
+        for other in pairs(state.open_filters) do
+            if other ~= filter then table.insert(others, other) end
+        end
+        vim.ui.select(others, {
+            prompt = "Combine with",
+            format_item = function(other) return state.filter_titles[other] or tostring(other) end
+        }, function(other)
+            if not other then return end
+            local combined = tonumber(lib.log_engine_filter_combine(state.engine, filter, other, op[1]))
+            if combined < 0 then return end
+            state.filter_titles[combined] = "(" .. state.filter_titles[filter] .. ")" .. op[2] .. "(" .. state.filter_titles[other] .. ")"
+            default_context(state, combined)
+            show_filter(bufnr, combined)
+        end)
+    end, {
+        nargs = 1,
+        complete = function() return { "and", "or", "not" } end
+    })
+
+    -- the file grew under watch mode. the engine already appended the new matches, the rows
+    -- on screen stay put and the new ones show up at the bottom if the window reaches the end.
+    -- `reset` is for a reopened file, every row may have changed.
+    state.filter_views[view_buf] = function(reset)
+        if not reset and offset + vim.api.nvim_buf_line_count(view_buf) < known_total then return end
+        local lines = load(offset)
+        vim.bo[view_buf].modifiable = true
+        vim.api.nvim_buf_set_lines(view_buf, 0, -1, false, lines)
+        vim.bo[view_buf].modifiable = false
+    end
+
+    vim.api.nvim_create_autocmd("BufWipeout", {
+        buffer = view_buf,
+        callback = function()
+            state.filter_views[view_buf] = nil
+            state.open_filters[filter] = nil
+            -- the engine may already be gone with its buffer
+            if _G.JuanLogStates[bufnr] == state then
+                park_filter(state, filter)
+            end
+        end
+    })
+end
+
+-- the engine's audit trail (edits, saves, filters, refreshes), one readable line per entry
+local function audit_lines(state)
+    local len_ptr = ffi.new("size_t[1]")
+    local ptr = lib.log_engine_audit(state.engine, len_ptr)
+    if ptr == nil then return {} end
+    local lines = {}
+    for entry in ffi.string(ptr, tonumber(len_ptr[0])):gmatch("[^\n]+") do
+        local at, kind, detail = entry:match("^([^\t]*)\t([^\t]*)\t(.*)$")
+        if at then
+            local stamp = tonumber(at) > 0 and os.date("%Y-%m-%d %H:%M:%S", math.floor(tonumber(at))) or "-"
+            table.insert(lines, string.format("%s  %-7s  %s", stamp, kind, detail))
+        end
+    end
+    return lines
+end
+
+local function format_secs(secs)
+    secs = math.floor(secs)
+    if secs < 60 then return secs .. "s" end
+    if secs < 3600 then return string.format("%dm%02ds", secs / 60, secs % 60) end
+    if secs < 86400 then return string.format("%dh%02dm", secs / 3600, (secs % 3600) / 60) end
+    return string.format("%dd%02dh", secs / 86400, (secs % 86400) / 3600)
+end
+
+-- digest of lines [start, start + count) in a split. gap rows jump to where the silence ended.
+local function open_summary(bufnr, start, count)
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return end
+    local len_ptr = ffi.new("size_t[1]")
+    local ptr = lib.log_engine_summarize(state.engine, start, count, len_ptr)
+    if ptr == nil then return end
+    local ok_json, digest = pcall(vim.json.decode, ffi.string(ptr, tonumber(len_ptr[0])))
+    if not ok_json then return end
+
+    local lines, numbers = {}, {}
+    local function add(text, line)
+        table.insert(lines, text)
+        numbers[#lines] = line or -1
+    end
+
+    add(string.format("Lines %d-%d (%d lines)", digest.start_line + 1, digest.start_line + digest.lines, digest.lines), digest.start_line)
+    if digest.time ~= vim.NIL then
+        add(string.format("Time: %s -> %s (%s)", digest.time.first, digest.time.last, format_secs(digest.time.span_secs)))
+    end
+    local levels = {}
+    for level, n in pairs(digest.levels) do table.insert(levels, { level, n }) end
+    table.sort(levels, function(a, b) return a[2] > b[2] end)
+    for i, l in ipairs(levels) do levels[i] = l[1] .. " " .. l[2] end
+    add("Levels: " .. table.concat(levels, ", "))
+
+    add("")
+    add("Top messages:")
+    for _, t in ipairs(digest.templates) do
+        add(string.format("  %8d  %s", t.count, t.template))
+    end
+
+    if next(digest.fields) then
+        add("")
+        add("Fields:")
+        for key, values in pairs(digest.fields) do
+            local parts = {}
+            for _, v in ipairs(values) do table.insert(parts, v.value .. " (" .. v.count .. ")") end
+            add("  " .. key .. ": " .. table.concat(parts, ", "))
+        end
+    end
+
+    if #digest.gaps > 0 then
+        add("")
+        add("Gaps:")
+        for _, g in ipairs(digest.gaps) do
+            add(string.format("  %s silent, %s -> %s", format_secs(g.secs), g.from, g.to), g.line)
+        end
+    end
+
+    open_view(bufnr, "juanlog://" .. bufnr .. "/summary", lines, numbers)
+end
+
+-- streams with a retention limit drop their oldest lines, which shifts every line number
+-- down. keep the loaded window on the same lines (reload it if its top is gone) and
+-- redo the filter splits. returns how many lines went since the last call.
+local function catch_up_eviction(bufnr, state)
+    local evicted = tonumber(lib.log_engine_evicted_lines(state.engine))
+    local delta = evicted - state.evicted
+    if delta == 0 then return 0 end
+    state.evicted = evicted
+    if state.offset >= delta then
+        state.offset = state.offset - delta
+    else
+        local winid = vim.fn.bufwinid(bufnr)
+        if winid ~= -1 then
+            local line = state.offset + vim.api.nvim_win_get_cursor(winid)[1] - 1
+            vim.api.nvim_win_call(winid, function()
+                jump_to_line(bufnr, state, math.max(0, line - delta))
+            end)
+        else
+            state.updating = true
+            state.offset = 0
+            vim.api.nvim_buf_set_lines(bufnr, 0, -1, false, fetch_lines(state.engine, 0, config.dynamic_chunk_size))
+            state.updating = false
+        end
+    end
+    for _, on_grow in pairs(state.filter_views) do on_grow(true) end
+    return delta
+end
+
+-- the file grew: rows from `first_changed` on that are loaded (an unfinished last line that
+-- got more text) or fit in the loaded window get fetched again. leaves edited buffers alone.
+local function redraw_changed(bufnr, state, first_changed)
+    if vim.bo[bufnr].modified then return end
+    local loaded = vim.api.nvim_buf_line_count(bufnr)
+    local from = first_changed - state.offset
+    if from < 0 or from > loaded then return end
+    local upto = math.min(state.total, state.offset + math.max(loaded, config.dynamic_chunk_size))
+    if upto <= first_changed then return end
+    state.updating = true
+    vim.api.nvim_buf_set_lines(bufnr, from, -1, false, fetch_lines(state.engine, first_changed, upto - first_changed))
+    vim.api.nvim_buf_set_option(bufnr, 'modified', false)
+    state.updating = false
+end
+
+-- a change the engine mustn't get (a read-only buffer typed in anyway, a binary line's
+-- preview edited). the engine never saw it, so once it's done the loaded window gets what
+-- the engine has put back, one redraw for a burst of changes. `why` is what the user is told.
+local function revert_edits(bufnr, state, why)
+    if state.revert_pending then return end
+    state.revert_pending = true
+    vim.schedule(function()
+        state.revert_pending = false
+        if _G.JuanLogStates[bufnr] ~= state or not vim.api.nvim_buf_is_valid(bufnr) then return end
+        local loaded = vim.api.nvim_buf_line_count(bufnr)
+        local upto = math.min(state.total, state.offset + math.max(loaded, config.dynamic_chunk_size))
+        state.updating = true
+        vim.api.nvim_buf_set_lines(bufnr, 0, -1, false, fetch_lines(state.engine, state.offset, upto - state.offset))
+        if state.read_only then vim.api.nvim_buf_set_option(bufnr, 'modified', false) end
+        state.updating = false
+        vim.notify("[JuanLog] " .. why, vim.log.levels.WARN)
+    end)
+end
+
+-- the engine holds new lines back while paused: nothing gets reindexed and caches stay warm.
+-- that's while :LogPause is on, or while following with the cursor scrolled up to read.
+-- returns true when it just resumed, everything held back is in by then.
+local function sync_pause(bufnr, state)
+    local reading = false
+    local winid = vim.fn.bufwinid(bufnr)
+    if state.follow and winid ~= -1 then
+        reading = state.offset + vim.api.nvim_win_get_cursor(winid)[1] < state.total
+    end
+    local pause = state.hold or reading
+    if pause == state.paused then return false end
+    state.paused = pause
+    if pause then
+        lib.log_engine_pause(state.engine)
+        return false
+    end
+    -- a file that shrank meanwhile shows up as a rotation on the next poll
+    lib.log_engine_resume(state.engine)
+    return true
+end
+
+local function throughput(state)
+    local lines = ffi.new("double[1]")
+    local bytes = ffi.new("double[1]")
+    lib.log_engine_throughput(state.engine, lines, bytes)
+    return lines[0], bytes[0]
+end
+
+-- follow mode: after the file grew, keep the last line in view. only while the cursor sits
+-- on what used to be the last line, scrolling up to read something pauses it until G.
+-- a producer going faster than follow_warn_rate gets one warning until it slows down again.
+local function follow_tail(bufnr, state, old_total)
+    local rate = throughput(state)
+    local too_fast = config.follow_warn_rate > 0 and rate > config.follow_warn_rate
+    if too_fast and not state.warned_rate then
+        vim.notify(string.format("[JuanLog] %d lines/s coming in, more than following can show; :LogFilter what you're after or :LogFollow to stop", math.floor(rate)), vim.log.levels.WARN)
+    end
+    state.warned_rate = too_fast
+    local winid = vim.fn.bufwinid(bufnr)
+    if winid == -1 then return end
+    local row = vim.api.nvim_win_get_cursor(winid)[1]
+    if state.offset + row < old_total then return end
+    vim.api.nvim_win_call(winid, function()
+        jump_to_line(bufnr, state, math.max(0, state.total - 1))
+        vim.cmd("normal! zb")
+    end)
+end
+
+-- pause state changed outside of a watch report (:LogPause, :LogFollow): if the engine just
+-- resumed, the lines it took in show up with its grew event (see on_grew). returns how many
+-- came in, nil if nothing resumed.
+local function catch_up(bufnr, state)
+    if not sync_pause(bufnr, state) then return nil end
+    return math.max(0, tonumber(lib.log_engine_total_lines(state.engine)) - state.total)
+end
+
+-- the file was rotated or truncated and the engine reopened it: everything loaded is stale.
+-- `kept` says whether the edits made it over, if not the buffer has nothing left to save.
+local function reload_reopened(bufnr, state, kept)
+    state.total = tonumber(lib.log_engine_total_lines(state.engine))
+    local winid = vim.fn.bufwinid(bufnr)
+    if winid ~= -1 then
+        local line = state.offset + vim.api.nvim_win_get_cursor(winid)[1] - 1
+        if state.follow then line = state.total - 1 end
+        vim.api.nvim_win_call(winid, function()
+            jump_to_line(bufnr, state, math.min(line, math.max(0, state.total - 1)))
+        end)
+    else
+        state.updating = true
+        state.offset = 0
+        vim.api.nvim_buf_set_lines(bufnr, 0, -1, false, fetch_lines(state.engine, 0, config.dynamic_chunk_size))
+        state.updating = false
+    end
+    if not kept then vim.api.nvim_buf_set_option(bufnr, 'modified', false) end
+    for _, on_grow in pairs(state.filter_views) do on_grow(true) end
+end
+
+-- the file grew, whichever call noticed: loaded rows from `first` on are fetched again, open
+-- filters get their new matches and following keeps the end in view
+local function on_grew(bufnr, state, first)
+    local old_total = state.total
+    state.total = tonumber(lib.log_engine_total_lines(state.engine))
+    local evicted = catch_up_eviction(bufnr, state)
+    old_total = old_total - evicted
+    if first < state.total then redraw_changed(bufnr, state, first) end
+    if evicted == 0 then
+        for _, on_grow in pairs(state.filter_views) do on_grow() end
+    end
+    if state.follow then follow_tail(bufnr, state, old_total) end
+end
+
+-- what the engine reports (the file grew, it was reopened) reaches every buffer holding the
+-- engine here, on the main loop. calls can run on libuv workers, where a LuaJIT callback
+-- mustn't, so the engine only keeps the events and wakes an async handle (uv_async_send is
+-- safe from any thread), see log_engine_set_wakeup.
+local EVENT_GREW = 2 -- a = total lines, b = first changed line
+local EVENT_ROTATED = 4 -- a = total lines, b = what log_engine_reopen returned
+local EVENT_CHANGED = 16 -- the engine's own watch saw the file change, see start_watch
+
+local function deliver_events()
+    local engine, event = ffi.new("uint64_t[1]"), ffi.new("uint32_t[1]")
+    local a, b = ffi.new("uint64_t[1]"), ffi.new("uint64_t[1]")
+    while lib.log_engine_next_event(engine, event, a, b) do
+        for bufnr, state in pairs(_G.JuanLogStates) do
+            if lib.log_engine_same(state.engine, engine[0]) then
+                if event[0] == EVENT_GREW then
+                    on_grew(bufnr, state, tonumber(b[0]))
+                elseif event[0] == EVENT_ROTATED then
+                    reload_reopened(bufnr, state, bit.band(tonumber(b[0]), REOPEN_KEPT_EDITS) ~= 0)
+                elseif event[0] == EVENT_CHANGED and state.watch_changed then
+                    state.watch_changed()
+                end
+            end
+        end
+    end
+end
+
+if lib then
+    local wake_loop = vim.schedule_wrap(deliver_events)
+    local async = vim.loop.new_async(wake_loop)
+    -- the handle luv wraps, and libuv's own send from the editor's exported symbols
+    local found, send = pcall(function() return ffi.C.uv_async_send end)
+    if found then
+        lib.log_engine_set_wakeup(EVENT_GREW + EVENT_ROTATED + EVENT_CHANGED, send, ffi.cast("void**", async)[0])
+    else
+        -- a build that doesn't export libuv: the events wait in the engine to be polled
+        async:close()
+        lib.log_engine_set_wakeup(EVENT_GREW + EVENT_ROTATED + EVENT_CHANGED, nil, nil)
+        vim.loop.new_timer():start(config.watch_debounce_ms, config.watch_debounce_ms, wake_loop)
+    end
+end
+
+-- watch mode. a chatty log can fire the fs watcher thousands of times a second, so an event
+-- only arms a one-shot timer and everything that piled up by the time it runs is reported
+-- as one summary: a notification plus a `User JuanLogChanged` autocmd carrying the counts.
+local function stop_watch(state)
+    if not state.watcher then return end
+    if state.native_watch then
+        lib.log_engine_unwatch_native(state.engine)
+    else
+        state.watcher:stop()
+        state.watcher:close()
+    end
+    state.watch_timer:stop()
+    state.watch_timer:close()
+    state.watcher = nil
+    state.watch_timer = nil
+    state.native_watch = nil
+    state.watch_changed = nil
+end
+
+local function start_watch(bufnr, state, filepath)
+    if state.watcher then return end
+    state.watch_timer = vim.loop.new_timer()
+    local pending = false
+
+    local report
+    local function changed()
+        if pending then return end
+        pending = true
+        state.watch_timer:start(config.watch_debounce_ms, 0, vim.schedule_wrap(report))
+    end
+
+    report = function()
+        pending = false
+        if _G.JuanLogStates[bufnr] ~= state or not state.watcher then return end
+        local bytes = ffi.new("uint64_t[1]")
+        local lines = ffi.new("uint64_t[1]")
+        local rotated = ffi.new("bool[1]")
+        if not lib.log_engine_poll_changes(state.engine, bytes, lines, rotated) then
+            -- a flaky mount: the engine says when to look again instead of sleeping on us
+            local after = tonumber(lib.log_engine_io_retry_after_ms(state.engine))
+            if after > 0 then
+                pending = true
+                state.watch_timer:start(after, 0, vim.schedule_wrap(report))
+            end
+            return
+        end
+
+        local changes = { bytes = tonumber(bytes[0]), lines = tonumber(lines[0]), rotated = rotated[0] }
+        if changes.rotated then
+            -- the watcher is tied to the old file, nothing more will come from it. reopen and
+            -- watch whatever is at the path now, logrotate may not have created it yet so
+            -- give it a few tries.
+            stop_watch(state)
+            local tries = 0
+            local function reopen()
+                if _G.JuanLogStates[bufnr] ~= state then return end
+                local status = lib.log_engine_reopen(state.engine)
+                if status < 0 then
+                    tries = tries + 1
+                    if tries < 30 then
+                        vim.defer_fn(reopen, 1000)
+                    else
+                        vim.notify("[JuanLog] " .. filepath .. " was rotated or truncated and nothing came back, reopen it to see the new file", vim.log.levels.WARN)
+                    end
+                    return
+                end
+                -- the buffer itself reloads with the engine's rotated event
+                local kept = bit.band(status, REOPEN_KEPT_EDITS) ~= 0
+                local had_edits = vim.bo[bufnr].modified
+                start_watch(bufnr, state, filepath)
+                if bit.band(status, REOPEN_CLOSED_SNAPSHOTS) ~= 0 then
+                    vim.notify("[JuanLog] " .. filepath .. " was truncated in place, its snapshots were closed", vim.log.levels.WARN)
+                end
+                if kept then
+                    vim.notify("[JuanLog] " .. filepath .. " was replaced on disk with the same content, reopened it")
+                else
+                    local msg = "[JuanLog] " .. filepath .. " was rotated or truncated, reopened it"
+                    local rotated = tonumber(lib.log_engine_rotated_lines(state.engine))
+                    if rotated > 0 then
+                        msg = msg .. string.format(", the %d lines above line %d are from the rotated file", rotated, rotated + 1)
+                    end
+                    if had_edits then
+                        vim.notify(msg .. " (your unsaved edits were dropped)", vim.log.levels.WARN)
+                    else
+                        vim.notify(msg)
+                    end
+                end
+            end
+            reopen()
+        else
+            -- pull the new lines in so G and scrolling reach them. the buffer and its filters
+            -- catch up with the engine's grew event, see on_grew
+            sync_pause(bufnr, state)
+            local result = lib.log_engine_refresh_delta(state.engine, nil, nil)
+            if result >= 2 then
+                -- truncated or replaced between the poll and the refresh, the engine already
+                -- reopened it (the buffer reloads with the rotated event) and the old watch
+                -- went with the old file
+                stop_watch(state)
+                start_watch(bufnr, state, filepath)
+            end
+            if state.pipe and not state.pipe_done and lib.log_engine_pipe_status(state.engine) > 0 then
+                state.pipe_done = true
+                vim.notify("[JuanLog] " .. filepath .. ": the other end closed the pipe")
+            end
+            -- when following, the new lines showing up is the notification
+            if not state.follow then
+                local held = state.paused and " (paused, :LogPause to show them)" or ""
+                vim.notify(string.format("[JuanLog] +%d lines (%d bytes) appended to %s%s", changes.lines, changes.bytes, filepath, held))
+            end
+        end
+        changes.bufnr = bufnr
+        vim.api.nvim_exec_autocmds("User", { pattern = "JuanLogChanged", data = changes })
+    end
+
+    if state.pipe then
+        -- the engine reads the pipe into a file of its own, there's no path here to watch
+        state.watcher = vim.loop.new_timer()
+        state.watcher:start(config.watch_debounce_ms, config.watch_debounce_ms, changed)
+    elseif lib.log_engine_watch_native(state.engine) then
+        -- the engine watches the file on a thread of its own and says so with EVENT_CHANGED,
+        -- which deliver_events hands to changed on the main loop
+        state.watcher = true
+        state.native_watch = true
+        state.watch_changed = changed
+    else
+        state.watcher = vim.loop.new_fs_event()
+        state.watcher:start(filepath, {}, changed)
+    end
+end
+
+local function setup_dynamic_window(bufnr, engine, total_lines, filepath)
+    local state = {
+        offset = 0,
+        total = total_lines,
+        bufnr = bufnr,
+        engine = engine,
+        updating = false, -- semaphore to prevent recursion loops
+        last_query = nil,
+        last_mode = SEARCH_LITERAL,
+        expand = config.expand_escaped_newlines,
+        pipe = lib.log_engine_pipe_status(engine) >= 0, -- a fifo, read into a spill file by the engine
+        evicted = 0, -- lines dropped by the retention limits, see catch_up_eviction
+        follow = config.follow,
+        fold = config.fold_entries,
+        fold_below = level_rank(config.fold_below), -- rank, wins over fold
+        repeats = config.collapse_repeats and (config.collapse_repeats == "template" and "template" or "exact") or nil, -- wins over both
+        records = config.records, -- n/N and new filters go by whole records, see record_bounds
+        deltas = config.time_deltas,
+        anchor = nil, -- line the deltas are also measured from, see :LogAnchor
+        zone = config.time_zone ~= nil, -- stamps drawn converted, see show_zones
+        strip_ansi = config.strip_ansi or config.ansi_colors, -- lines come without terminal escapes
+        hex_binary = config.hex_binary, -- binary lines come as hex previews, see show_binary
+        ansi_colors = config.ansi_colors, -- drawn in their colors, see show_ansi_colors
+        columns = table_column_names(engine) or config.columns and table.concat(config.columns, " ") or nil, -- see show_columns
+        folds = nil, -- loaded row -> foldexpr result, see fold_entries
+        filter_views = {}, -- filter split buffer -> refresh after the file grew
+        open_filters = {}, -- filter handles with a split open
+        parked = {}, -- closed filter handles, most recent first
+        filter_titles = {}, -- filter handle -> what it filters on, for pickers
+        record_filters = {}, -- filter handles showing whole records, see :LogRecords
+        read_only = false, -- a pure viewer, see revert_edits
+        timer = vim.loop.new_timer(),
+        trim_timer = vim.loop.new_timer()
+    }
+    _G.JuanLogStates[bufnr] = state
+
+    -- the engine only expires caches when it gets called, so poke it while the buffer sits idle
+    lib.log_engine_set_cache_ttl(engine, config.cache_ttl)
+    lib.log_engine_set_match_limits(engine, config.min_highlight_query, config.max_highlights)
+    lib.log_engine_set_io_retries(engine, config.io_retries, config.io_retry_delay_ms)
+    lib.log_engine_set_filter_slice(engine, config.filter_slice_mb * 1024 * 1024)
+    lib.log_engine_set_keep_rotated(engine, config.keep_rotated_mb * 1024 * 1024)
+    lib.log_engine_set_throughput_window(engine, config.throughput_window_s * 1000)
+    local trim_every = math.max(1, config.cache_ttl) * 1000
+    state.trim_timer:start(trim_every, trim_every, vim.schedule_wrap(function()
+        if _G.JuanLogStates[bufnr] == state then
+            lib.log_engine_trim_caches(state.engine, config.cache_ttl)
+        end
+    end))
+
+    state.updating = true
+    local initial_lines = fetch_lines(engine, 0, config.dynamic_chunk_size)
+    vim.api.nvim_buf_set_lines(bufnr, 0, -1, false, initial_lines)
+    vim.api.nvim_buf_set_option(bufnr, 'modified', false)
+    state.updating = false
+
+    local winid = vim.fn.bufwinid(bufnr)
+    if winid ~= -1 and config.enable_custom_statuscol then
+        vim.wo[winid].statuscolumn = "%!v:lua._juan_log_statuscol()"
+        vim.wo[winid].number = true
+    end
+    if winid ~= -1 and state.expand then
+        vim.wo[winid].conceallevel = 2
+    end
+    if winid ~= -1 and state.columns then
+        vim.wo[winid].wrap = false
+    end
+    show_ansi_colors(bufnr, state)
+    highlight_levels(bufnr, state)
+    show_binary(bufnr, state)
+    show_notes(bufnr, state)
+    expand_escapes(bufnr, state)
+    show_deltas(bufnr, state)
+    show_zones(bufnr, state)
+    show_columns(bufnr, state)
+    fold_entries(bufnr, state)
+
+    -- a pipe only ever grows, there's nothing to look at but its end
+    if state.pipe then
+        state.follow = true
+        lib.log_engine_set_retention(engine, config.stream_max_lines, config.stream_max_mb * 1024 * 1024)
+    end
+    if config.watch or state.follow then
+        start_watch(bufnr, state, filepath)
+    end
+
+    -- listen for edits and send them to the rust piece table
+    vim.api.nvim_buf_attach(bufnr, false, {
+        on_lines = function(_, _, _, firstline, lastline, new_lastline)
+            if state.updating then return end
+            -- anything after a reverted change lands on lines the engine numbers differently,
+            -- the revert puts the whole window back anyway
+            if state.revert_pending then return end
+            if state.read_only then
+                revert_edits(bufnr, state, "This log is open read-only")
+                return
+            end
+            
+            local start_line = state.offset + firstline
+            local num_deleted = lastline - firstline
+            -- held back with the rest of the burst while it goes down the buffer, lines past
+            -- the edits before it are where the engine has them, `shift` further down
+            local key = tonumber(state.engine)
+            local edits = unsent[key]
+            if edits and start_line < edits.frontier then
+                send_edits(state.engine)
+                edits = nil
+            end
+            local shift = edits and edits.shift or 0
+            -- a binary line is only its hex preview here, sending that back would overwrite the
+            -- bytes on save
+            if state.hex_binary and num_deleted > 0 then
+                local count_ptr = ffi.new("uint64_t[1]")
+                if raw_lib.log_engine_binary_regions(state.engine, start_line - shift, num_deleted, count_ptr) ~= nil and count_ptr[0] > 0 then
+                    revert_edits(bufnr, state, "Binary lines can't be edited through their preview, :LogBinary shows them as text")
+                    return
+                end
+            end
+            
+            local new_lines = vim.api.nvim_buf_get_lines(bufnr, firstline, new_lastline, false)
+            local new_text = table.concat(new_lines, "\n")
+            -- what the engine makes of it: one line per \n, an empty last one dropped
+            local added = #new_lines
+            if added > 0 and new_lines[added] == "" then added = added - 1 end
+
+            if not edits then
+                edits = { shift = 0, frontier = 0 }
+                unsent[key] = edits
+                local engine = state.engine
+                vim.schedule(function() send_edits(engine) end)
+            end
+            table.insert(edits, { start_line, num_deleted, new_text })
+            edits.shift = edits.shift + added - num_deleted
+            edits.frontier = start_line + added
+            state.total = state.total + added - num_deleted
+        end
+    })
+
+    -- hijack save command
+    vim.api.nvim_create_autocmd("BufWriteCmd", {
+        buffer = bufnr,
+        callback = function()
+            if state.pipe then
+                vim.notify("[JuanLog] " .. filepath .. " is a pipe, there's nothing to write back to", vim.log.levels.ERROR)
+                return
+            end
+            local success = lib.log_engine_save_n(state.engine, filepath, #filepath)
+            if success then
+                vim.api.nvim_buf_set_option(bufnr, 'modified', false)
+                if config.write_audit then
+                    vim.fn.writefile(audit_lines(state), filepath .. ".audit")
+                end
+            else
+                vim.notify("[JuanLog] Can't write " .. filepath .. ": " .. last_error(), vim.log.levels.ERROR)
+            end
+        end
+    })
+
+    -- infinite scrolling magic. 
+    -- if cursor hits the margin, fetch next/prev chunk and shift everything.
+    vim.api.nvim_create_autocmd({"CursorMoved", "CursorMovedI"}, {
+        buffer = bufnr,
+        callback = function()
+            if state.updating then return end
+            
+            state.timer:stop()
+            state.timer:start(15, 0, vim.schedule_wrap(function()
+                if state.updating or not vim.api.nvim_buf_is_valid(bufnr) then return end
+
+                local cursor = vim.api.nvim_win_get_cursor(0)
+                local row = cursor[1]
+                local buf_lines = vim.api.nvim_buf_line_count(bufnr)
+                
+                local shift_needed = false
+                local new_offset = state.offset
+
+                -- hit bottom margin?
+                if row > (buf_lines - config.dynamic_margin) and (state.offset + buf_lines < state.total) then
+                    local shift_amount = math.floor(config.dynamic_chunk_size / 2)
+                    new_offset = state.offset + shift_amount
+                    
+                    if new_offset + config.dynamic_chunk_size > state.total then
+                        new_offset = state.total - config.dynamic_chunk_size
+                    end
+                    shift_needed = true
+                end
+
+                -- hit top margin?
+                if row < config.dynamic_margin and state.offset > 0 then
+                    local shift_amount = math.floor(config.dynamic_chunk_size / 2)
+                    new_offset = math.max(0, state.offset - shift_amount)
+                    shift_needed = true
+                end
+
+                if shift_needed and new_offset ~= state.offset then
+                    state.updating = true
+                    local was_modified = vim.api.nvim_buf_get_option(bufnr, 'modified')
+                    
+                    local new_lines = fetch_lines(engine, new_offset, config.dynamic_chunk_size)
+                    
+                    -- swap buffer content seamlessly
+                    vim.api.nvim_buf_set_lines(bufnr, 0, -1, false, new_lines)
+                    
+                    -- adjust cursor relative to the new window
                     local new_row = (state.offset + row) - new_offset
                     new_row = math.max(1, math.min(new_row, #new_lines))
                     
@@ -245,103 +2135,810 @@ local function setup_dynamic_window(bufnr, engine, total_lines, filepath)
                     state.offset = new_offset
                     vim.api.nvim_buf_set_option(bufnr, 'modified', was_modified)
                     state.updating = false
+                    show_ansi_colors(bufnr, state)
+                    highlight_levels(bufnr, state)
+                    show_binary(bufnr, state)
+                    show_notes(bufnr, state)
+                    highlight_matches(bufnr, state)
+                    expand_escapes(bufnr, state)
+                    show_deltas(bufnr, state)
+                    show_zones(bufnr, state)
+                    show_columns(bufnr, state)
+                    fold_entries(bufnr, state)
                 end
             end))
         end
     })
 end
 
-function M.attach_to_buffer(bufnr, filepath)
+-- with a `source` the engine doesn't open filepath itself, which is then just the name:
+-- a number is a descriptor (from vim.loop.fs_open, say, a file deleted while open still
+-- shows, the descriptor stays the caller's to close), a string is the content itself.
+function M.attach_to_buffer(bufnr, filepath, source)
     if not lib then 
         return 
     end
 
-    local engine = lib.log_engine_new(filepath)
-    if engine == nil then 
-        return 
+    local err_ptr = ffi.new("int32_t[1]")
+    local engine
+    local real = source == nil and vim.loop.fs_realpath(filepath) or nil
+    local holders = real and _G.JuanLogEngines[real]
+    local shared = holders and next(holders)
+    if shared then
+        engine = lib.log_engine_retain(shared)
+    elseif type(source) == "number" then
+        engine = lib.log_engine_new_from_fd(source)
+    elseif type(source) == "string" then
+        engine = lib.log_engine_new_from_bytes(source, #source)
+    else
+        local options = ffi.new("LogEngineOptions", {
+            version = 3,
+            threads = config.threads,
+            io_retries = config.io_retries,
+            io_retry_delay_ms = config.io_retry_delay_ms,
+        })
+        if is_windows then
+            -- localized paths only survive the trip as UTF-16
+            local wide = vim.fn.iconv(filepath, "utf-8", "utf-16le") .. "\0\0"
+            engine = lib.log_engine_open_w(ffi.cast("const uint16_t*", wide), config.open_timeout_ms, options, err_ptr)
+        else
+            engine = lib.log_engine_open(filepath, config.open_timeout_ms, options, err_ptr)
+        end
+    end
+    if engine == 0 then
+        vim.notify("[JuanLog] Can't open " .. filepath .. ": " .. last_error(), vim.log.levels.ERROR)
+        return
+    end
+
+    if real then
+        _G.JuanLogEngines[real] = _G.JuanLogEngines[real] or {}
+        _G.JuanLogEngines[real][tonumber(engine)] = true
+    end
+
+    local total_lines = tonumber(lib.log_engine_total_lines(engine))
+    -- files only, text and streams handed to the plugin are its own to change. some files
+    -- open read-only whatever the config says (journalctl exports, shown rewritten)
+    local read_only = config.read_only and source == nil
+    if read_only and not shared then
+        lib.log_engine_set_read_only(engine)
+    end
+    read_only = read_only or lib.log_engine_is_read_only(engine)
+    if read_only then
+        vim.bo[bufnr].readonly = true
+    end
+    -- a shared engine was set up by the buffer that opened it
+    if not shared then
+        for _, format in ipairs(formats) do
+            if not lib.log_engine_register_format(engine, format[1], format[2]) then
+                vim.notify("[JuanLog] format " .. format[1] .. ": " .. last_error(), vim.log.levels.WARN)
+            end
+        end
+        if config.strip_ansi or config.ansi_colors then
+            lib.log_engine_set_strip_ansi(engine, true)
+        end
+        if not config.hex_binary then
+            lib.log_engine_set_hex_binary(engine, false)
+        end
+        -- notes others left on this log, see :LogNote
+        if source == nil and vim.loop.fs_stat(filepath .. ".notes") then
+            lib.log_engine_notes_sync(engine)
+        end
+        if (config.time_zone or config.assume_time_zone) and not lib.log_engine_set_time_zone(engine, config.time_zone or "", config.assume_time_zone or "") then
+            vim.notify("[JuanLog] time_zone: " .. last_error(), vim.log.levels.WARN)
+        end
+        if config.delimited and source == nil and filepath:lower():match("%.[ct]sv$") and not lib.log_engine_set_delimited(engine, true, 0) then
+            vim.notify("[JuanLog] " .. filepath .. " isn't read as a table: " .. last_error(), vim.log.levels.WARN)
+        end
     end
 
-    local total_lines = tonumber(lib.log_engine_total_lines(engine))
+    vim.api.nvim_buf_set_option(bufnr, 'buftype', 'acwrite')
+    vim.api.nvim_buf_set_option(bufnr, 'swapfile', false)
+    vim.api.nvim_buf_set_name(bufnr, filepath)
+    
+    -- turn off expensive stuff for huge files
+    if not config.syntax then
+        pcall(function() vim.opt_local.syntax = "off" end)
+    else
+        local ft = vim.filetype.match({ filename = filepath })
+        if ft then
+            vim.api.nvim_buf_set_option(bufnr, 'filetype', ft)
+        end
+    end
+    pcall(function() vim.opt_local.spell = false end)
+
+    if config.mode == "load_all" then
+        load_all_lines(bufnr, engine, total_lines)
+        -- everything's loaded, nothing needs to write to the buffer again
+        if read_only then vim.bo[bufnr].modifiable = false end
+    else
+        setup_dynamic_window(bufnr, engine, total_lines, filepath)
+        _G.JuanLogStates[bufnr].read_only = read_only
+        
+        -- once the window is up, the file's levels at a glance
+        if config.level_counts then
+            vim.schedule(function()
+                local state = _G.JuanLogStates[bufnr]
+                local counts = state and level_counts(state)
+                if counts then vim.notify("[JuanLog] " .. format_level_counts(counts), vim.log.levels.INFO) end
+            end)
+        end
+
+        -- standard / search won't work because lines aren't loaded.
+        -- implementing custom search commands that query the engine.
+        vim.api.nvim_buf_create_user_command(bufnr, "Logfind", function(opts)
+            find_closest(bufnr, opts.args, SEARCH_LITERAL)
+        end, { nargs = 1 })
+
+        -- structured search, e.g. :LogQuery level:error AND msg~"timeout"
+        vim.api.nvim_buf_create_user_command(bufnr, "LogQuery", function(opts)
+            find_closest(bufnr, opts.args, SEARCH_QUERY)
+        end, { nargs = 1, complete = function(arglead) return complete_query(bufnr, arglead) end })
+
+        -- wildcard search, e.g. :LogGlob conn-*-failed
+        vim.api.nvim_buf_create_user_command(bufnr, "LogGlob", function(opts)
+            find_closest(bufnr, opts.args, SEARCH_GLOB)
+        end, { nargs = 1 })
+
+        -- only the matching lines, e.g. :LogFilter ERROR, :LogFilter! level>=warn (query syntax)
+        -- or :LogFilter re:timeout|refused (regex)
+        vim.api.nvim_buf_create_user_command(bufnr, "LogFilter", function(opts)
+            local query, mode = filter_args(opts)
+            open_filter(bufnr, query, mode)
+        end, { nargs = 1, bang = true, complete = function(arglead) return complete_query(bufnr, arglead) end })
+
+        -- pick one of the cursor line's fields, get a filter split of the lines with that value
+        vim.api.nvim_buf_create_user_command(bufnr, "LogFields", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local fields = M.fields(bufnr, state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1)
+            if #fields == 0 then
+                vim.notify("[JuanLog] No fields on this line", vim.log.levels.WARN)
+                return
+            end
+            vim.ui.select(fields, {
+                prompt = "Filter on",
+                format_item = function(field) return field[1] .. "=" .. field[2] end,
+            }, function(field)
+                if field then open_filter(bufnr, field[1] .. ":" .. query_value(field[2]), SEARCH_QUERY) end
+            end)
+        end, {})
+
+        -- warn and above, error and above... same as :LogFilter! level>=warn
+        vim.api.nvim_buf_create_user_command(bufnr, "LogLevel", function(opts)
+            open_filter(bufnr, "level>=" .. opts.args, SEARCH_QUERY)
+        end, {
+            nargs = 1,
+            complete = function() return vim.list_slice(LEVELS) end
+        })
+
+        -- how many lines of the whole file there are at every level
+        vim.api.nvim_buf_create_user_command(bufnr, "LogLevels", function()
+            local state = _G.JuanLogStates[bufnr]
+            local counts = state and level_counts(state)
+            if counts then vim.notify("[JuanLog] " .. format_level_counts(counts), vim.log.levels.INFO) end
+        end, {})
+
+        -- everything but the matching lines, e.g. :LogExclude healthcheck
+        vim.api.nvim_buf_create_user_command(bufnr, "LogExclude", function(opts)
+            local query, mode = filter_args(opts)
+            open_filter(bufnr, query, mode, nil, true)
+        end, { nargs = 1, bang = true })
+
+        -- only lines stamped inside a window, e.g. :LogTime 2024-01-01 12:00 .. 2024-01-01 12:05
+        vim.api.nvim_buf_create_user_command(bufnr, "LogTime", function(opts)
+            open_filter(bufnr, opts.args, FILTER_TIME)
+        end, { nargs = 1 })
+
+        -- reopen a closed filter split, instantly: its results were kept
+        vim.api.nvim_buf_create_user_command(bufnr, "LogFilters", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state or #state.parked == 0 then return end
+            vim.ui.select(vim.list_slice(state.parked), {
+                prompt = "Reopen filter",
+                format_item = function(filter) return state.filter_titles[filter] or tostring(filter) end
+            }, function(filter, idx)
+                if not filter or not lib.log_engine_filter_unpark(state.engine, filter) then return end
+                table.remove(state.parked, idx)
+                show_filter(bufnr, filter)
+            end)
+        end, {})
+
+        -- everything changed through the engine this session: edits, saves, filters
+        vim.api.nvim_buf_create_user_command(bufnr, "LogAudit", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            open_view(bufnr, "juanlog://" .. bufnr .. "/audit", audit_lines(state), {})
+        end, {})
+
+        -- a note on the cursor line for whoever else opens this log, kept in <file>.notes once
+        -- synced. without text it removes the line's note.
+        vim.api.nvim_buf_create_user_command(bufnr, "LogNote", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local line = state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1
+            local author = config.notes_author or vim.env.USER or vim.env.USERNAME or "?"
+            if not lib.log_engine_note_set(state.engine, line, author, opts.args) then
+                vim.notify("[JuanLog] Can't keep notes on this log: " .. last_error(), vim.log.levels.ERROR)
+                return
+            end
+            show_notes(bufnr, state)
+        end, { nargs = "?" })
+
+        -- merge your notes with everyone else's in <file>.notes. lines you both changed keep
+        -- the newer note, the others land in the quickfix list.
+        vim.api.nvim_buf_create_user_command(bufnr, "LogNotesSync", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local conflicts = tonumber(lib.log_engine_notes_sync(state.engine))
+            if conflicts < 0 then
+                local level = lib.log_engine_last_error_code() == ERROR_LOCKED and vim.log.levels.WARN or vim.log.levels.ERROR
+                vim.notify("[JuanLog] Can't sync the notes: " .. last_error(), level)
+                return
+            end
+            show_notes(bufnr, state)
+            if conflicts == 0 then
+                vim.notify("[JuanLog] Notes synced")
+                return
+            end
+            local len_ptr = ffi.new("size_t[1]")
+            local ptr = lib.log_engine_notes_conflicts(state.engine, len_ptr)
+            local items = {}
+            for row in (ptr ~= nil and ffi.string(ptr, len_ptr[0]) or ""):gmatch("[^\n]+") do
+                local line, kept_by, kept, lost_by, lost = row:match("^(%d+)\t([^\t]*)\t([^\t]*)\t([^\t]*)\t(.*)$")
+                if line then
+                    table.insert(items, {
+                        bufnr = bufnr,
+                        lnum = tonumber(line) - state.offset + 1,
+                        text = string.format("kept %s: %s / lost %s: %s", unescape_note(kept_by), unescape_note(kept),
+                            unescape_note(lost_by), unescape_note(lost)),
+                    })
+                end
+            end
+            vim.fn.setqflist({}, " ", { title = "JuanLog note conflicts", items = items })
+            vim.notify(string.format("[JuanLog] Notes synced, %d lines were changed by someone else too (see :copen)", conflicts), vim.log.levels.WARN)
+        end, {})
+
+        -- :'<,'>LogSummary for a range, without one it covers the loaded window
+        vim.api.nvim_buf_create_user_command(bufnr, "LogSummary", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            if opts.range > 0 then
+                open_summary(bufnr, state.offset + opts.line1 - 1, opts.line2 - opts.line1 + 1)
+            else
+                open_summary(bufnr, state.offset, vim.api.nvim_buf_line_count(bufnr))
+            end
+        end, { range = true })
+
+        -- pick a previous query from the engine's history and search it again
+        vim.api.nvim_buf_create_user_command(bufnr, "LogHistory", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+
+            local items = {}
+            local len_ptr = ffi.new("size_t[1]")
+            local hits_ptr = ffi.new("uint64_t[1]")
+            local mode_ptr = ffi.new("uint32_t[1]")
+            for i = 0, tonumber(lib.log_engine_history_len(state.engine)) - 1 do
+                local q_ptr = lib.log_engine_history_get(state.engine, i, len_ptr, hits_ptr, mode_ptr)
+                if q_ptr ~= nil then
+                    table.insert(items, {
+                        query = ffi.string(q_ptr, tonumber(len_ptr[0])),
+                        -- all ones: a search on a big file that hasn't been through all of it
+                        hits = hits_ptr[0] ~= 0xffffffffffffffffULL and tonumber(hits_ptr[0]) or nil,
+                        mode = tonumber(mode_ptr[0])
+                    })
+                end
+            end
+            if #items == 0 then return end
+
+            vim.ui.select(items, {
+                prompt = "Log search history",
+                format_item = function(item)
+                    if not item.hits then return item.query .. " (not counted yet)" end
+                    return string.format("%s (%d hits)", item.query, item.hits)
+                end
+            }, function(item)
+                if item then
+                    find_closest(bufnr, item.query, item.mode)
+                end
+            end)
+        end, {})
+
+        -- first N and last M lines in a split, for "what did it say at startup and how did it die"
+        vim.api.nvim_buf_create_user_command(bufnr, "LogHeadTail", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local head = tonumber(opts.fargs[1]) or config.head_tail_lines
+            local tail = tonumber(opts.fargs[2]) or head
+
+            local len_ptr = ffi.new("size_t[1]")
+            local block_ptr = lib.log_engine_head_tail(state.engine, head, tail, len_ptr)
+            if block_ptr == nil then return end
+            local text = ffi.string(block_ptr, tonumber(len_ptr[0]))
+            if text:sub(-1) == "\n" then text = text:sub(1, -2) end
+            local lines = vim.split(text, "\n", { plain = true })
+
+            local numbers = {}
+            for row = 0, #lines - 1 do
+                numbers[row + 1] = tonumber(lib.log_engine_head_tail_line(state.engine, head, tail, row))
+            end
+            open_view(bufnr, "juanlog://" .. bufnr .. "/headtail", lines, numbers)
+        end, { nargs = "*" })
+
+        -- the whole file at a glance: overview_rows (or :LogOverview N) lines picked from
+        -- evenly spaced slices of bytes, <CR> jumps there. with ! they're evenly spaced by line
+        -- number instead, exact but it has to walk to every one of them.
+        vim.api.nvim_buf_create_user_command(bufnr, "LogOverview", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local mode, step, rows = opts.bang and 0 or 1, 0, tonumber(opts.args) or config.overview_rows
+
+            local len_ptr = ffi.new("size_t[1]")
+            local block_ptr = lib.log_engine_sample(state.engine, mode, step, rows, len_ptr)
+            local lines = block_to_lines(block_ptr, len_ptr, state.engine, lib.log_engine_generation(state.engine))
+            if #lines == 0 then return end
+            local numbers = {}
+            for row = 0, #lines - 1 do
+                numbers[row + 1] = tonumber(lib.log_engine_sample_line(state.engine, mode, step, rows, row))
+            end
+            open_view(bufnr, "juanlog://" .. bufnr .. "/overview", lines, numbers)
+        end, { nargs = "?", bang = true })
+
+        -- start/stop reporting appends and rotation of the file on disk
+        vim.api.nvim_buf_create_user_command(bufnr, "LogWatch", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            if state.watcher then
+                stop_watch(state)
+                vim.notify("[JuanLog] stopped watching " .. filepath)
+            else
+                start_watch(bufnr, state, filepath)
+                vim.notify("[JuanLog] watching " .. filepath)
+            end
+        end, {})
+
+        -- tail -f: watch the file and keep its end in view
+        vim.api.nvim_buf_create_user_command(bufnr, "LogFollow", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            state.follow = not state.follow
+            if not state.follow then
+                -- only stop watching if following is what started it
+                if state.follow_watch then stop_watch(state) end
+                catch_up(bufnr, state)
+                vim.notify("[JuanLog] stopped following " .. filepath)
+                return
+            end
+            state.follow_watch = not state.watcher
+            start_watch(bufnr, state, filepath)
+            jump_to_line(bufnr, state, math.max(0, state.total - 1))
+            vim.cmd("normal! zb")
+            vim.notify("[JuanLog] following " .. filepath)
+        end, {})
+
+        -- hold new lines back while reading, catch up with all of them when toggled off
+        vim.api.nvim_buf_create_user_command(bufnr, "LogPause", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            state.hold = not state.hold
+            if state.hold then
+                sync_pause(bufnr, state)
+                vim.notify("[JuanLog] paused " .. filepath .. ", :LogPause again to catch up")
+                return
+            end
+            local added = catch_up(bufnr, state)
+            if added then
+                vim.notify(string.format("[JuanLog] resumed %s, %d new lines", filepath, added))
+            else
+                vim.notify("[JuanLog] unpaused " .. filepath .. ", following picks up when you're back at the end")
+            end
+        end, {})
+
+        -- toggle drawing literal \n sequences as line breaks
+        vim.api.nvim_buf_create_user_command(bufnr, "LogExpand", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            state.expand = not state.expand
+            if state.expand then vim.wo.conceallevel = 2 end
+            expand_escapes(bufnr, state)
+        end, {})
 
-    vim.api.nvim_buf_set_option(bufnr, 'buftype', 'acwrite')
-    vim.api.nvim_buf_set_option(bufnr, 'swapfile', false)
-    vim.api.nvim_buf_set_name(bufnr, filepath)
-    
-    -- turn off expensive stuff for huge files
-    if not config.syntax then
-        pcall(function() vim.opt_local.syntax = "off" end)
-    else
-        local ft = vim.filetype.match({ filename = filepath })
-        if ft then
-            vim.api.nvim_buf_set_option(bufnr, 'filetype', ft)
-        end
-    end
-    pcall(function() vim.opt_local.spell = false end)
+        -- toggle showing how long after the previous stamped line each line came
+        vim.api.nvim_buf_create_user_command(bufnr, "LogDeltas", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            state.deltas = not state.deltas
+            show_deltas(bufnr, state)
+        end, {})
 
-    if config.mode == "load_all" then
-        load_all_lines(bufnr, engine, total_lines)
-    else
-        setup_dynamic_window(bufnr, engine, total_lines, filepath)
-        
-        -- standard / search won't work because lines aren't loaded.
-        -- implementing custom search commands that query the engine.
-        vim.api.nvim_buf_create_user_command(bufnr, "Logfind", function(opts)
+        -- measure deltas from the cursor line too (again on the same line drops it)
+        vim.api.nvim_buf_create_user_command(bufnr, "LogAnchor", function()
             local state = _G.JuanLogStates[bufnr]
             if not state then return end
+            local line = state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1
+            state.anchor = state.anchor ~= line and line or nil
+            state.deltas = true
+            show_deltas(bufnr, state)
+        end, {})
 
-            local query = opts.args
-            if query == "" then return end
-            
-            state.last_query = query
+        -- show timestamps in another zone, e.g. :LogZone local, or :LogZone +09:00 utc when
+        -- stamps without an offset are in UTC. no argument goes back to the stamps as written
+        vim.api.nvim_buf_create_user_command(bufnr, "LogZone", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local target, assume = opts.fargs[1] or "", opts.fargs[2] or config.assume_time_zone or ""
+            if not lib.log_engine_set_time_zone(state.engine, target, assume) then
+                vim.notify("[JuanLog] " .. last_error(), vim.log.levels.WARN)
+                return
+            end
+            state.zone = target ~= ""
+            if state.zone then
+                vim.wo.conceallevel = 2
+            end
+            show_zones(bufnr, state)
+        end, { nargs = "*" })
 
-            local cursor = vim.api.nvim_win_get_cursor(0)
-            local current_line_idx = state.offset + cursor[1] - 1 
-            
-            -- try to find the closest match (up or down)
-            local start_down = current_line_idx + 1
-            local found_down = tonumber(lib.log_engine_search(state.engine, query, start_down))
+        -- binary lines as hex previews, or as the text they make (replacement characters)
+        vim.api.nvim_buf_create_user_command(bufnr, "LogBinary", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            state.hex_binary = not state.hex_binary
+            lib.log_engine_set_hex_binary(state.engine, state.hex_binary)
+            -- the loaded lines themselves change, load them again
+            jump_to_line(bufnr, state, state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1)
+        end, {})
 
-            local start_up = math.max(0, current_line_idx - 1)
-            local found_up = -1
-            
-            if current_line_idx > 0 then
-                found_up = tonumber(lib.log_engine_search_backward(state.engine, query, start_up))
+        -- the real bytes of the binary lines under the cursor (the whole run of them, or just
+        -- the line), dumped like xxd in a split, up to hex_dump_max bytes of it
+        vim.api.nvim_buf_create_user_command(bufnr, "LogHex", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local line = state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1
+            local first, last = line, line
+            local count_ptr = ffi.new("uint64_t[1]")
+            local pairs_ptr = lib.log_engine_binary_regions(state.engine, state.offset, vim.api.nvim_buf_line_count(bufnr), count_ptr)
+            for i = 0, (pairs_ptr ~= nil and tonumber(count_ptr[0]) or 0) - 1 do
+                local a, b = tonumber(pairs_ptr[i * 2]), tonumber(pairs_ptr[i * 2 + 1])
+                if a <= line and line <= b then first, last = a, b end
+            end
+            local bytes = M.bytes(bufnr, first, last)
+            if not bytes then return end
+            local rows = hex_dump(bytes:sub(1, config.hex_dump_max))
+            if #bytes > config.hex_dump_max then
+                rows[#rows + 1] = string.format("... %d more bytes", #bytes - config.hex_dump_max)
             end
+            local numbers = {}
+            for row = 1, #rows do numbers[row] = first end
+            open_view(bufnr, "juanlog://" .. bufnr .. "/hex/" .. first, rows, numbers)
+        end, {})
 
-            local target_line = -1
+        -- logs captured from a terminal: show them without the color codes, or with them again
+        -- with a bang, toggle drawing the colors they ask for (stripping them too)
+        vim.api.nvim_buf_create_user_command(bufnr, "LogAnsi", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            if opts.bang then
+                state.ansi_colors = not state.ansi_colors
+                state.strip_ansi = state.strip_ansi or state.ansi_colors
+            else
+                state.strip_ansi = not state.strip_ansi
+            end
+            lib.log_engine_set_strip_ansi(state.engine, state.strip_ansi)
+            -- the loaded lines themselves change, load them again
+            jump_to_line(bufnr, state, state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1)
+        end, { bang = true })
 
-            if found_down >= 0 and found_up >= 0 then
-                local dist_down = found_down - current_line_idx
-                local dist_up = current_line_idx - found_up
-                if dist_up < dist_down then
-                    target_line = found_up
-                else
-                    target_line = found_down
+        -- toggle folding multi-line entries under their first line, or with a level
+        -- fold whatever is less severe, e.g. :LogFold warn
+        vim.api.nvim_buf_create_user_command(bufnr, "LogFold", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            if opts.args ~= "" then
+                local rank = level_rank(opts.args)
+                if not rank then
+                    vim.notify("[JuanLog] unknown level: " .. opts.args, vim.log.levels.ERROR)
+                    return
                 end
-            elseif found_down >= 0 then
-                target_line = found_down
-            elseif found_up >= 0 then
-                target_line = found_up
+                state.fold_below = rank
+            elseif state.fold or state.fold_below then
+                state.fold, state.fold_below = false, nil
+            else
+                state.fold = true
             end
+            fold_entries(bufnr, state)
+        end, { nargs = "?", complete = function() return vim.list_slice(LEVELS) end })
+
+        -- runs of the same line over and over (retry storms) folded into their first line
+        -- with a count, zo opens one. with ! lines that only differ in their numbers and ids
+        -- count as the same too. toggles
+        vim.api.nvim_buf_create_user_command(bufnr, "LogRepeats", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local mode = opts.bang and "template" or "exact"
+            state.repeats = state.repeats ~= mode and mode or nil
+            fold_entries(bufnr, state)
+        end, { bang = true })
+
+        -- n/N a record at a time (a line and its stack trace or wrapped text), and filters
+        -- opened from now on showing whole records, or back to lines
+        vim.api.nvim_buf_create_user_command(bufnr, "LogRecords", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            state.records = not state.records
+            vim.notify("[JuanLog] " .. (state.records and "by records" or "by lines"))
+        end, {})
 
-            if target_line >= 0 then
-                jump_to_line(bufnr, state, target_line)
+        -- open the file the cursor line was logged from, at that line, looking for it under
+        -- config.source_path (or 'path') when it isn't relative to here
+        vim.api.nvim_buf_create_user_command(bufnr, "LogSource", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local path, lnum = M.source(bufnr, state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1)
+            if not path then
+                vim.notify("[JuanLog] This line doesn't say where it was logged from", vim.log.levels.WARN)
+                return
             end
-        end, { nargs = 1 })
+            local found = path
+            if vim.fn.filereadable(path) == 0 then
+                found = vim.fn.findfile(path, config.source_path or vim.o.path)
+            end
+            if found == "" then
+                vim.notify("[JuanLog] Can't find " .. path .. ", set source_path to where the code is", vim.log.levels.WARN)
+                return
+            end
+            vim.cmd("split " .. (lnum > 0 and "+" .. lnum .. " " or "") .. vim.fn.fnameescape(found))
+        end, {})
 
         -- how many lines did we actually parse?
         vim.api.nvim_buf_create_user_command(bufnr, "LogLines", function()
             local state = _G.JuanLogStates[bufnr]
             if state then
-                vim.notify("[JuanLog] Total lines: " .. state.total, vim.log.levels.INFO)
+                local msg = "[JuanLog] Total lines: " .. state.total
+                -- if the plugin ever called the engine wrong, say so here instead of silently
+                local misuses = tonumber(lib.log_engine_misuse_count())
+                if misuses > 0 then
+                    local len = ffi.new("size_t[1]")
+                    local last = lib.log_engine_misuse_last(len)
+                    msg = msg .. "\n[JuanLog] FFI misuse: " .. misuses .. " (last: " .. ffi.string(last, len[0]) .. ")"
+                end
+                vim.notify(msg, vim.log.levels.INFO)
+            end
+        end, {})
+
+        -- jump to the first line at or after a time, e.g. :LogSeek 2024-01-01 12:00. a bare
+        -- clock time (:LogSeek 14:03) is on the day of the line under the cursor
+        vim.api.nvim_buf_create_user_command(bufnr, "LogSeek", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local secs = seek_secs(state, vim.trim(opts.args))
+            if not secs then
+                vim.notify("juan_log: not a time: " .. opts.args, vim.log.levels.WARN)
+                return
+            end
+            local line = tonumber(lib.log_engine_seek_time(state.engine, secs))
+            if line < 0 then
+                vim.notify("juan_log: the log ends before " .. opts.args, vim.log.levels.WARN)
+                return
+            end
+            jump_to_line(bufnr, state, line)
+        end, { nargs = 1 })
+
+        -- group by: the most common values of a field (:LogTop path) or of what a regex's
+        -- first group catches (:LogTop /GET (\S+)/), in the selected range or the whole
+        -- file. <CR> opens a filter split with the lines having that value.
+        vim.api.nvim_buf_create_user_command(bufnr, "LogTop", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local pattern = opts.args:match("^/(.+)/$")
+            local start, count = 0, state.total
+            if opts.range > 0 then
+                start, count = state.offset + opts.line1 - 1, opts.line2 - opts.line1 + 1
+            end
+            local found, err = value_counts(state, start, count, pattern or opts.args, pattern ~= nil, config.max_values)
+            if not found then
+                vim.notify("[JuanLog] " .. err, vim.log.levels.ERROR)
+                return
+            end
+            if #found == 0 then
+                vim.notify("[JuanLog] No " .. opts.args .. " in these lines", vim.log.levels.WARN)
+                return
+            end
+            local lines = {}
+            for i, v in ipairs(found) do lines[i] = string.format("%10s  %s", group_digits(v.count), v.value) end
+            local view_buf = open_view(bufnr, "juanlog://" .. bufnr .. "/top", lines, {})
+            vim.keymap.set("n", "<CR>", function()
+                local v = found[vim.api.nvim_win_get_cursor(0)[1]]
+                local winid = vim.fn.bufwinid(bufnr)
+                if not v or winid == -1 or _G.JuanLogStates[bufnr] ~= state then return end
+                vim.api.nvim_set_current_win(winid)
+                if pattern then
+                    open_filter(bufnr, v.value, SEARCH_LITERAL)
+                else
+                    open_filter(bufnr, opts.args .. ":" .. query_value(v.value), SEARCH_QUERY)
+                end
+            end, { buffer = view_buf, silent = true })
+        end, { nargs = 1, range = true, complete = function() return window_fields(bufnr, _G.JuanLogStates[bufnr]) end })
+
+        -- what the log mostly says: its messages grouped into templates with the variable
+        -- words as *, most common first, for the selected range or the whole file. <CR> jumps
+        -- to the first line of one.
+        vim.api.nvim_buf_create_user_command(bufnr, "LogTemplates", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local start, count = 0, state.total
+            if opts.range > 0 then
+                start, count = state.offset + opts.line1 - 1, opts.line2 - opts.line1 + 1
+            end
+            local found = templates(state, start, count, config.max_templates) or {}
+            if #found == 0 then return end
+            local lines, numbers = {}, {}
+            for i, t in ipairs(found) do
+                lines[i] = string.format("%10s  %s", group_digits(t.count), t.template)
+                numbers[i] = t.line
+            end
+            open_view(bufnr, "juanlog://" .. bufnr .. "/templates", lines, numbers)
+        end, { range = true })
+
+        -- every place the log went quiet for gap_s or more (:LogGaps 5m for another
+        -- threshold), in the selected range or the whole file, in a split. <CR> jumps to the
+        -- line that broke the silence.
+        vim.api.nvim_buf_create_user_command(bufnr, "LogGaps", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local min_secs = config.gap_s
+            if opts.args ~= "" then
+                min_secs = duration_secs(vim.trim(opts.args))
+                if not min_secs or min_secs <= 0 then
+                    vim.notify("juan_log: not a duration: " .. opts.args, vim.log.levels.WARN)
+                    return
+                end
+            end
+            local start, count = 0, state.total
+            if opts.range > 0 then
+                start, count = state.offset + opts.line1 - 1, opts.line2 - opts.line1 + 1
+            end
+            local gaps = time_gaps(state, start, count, min_secs) or {}
+            if #gaps == 0 then
+                vim.notify("juan_log: no gaps of " .. format_secs(min_secs) .. " or more", vim.log.levels.INFO)
+                return
+            end
+            local numbers = {}
+            for i, gap in ipairs(gaps) do numbers[i] = gap.line end
+            local lines = fetch_lines_at(state.engine, numbers)
+            for i, gap in ipairs(gaps) do
+                lines[i] = string.format("%8s  %s", format_secs(gap.secs), lines[i] or "")
+            end
+            open_view(bufnr, "juanlog://" .. bufnr .. "/gaps", lines, numbers)
+        end, { nargs = "?", range = true })
+
+        -- how busy the log was over its whole time range as a sparkline in a split, a column
+        -- per second, minute, hour or day (the smallest that fits the window, or :LogActivity
+        -- minute, :LogActivity 5m). <CR> on a column jumps to the first line of that time.
+        vim.api.nvim_buf_create_user_command(bufnr, "LogActivity", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local first, last = ffi.new("double[1]"), ffi.new("double[1]")
+            if lib.log_engine_time_range(state.engine, first, last) == 0 then
+                vim.notify("juan_log: no timestamps in this log", vim.log.levels.WARN)
+                return
+            end
+            local from, span = first[0], last[0] - first[0]
+            local bucket
+            if opts.args ~= "" then
+                bucket = BUCKETS[opts.args] or duration_secs(vim.trim(opts.args))
+                if not bucket or bucket <= 0 then
+                    vim.notify("juan_log: not a bucket size: " .. opts.args, vim.log.levels.WARN)
+                    return
+                end
+            else
+                local width = vim.api.nvim_win_get_width(0)
+                bucket = BUCKETS.day
+                for _, size in ipairs({ BUCKETS.second, BUCKETS.minute, BUCKETS.hour }) do
+                    if span / size < width then
+                        bucket = size
+                        break
+                    end
+                end
+            end
+            local counts = time_histogram(state, from, last[0] + bucket, bucket)
+            if not counts then
+                vim.notify("juan_log: " .. format_secs(span) .. " in buckets of " .. format_secs(bucket) .. " is too many, try a bigger one", vim.log.levels.WARN)
+                return
+            end
+
+            local busiest = 1
+            for _, n in ipairs(counts) do busiest = math.max(busiest, n) end
+            local sparks = {}
+            for i, n in ipairs(counts) do
+                sparks[i] = SPARKS[math.ceil(n / busiest * (#SPARKS - 1)) + 1]
+            end
+            local lines = {
+                table.concat(sparks),
+                string.format("%s -> %s, %s a column, %s lines at the busiest",
+                    os.date("!%Y-%m-%d %H:%M:%S", math.floor(from)), os.date("!%Y-%m-%d %H:%M:%S", math.floor(last[0])),
+                    format_secs(bucket), group_digits(busiest))
+            }
+
+            vim.cmd("split")
+            local view_buf = vim.api.nvim_create_buf(false, true)
+            vim.api.nvim_win_set_buf(0, view_buf)
+            vim.api.nvim_win_set_height(0, #lines)
+            vim.wo.wrap = false
+            vim.api.nvim_buf_set_lines(view_buf, 0, -1, false, lines)
+            vim.api.nvim_buf_set_name(view_buf, "juanlog://" .. bufnr .. "/activity")
+            vim.bo[view_buf].modifiable = false
+            vim.bo[view_buf].bufhidden = "wipe"
+            vim.keymap.set("n", "<CR>", function()
+                local column = vim.fn.charcol(".")
+                local winid = vim.fn.bufwinid(bufnr)
+                if vim.fn.line(".") ~= 1 or not counts[column] or winid == -1 or _G.JuanLogStates[bufnr] ~= state then return end
+                local line = tonumber(lib.log_engine_seek_time(state.engine, from + (column - 1) * bucket))
+                if line < 0 then return end
+                vim.api.nvim_set_current_win(winid)
+                jump_to_line(bufnr, state, line)
+            end, { buffer = view_buf, silent = true })
+        end, { nargs = "?", complete = function() return vim.tbl_keys(BUCKETS) end })
+
+        -- only some fields of every line, lined up: :LogColumns ts level msg. without
+        -- arguments it toggles, back to the full lines or to the `columns` config
+        vim.api.nvim_buf_create_user_command(bufnr, "LogColumns", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            if opts.args ~= "" then
+                state.columns = opts.args
+            elseif state.columns then
+                state.columns = nil
+            elseif config.columns then
+                state.columns = table.concat(config.columns, " ")
+            else
+                vim.notify("[JuanLog] Which fields? e.g. :LogColumns ts level msg", vim.log.levels.WARN)
+                return
+            end
+            -- a wrapped line would show its tail below the overlay
+            local winid = vim.fn.bufwinid(bufnr)
+            if winid ~= -1 then vim.wo[winid].wrap = state.columns == nil end
+            show_columns(bufnr, state)
+        end, {
+            nargs = "*",
+            complete = function(arglead)
+                local state = _G.JuanLogStates[bufnr]
+                if not state then return {} end
+                return vim.tbl_filter(function(name) return vim.startswith(name, arglead) end,
+                    window_fields(bufnr, state, nil))
+            end,
+        })
+
+        -- read the log as csv/tsv, the first line naming the columns, and show them all lined
+        -- up, or as plain lines again. :LogCsv ; (or tab, |) says what's between cells
+        vim.api.nvim_buf_create_user_command(bufnr, "LogCsv", function(opts)
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local delimiter = opts.args == "tab" and "\t" or opts.args
+            local enable = opts.args ~= "" or table_columns(state.engine) == nil
+            if not lib.log_engine_set_delimited(state.engine, enable, enable and delimiter:byte() or 0) then
+                vim.notify("[JuanLog] Not a table: " .. last_error(), vim.log.levels.WARN)
+                return
             end
+            state.columns = table_column_names(state.engine) or config.columns and table.concat(config.columns, " ") or nil
+            local winid = vim.fn.bufwinid(bufnr)
+            if winid ~= -1 then vim.wo[winid].wrap = state.columns == nil end
+            -- levels and highlights come from the cells now, draw the window again
+            jump_to_line(bufnr, state, state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1)
+        end, { nargs = "?", complete = function() return { ",", "tab", ";", "|" } end })
+
+        -- expand the json on the cursor line, or collapse it again
+        vim.api.nvim_buf_create_user_command(bufnr, "LogJson", function()
+            local state = _G.JuanLogStates[bufnr]
+            if state then toggle_json(bufnr, state) end
         end, {})
 
         -- teleport to absolute line. vim's native :1234 won't work here.
         vim.api.nvim_buf_create_user_command(bufnr, "LogJump", function(opts)
             local state = _G.JuanLogStates[bufnr]
             if not state then return end
+            -- numbers are the ones in the status column, which count evicted lines too
             local target = tonumber(opts.args)
+            if target then target = target - state.evicted end
             if target and target > 0 and target <= state.total then
                 jump_to_line(bufnr, state, target - 1)
             end
@@ -354,10 +2951,16 @@ function M.attach_to_buffer(bufnr, filepath)
 
             local cursor = vim.api.nvim_win_get_cursor(0)
             local start_line = state.offset + cursor[1]
+            -- by records: past the rest of this one, landing on the start of the next hit's
+            if state.records then
+                local _, last = record_bounds(state, start_line - 1)
+                start_line = last + 1
+            end
 
-            local found_line = tonumber(lib.log_engine_search(state.engine, state.last_query, start_line))
+            local found_line = search(state, state.last_query, state.last_mode, start_line, false)
 
             if found_line >= 0 then
+                if state.records then found_line = record_bounds(state, found_line) end
                 jump_to_line(bufnr, state, found_line)
             end
         end, { buffer = bufnr, silent = true })
@@ -368,19 +2971,52 @@ function M.attach_to_buffer(bufnr, filepath)
 
             local cursor = vim.api.nvim_win_get_cursor(0)
             local current_abs_line = state.offset + cursor[1] - 1
+            if state.records then
+                current_abs_line = record_bounds(state, current_abs_line)
+            end
             
             if current_abs_line <= 0 then 
                 return 
             end
 
             local start_line = current_abs_line - 1
-            local found_line = tonumber(lib.log_engine_search_backward(state.engine, state.last_query, start_line))
+            local found_line = search(state, state.last_query, state.last_mode, start_line, true)
 
             if found_line >= 0 then
+                if state.records then found_line = record_bounds(state, found_line) end
                 jump_to_line(bufnr, state, found_line)
             end
         end, { buffer = bufnr, silent = true })
 
+        -- ]] / [[ to the start of the next / previous record, over stack traces and wrapped
+        -- lines. [[ inside a record goes to its start first
+        vim.keymap.set("n", "]]", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local line = state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1
+            for _ = 1, vim.v.count1 do
+                local _, last = record_bounds(state, line)
+                if last + 1 >= state.total then break end
+                line = last + 1
+            end
+            jump_to_line(bufnr, state, line)
+        end, { buffer = bufnr, silent = true })
+
+        vim.keymap.set("n", "[[", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local line = state.offset + vim.api.nvim_win_get_cursor(0)[1] - 1
+            for _ = 1, vim.v.count1 do
+                local first = record_bounds(state, line)
+                if first == line then
+                    if line == 0 then break end
+                    first = record_bounds(state, line - 1)
+                end
+                line = first
+            end
+            jump_to_line(bufnr, state, line)
+        end, { buffer = bufnr, silent = true })
+
         -- hijack gg to go to the actual start of the file
         vim.keymap.set("n", "gg", function()
             local state = _G.JuanLogStates[bufnr]
@@ -392,6 +3028,34 @@ function M.attach_to_buffer(bufnr, filepath)
             local state = _G.JuanLogStates[bufnr]
             if state then jump_to_line(bufnr, state, math.max(0, state.total - 1)) end
         end, { buffer = bufnr, silent = true })
+
+        -- {count}% by bytes of the whole file, not the lines that happen to be loaded
+        vim.keymap.set("n", "%", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state or vim.v.count == 0 then
+                vim.cmd("normal! %")
+                return
+            end
+            local size = lib.log_engine_line_to_byte(state.engine, state.total)
+            local offset = size * math.min(vim.v.count, 100) / 100
+            jump_to_line(bufnr, state, tonumber(lib.log_engine_byte_to_line(state.engine, offset)))
+        end, { buffer = bufnr, silent = true })
+
+        -- json lines files get <CR> for :LogJson, there's nothing else to do on a line of one
+        if lib.log_engine_is_json_lines(engine) then
+            vim.keymap.set("n", "<CR>", function()
+                local state = _G.JuanLogStates[bufnr]
+                if state then toggle_json(bufnr, state) end
+            end, { buffer = bufnr, silent = true })
+        end
+
+        -- {count}go, byte 1 is the first one like vim's
+        vim.keymap.set("n", "go", function()
+            local state = _G.JuanLogStates[bufnr]
+            if not state then return end
+            local offset = math.max(vim.v.count1 - 1, 0)
+            jump_to_line(bufnr, state, tonumber(lib.log_engine_byte_to_line(state.engine, offset)))
+        end, { buffer = bufnr, silent = true })
     end
 
     vim.api.nvim_create_autocmd("BufWipeout", {
@@ -402,15 +3066,455 @@ function M.attach_to_buffer(bufnr, filepath)
                 state.timer:stop()
                 state.timer:close()
             end
-            lib.log_engine_free(engine)
+            if state and state.trim_timer then
+                state.trim_timer:stop()
+                state.trim_timer:close()
+            end
+            unsent[tonumber(engine)] = nil
+            if state then stop_watch(state) end
+            if real then _G.JuanLogEngines[real][tonumber(engine)] = nil end
+            lib.log_engine_release(engine)
             _G.JuanLogStates[bufnr] = nil
         end
     })
 end
 
+-- the filter splits open on a log buffer as plain data, for session plugins:
+-- { { setup = "<engine text>", title = "ERROR > not healthcheck" }, ... } in the order they
+-- were opened, combined ones included.
+function M.export_filters(bufnr)
+    bufnr = bufnr or vim.api.nvim_get_current_buf()
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return {} end
+    local handles = vim.tbl_keys(state.open_filters)
+    table.sort(handles)
+    local out = {}
+    local len_ptr = ffi.new("size_t[1]")
+    for _, filter in ipairs(handles) do
+        local text = lib.log_engine_filter_export(state.engine, filter, len_ptr)
+        if text ~= nil then
+            table.insert(out, { setup = ffi.string(text, len_ptr[0]), title = state.filter_titles[filter] })
+        end
+    end
+    return out
+end
+
+-- opens every filter from export_filters again, rebuilt against the file as it is now
+function M.import_filters(bufnr, filters)
+    bufnr = bufnr or vim.api.nvim_get_current_buf()
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return end
+    for _, saved in ipairs(filters or {}) do
+        local filter = tonumber(lib.log_engine_filter_import(state.engine, saved.setup))
+        if filter == SEARCH_INVALID then
+            vim.notify("[JuanLog] Can't restore filter: " .. (saved.title or "?"), vim.log.levels.WARN)
+        elseif filter > 0 then
+            state.filter_titles[filter] = saved.title or "restored"
+            if saved.setup:find("\nrecords\n", 1, true) then state.record_filters[filter] = true end
+            show_filter(bufnr, filter)
+        end
+    end
+end
+
+-- feed text into a log buffer from outside: a job's output, an rpc stream. `data` is a
+-- string or a list of lines the way jobstart hands them out (the last item is the start of
+-- a line that isn't finished yet, "" when the chunk ended in a newline). arrivals within
+-- watch_debounce_ms are shown together.
+function M.append(bufnr, data)
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return end
+    local text = type(data) == "table" and table.concat(data, "\n") or data
+    if text == "" then return end
+    local old_total = state.total
+    state.total = tonumber(lib.log_engine_append(state.engine, text))
+    if state.append_pending then return end
+    state.append_pending = true
+    vim.defer_fn(function()
+        state.append_pending = false
+        if _G.JuanLogStates[bufnr] ~= state then return end
+        if sync_pause(bufnr, state) then
+            state.total = tonumber(lib.log_engine_total_lines(state.engine))
+        end
+        local evicted = catch_up_eviction(bufnr, state)
+        if evicted == 0 then
+            for _, on_grow in pairs(state.filter_views) do on_grow() end
+        end
+        if state.follow then follow_tail(bufnr, state, old_total - evicted) end
+    end, config.watch_debounce_ms)
+end
+
+-- how fast lines are coming in: lines and bytes per second over throughput_window_s, for
+-- statuslines. nil for buffers that aren't log buffers.
+function M.throughput(bufnr)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    return throughput(state)
+end
+
+-- sizes and counts for statuslines, plus where the cursor is: its line (0-based, in the whole
+-- log) and how far through the content that is by bytes, the way {count}% jumps. the cursor
+-- part is left out when the buffer isn't in a window. nil for buffers that aren't log buffers.
+function M.stats(bufnr)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    local raw = ffi.new("LogEngineStats", { version = 1 })
+    if not lib.log_engine_stats(state.engine, raw) then return nil end
+    local stats = {
+        file_size = tonumber(raw.file_size),
+        total_lines = tonumber(raw.total_lines),
+        original_lines = tonumber(raw.original_lines),
+        memory_lines = tonumber(raw.memory_lines),
+        pieces = tonumber(raw.pieces),
+        indexed_percent = raw.indexed_percent,
+    }
+    local winid = vim.fn.bufwinid(bufnr)
+    if winid ~= -1 then
+        stats.line = state.offset + vim.api.nvim_win_get_cursor(winid)[1] - 1
+        local size = tonumber(lib.log_engine_line_to_byte(state.engine, state.total))
+        local at = tonumber(lib.log_engine_line_to_byte(state.engine, stats.line))
+        stats.percent = size > 0 and at * 100 / size or 0
+    end
+    return stats
+end
+
+local TIME_FORMATS = { "iso", "epoch", "syslog", "glog" }
+
+-- when a line of the log happened (0-based), seconds since the epoch: its own timestamp or,
+-- for a stack trace or a wrapped message, the one of the line that started it. nil when
+-- there's none, or for buffers that aren't log buffers.
+function M.time(bufnr, line)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    local secs = lib.log_engine_line_time(state.engine, line)
+    if secs ~= secs then return nil end
+    return secs
+end
+
+-- the first and last timestamps in the file and how they're written ("iso", "epoch",
+-- "syslog", "glog"). nil when it has none.
+function M.time_range(bufnr)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    local first, last = ffi.new("double[1]"), ffi.new("double[1]")
+    local format = lib.log_engine_time_range(state.engine, first, last)
+    if format == 0 then return nil end
+    return { first = first[0], last = last[0], format = TIME_FORMATS[format] }
+end
+
+-- the engine's report for :checkhealth (see health.lua) as a table: mapping, index, caches,
+-- what's still running. nil for buffers that aren't log buffers.
+function M.diagnostics(bufnr)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    local len_ptr = ffi.new("size_t[1]")
+    local ptr = lib.log_engine_diagnostics(state.engine, len_ptr)
+    if ptr == nil then return nil end
+    return vim.json.decode(ffi.string(ptr, tonumber(len_ptr[0])))
+end
+
+-- which library got loaded, and whether it did at all
+function M.library()
+    return { path = so_path, loaded = lib ~= nil, abi = ABI_VERSION }
+end
+
+-- the text of one line of the log, 0-based like everything else here and not just the part
+-- loaded in the buffer. for hover previews and mappings that want a line without moving the
+-- window. nil past the end.
+function M.line(bufnr, line)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    return fetch_line(state.engine, line)
+end
+
+-- the fields on a line (0-based), logfmt key=value pairs or json keys: { { key, value }, ... }
+-- in the order they come. nil for buffers that aren't log buffers.
+function M.fields(bufnr, line)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    local len_ptr = ffi.new("size_t[1]")
+    local ptr = lib.log_engine_line_fields(state.engine, line, len_ptr)
+    return tab_rows(ptr, tonumber(len_ptr[0]))
+end
+
+-- lines per level in the whole log: { trace = n, debug = n, info = n, warn = n, error = n,
+-- fatal = n, none = n }. the first call reads the whole file, in parallel. nil for buffers
+-- that aren't log buffers.
+function M.level_counts(bufnr)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    return level_counts(state)
+end
+
+-- the most common values of a field in lines [start, start + count) or the whole log, for
+-- group by: { { value, count }, ... }, most common first, at most max (default max_values,
+-- 0 = all). with regex, field is a pattern and the values are what its first group caught
+-- (the whole match without one). nil and the reason when the pattern doesn't compile, nil
+-- for buffers that aren't log buffers.
+function M.top_values(bufnr, field, opts)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    opts = opts or {}
+    return value_counts(state, opts.start or 0, opts.count or state.total, field, opts.regex or false, opts.max or config.max_values)
+end
+
+-- what the log mostly says: { { template, count, line }, ... }, its messages grouped into
+-- templates with the variable words as "*" ("Connection to * timed out after *ms"), most
+-- common first with the first line of each, in lines [start, start + count) or the whole
+-- log. at most max (default max_templates, 0 = all). nil for buffers that aren't log buffers.
+function M.templates(bufnr, start, count, max)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    return templates(state, start or 0, count or state.total, max or config.max_templates)
+end
+
+-- how many lines were written per bucket_secs from `from` up to `to` (seconds since the
+-- epoch, like time()), a count per bucket, for drawing activity. a line without a stamp
+-- counts with the stamped line above it. nil when to isn't after from, the bucket isn't
+-- above 0, there'd be more than 100000 buckets, or for buffers that aren't log buffers.
+function M.histogram(bufnr, from, to, bucket_secs)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    return time_histogram(state, from, to, bucket_secs)
+end
+
+-- the whole run of repeats line (0-based) is in, however far it goes: what :LogRepeats
+-- collapsed into one line, expanded. { first = , last = , lines = { ... } }, with templated
+-- lines that only differ in their numbers count as repeats too. a line that repeats nothing
+-- is a run of one. nil past the end, or for buffers that aren't log buffers.
+function M.repeat_run(bufnr, line, templated)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    local first, last = ffi.new("uint64_t[1]"), ffi.new("uint64_t[1]")
+    if not lib.log_engine_repeat_run(state.engine, line, templated or false, first, last) then return nil end
+    local from, to = tonumber(first[0]), tonumber(last[0])
+    return { first = from, last = to, lines = fetch_lines(state.engine, from, to - from + 1) }
+end
+
+-- where the log went quiet: { { line = 0-based line, secs = silence before it }, ... } for
+-- every line with a timestamp written at least min_secs (default gap_s) after the ones
+-- before it, in lines [start, start + count) or the whole log. nil for buffers that aren't
+-- log buffers.
+function M.gaps(bufnr, min_secs, start, count)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    return time_gaps(state, start or 0, count or state.total, min_secs or config.gap_s)
+end
+
+-- the bytes of lines first..last (0-based) exactly as they are on file, line breaks included
+-- and nothing replaced: what's behind the hex preview of a binary line. nil for buffers that
+-- aren't log buffers.
+function M.bytes(bufnr, first, last)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    local len_ptr = ffi.new("size_t[1]")
+    local ptr = lib.log_engine_raw_bytes(state.engine, first, last - first + 1, len_ptr)
+    if ptr == nil then return nil end
+    return ffi.string(ptr, len_ptr[0])
+end
+
+-- the columns of a log buffer read as csv/tsv, in order: { { name, kind, width }, ... } with
+-- the name queries use, what the first rows hold ("int", "float", "time", "bool", "text")
+-- and the widest cell there. nil when it isn't read as one.
+function M.columns(bufnr)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    return table_columns(state.engine)
+end
+
+-- where in the source a line (0-based) was logged from: glog's `server.cc:88]`, or a
+-- caller/source/location/file field. the path as written and the line in it, 0 when the
+-- log doesn't say. nil when the line has none.
+function M.source(bufnr, line)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    local len_ptr, line_ptr = ffi.new("size_t[1]"), ffi.new("uint64_t[1]")
+    local ptr = lib.log_engine_source_location(state.engine, line, len_ptr, line_ptr)
+    if ptr == nil then return nil end
+    return ffi.string(ptr, len_ptr[0]), tonumber(line_ptr[0])
+end
+
+-- the severity of `count` lines from `start` (0-based), one of LEVELS or false for lines
+-- without one: { "info", false, "error", ... }. nil for buffers that aren't log buffers.
+function M.levels(bufnr, start, count)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    local count_ptr = ffi.new("uint64_t[1]")
+    local ranks = lib.log_engine_line_levels(state.engine, start, count, count_ptr)
+    if ranks == nil then return nil end
+    local out = {}
+    for i = 0, tonumber(count_ptr[0]) - 1 do
+        out[i + 1] = LEVELS[ranks[i] + 1] or false
+    end
+    return out
+end
+
+-- many lines at once by number (0-based, any order), for pickers and bookmark lists: one
+-- call into the engine instead of one per line. same order back, "" for numbers past the end.
+function M.lines(bufnr, numbers)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    return fetch_lines_at(state.engine, numbers)
+end
+
+-- the log buffer's content frozen as it is now, for reads over many calls (exporting,
+-- copying it elsewhere) that shouldn't see lines arriving or edits made meanwhile.
+-- `lines(start, count)` is 0-based like everything else here. free() it when done.
+function M.snapshot(bufnr)
+    if not bufnr or bufnr == 0 then bufnr = vim.api.nvim_get_current_buf() end
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    local engine = state.engine
+    local id = lib.log_engine_snapshot(engine)
+    local snapshot = { total = tonumber(lib.log_engine_snapshot_total_lines(engine, id)) }
+    function snapshot.lines(start, count)
+        if _G.JuanLogStates[bufnr] ~= state then return {} end
+        local len_ptr = ffi.new("size_t[1]")
+        local block = lib.log_engine_snapshot_get_block(engine, id, start, count, len_ptr)
+        return block_to_lines(block, len_ptr, engine, lib.log_engine_generation(engine))
+    end
+    function snapshot.save(path)
+        return _G.JuanLogStates[bufnr] == state and lib.log_engine_snapshot_save(engine, id, path)
+    end
+    function snapshot.free()
+        if _G.JuanLogStates[bufnr] == state then lib.log_engine_snapshot_free(engine, id) end
+        state = nil
+    end
+    return snapshot
+end
+
+-- text that isn't in any file (a register, something decompressed, another buffer's lines)
+-- in a log buffer of its own, opened in the current window. `text` is a string or a list of
+-- lines. :w writes it to `name` when that's a real path.
+function M.open_text(text, name)
+    if type(text) == "table" then text = table.concat(text, "\n") end
+    vim.cmd("enew")
+    local bufnr = vim.api.nvim_get_current_buf()
+    M.attach_to_buffer(bufnr, name or ("juanlog://" .. bufnr .. "/text"), text)
+    if not _G.JuanLogStates[bufnr] then return nil end
+    return bufnr
+end
+
+-- an empty log buffer for M.append to fill, opened in the current window. the engine needs
+-- a file underneath, an empty scratch one does. follows its end from the start.
+function M.open_stream(name)
+    local path = vim.fn.tempname()
+    vim.fn.writefile({}, path)
+    vim.cmd("enew")
+    local bufnr = vim.api.nvim_get_current_buf()
+    M.attach_to_buffer(bufnr, path)
+    local state = _G.JuanLogStates[bufnr]
+    if not state then return nil end
+    state.follow = true
+    lib.log_engine_set_retention(state.engine, config.stream_max_lines, config.stream_max_mb * 1024 * 1024)
+    pcall(vim.api.nvim_buf_set_name, bufnr, "juanlog://" .. bufnr .. "/" .. (name or "stream"))
+    return bufnr
+end
+
+-- :LogRun, a shell command's stdout and stderr streamed into a log buffer
+function M.run(cmd)
+    local bufnr = M.open_stream(cmd)
+    if not bufnr then return end
+    local function feed(_, data) M.append(bufnr, data) end
+    local job = vim.fn.jobstart(cmd, {
+        on_stdout = feed,
+        on_stderr = feed,
+        on_exit = function(_, code)
+            vim.notify(string.format("[JuanLog] %s exited with %d", cmd, code))
+        end
+    })
+    if job <= 0 then
+        vim.notify("[JuanLog] Can't run " .. cmd, vim.log.levels.ERROR)
+        return
+    end
+    vim.api.nvim_create_autocmd("BufWipeout", {
+        buffer = bufnr,
+        callback = function() pcall(vim.fn.jobstop, job) end
+    })
+    return bufnr
+end
+
+-- a line format for every log buffer: `template` is the line with %{name} where a field goes,
+-- e.g. "%{ts} [%{level}] %{logger} - %{msg}", %% for a plain %. lines it matches have those
+-- fields in queries, :LogFields, :LogColumns and level colors. registering a name again
+-- replaces it. buffers open now get it right away, the rest when they're opened. true, or
+-- false and why when it doesn't compile (only known once a buffer is open to try it on).
+function M.register_format(name, template)
+    for _, state in pairs(_G.JuanLogStates) do
+        if not lib.log_engine_register_format(state.engine, name, template) then
+            return false, last_error()
+        end
+    end
+    for _, format in ipairs(formats) do
+        if format[1] == name then
+            format[2] = template
+            return true
+        end
+    end
+    table.insert(formats, { name, template })
+    return true
+end
+
+function M.unregister_format(name)
+    for _, state in pairs(_G.JuanLogStates) do
+        lib.log_engine_unregister_format(state.engine, name)
+    end
+    for i, format in ipairs(formats) do
+        if format[1] == name then
+            table.remove(formats, i)
+            return true
+        end
+    end
+    return false
+end
+
+-- the engine in a process of its own (juanlog-server, built next to the library), talking
+-- msgpack-rpc: a crash in it doesn't take neovim down. returns the channel for
+-- vim.rpcrequest, e.g. vim.rpcrequest(chan, "open", path, 0) for a handle. see the README.
+function M.server()
+    local exe = is_windows and "juanlog-server.exe" or "juanlog-server"
+    local path = so_path:gsub("[^/\\]*$", exe)
+    if not vim.loop.fs_stat(path) then
+        vim.notify("[JuanLog] No server binary at " .. path, vim.log.levels.ERROR)
+        return nil
+    end
+    local chan = vim.fn.jobstart({ path }, { rpc = true })
+    if chan <= 0 then
+        vim.notify("[JuanLog] Can't start " .. path, vim.log.levels.ERROR)
+        return nil
+    end
+    return chan
+end
+
 function M.setup(user_config)
     if user_config then config = vim.tbl_extend("force", config, user_config) end
 
+    for name, template in pairs(config.formats or {}) do
+        local ok, err = M.register_format(name, template)
+        if not ok then vim.notify("[JuanLog] format " .. name .. ": " .. err, vim.log.levels.ERROR) end
+    end
+
+    vim.api.nvim_create_user_command("LogRun", function(opts)
+        M.run(opts.args)
+    end, { nargs = "+", complete = "shellcmd" })
+
     vim.api.nvim_create_autocmd("BufReadCmd", {
         pattern = config.patterns,
         callback = function(ev)
@@ -421,8 +3525,9 @@ function M.setup(user_config)
                 return
             end
 
-            -- hijack huge files, pass small ones to standard vim
-            if stat.size > config.threshold_size then
+            -- hijack huge files and pipes (reading those the normal way blocks), pass small
+            -- ones to standard vim
+            if stat.size > config.threshold_size or stat.type == "fifo" then
                 vim.schedule(function()
                     if vim.api.nvim_buf_is_valid(ev.buf) then
                         M.attach_to_buffer(ev.buf, file)
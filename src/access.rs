@@ -0,0 +1,99 @@
+use crate::fields;
+
+// web server access logs, apache's and nginx's common and combined formats:
+//
+//   203.0.113.7 - bob [10/Oct/2024:13:55:36 -0700] "GET /api/users?id=3 HTTP/1.1" 200 2326 "https://ref/" "curl/8.4" 0.012
+//
+// no key=value anywhere, so without this none of it would be a field. a number after the
+// quoted parts (nginx's $request_time, apache's %D/%T) is taken as the latency, key=value
+// pairs there (rt=0.012 upstream=...) are fields like in any logfmt line.
+
+pub(crate) struct Access<'a> {
+    fields: Vec<(&'static str, &'a str)>,
+    rest: &'a str, // whatever comes after the known parts
+}
+
+pub(crate) fn parse(line: &str) -> Option<Access<'_>> {
+    let (ip, rest) = line.split_once(' ')?;
+    if ip.is_empty() || !ip.bytes().all(|b| b.is_ascii_hexdigit() || matches!(b, b'.' | b':')) {
+        return None; // an address, v4 or v6. host names in that spot are rare enough
+    }
+    let (ident, rest) = rest.split_once(' ')?;
+    let (user, rest) = rest.split_once(' ')?;
+    let (time, rest) = rest.strip_prefix('[')?.split_once("] ")?;
+    let (request, rest) = quoted(rest)?;
+    let (status, rest) = rest.trim_start().split_once(' ').unwrap_or((rest.trim_start(), ""));
+    if status.len() != 3 || !status.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (bytes, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+
+    let mut fields = vec![("ip", ip), ("ident", ident), ("user", user), ("time", time)];
+    let mut parts = request.splitn(3, ' ');
+    for name in ["method", "path", "protocol"] {
+        if let Some(part) = parts.next().filter(|part| !part.is_empty()) {
+            fields.push((name, part));
+        }
+    }
+    fields.extend([("status", status), ("bytes", bytes)]);
+    for name in ["referer", "agent"] {
+        let Some((value, after)) = quoted(rest.trim_start()) else { break };
+        fields.push((name, value));
+        rest = after;
+    }
+    // a bare number first thing after that is how long the request took
+    let rest = rest.trim_start();
+    let (first, after) = rest.split_once(' ').unwrap_or((rest, ""));
+    let rest = if first.parse::<f64>().is_ok() {
+        fields.push(("latency", first));
+        after
+    } else {
+        rest
+    };
+    Some(Access { fields, rest })
+}
+
+impl<'a> Access<'a> {
+    pub(crate) fn get(&self, name: &str) -> Option<&'a str> {
+        let name = match name {
+            "ts" | "timestamp" => "time",
+            "url" | "uri" | "request_uri" => "path",
+            "referrer" => "referer",
+            "user_agent" => "agent",
+            "request_time" | "duration" => "latency",
+            name => name,
+        };
+        self.fields.iter().find(|(key, _)| *key == name).map(|(_, value)| *value)
+    }
+
+    // the severity the status makes it: 5xx error, 4xx warn, the rest info
+    pub(crate) fn level(&self) -> &'static str {
+        match self.get("status").and_then(|status| status.bytes().next()) {
+            Some(b'5') => "error",
+            Some(b'4') => "warn",
+            _ => "info",
+        }
+    }
+
+    // every field, the known parts first and then pairs trailing them
+    pub(crate) fn pairs(&self) -> Vec<(&'a str, std::borrow::Cow<'a, str>)> {
+        let mut out: Vec<_> = self.fields.iter().map(|&(key, value)| (key, value.into())).collect();
+        out.extend(fields::text_pairs(self.rest));
+        out
+    }
+}
+
+// `"..."` at the start: (what's inside, the rest after the closing quote). \" inside is
+// kept as written, only the bounds matter
+fn quoted(text: &str) -> Option<(&str, &str)> {
+    let body = text.strip_prefix('"')?;
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some((&body[..i], &body[i + 1..])),
+            _ => escaped = false,
+        }
+    }
+    None
+}
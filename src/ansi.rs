@@ -0,0 +1,236 @@
+use memchr::memchr;
+use std::borrow::Cow;
+
+// terminal escape sequences, the way they end up in logs captured from a terminal: colors
+// (\x1b[31m), cursor and erase codes (\x1b[2K), window titles and hyperlinks (\x1b]...\x07).
+// with strip_ansi on, lines are handed out without them and search runs on them that way
+// too, so "ERROR: x" finds a line with the word painted red. lines written back stripped
+// get their escapes back, see restore.
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+// the first escape sequence at or after `from`: (start, end). one cut off by the end of the
+// line runs to the end of it, never past a line break, blocks are stripped whole.
+pub(crate) fn next_escape(text: &[u8], from: usize) -> Option<(usize, usize)> {
+    let start = from + memchr(ESC, text.get(from..)?)?;
+    let rest = &text[start + 1..];
+    let rest = &rest[..memchr(b'\n', rest).unwrap_or(rest.len())];
+    let len = match rest.first() {
+        // CSI: parameters and intermediates, then one final byte in @..~
+        Some(b'[') => rest[1..].iter().position(|b| (0x40..=0x7e).contains(b)).map_or(rest.len(), |end| end + 2),
+        // OSC: up to BEL or ESC \
+        Some(b']') => {
+            let end = rest.iter().position(|&b| b == BEL || b == ESC).unwrap_or(rest.len());
+            match rest.get(end) {
+                Some(&ESC) if rest.get(end + 1) == Some(&b'\\') => end + 2,
+                Some(&BEL) => end + 1,
+                _ => end,
+            }
+        }
+        // character set picks like ESC ( B take one more byte
+        Some(b'(' | b')' | b'*' | b'+') => rest.len().min(2),
+        Some(_) => 1,
+        None => 0,
+    };
+    Some((start, start + 1 + len))
+}
+
+pub(crate) fn strip(text: &str) -> Cow<'_, str> {
+    if memchr(ESC, text.as_bytes()).is_none() {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut pos = 0;
+    while let Some((start, end)) = next_escape(text.as_bytes(), pos) {
+        out.push_str(&text[pos..start]);
+        pos = end;
+    }
+    out.push_str(&text[pos..]);
+    Cow::Owned(out)
+}
+
+// strip everything pushed to `out` from `from` on, for blocks built in place
+pub(crate) fn strip_from(out: &mut String, from: usize) {
+    if let Cow::Owned(stripped) = strip(&out[from..]) {
+        out.truncate(from);
+        out.push_str(&stripped);
+    }
+}
+
+// a line the way get_block hands it out: invalid bytes replaced, escapes gone when `strip`
+pub(crate) fn shown(bytes: &[u8], strip: bool) -> Cow<'_, str> {
+    match String::from_utf8_lossy(bytes) {
+        Cow::Borrowed(text) if strip => self::strip(text),
+        Cow::Owned(text) if strip => Cow::Owned(self::strip(&text).into_owned()),
+        text => text,
+    }
+}
+
+// strip for bytes that may not be utf-8, what search runs on with strip_ansi on
+pub(crate) fn strip_bytes(bytes: &[u8]) -> Cow<'_, [u8]> {
+    if memchr(ESC, bytes).is_none() {
+        return Cow::Borrowed(bytes);
+    }
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while let Some((start, end)) = next_escape(bytes, pos) {
+        out.extend_from_slice(&bytes[pos..start]);
+        pos = end;
+    }
+    out.extend_from_slice(&bytes[pos..]);
+    Cow::Owned(out)
+}
+
+// `new` in place of `old`, a line that was handed out stripped and edited: old's escapes go
+// back in, at the same spot counted from the start up to where the text changed and from
+// the end after it. the ones inside what changed go at the end of the new text there.
+pub(crate) fn restore(old: &str, new: &str) -> String {
+    let mut plain = String::with_capacity(old.len());
+    let mut escapes = Vec::new(); // (column in plain, the escape)
+    let mut pos = 0;
+    while let Some((start, end)) = next_escape(old.as_bytes(), pos) {
+        plain.push_str(&old[pos..start]);
+        escapes.push((plain.len(), &old[start..end]));
+        pos = end;
+    }
+    plain.push_str(&old[pos..]);
+    if plain == new {
+        return old.to_string();
+    }
+    let mut prefix = plain.bytes().zip(new.bytes()).take_while(|(a, b)| a == b).count();
+    while !new.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    let room = plain.len().min(new.len()) - prefix;
+    let mut suffix = plain.bytes().rev().zip(new.bytes().rev()).take(room).take_while(|(a, b)| a == b).count();
+    while !new.is_char_boundary(new.len() - suffix) {
+        suffix -= 1;
+    }
+    let mut out = String::with_capacity(new.len() + old.len() - plain.len());
+    let mut written = 0;
+    for (col, escape) in escapes {
+        let at = if col <= prefix {
+            col
+        } else if col >= plain.len() - suffix {
+            col - (plain.len() - suffix) + (new.len() - suffix)
+        } else {
+            new.len() - suffix
+        };
+        out.push_str(&new[written..at]);
+        out.push_str(escape);
+        written = at;
+    }
+    out.push_str(&new[written..]);
+    out
+}
+
+// what SGR codes (\x1b[...m) set up, for drawing a line in its colors. a color is 0 for the
+// terminal's default, 1 + n for palette color n (0-255), or RGB | TRUE_COLOR.
+const TRUE_COLOR: u32 = 1 << 24;
+const BOLD: u32 = 1;
+const DIM: u32 = 2;
+const ITALIC: u32 = 4;
+const UNDERLINE: u32 = 8;
+const REVERSE: u32 = 16;
+const STRIKETHROUGH: u32 = 32;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Style {
+    pub(crate) fg: u32,
+    pub(crate) bg: u32,
+    pub(crate) attrs: u32,
+}
+
+impl Style {
+    fn apply(&mut self, params: &[u8]) {
+        let groups: Vec<&[u8]> = params.split(|&b| b == b';').collect();
+        let mut i = 0;
+        while i < groups.len() {
+            let mut parts = groups[i].split(|&b| b == b':').map(parse_code);
+            let code = parts.next().unwrap_or(0);
+            i += 1;
+            match code {
+                0 => *self = Style::default(),
+                1 => self.attrs |= BOLD,
+                2 => self.attrs |= DIM,
+                3 => self.attrs |= ITALIC,
+                4 => self.attrs |= UNDERLINE,
+                7 => self.attrs |= REVERSE,
+                9 => self.attrs |= STRIKETHROUGH,
+                22 => self.attrs &= !(BOLD | DIM),
+                23 => self.attrs &= !ITALIC,
+                24 => self.attrs &= !UNDERLINE,
+                27 => self.attrs &= !REVERSE,
+                29 => self.attrs &= !STRIKETHROUGH,
+                30..=37 => self.fg = 1 + code - 30,
+                90..=97 => self.fg = 1 + code - 90 + 8,
+                39 => self.fg = 0,
+                40..=47 => self.bg = 1 + code - 40,
+                100..=107 => self.bg = 1 + code - 100 + 8,
+                49 => self.bg = 0,
+                38 | 48 => {
+                    let mut sub: Vec<u32> = parts.collect();
+                    let color = if sub.is_empty() {
+                        // 38;5;n and 38;2;r;g;b, the rest comes in the next groups
+                        let rest: Vec<u32> = groups[i..].iter().take(4).map(|group| parse_code(group)).collect();
+                        let (color, used) = extended(&rest);
+                        i += used;
+                        color
+                    } else {
+                        // 38:5:n and 38:2:id:r:g:b, the color space id is often left out
+                        if sub.len() == 5 && sub[0] == 2 {
+                            sub.remove(1);
+                        }
+                        extended(&sub).0
+                    };
+                    match (code, color) {
+                        (38, Some(color)) => self.fg = color,
+                        (_, Some(color)) => self.bg = color,
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// an empty code counts as 0, like in \x1b[m
+fn parse_code(code: &[u8]) -> u32 {
+    std::str::from_utf8(code).ok().and_then(|code| code.parse().ok()).unwrap_or(0)
+}
+
+// the color after 38/48: 5, n for a palette color, 2, r, g, b for a true one. with how many
+// of the values it took
+fn extended(values: &[u32]) -> (Option<u32>, usize) {
+    match values {
+        [5, n, ..] => (Some(1 + n.min(&255)), 2),
+        [2, r, g, b, ..] => (Some(TRUE_COLOR | r.min(&255) << 16 | g.min(&255) << 8 | b.min(&255)), 4),
+        _ => (None, values.len().min(1)),
+    }
+}
+
+// (col, len, style) for the colored runs of a line, columns in the line as handed out: with
+// the escapes when `stripped` is false, without when it's true. every line starts in the
+// default colors, most programs reset at the end of each line anyway.
+pub(crate) fn colors(text: &[u8], stripped: bool, out: &mut Vec<(usize, usize, Style)>) {
+    let mut style = Style::default();
+    let (mut pos, mut removed) = (0, 0);
+    let mut push = |from: usize, to: usize, style: Style, removed: usize| {
+        if to > from && style != Style::default() {
+            let col = if stripped { from - removed } else { from };
+            out.push((col, to - from, style));
+        }
+    };
+    while let Some((start, end)) = next_escape(text, pos) {
+        push(pos, start, style, removed);
+        let escape = &text[start..end];
+        if let Some(params) = escape.strip_prefix(b"\x1b[").and_then(|rest| rest.strip_suffix(b"m")) {
+            style.apply(params);
+        }
+        removed += end - start;
+        pos = end;
+    }
+    push(pos, text.len(), style, removed);
+}
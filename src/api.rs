@@ -0,0 +1,638 @@
+// the engine for rust callers (TUI viewers, servers): the same LogEngine the C ABI hands
+// out, with safe methods instead of pointers that live until the next call. everything here
+// takes &self, the handle locks internally like it does for the plugin, so one engine can be
+// shared between threads in an Arc. lines are 0-based throughout.
+
+use crate::options::{self, Options};
+use crate::ansi;
+use crate::fields;
+use crate::health;
+use crate::pool;
+use crate::summary::Summary;
+use crate::zone::Zone;
+use crate::{Engine, LogEngine, Piece as Stored, MAX_BUCKETS, REFRESH_GREW, REFRESH_NOTHING, REFRESH_REOPENED};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+pub use crate::matcher::SearchMode;
+
+/// One run of the piece table: consecutive lines that either still come straight from the
+/// file or live in memory because they were edited or appended this session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Piece {
+    /// First line of the run, as a line of the current content.
+    pub first_line: u64,
+    /// How many lines the run covers.
+    pub line_count: u64,
+    /// Whether the lines live in memory rather than the mapped file.
+    pub in_memory: bool,
+}
+
+/// Where an engine stands, see [`LogEngine::stats`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    /// Bytes the file has on disk now.
+    pub file_size: u64,
+    /// Lines in the content, `original_lines + memory_lines`.
+    pub total_lines: u64,
+    /// Lines still read straight from the file.
+    pub original_lines: u64,
+    /// Lines edited or appended this session.
+    pub memory_lines: u64,
+    /// Runs in the piece table.
+    pub pieces: u64,
+    /// How much of `file_size` the line index covers, 0 to 100.
+    pub indexed_percent: f64,
+}
+
+/// A run of text colored by terminal escapes, see [`LogEngine::ansi_colors`]. A color is `0`
+/// for the terminal's default, `1 + n` for palette color `n` (0-255), or `0x1000000 |
+/// 0xRRGGBB` for a true color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnsiSpan {
+    /// Line of the current content.
+    pub line: u64,
+    /// Byte column in the line as [`LogEngine::lines`] hands it out.
+    pub col: u64,
+    /// Bytes the run takes there.
+    pub len: u64,
+    /// Foreground color.
+    pub fg: u32,
+    /// Background color.
+    pub bg: u32,
+    /// Bold 1, dim 2, italic 4, underline 8, reverse 16, strikethrough 32.
+    pub attrs: u32,
+}
+
+/// What [`LogEngine::refresh`] found on disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Refresh {
+    /// Nothing changed.
+    Unchanged,
+    /// Lines were appended; everything from `first_changed` on is new or different.
+    Grew { first_changed: u64 },
+    /// The file was rotated, truncated or replaced and reopened.
+    /// With `kept_edits`, lines before `first_changed` are unchanged; otherwise treat
+    /// everything as new.
+    Reopened { first_changed: u64, kept_edits: bool },
+}
+
+impl LogEngine {
+    /// Maps and indexes the file at `path`. Edits stay in memory until [`LogEngine::save`].
+    /// `journalctl -o export` output is shown a record per line (the time, then the fields
+    /// as `KEY=value`) and opens read-only.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<LogEngine> {
+        Engine::new(path.as_ref(), &Options::default(), &AtomicBool::new(false)).map(LogEngine::wrap)
+    }
+
+    /// Like [`LogEngine::open`], but as a pure viewer: edits and appends are ignored and
+    /// [`LogEngine::save`] fails, nothing is ever kept in memory for them.
+    pub fn open_read_only(path: impl AsRef<Path>) -> io::Result<LogEngine> {
+        let options = Options { read_only: true, ..Options::default() };
+        Engine::new(path.as_ref(), &options, &AtomicBool::new(false)).map(LogEngine::wrap)
+    }
+
+    /// Like [`LogEngine::open`], but the engine's searches and scans run on `threads` threads
+    /// of its own, `1` keeping them sequential, instead of the global rayon pool (`0`).
+    pub fn open_with_threads(path: impl AsRef<Path>, threads: usize) -> io::Result<LogEngine> {
+        let pool = match threads {
+            0 => None,
+            threads => Some(options::thread_pool(threads)?),
+        };
+        let options = Options { pool, ..Options::default() };
+        Engine::new(path.as_ref(), &options, &AtomicBool::new(false)).map(LogEngine::wrap)
+    }
+
+    /// Registers a line format for this engine, e.g. `"%{ts} [%{level}] %{logger} - %{msg}"`
+    /// (`%%` for a plain `%`). Lines it matches have those fields in queries, field lists,
+    /// columns and level spans, ahead of JSON and logfmt, and each starts a record. Fields
+    /// need text between them and can't be empty. Registering `name` again replaces it.
+    /// Levels, times and cached searches are worked out again; filters made before keep
+    /// what they matched.
+    pub fn register_format(&self, name: &str, template: &str) -> Result<(), String> {
+        self.write().register_format(name, template)
+    }
+
+    /// Forgets a format from [`LogEngine::register_format`], `false` if there was none.
+    pub fn unregister_format(&self, name: &str) -> bool {
+        self.write().unregister_format(name)
+    }
+
+    /// Whether edits are ignored and [`LogEngine::save`] fails, see
+    /// [`LogEngine::open_read_only`] and [`LogEngine::open`].
+    pub fn is_read_only(&self) -> bool {
+        self.read().options.read_only
+    }
+
+    /// An engine over a copy of `bytes` instead of a file on disk.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<LogEngine> {
+        Engine::from_bytes(bytes).map(LogEngine::wrap)
+    }
+
+    /// Number of lines, edits included.
+    pub fn total_lines(&self) -> u64 {
+        self.read().total_lines() as u64
+    }
+
+    /// Where `line` starts as a byte offset into the edited content: the file's bytes with
+    /// the edits in place, edited lines ending in `\n`. Past the last line, the size of it all.
+    pub fn line_to_byte(&self, line: u64) -> u64 {
+        self.read().byte_of_line(usize::try_from(line).unwrap_or(usize::MAX)) as u64
+    }
+
+    /// The line the byte at `offset` is in; the last line for an offset past the end.
+    pub fn byte_to_line(&self, offset: u64) -> u64 {
+        self.read().line_of_byte(usize::try_from(offset).unwrap_or(usize::MAX)) as u64
+    }
+
+    /// When `line` happened, in seconds since the epoch: its own timestamp, or for a line
+    /// without one (a stack trace, a wrapped message) the one of the nearest line above with
+    /// one. Zones are ignored and syslog stamps are taken to be in 1970.
+    pub fn line_time(&self, line: u64) -> Option<f64> {
+        self.read().line_time(usize::try_from(line).ok()?)
+    }
+
+    /// The record `line` is part of as `(first, last)` lines: the line that starts it (with a
+    /// timestamp, or not indented in a file without them) and the stack trace or wrapped text
+    /// under it. `None` past the end.
+    pub fn record_bounds(&self, line: u64) -> Option<(u64, u64)> {
+        let (first, last) = self.read().record_bounds(usize::try_from(line).ok()?, 0)?;
+        Some((first as u64, last as u64))
+    }
+
+    /// Runs of repeated lines in `count` from `start` as `(first, last)`: two or more lines in
+    /// a row that are the same but for their timestamps, or with `templated` the same message
+    /// but for its numbers and ids (`retry 3 failed`, `retry 4 failed`). Runs are cut where
+    /// the range ends.
+    pub fn repeat_runs(&self, start: u64, count: u64, templated: bool) -> Vec<(u64, u64)> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        self.write().repeat_runs(start, count, templated).chunks(2).map(|pair| (pair[0], pair[1])).collect()
+    }
+
+    /// The whole run of repeats `line` is in as `(first, last)`, however far it goes: what a
+    /// run collapsed into one line stands for. `(line, line)` for a line that repeats
+    /// nothing, `None` past the end.
+    pub fn repeat_run(&self, line: u64, templated: bool) -> Option<(u64, u64)> {
+        let (first, last) = self.read().repeat_run(usize::try_from(line).ok()?, templated)?;
+        Some((first as u64, last as u64))
+    }
+
+    /// The first and last timestamps in the file, `None` if it has none. Lines added by
+    /// edits don't count.
+    pub fn time_range(&self) -> Option<(f64, f64)> {
+        self.write().time_index().range()
+    }
+
+    /// For every line of `count` from `start`: seconds since the nearest stamped line above
+    /// it, and since `anchor`'s time. `None` for lines without a timestamp of their own and
+    /// where there's nothing to compare with.
+    pub fn time_deltas(&self, start: u64, count: u64, anchor: Option<u64>) -> Vec<(Option<f64>, Option<f64>)> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        let anchor = anchor.and_then(|line| usize::try_from(line).ok());
+        let known = |secs: f64| (!secs.is_nan()).then_some(secs);
+        self.write().time_deltas(start, count, anchor).chunks(2).map(|pair| (known(pair[0]), known(pair[1]))).collect()
+    }
+
+    /// Where the log went quiet in `count` lines from `start`: `(line, secs)` for every line
+    /// with a timestamp written at least `min_secs` after the ones before it, `secs` being how
+    /// long after. Empty when `min_secs` isn't above 0.
+    pub fn time_gaps(&self, start: u64, count: u64, min_secs: f64) -> Vec<(u64, f64)> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        if min_secs.is_nan() || min_secs <= 0.0 {
+            return Vec::new();
+        }
+        self.write().time_gaps(start, count, min_secs).chunks(2).map(|pair| (pair[0], pair[1] as f64 / 1000.0)).collect()
+    }
+
+    /// How many lines were written per `bucket_secs` from `from` up to `to` (seconds since
+    /// the epoch), a count per bucket: the log's activity over time. A line without a
+    /// timestamp counts with the stamped line above it. Empty when `to` isn't after `from`,
+    /// the bucket isn't above 0 or there would be more than 100000 buckets.
+    pub fn time_histogram(&self, from: f64, to: f64, bucket_secs: f64) -> Vec<u64> {
+        if from.is_nan() || to.is_nan() || to <= from || bucket_secs.is_nan() || bucket_secs <= 0.0 {
+            return Vec::new();
+        }
+        if (to - from) / bucket_secs > MAX_BUCKETS as f64 {
+            return Vec::new();
+        }
+        self.write().time_histogram(from, to, bucket_secs).to_vec()
+    }
+
+    /// The severity of every line in `count` from `start`: `"trace"`, `"debug"`, `"info"`,
+    /// `"warn"`, `"error"` or `"fatal"`, going by its level field, a syslog `<priority>` in
+    /// front or the first word that reads like one. `None` for lines without any.
+    pub fn line_levels(&self, start: u64, count: u64) -> Vec<Option<&'static str>> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        self.write().line_levels(start, count).unwrap_or_default().iter().map(|&rank| fields::level_name(rank as usize)).collect()
+    }
+
+    /// How many lines of the whole log are at each severity, `"trace"` to `"fatal"` and then
+    /// `"none"` for lines without one. The first call reads the whole file, in parallel.
+    pub fn level_counts(&self) -> Vec<(&'static str, u64)> {
+        let counts = self.write().level_counts().unwrap_or_default();
+        let names = (0..counts.len()).map(|rank| fields::level_name(rank).unwrap_or("none"));
+        names.zip(counts).collect()
+    }
+
+    /// Where each line in `count` from `start` says its severity, for coloring levels:
+    /// `(line, col, len, level)` with the column and length in bytes. Lines without a level
+    /// of their own are left out.
+    pub fn level_spans(&self, start: u64, count: u64) -> Vec<(u64, u64, u64, &'static str)> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        let mut engine = self.write();
+        let spans = engine.level_spans(start, count);
+        spans
+            .chunks(4)
+            .filter_map(|span| Some((span[0], span[1], span[2], fields::level_name(span[3] as usize)?)))
+            .collect()
+    }
+
+    /// Hand lines out without terminal escape sequences (`\x1b[31m` colors, erase codes,
+    /// titles) from now on, or with them again. Searches and filters made from then on match
+    /// the lines the way they're handed out, and a stripped line edited and written back
+    /// gets its escapes back.
+    pub fn set_strip_ansi(&self, strip: bool) {
+        self.write().set_strip_ansi(strip);
+    }
+
+    /// Hand binary lines (mostly control bytes or invalid UTF-8, a blob written into the log)
+    /// out as a short hex preview, `[binary 312 bytes] 7f 45 4c 46 …`, which is the default,
+    /// or as text full of replacement characters. Searches and saving use the real bytes.
+    pub fn set_hex_binary(&self, hex: bool) {
+        self.write().hex_binary = hex;
+    }
+
+    /// The runs of binary lines in `count` lines from `start`, as `(first, last)` line pairs.
+    pub fn binary_regions(&self, start: u64, count: u64) -> Vec<(u64, u64)> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        self.write().binary_regions(start, count).chunks(2).map(|pair| (pair[0], pair[1])).collect()
+    }
+
+    /// The bytes of `count` lines from `start` exactly as they are on file, line breaks
+    /// included: what's behind a hex preview.
+    pub fn raw_bytes(&self, start: u64, count: u64) -> Vec<u8> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        self.write().raw_bytes(start, count).to_vec()
+    }
+
+    /// The colors terminal escapes (`\x1b[31m` and friends) give the text of `count` lines
+    /// from `start`, to draw a captured terminal log the way it looked. Every line starts in
+    /// the default colors.
+    pub fn ansi_colors(&self, start: u64, count: u64) -> Vec<AnsiSpan> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        let mut engine = self.write();
+        let spans = engine.ansi_colors(start, count);
+        spans
+            .chunks(6)
+            .map(|span| AnsiSpan {
+                line: span[0],
+                col: span[1],
+                len: span[2],
+                fg: span[3] as u32,
+                bg: span[4] as u32,
+                attrs: span[5] as u32,
+            })
+            .collect()
+    }
+
+    /// Show stamps in `target` (`"utc"`, `"local"`, or an offset like `"+05:30"`), taking
+    /// those that don't give an offset to be in `assume` (UTC when `None`), for seeking and
+    /// everything else that goes by time as well. `None` for the target turns the display
+    /// off, `assume` still holds. `false` when either isn't a zone.
+    pub fn set_time_zone(&self, target: Option<&str>, assume: Option<&str>) -> bool {
+        let target = match target.filter(|target| !target.is_empty()) {
+            Some(target) => Zone::parse(target).map(Some),
+            None => Some(None),
+        };
+        let (Some(target), Some(assume)) = (target, assume.map_or(Some(Zone::Utc), Zone::parse)) else {
+            return false;
+        };
+        self.write().set_time_zone(target, assume);
+        true
+    }
+
+    /// For every line of `count` from `start`, where its stamp is (byte column and length)
+    /// and how it reads in the zone from `set_time_zone`. `None` for lines without one, or
+    /// all of them with no zone set.
+    pub fn zoned_times(&self, start: u64, count: u64) -> Vec<Option<(u64, u64, String)>> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        let engine = self.read();
+        let Some(zone) = &engine.zone else {
+            return Vec::new();
+        };
+        let mut out = Vec::new();
+        engine.for_each_line(start, count, |_, bytes| {
+            let converted = zone.convert(&String::from_utf8_lossy(bytes), &engine.templates);
+            out.push(converted.map(|(col, len, stamp)| (col as u64, len as u64, stamp)));
+        });
+        out
+    }
+
+    /// The fields on a line, logfmt `key=value` pairs or JSON keys, in the order they come.
+    /// Keys of nested JSON objects are listed without a prefix. In a CSV/TSV file, the cells
+    /// under their column names.
+    pub fn line_fields(&self, line: u64) -> Vec<(String, String)> {
+        let engine = self.read();
+        let Some(text) = usize::try_from(line).ok().and_then(|line| engine.line_text(line)) else {
+            return Vec::new();
+        };
+        engine.pairs(&text).into_iter().map(|(key, value)| (key.to_string(), value.into_owned())).collect()
+    }
+
+    /// Reads the file as CSV/TSV from now on, the first line naming the columns: `delimiter`
+    /// between cells, or `Some(0)` to tell which from the first lines. Query fields, `line_fields`,
+    /// `field_counts`, `project` and levels go by the columns then. `None` reads it as plain
+    /// lines again. `Err` when the first line isn't a header that fits the rows.
+    pub fn set_delimited(&self, delimiter: Option<u8>) -> Result<(), String> {
+        self.write().set_delimited(delimiter)
+    }
+
+    /// The columns of a file read as CSV/TSV, in order: the name queries use, what the sampled
+    /// cells hold (`"int"`, `"float"`, `"time"`, `"bool"` or `"text"`) and the widest of them in
+    /// chars. `None` when it isn't read as one.
+    pub fn columns(&self) -> Option<Vec<(String, String, u64)>> {
+        let engine = self.read();
+        let columns = engine.columns.as_ref()?;
+        Some(
+            columns
+                .names
+                .iter()
+                .zip(&columns.kinds)
+                .zip(&columns.widths)
+                .map(|((name, kind), &width)| (name.clone(), kind.to_string(), width as u64))
+                .collect(),
+        )
+    }
+
+    /// Where in the code `line` was logged from: glog's `server.cc:88`, or a `caller`,
+    /// `location`, `source` or `file` field. The path as written and the line in it, 0 when
+    /// the field doesn't say.
+    pub fn source_location(&self, line: u64) -> Option<(String, u64)> {
+        let engine = self.read();
+        fields::source_location(&engine.templates, &engine.line_text(usize::try_from(line).ok()?)?)
+    }
+
+    /// The field names used in `count` lines from `start` with how often they come up, most
+    /// common first. With a `key`, the values that field takes there instead.
+    pub fn field_counts(&self, start: u64, count: u64, key: Option<&str>) -> Vec<(String, u64)> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        let mut engine = self.write();
+        let block = engine.field_counts(start, count, key);
+        block
+            .lines()
+            .filter_map(|row| row.rsplit_once('\t'))
+            .map(|(text, count)| (text.to_string(), count.parse().unwrap_or(0)))
+            .collect()
+    }
+
+    /// What the lines in `count` from `start` mostly say: their messages grouped into
+    /// templates with the variable words as `*` (`"Connection to * timed out after *ms"`),
+    /// as `(template, count, first line)`, most common first, at most `max` (0 for all).
+    /// The range is mined in parallel.
+    pub fn templates(&self, start: u64, count: u64, max: u64) -> Vec<(String, u64, u64)> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        let max = match usize::try_from(max) {
+            Ok(0) | Err(_) => usize::MAX,
+            Ok(max) => max,
+        };
+        let mut engine = self.write();
+        let block = engine.templates(start, count, max);
+        block
+            .lines()
+            .filter_map(|row| {
+                let mut cells = row.splitn(3, '\t');
+                let count = cells.next()?.parse().ok()?;
+                let first_line = cells.next()?.parse().ok()?;
+                Some((cells.next()?.to_string(), count, first_line))
+            })
+            .collect()
+    }
+
+    /// The values `field` takes in `count` lines from `start` with how often, most common
+    /// first, at most `max` (0 for all): the paths, hosts or status codes that come up the
+    /// most. With `regex`, `field` is a pattern and the values are what its first group
+    /// caught (the whole match without one). Counted in parallel. `Err` with the reason when
+    /// the pattern doesn't compile.
+    pub fn value_counts(&self, start: u64, count: u64, field: &str, regex: bool, max: u64) -> Result<Vec<(String, u64)>, String> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Ok(Vec::new());
+        };
+        let pattern = regex.then(|| regex::Regex::new(field)).transpose().map_err(|err| err.to_string())?;
+        let max = match usize::try_from(max) {
+            Ok(0) | Err(_) => usize::MAX,
+            Ok(max) => max,
+        };
+        let mut engine = self.write();
+        let block = engine.value_counts(start, count, field, pattern.as_ref(), max);
+        Ok(block
+            .lines()
+            .filter_map(|row| row.rsplit_once('\t'))
+            .map(|(value, count)| (value.to_string(), count.parse().unwrap_or(0)))
+            .collect())
+    }
+
+    /// Only `names` of every line in `count` from `start`, one row per line lined up in
+    /// columns (`["ts", "level", "msg"]` finds the usual aliases and plain text stamps and
+    /// levels too). Lines without any of them, like stack frames, come through whole.
+    pub fn project(&self, start: u64, count: u64, names: &[&str]) -> Vec<String> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        if names.is_empty() {
+            return Vec::new();
+        }
+        self.write().project(start, count, names).lines().map(str::to_string).collect()
+    }
+
+    /// Whether the file looks like JSON lines (one object per line), going by its first
+    /// lines.
+    pub fn is_json_lines(&self) -> bool {
+        self.read().json_lines()
+    }
+
+    /// Shows the JSON on `line` pretty-printed over several lines and returns how many, or
+    /// `None` when it isn't JSON. Only the view changes: `save` writes it back on one line.
+    pub fn json_expand(&self, line: u64) -> Option<u64> {
+        let count = self.write().expand_json(usize::try_from(line).ok()?);
+        (count > 0).then_some(count as u64)
+    }
+
+    /// Puts the expanded JSON `line` is part of back on one line and returns that line,
+    /// `None` when `line` isn't in an expansion.
+    pub fn json_collapse(&self, line: u64) -> Option<u64> {
+        self.write().collapse_json(usize::try_from(line).ok()?).map(|line| line as u64)
+    }
+
+    /// The first line stamped at or after `secs` (seconds since the epoch), `None` when the
+    /// log ends before then. Lines out of order count by the latest stamp up to them, and
+    /// only a chunk or so of the file is read however big it is.
+    pub fn seek_time(&self, secs: f64) -> Option<u64> {
+        self.write().seek_time(secs).map(|line| line as u64)
+    }
+
+    /// The text of one line without its line break, or `None` past the end.
+    /// Bytes that aren't UTF-8 are replaced.
+    pub fn line(&self, line: u64) -> Option<String> {
+        self.read().line_text(usize::try_from(line).ok()?)
+    }
+
+    /// Up to `count` lines from `start`, fewer near the end.
+    pub fn lines(&self, start: u64, count: u64) -> Vec<String> {
+        let (Ok(start), Ok(count)) = (usize::try_from(start), usize::try_from(count)) else {
+            return Vec::new();
+        };
+        let mut lines = Vec::new();
+        self.read()
+            .for_each_line(start, count, |_, bytes| lines.push(String::from_utf8_lossy(bytes).into_owned()));
+        lines
+    }
+
+    /// The next line at or after `start` (at or before it with `backward`) matching `query`,
+    /// interpreted as `mode` says. `Err` holds why the query doesn't compile.
+    pub fn search(&self, query: &str, mode: SearchMode, start: u64, backward: bool) -> Result<Option<u64>, String> {
+        let start = usize::try_from(start).unwrap_or(usize::MAX);
+        let mut engine = self.write();
+        let _pool = pool::enter(engine.options.pool.clone());
+        let found = if backward {
+            engine.search_backward(mode, query.as_bytes(), start)
+        } else {
+            engine.search_forward(mode, query.as_bytes(), start)
+        };
+        Ok(found?.map(|line| line as u64))
+    }
+
+    /// Replaces `deleted` lines from `start` with `lines`.
+    pub fn replace_lines<S: AsRef<str>>(&self, start: u64, deleted: u64, lines: &[S]) {
+        let lines = lines.iter().map(|line| line.as_ref().to_string()).collect();
+        let start = usize::try_from(start).unwrap_or(usize::MAX);
+        let deleted = usize::try_from(deleted).unwrap_or(usize::MAX);
+        self.write().replace_lines(start, deleted, lines);
+    }
+
+    /// Applies `(start, deleted, lines)` replacements in order, each against the content the
+    /// ones before it left, as a single change: one bump for filter views and caches, one
+    /// audit entry.
+    pub fn apply_edits<S: AsRef<str>>(&self, edits: &[(u64, u64, &[S])]) {
+        let edits = edits
+            .iter()
+            .map(|(start, deleted, lines)| {
+                let start = usize::try_from(*start).unwrap_or(usize::MAX);
+                let deleted = usize::try_from(*deleted).unwrap_or(usize::MAX);
+                (start, deleted, lines.iter().map(|line| line.as_ref().to_string()).collect())
+            })
+            .collect();
+        self.write().apply_edits(edits);
+    }
+
+    /// Adds `text` at the end, a partial last line joining the one before.
+    pub fn append(&self, text: &str) {
+        self.write().append(text);
+    }
+
+    /// A digest of `count` lines from `start` as JSON: time span, lines per level, the most
+    /// frequent messages and fields, notable gaps. The same thing `:LogSummary` shows.
+    pub fn summary(&self, start: u64, count: u64) -> String {
+        let start = usize::try_from(start).unwrap_or(usize::MAX);
+        let count = usize::try_from(count).unwrap_or(usize::MAX);
+        let mut summary = Summary::new(start);
+        let engine = self.read();
+        engine.for_each_line(start, count, |line, bytes| {
+            let text = ansi::shown(bytes, engine.strip_ansi);
+            summary.add(line, &text, &engine.message(&text), &engine.templates);
+        });
+        summary.to_json()
+    }
+
+    /// The piece table as it stands: which runs of lines are still the file's and which
+    /// were edited.
+    pub fn pieces(&self) -> Vec<Piece> {
+        let engine = self.read();
+        let mut first_line = 0;
+        engine
+            .pieces
+            .iter()
+            .map(|piece| {
+                let line_count = piece.line_count() as u64;
+                let run = Piece { first_line, line_count, in_memory: matches!(piece, Stored::Memory { .. }) };
+                first_line += line_count;
+                run
+            })
+            .collect()
+    }
+
+    /// A report on the engine (mapping, index, caches, what's still running) and the library
+    /// as one JSON object, the same one `:checkhealth juan_log` shows.
+    pub fn diagnostics(&self) -> String {
+        health::report(&self.read())
+    }
+
+    /// Sizes and counts for a status line.
+    pub fn stats(&self) -> Stats {
+        let stats = self.read().stats();
+        Stats {
+            file_size: stats.file_size,
+            total_lines: stats.total_lines,
+            original_lines: stats.original_lines,
+            memory_lines: stats.memory_lines,
+            pieces: stats.pieces,
+            indexed_percent: stats.indexed_percent,
+        }
+    }
+
+    /// An independent, read-only copy of the content as it is now, cheap to take: the mapped
+    /// file and edited lines are shared, not copied. Meant for work on another thread (an
+    /// export, a long search) while this engine keeps being edited; edits on the copy are
+    /// ignored and it doesn't follow the file. Don't keep one across the file being truncated
+    /// in place: its lines are gone, and unlike the C API this one doesn't check for it.
+    pub fn freeze(&self) -> io::Result<LogEngine> {
+        self.write().freeze().map(LogEngine::wrap)
+    }
+
+    /// Writes the current content, edits included, to `path` (through a temporary file
+    /// renamed over it).
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut engine = self.write();
+        let saved = engine.save(path);
+        let shown = path.display();
+        let detail = if saved.is_ok() { shown.to_string() } else { format!("{} (failed)", shown) };
+        engine.audit.record("save", detail);
+        saved
+    }
+
+    /// Picks up what happened to the file on disk since the last look.
+    pub fn refresh(&self) -> io::Result<Refresh> {
+        let mut engine = self.write();
+        let _pool = pool::enter(engine.options.pool.clone());
+        let (result, first_changed) = engine.refresh_delta()?;
+        let first_changed = first_changed as u64;
+        Ok(match result {
+            REFRESH_NOTHING => Refresh::Unchanged,
+            REFRESH_GREW => Refresh::Grew { first_changed },
+            kept => Refresh::Reopened { first_changed, kept_edits: kept != REFRESH_REOPENED },
+        })
+    }
+}
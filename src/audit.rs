@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// every change made through the engine this session, oldest first, so "what did I actually
+// change in this log before saving?" has an answer. in memory only, the plugin decides
+// whether it ends up on disk. capped, since insert mode is one edit per keystroke.
+const AUDIT_CAPACITY: usize = 10_000;
+// how much of an edit's new text is kept in its entry
+const PREVIEW_CHARS: usize = 60;
+
+struct AuditEntry {
+    at: f64, // unix seconds
+    kind: &'static str,
+    detail: String,
+}
+
+pub(crate) struct AuditLog {
+    entries: VecDeque<AuditEntry>,
+    dropped: usize, // oldest entries pushed out by the cap
+    muted: bool,    // while the engine rebuilds something it records as one entry
+}
+
+impl AuditLog {
+    pub(crate) fn new() -> Self {
+        AuditLog {
+            entries: VecDeque::new(),
+            dropped: 0,
+            muted: false,
+        }
+    }
+
+    // returns whether it was muted before, to put back
+    pub(crate) fn mute(&mut self, muted: bool) -> bool {
+        std::mem::replace(&mut self.muted, muted)
+    }
+
+    pub(crate) fn record(&mut self, kind: &'static str, detail: String) {
+        if self.muted {
+            return;
+        }
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |d| d.as_secs_f64());
+        if self.entries.len() == AUDIT_CAPACITY {
+            self.entries.pop_front();
+            self.dropped += 1;
+        }
+        self.entries.push_back(AuditEntry { at, kind, detail });
+    }
+
+    // one entry per line: unix seconds, kind, detail, tab separated. tabs and newlines in
+    // the detail are flattened so every entry stays on its line.
+    pub(crate) fn to_text(&self) -> String {
+        let mut out = String::new();
+        if self.dropped > 0 {
+            out.push_str(&format!("0\tdropped\t{} older entries\n", self.dropped));
+        }
+        for entry in &self.entries {
+            let detail = entry.detail.replace(['\t', '\n', '\r'], " ");
+            out.push_str(&format!("{:.3}\t{}\t{}\n", entry.at, entry.kind, detail));
+        }
+        out
+    }
+}
+
+// the start of an edit's new text, enough to recognize it in the trail
+pub(crate) fn preview(lines: &[String]) -> String {
+    let first = lines.first().map_or("", String::as_str);
+    match first.char_indices().nth(PREVIEW_CHARS) {
+        Some((cut, _)) => format!("{}...", &first[..cut]),
+        None => first.to_string(),
+    }
+}
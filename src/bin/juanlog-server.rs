@@ -0,0 +1,5 @@
+// the engine out of process, msgpack-rpc on stdio or --listen. see src/rpc.rs.
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(juanlog::rpc::run(&args));
+}
@@ -0,0 +1,116 @@
+use juanlog::api::SearchMode;
+use juanlog::LogEngine;
+use std::io::{self, BufWriter, Write};
+
+// juanlogs, the engine without neovim: the same opening, searching and line fetching the
+// plugin does, so a slow search or a wrong line can be reproduced (and timed) in a shell,
+// and scripts get the engine's speed on files grep chokes on. line numbers are 1-based here,
+// like the plugin's status column and every other command line tool.
+
+const USAGE: &str = "usage:
+  juanlogs search FILE PATTERN [--regex | --glob | --query]   every matching line, numbered
+  juanlogs lines FILE FROM..TO                                 lines FROM to TO (either may be left out)
+  juanlogs stats FILE                                          path, size, line count and the summary as JSON, one `name: value` per line";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(run(&args));
+}
+
+// exit codes like grep: 0 found something, 1 nothing matched, 2 trouble
+fn run(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["search", file, pattern] => search(file, pattern, SearchMode::Literal),
+        ["search", file, pattern, flag] => match mode_flag(flag) {
+            Some(mode) => search(file, pattern, mode),
+            None => return usage(),
+        },
+        ["lines", file, range] => match parse_range(range) {
+            Some((from, to)) => lines(file, from, to),
+            None => return usage(),
+        },
+        ["stats", file] => stats(file),
+        _ => return usage(),
+    };
+    match result {
+        Ok(found) => i32::from(!found),
+        // head and friends closing the pipe early isn't a failure
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => 0,
+        Err(err) => {
+            eprintln!("juanlogs: {}", err);
+            2
+        }
+    }
+}
+
+fn usage() -> i32 {
+    eprintln!("{}", USAGE);
+    2
+}
+
+fn mode_flag(flag: &str) -> Option<SearchMode> {
+    match flag {
+        "--regex" => Some(SearchMode::Regex),
+        "--glob" => Some(SearchMode::Glob),
+        "--query" => Some(SearchMode::Query),
+        _ => None,
+    }
+}
+
+// "1000..2000", "1000..", "..2000", both ends included. None for anything else
+fn parse_range(range: &str) -> Option<(u64, u64)> {
+    let (from, to) = range.split_once("..")?;
+    let from = if from.is_empty() { 1 } else { from.parse().ok()? };
+    let to = if to.is_empty() { u64::MAX } else { to.parse().ok()? };
+    (from >= 1 && from <= to).then_some((from, to))
+}
+
+fn open(file: &str) -> io::Result<LogEngine> {
+    LogEngine::open(file).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", file, err)))
+}
+
+fn search(file: &str, pattern: &str, mode: SearchMode) -> io::Result<bool> {
+    let log = open(file)?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut next = 0;
+    let mut found = false;
+    // the match index is built by the first search, the rest walk it
+    while let Some(line) = log.search(pattern, mode, next, false).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))? {
+        writeln!(out, "{}:{}", line + 1, log.line(line).unwrap_or_default())?;
+        found = true;
+        next = line + 1;
+    }
+    out.flush()?;
+    Ok(found)
+}
+
+fn lines(file: &str, from: u64, to: u64) -> io::Result<bool> {
+    let log = open(file)?;
+    let to = to.min(log.total_lines());
+    let mut out = BufWriter::new(io::stdout().lock());
+    // a range of millions of lines goes out a block at a time, not all in memory
+    const BLOCK: u64 = 10_000;
+    let mut start = from - 1;
+    while start < to {
+        let count = BLOCK.min(to - start);
+        for line in log.lines(start, count) {
+            writeln!(out, "{}", line)?;
+        }
+        start += count;
+    }
+    out.flush()?;
+    Ok(from <= to)
+}
+
+fn stats(file: &str) -> io::Result<bool> {
+    let log = open(file)?;
+    let bytes = std::fs::metadata(file)?.len();
+    let total = log.total_lines();
+    let mut out = io::stdout().lock();
+    writeln!(out, "file: {}", file)?;
+    writeln!(out, "bytes: {}", bytes)?;
+    writeln!(out, "lines: {}", total)?;
+    writeln!(out, "summary: {}", log.summary(0, total))?;
+    Ok(true)
+}
@@ -0,0 +1,80 @@
+use memchr::{memchr2, memchr_iter};
+use std::borrow::Cow;
+use std::fmt::Write;
+
+// blobs that ended up in a log: a core dump cat'ed by mistake, a protobuf written raw, a
+// gzip'ed payload. as text they're walls of replacement characters split at whatever 0x0a
+// bytes happen to be in them. a line that's mostly bytes no text has is handed out as a
+// short hex preview instead (with hex_binary on, see Engine::shown), the real bytes are
+// still what's searched and saved, and raw_bytes gets them back.
+
+// shorter lines with a stray control byte are text with a stray control byte
+const MIN_LEN: usize = 8;
+// bytes shown in a preview
+const PREVIEW: usize = 16;
+
+// bytes no text line has: controls other than tab, line feed, vertical tab, form feed,
+// carriage return and escape (terminal logs are full of that one, see ansi.rs)
+fn is_control(b: u8) -> bool {
+    matches!(b, 0x00..=0x08 | 0x0e..=0x1a | 0x1c..=0x1f | 0x7f)
+}
+
+// whether a line is binary: at least three in ten of its bytes are controls or not utf-8
+pub(crate) fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() < MIN_LEN {
+        return false;
+    }
+    let mut odd = 0;
+    for chunk in bytes.utf8_chunks() {
+        odd += chunk.valid().bytes().filter(|&b| is_control(b)).count() + chunk.invalid().len();
+    }
+    odd * 10 >= bytes.len() * 3
+}
+
+// `[binary 312 bytes] 7f 45 4c 46 02 01 01 00 00 00 00 00 00 00 00 00 …`
+pub(crate) fn preview(bytes: &[u8]) -> String {
+    let mut out = format!("[binary {} bytes]", bytes.len());
+    for b in &bytes[..bytes.len().min(PREVIEW)] {
+        let _ = write!(out, " {:02x}", b);
+    }
+    if bytes.len() > PREVIEW {
+        out.push_str(" …");
+    }
+    out
+}
+
+// a line the way it's shown: the preview when it's binary, the text otherwise
+pub(crate) fn shown(bytes: &[u8]) -> Option<Cow<'_, str>> {
+    is_binary(bytes).then(|| Cow::Owned(preview(bytes)))
+}
+
+// lines of the file, with their line breaks, onto `out` as get_block hands them out. only a
+// block with something in it that isn't text is gone through a line at a time. a \r on its
+// own ends a line in the engine but not in the plugin, which splits on \n: blobs are full of
+// them, so there it's written as \n and the lines the plugin sees stay the engine's.
+pub(crate) fn push_lines(out: &mut String, bytes: &[u8]) {
+    let text = String::from_utf8_lossy(bytes);
+    let lone_cr = memchr_iter(b'\r', bytes).any(|at| bytes.get(at + 1) != Some(&b'\n'));
+    if matches!(text, Cow::Borrowed(_)) && !lone_cr && !bytes.iter().any(|&b| is_control(b)) {
+        out.push_str(&text);
+        return;
+    }
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let end = memchr2(b'\n', b'\r', &bytes[pos..]).map_or(bytes.len(), |at| pos + at);
+        let body = &bytes[pos..end];
+        match shown(body) {
+            Some(preview) => out.push_str(&preview),
+            None => out.push_str(&String::from_utf8_lossy(body)),
+        }
+        if bytes[end..].starts_with(b"\r\n") {
+            out.push_str("\r\n");
+            pos = end + 2;
+        } else if end < bytes.len() {
+            out.push('\n');
+            pos = end + 1;
+        } else {
+            pos = end;
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+// tiny keyed cache that remembers when each entry was last touched.
+// nothing gets evicted on insert, only when someone calls trim(). an engine left
+// open for days in some forgotten tab should not hoard every query ever run.
+#[derive(Clone)]
+pub(crate) struct TtlCache<K, V> {
+    entries: HashMap<K, (V, Instant)>,
+}
+
+impl<K: Eq + Hash, V> TtlCache<K, V> {
+    pub(crate) fn new() -> Self {
+        TtlCache {
+            entries: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let entry = self.entries.get_mut(key)?;
+        entry.1 = Instant::now();
+        Some(&entry.0)
+    }
+
+    pub(crate) fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let entry = self.entries.get_mut(key)?;
+        entry.1 = Instant::now();
+        Some(&mut entry.0)
+    }
+
+    // without counting as a use
+    pub(crate) fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    pub(crate) fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.entries.remove(key).map(|(value, _)| value)
+    }
+
+    // without counting as a use
+    pub(crate) fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.entries.contains_key(key)
+    }
+
+    // without counting as a use
+    pub(crate) fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values().map(|(value, _)| value)
+    }
+
+    // to change in place, without counting as a use
+    pub(crate) fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.entries.values_mut().map(|(value, _)| value)
+    }
+
+    pub(crate) fn insert(&mut self, key: K, value: V) {
+        self.entries.insert(key, (value, Instant::now()));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn weight(&self, weigh: impl Fn(&V) -> usize) -> usize {
+        self.entries.values().map(|(value, _)| weigh(value)).sum()
+    }
+
+    // drop the least recently used until what's left weighs at most `budget`. the newest
+    // entry always stays, however much it weighs. returns how many entries died.
+    pub(crate) fn shrink_to(&mut self, budget: usize, weigh: impl Fn(&V) -> usize) -> usize
+    where
+        K: Clone,
+    {
+        let mut total: usize = self.entries.values().map(|(value, _)| weigh(value)).sum();
+        let mut evicted = 0;
+        while total > budget && self.entries.len() > 1 {
+            let Some(oldest) = self.entries.iter().min_by_key(|(_, (_, last_used))| *last_used).map(|(key, _)| key.clone()) else {
+                break;
+            };
+            if let Some((value, _)) = self.entries.remove(&oldest) {
+                total -= weigh(&value);
+                evicted += 1;
+            }
+        }
+        evicted
+    }
+
+    // drop everything idle for longer than max_idle. returns how many entries died.
+    pub(crate) fn trim(&mut self, max_idle: Duration, now: Instant) -> usize {
+        let before = self.entries.len();
+        self.entries
+            .retain(|_, (_, last_used)| now.saturating_duration_since(*last_used) < max_idle);
+        before - self.entries.len()
+    }
+}
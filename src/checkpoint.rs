@@ -0,0 +1,38 @@
+// one hash per fixed-size block of logical lines, so two copies of a log (another tool,
+// another engine on another box) can compare hashes and only ship the blocks that differ.
+// the recipe is fixed so anyone can recompute it: FNV-1a 64 over every line in the block,
+// each followed by a single \n. the line's own terminator is not hashed, so CRLF and LF
+// copies of the same text agree.
+
+use std::collections::HashMap;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+pub(crate) struct BlockHasher(u64);
+
+impl BlockHasher {
+    pub(crate) fn new() -> Self {
+        BlockHasher(FNV_OFFSET)
+    }
+
+    pub(crate) fn line(&mut self, bytes: &[u8]) {
+        for &b in bytes.iter().chain(b"\n") {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub(crate) fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+// hashes computed so far for one block size, by block number: only the blocks someone asked
+// for, a sync looking at a few regions of a huge log doesn't pay for a slot per block.
+// anything that edits the buffer invalidates it.
+pub(crate) struct Checkpoints {
+    pub(crate) block_lines: usize,
+    pub(crate) edits: u64,
+    pub(crate) hashes: HashMap<usize, u64>,
+}
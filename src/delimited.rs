@@ -0,0 +1,196 @@
+use crate::fields;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+// csv/tsv exports (telemetry dumps, spreadsheets) read as a table: the first line names the
+// columns, every other line is a row of cells. the header gives the cells names, so they're
+// fields like logfmt keys: queries (`status>=500 AND region:eu`), :LogFields, :LogColumns.
+// what's kept is only the header and what a sample of rows says about each column, the
+// file itself is never parsed as a whole. cells are split a line at a time, so a quoted
+// value with a line break in it comes out as two broken rows.
+
+// rows looked at for kinds and widths, from the top
+pub(crate) const SAMPLE: usize = 1000;
+// delimiters tried, in the order ties go
+const DELIMITERS: &[u8] = b"\t,;|";
+
+#[derive(Clone)]
+pub(crate) struct Columns {
+    pub(crate) header: Arc<str>, // the line naming them, which isn't a row
+    pub(crate) delimiter: u8,
+    pub(crate) names: Vec<String>,
+    pub(crate) kinds: Vec<&'static str>, // "int", "float", "time", "bool" or "text"
+    pub(crate) widths: Vec<usize>,       // widest cell in the sample, header included, in chars
+}
+
+// the cells of a line in order. "quoted" cells can hold the delimiter, "" is a quote in them
+pub(crate) struct Cells<'a> {
+    rest: Option<&'a str>,
+    delimiter: char,
+}
+
+pub(crate) fn cells(line: &str, delimiter: u8) -> Cells<'_> {
+    Cells { rest: Some(line.strip_suffix('\r').unwrap_or(line)), delimiter: delimiter as char }
+}
+
+impl<'a> Iterator for Cells<'a> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Cow<'a, str>> {
+        let rest = self.rest?;
+        if let Some(body) = rest.strip_prefix('"') {
+            // up to the quote that isn't doubled, then the delimiter
+            let mut at = 0;
+            while let Some(quote) = body[at..].find('"') {
+                at += quote;
+                if body[at + 1..].starts_with('"') {
+                    at += 2;
+                    continue;
+                }
+                let after = &body[at + 1..];
+                let end = after.find(self.delimiter);
+                self.rest = end.map(|end| &after[end + 1..]);
+                let value = &body[..at];
+                return Some(if value.contains("\"\"") { Cow::Owned(value.replace("\"\"", "\"")) } else { Cow::Borrowed(value) });
+            }
+            // never closed, the rest of the line is the cell
+            self.rest = None;
+            return Some(Cow::Borrowed(body));
+        }
+        match rest.find(self.delimiter) {
+            Some(end) => {
+                self.rest = Some(&rest[end + 1..]);
+                Some(Cow::Borrowed(&rest[..end]))
+            }
+            None => {
+                self.rest = None;
+                Some(Cow::Borrowed(rest))
+            }
+        }
+    }
+}
+
+// `Request Time` -> `Request_Time`: what query field names can be
+fn field_name(header: &str, index: usize) -> String {
+    let name: String = header
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '@') { c } else { '_' })
+        .collect();
+    if name.is_empty() { format!("col{}", index + 1) } else { name }
+}
+
+fn kind(value: &str) -> &'static str {
+    if value.parse::<i64>().is_ok() {
+        "int"
+    } else if value.parse::<f64>().is_ok() {
+        "float"
+    } else if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+        "bool"
+    } else if fields::timestamp_secs(value).is_some() {
+        "time"
+    } else {
+        "text"
+    }
+}
+
+// what two kinds of values in one column make it: ints among floats are floats, anything
+// else mixed is text
+fn widen(a: &'static str, b: &'static str) -> &'static str {
+    match (a, b) {
+        _ if a == b => a,
+        ("int", "float") | ("float", "int") => "float",
+        _ => "text",
+    }
+}
+
+impl Columns {
+    // the header and some rows after it (`lines`, from the top). delimiter 0 picks the one
+    // the header splits on most that the rows agree with.
+    pub(crate) fn detect(lines: &[String], delimiter: u8) -> Result<Columns, String> {
+        let header = lines.first().ok_or("the file is empty")?;
+        let rows = &lines[1..lines.len().min(SAMPLE + 1)];
+        let delimiter = if delimiter != 0 {
+            delimiter
+        } else {
+            let agreeing = |delimiter: u8| {
+                let width = cells(header, delimiter).count();
+                let same = rows.iter().filter(|row| cells(row, delimiter).count() == width).count();
+                (width > 1 && same * 10 >= rows.len() * 9).then_some(width)
+            };
+            DELIMITERS
+                .iter()
+                .filter_map(|&delimiter| agreeing(delimiter).map(|width| (width, delimiter)))
+                .max_by_key(|&(width, delimiter)| (width, std::cmp::Reverse(DELIMITERS.iter().position(|&d| d == delimiter))))
+                .map(|(_, delimiter)| delimiter)
+                .ok_or("no delimiter splits the first line and the ones after it the same way")?
+        };
+
+        let headers: Vec<Cow<str>> = cells(header, delimiter).collect();
+        if headers.len() < 2 {
+            return Err(format!("the first line has no {:?} in it", delimiter as char));
+        }
+        // a row of data where the header should be: numbers, dates
+        if headers.iter().all(|cell| cell.trim().is_empty() || kind(cell.trim()) != "text") {
+            return Err("the first line reads like data, not column names".to_string());
+        }
+        let mut names: Vec<String> = Vec::with_capacity(headers.len());
+        for (i, header) in headers.iter().enumerate() {
+            let mut name = field_name(header, i);
+            if names.contains(&name) {
+                name = format!("{}_{}", name, i + 1);
+            }
+            names.push(name);
+        }
+
+        let mut kinds: Vec<Option<&'static str>> = vec![None; names.len()];
+        let mut widths: Vec<usize> = headers.iter().map(|header| header.chars().count()).collect();
+        for row in rows {
+            for (i, cell) in cells(row, delimiter).take(names.len()).enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+                let cell = cell.trim();
+                if !cell.is_empty() {
+                    let found = kind(cell);
+                    kinds[i] = Some(kinds[i].map_or(found, |known| widen(known, found)));
+                }
+            }
+        }
+        let kinds = kinds.into_iter().map(|kind| kind.unwrap_or("text")).collect();
+        Ok(Columns { header: header.as_str().into(), delimiter, names, kinds, widths })
+    }
+
+    // the column a field name is, exactly or as one of the usual aliases (a `severity`
+    // column answers `level`)
+    pub(crate) fn index(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .position(|known| known == name)
+            .or_else(|| self.names.iter().position(|known| known.eq_ignore_ascii_case(name)))
+            .or_else(|| self.names.iter().position(|known| fields::same_field(&known.to_ascii_lowercase(), name)))
+    }
+
+    pub(crate) fn get<'a>(&self, line: &'a str, name: &str) -> Option<Cow<'a, str>> {
+        cells(line, self.delimiter).nth(self.index(name)?)
+    }
+
+    // a row's severity rank off its level column (or severity, lvl...), None without one
+    pub(crate) fn level(&self, line: &str) -> Option<usize> {
+        fields::level_rank(&self.get(line, "level")?)
+    }
+
+    // (col, len, rank) of a row's level cell, like fields::level_span
+    pub(crate) fn level_span(&self, line: &str) -> Option<(usize, usize, usize)> {
+        match self.get(line, "level")? {
+            Cow::Borrowed(found) => {
+                let rank = fields::level_rank(found)?;
+                Some((found.as_ptr() as usize - line.as_ptr() as usize, found.len(), rank))
+            }
+            Cow::Owned(_) => None, // had a doubled quote, isn't in the line as such
+        }
+    }
+
+    // (column name, cell) for every cell of a row, cells past the header's last column left out
+    pub(crate) fn pairs<'a>(&'a self, line: &'a str) -> Vec<(&'a str, Cow<'a, str>)> {
+        self.names.iter().map(String::as_str).zip(cells(line, self.delimiter)).collect()
+    }
+}
@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// the plugin calling us wrong (null handles, ranges past the end, freeing an engine another
+// thread is still using) used to just hit an early return and vanish. now it leaves a trace:
+// a counter that always ticks, plus the last offending call. the message is only
+// rebuilt every so often so a buggy loop hammering us doesn't turn into a format! storm.
+
+const MESSAGE_EVERY: Duration = Duration::from_millis(100);
+
+static MISUSE_COUNT: AtomicU64 = AtomicU64::new(0);
+static LAST_MISUSE: Mutex<(Option<Instant>, String)> = Mutex::new((None, String::new()));
+
+thread_local! {
+    // copy handed out over FFI, lives until the next call on this thread
+    static LAST_MISUSE_OUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+pub(crate) fn misuse(describe: impl FnOnce() -> String) {
+    MISUSE_COUNT.fetch_add(1, Ordering::Relaxed);
+    fail(ERROR_MISUSE, String::new());
+    let Ok(mut last) = LAST_MISUSE.lock() else {
+        return;
+    };
+    let now = Instant::now();
+    if last.0.is_some_and(|at| now.duration_since(at) < MESSAGE_EVERY) {
+        return;
+    }
+    *last = (Some(now), describe());
+}
+
+pub(crate) fn misuse_count() -> u64 {
+    MISUSE_COUNT.load(Ordering::Relaxed)
+}
+
+// runs f with a snapshot of the last message that stays put until the next call
+pub(crate) fn with_last_misuse<R>(f: impl FnOnce(&str) -> R) -> R {
+    let current = LAST_MISUSE.lock().map(|l| l.1.clone()).unwrap_or_default();
+    LAST_MISUSE_OUT.with(|out| {
+        let mut out = out.borrow_mut();
+        *out = current;
+        f(&out)
+    })
+}
+
+// why the last call on this thread failed, errno style: every failure sets it, success
+// leaves it alone, so it's only worth reading right after a null/false/-1 came back.
+// numbers are part of the C ABI, don't reorder.
+pub(crate) const ERROR_NONE: i32 = 0;
+pub(crate) const ERROR_NOT_FOUND: i32 = 1;
+pub(crate) const ERROR_PERMISSION: i32 = 2;
+pub(crate) const ERROR_NOT_A_FILE: i32 = 3; // directory, device node, socket
+pub(crate) const ERROR_TIMED_OUT: i32 = 4;
+pub(crate) const ERROR_MAP: i32 = 5; // opened fine, mmap failed (address space, odd filesystem)
+pub(crate) const ERROR_IO: i32 = 6; // any other read or write going wrong
+pub(crate) const ERROR_BAD_QUERY: i32 = 7; // doesn't compile in the mode asked for
+pub(crate) const ERROR_TRUNCATED: i32 = 8; // the file got shorter, reopen instead of refreshing
+pub(crate) const ERROR_MISUSE: i32 = 9; // the message is the last misuse, see above
+pub(crate) const ERROR_CANCELLED: i32 = 10; // log_engine_cancel stopped it, see ops.rs
+pub(crate) const ERROR_PANIC: i32 = 11; // a bug in here, caught before it took neovim down (unwind.rs)
+pub(crate) const ERROR_READ_ONLY: i32 = 12; // an edit or save on an engine opened read-only
+pub(crate) const ERROR_LOCKED: i32 = 13; // another session holds the file's lock, see notes.rs
+
+thread_local! {
+    static LAST_ERROR: RefCell<(i32, String)> = const { RefCell::new((ERROR_NONE, String::new())) };
+    // same deal as LAST_MISUSE_OUT
+    static LAST_ERROR_OUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+pub(crate) fn fail(code: i32, message: String) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = (code, message));
+}
+
+pub(crate) fn io_failure(err: &io::Error) {
+    let code = if err.get_ref().is_some_and(|inner| inner.is::<MapFailed>()) {
+        ERROR_MAP
+    } else if err.get_ref().is_some_and(|inner| inner.is::<ReadOnly>()) {
+        ERROR_READ_ONLY
+    } else if is_cancelled(err) {
+        ERROR_CANCELLED
+    } else {
+        match err.kind() {
+            io::ErrorKind::NotFound => ERROR_NOT_FOUND,
+            io::ErrorKind::PermissionDenied => ERROR_PERMISSION,
+            io::ErrorKind::InvalidInput => ERROR_NOT_A_FILE, // what Engine::new says for those
+            io::ErrorKind::TimedOut => ERROR_TIMED_OUT,
+            io::ErrorKind::WouldBlock => ERROR_LOCKED,
+            _ => ERROR_IO,
+        }
+    };
+    fail(code, err.to_string());
+}
+
+// tags an mmap error so io_failure can tell it from the open before it
+#[derive(Debug)]
+struct MapFailed(io::Error);
+
+impl fmt::Display for MapFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "can't map the file: {}", self.0)
+    }
+}
+
+impl std::error::Error for MapFailed {}
+
+pub(crate) fn map_failed(err: io::Error) -> io::Error {
+    io::Error::other(MapFailed(err))
+}
+
+// what a save on a read-only engine fails with
+#[derive(Debug)]
+struct ReadOnly;
+
+impl fmt::Display for ReadOnly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "opened read-only")
+    }
+}
+
+impl std::error::Error for ReadOnly {}
+
+pub(crate) fn read_only() -> io::Error {
+    io::Error::other(ReadOnly)
+}
+
+// what a call stopped by log_engine_cancel fails with (ops::cancelled_error). not an
+// Interrupted: that's an EINTR, which retry.rs tries again and nobody asked to stop
+#[derive(Debug)]
+struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+pub(crate) fn cancelled() -> io::Error {
+    io::Error::other(Cancelled)
+}
+
+pub(crate) fn is_cancelled(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<Cancelled>())
+}
+
+pub(crate) fn last_error_code() -> i32 {
+    LAST_ERROR.with(|last| last.borrow().0)
+}
+
+// runs f with the last error's message, kept until the next call like with_last_misuse
+pub(crate) fn with_last_error<R>(f: impl FnOnce(&str) -> R) -> R {
+    let (code, message) = LAST_ERROR.with(|last| last.borrow().clone());
+    if code == ERROR_MISUSE {
+        return with_last_misuse(f);
+    }
+    LAST_ERROR_OUT.with(|out| {
+        let mut out = out.borrow_mut();
+        *out = message;
+        f(&out)
+    })
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+// what a log is mostly made of: its messages grouped into templates, drain style
+// ("Connection to * timed out after *ms" x 4231). a message is split into words and what
+// has a digit in it is taken for a variable up front (see mask). messages with the same number of
+// words starting with the same PREFIX words land in one group, and in there a message joins
+// the template it shares the most words with, if that's at least SIMILAR of them; the words
+// that differ become `*`. no regexes per format, no second pass over the lines.
+//
+// slices of a range are mined on their own in parallel and their templates then mined the
+// same way, weighted by their counts (see merge).
+
+// words a group is keyed on after the word count
+const PREFIX: usize = 2;
+// share of words a message has in common with a template to join it
+const SIMILAR: f64 = 0.5;
+// templates kept at most, a guard against a log of nothing but unique lines. messages that
+// fit none of them past that aren't counted.
+const MAX_TEMPLATES: usize = 50_000;
+
+const WILDCARD: &str = "*";
+
+pub(crate) struct Template {
+    pub(crate) words: Vec<String>,
+    pub(crate) count: u64,
+    pub(crate) first_line: usize, // the earliest line it was seen on
+}
+
+#[derive(Default)]
+pub(crate) struct Drain {
+    groups: HashMap<(usize, Vec<String>), Vec<usize>>, // (word count, first words) -> templates
+    templates: Vec<Template>,
+}
+
+// a message's words, variables already masked
+fn words(message: &str) -> Vec<String> {
+    message.split_whitespace().map(mask).collect()
+}
+
+// a message as the template it would start: "retry 3 of 10 failed" -> "retry * of * failed"
+pub(crate) fn template(message: &str) -> String {
+    words(message).join(" ")
+}
+
+// every run of letters and digits with a digit in it is a `*`, a number keeps the unit after
+// it: `user=42` -> `user=*`, `10.0.0.7:8080` -> `*:*`, `500ms` -> `*ms`, `req-8f3a` -> `req-*`
+fn mask(word: &str) -> String {
+    if !word.bytes().any(|b| b.is_ascii_digit()) {
+        return word.to_string();
+    }
+    let bytes = word.as_bytes();
+    let mut out = String::with_capacity(word.len());
+    let mut at = 0;
+    while at < bytes.len() {
+        let start = at;
+        // dots between digits are part of the number (versions, addresses)
+        while at < bytes.len()
+            && (bytes[at].is_ascii_alphanumeric()
+                || (bytes[at] == b'.' && at > start && bytes[at - 1].is_ascii_digit() && bytes.get(at + 1).is_some_and(u8::is_ascii_digit)))
+        {
+            at += 1;
+        }
+        if at == start {
+            // punctuation, or the start of a multibyte char: up to the next char
+            let next = word[at..].chars().next().map_or(1, char::len_utf8);
+            out.push_str(&word[at..at + next]);
+            at += next;
+            continue;
+        }
+        let run = &word[start..at];
+        if !run.bytes().any(|b| b.is_ascii_digit()) {
+            out.push_str(run);
+            continue;
+        }
+        let number = run.bytes().take_while(|b| b.is_ascii_digit() || *b == b'.').count();
+        out.push_str(WILDCARD);
+        if number > 0 && run[number..].bytes().all(|b| b.is_ascii_alphabetic()) {
+            out.push_str(&run[number..]);
+        }
+    }
+    out
+}
+
+// share of a template's words that are the message's. a wildcard only counts for a word
+// masked as one, it says nothing about words it replaced
+fn similarity(template: &[String], words: &[String]) -> f64 {
+    let same = template.iter().zip(words).filter(|(t, w)| t == w).count();
+    same as f64 / template.len() as f64
+}
+
+impl Drain {
+    pub(crate) fn add(&mut self, line: usize, message: &str) {
+        let words = words(message);
+        if !words.is_empty() {
+            self.insert(words, 1, line);
+        }
+    }
+
+    // another slice's templates, as if its messages had come through here
+    pub(crate) fn merge(mut self, other: Drain) -> Drain {
+        for template in other.templates {
+            self.insert(template.words, template.count, template.first_line);
+        }
+        self
+    }
+
+    fn insert(&mut self, words: Vec<String>, count: u64, line: usize) {
+        let key = (words.len(), words.iter().take(PREFIX).cloned().collect());
+        let group = self.groups.entry(key).or_default();
+        let best = group
+            .iter()
+            .map(|&i| (i, similarity(&self.templates[i].words, &words)))
+            .filter(|&(_, similar)| similar >= SIMILAR)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match best {
+            Some((i, _)) => {
+                let template = &mut self.templates[i];
+                for (t, w) in template.words.iter_mut().zip(&words) {
+                    if t != w {
+                        *t = WILDCARD.to_string();
+                    }
+                }
+                template.count += count;
+                template.first_line = template.first_line.min(line);
+            }
+            None if self.templates.len() < MAX_TEMPLATES => {
+                group.push(self.templates.len());
+                self.templates.push(Template { words, count, first_line: line });
+            }
+            None => {}
+        }
+    }
+
+    // the templates, most common first (earliest seen first among equals), at most `max`
+    pub(crate) fn top(mut self, max: usize) -> Vec<Template> {
+        self.templates.sort_by(|a, b| b.count.cmp(&a.count).then(a.first_line.cmp(&b.first_line)));
+        self.templates.truncate(max);
+        self.templates
+    }
+}
@@ -0,0 +1,129 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::os::raw::c_void;
+use std::sync::{Mutex, PoisonError, RwLock};
+
+// telling the plugin instead of waiting to be asked: one callback for the whole library,
+// set with log_engine_set_callback, called for the events in its mask. what happens inside
+// the engine is only queued, the callback runs once the call that caused it has let go of
+// the engine (see Held in lib.rs), on the thread that made the call. so it can call straight
+// back in, but it also only fires while something is calling: growth shows up on the next
+// refresh or poll, not by itself. the exception is the native watch (see watch.rs), whose
+// thread sends EVENT_CHANGED without any call, the callback running on that thread.
+//
+// a host that can't take a call on just any thread (LuaJIT, whose callbacks must stay on
+// the thread running the interpreter, while calls may come from libuv workers) sets a
+// wakeup instead, log_engine_set_wakeup: the events go in a mailbox and `wake(handle)` is
+// called, uv_async_send for libuv, which any thread may call. the host's loop then takes
+// them out with log_engine_next_event, on its own thread.
+
+// bits of the mask and the event argument, part of the C ABI
+pub(crate) const EVENT_INDEXED: u32 = 1; // engine ready: a = lines, b = bytes
+pub(crate) const EVENT_GREW: u32 = 2; // lines came in on disk: a = total lines, b = first changed
+pub(crate) const EVENT_ROTATED: u32 = 4; // file reopened: a = total lines, b = what log_engine_reopen returns
+pub(crate) const EVENT_FILTER_DONE: u32 = 8; // a stepped filter finished: a = filter, b = hits
+pub(crate) const EVENT_CHANGED: u32 = 16; // the native watch saw the file change: a = 1 if rotated, b = bytes
+
+pub(crate) type Callback = extern "C" fn(engine: u64, event: u32, a: u64, b: u64, userdata: *mut c_void);
+
+struct Listener {
+    mask: u32,
+    callback: Callback,
+    userdata: usize, // the caller's pointer, only ever handed back
+}
+
+static LISTENER: RwLock<Option<Listener>> = RwLock::new(None);
+
+pub(crate) type Wake = extern "C" fn(handle: *mut c_void) -> i32;
+
+struct Wakeup {
+    mask: u32,
+    wake: Option<Wake>, // None: the host polls the mailbox instead
+    handle: usize, // the caller's pointer, only ever handed back
+}
+
+static WAKEUP: RwLock<Option<Wakeup>> = RwLock::new(None);
+// (engine, event, a, b). a loop that stopped taking them loses the oldest, not the memory
+static MAILBOX: Mutex<VecDeque<(u64, u32, u64, u64)>> = Mutex::new(VecDeque::new());
+const MAILBOX_MAX: usize = 4096;
+
+thread_local! {
+    static QUEUED: RefCell<Vec<(u32, u64, u64)>> = const { RefCell::new(Vec::new()) };
+}
+
+pub(crate) fn set(mask: u32, callback: Option<Callback>, userdata: *mut c_void) {
+    let listener = callback.filter(|_| mask != 0).map(|callback| Listener { mask, callback, userdata: userdata as usize });
+    *LISTENER.write().unwrap_or_else(PoisonError::into_inner) = listener;
+}
+
+// an empty mask turns it off, dropping what was still in the mailbox
+pub(crate) fn set_wakeup(mask: u32, wake: Option<Wake>, handle: *mut c_void) {
+    let wakeup = (mask != 0).then_some(Wakeup { mask, wake, handle: handle as usize });
+    *WAKEUP.write().unwrap_or_else(PoisonError::into_inner) = wakeup;
+    MAILBOX.lock().unwrap_or_else(PoisonError::into_inner).clear();
+}
+
+// the oldest event in the mailbox
+pub(crate) fn next() -> Option<(u64, u32, u64, u64)> {
+    MAILBOX.lock().unwrap_or_else(PoisonError::into_inner).pop_front()
+}
+
+fn wanted(event: u32) -> bool {
+    LISTENER.read().unwrap_or_else(PoisonError::into_inner).as_ref().is_some_and(|l| l.mask & event != 0)
+        || WAKEUP.read().unwrap_or_else(PoisonError::into_inner).as_ref().is_some_and(|w| w.mask & event != 0)
+}
+
+// from inside the engine, kept until the call is done
+pub(crate) fn emit(event: u32, a: u64, b: u64) {
+    if wanted(event) {
+        QUEUED.with(|queued| queued.borrow_mut().push((event, a, b)));
+    }
+}
+
+// whatever a call on `engine` queued, to the callback. the queue is taken first so a
+// callback calling back in starts on an empty one.
+pub(crate) fn flush(engine: u64) {
+    let queued = QUEUED.with(|queued| std::mem::take(&mut *queued.borrow_mut()));
+    if queued.is_empty() {
+        return;
+    }
+    post(engine, &queued);
+    let Some((mask, callback, userdata)) =
+        LISTENER.read().unwrap_or_else(PoisonError::into_inner).as_ref().map(|l| (l.mask, l.callback, l.userdata))
+    else {
+        return;
+    };
+    for (event, a, b) in queued {
+        if mask & event != 0 {
+            callback(engine, event, a, b, userdata as *mut c_void);
+        }
+    }
+}
+
+// into the mailbox, one wake for all of them
+fn post(engine: u64, queued: &[(u32, u64, u64)]) {
+    let Some((mask, wake, handle)) =
+        WAKEUP.read().unwrap_or_else(PoisonError::into_inner).as_ref().map(|w| (w.mask, w.wake, w.handle))
+    else {
+        return;
+    };
+    let mut posted = false;
+    {
+        let mut mailbox = MAILBOX.lock().unwrap_or_else(PoisonError::into_inner);
+        for &(event, a, b) in queued.iter().filter(|&&(event, _, _)| mask & event != 0) {
+            if mailbox.len() >= MAILBOX_MAX {
+                mailbox.pop_front();
+            }
+            mailbox.push_back((engine, event, a, b));
+            posted = true;
+        }
+    }
+    if let Some(wake) = wake.filter(|_| posted) {
+        wake(handle as *mut c_void);
+    }
+}
+
+// events from calls that didn't come through a number (the rust api) have nobody to go to
+pub(crate) fn discard() {
+    QUEUED.with(|queued| queued.borrow_mut().clear());
+}
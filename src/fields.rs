@@ -0,0 +1,462 @@
+use crate::access;
+use crate::glog;
+use crate::template::Templates;
+use crate::zone;
+use std::borrow::Cow;
+
+// best effort field extraction for a single line. no schema, no config:
+// json objects, logfmt key=value pairs, and a few well known fallbacks
+// (level/msg/ts) for plain text logs that have none of that. formats the caller
+// registered on the engine (template.rs) come first, they're the one thing that does know.
+
+// PRIORITY and MESSAGE are journald's
+const LEVEL_KEYS: &[&str] = &["level", "lvl", "severity", "loglevel", "PRIORITY"];
+const MSG_KEYS: &[&str] = &["msg", "message", "MESSAGE"];
+const TS_KEYS: &[&str] = &["ts", "time", "timestamp", "@timestamp"];
+// where in the code a line was logged from: glog's location, zap's caller, logrus' file
+const LOCATION_KEYS: &[&str] = &["location", "caller", "source", "file"];
+
+// canonical severities ordered by rank, lowest first
+const LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error", "fatal"];
+
+pub(crate) fn lookup<'a>(templates: &Templates, line: &'a str, name: &str) -> Option<Cow<'a, str>> {
+    let aliases: &[&str] = if LEVEL_KEYS.contains(&name) {
+        LEVEL_KEYS
+    } else if MSG_KEYS.contains(&name) {
+        MSG_KEYS
+    } else if TS_KEYS.contains(&name) {
+        TS_KEYS
+    } else {
+        &[]
+    };
+
+    let keys = if aliases.is_empty() { std::slice::from_ref(&name) } else { aliases };
+    if let Some(found) = templates.parse(line) {
+        let value = keys.iter().find_map(|key| found.iter().find(|(field, _)| field == key));
+        if let Some(&(_, value)) = value {
+            return Some(Cow::Borrowed(value));
+        }
+    }
+    // glog says its own level, pairs in the message don't get to override it
+    if let Some(glog) = glog::parse(line) {
+        if aliases == LEVEL_KEYS {
+            return Some(Cow::Borrowed(glog.level));
+        }
+        if let Some(value) = keys.iter().find_map(|key| glog.get(key)) {
+            return Some(Cow::Borrowed(value));
+        }
+    }
+    for key in keys {
+        if let Some(v) = json_value(line, key).or_else(|| kv_value(line, key)) {
+            return Some(v);
+        }
+    }
+
+    // access logs have fields, just no names written next to them
+    if let Some(access) = access::parse(line) {
+        if let Some(value) = keys.iter().find_map(|key| access.get(key)) {
+            return Some(Cow::Borrowed(value));
+        }
+        if aliases == LEVEL_KEYS {
+            return Some(Cow::Borrowed(access.level()));
+        }
+    }
+
+    // plain text fallbacks
+    if aliases == LEVEL_KEYS {
+        return syslog_priority(line).or_else(|| level_token(line)).map(Cow::Borrowed);
+    }
+    if aliases == MSG_KEYS {
+        return Some(Cow::Borrowed(line));
+    }
+    if aliases == TS_KEYS {
+        return leading_timestamp(line).map(Cow::Borrowed);
+    }
+    None
+}
+
+// "ERR", "Warning", "crit", syslog's numeric "3", bunyan/pino's "30"... -> one of LEVELS
+pub(crate) fn canonical_level(s: &str) -> Option<&'static str> {
+    let level = match s.to_ascii_lowercase().as_str() {
+        "trace" | "trc" => "trace",
+        "debug" | "dbg" | "7" => "debug",
+        "info" | "inf" | "notice" | "6" | "5" => "info",
+        "warn" | "warning" | "wrn" | "4" => "warn",
+        "error" | "err" | "3" => "error",
+        "fatal" | "critical" | "crit" | "panic" | "emerg" | "alert" | "2" | "1" | "0" => "fatal",
+        _ => return node_level(s),
+    };
+    Some(level)
+}
+
+// bunyan and pino: 10 trace, 20 debug, 30 info, 40 warn, 50 error, 60 fatal. pino's custom
+// levels in between get the standard one below them, anything past 60 is fatal
+fn node_level(s: &str) -> Option<&'static str> {
+    let n: u8 = s.parse().ok().filter(|n| (10..100).contains(n) && s.len() == 2)?;
+    LEVELS.get((n as usize / 10 - 1).min(LEVELS.len() - 1)).copied()
+}
+
+// the name to show for a level value: numeric ones ("30", journald's "6") as the severity
+// they stand for, words as they're written
+pub(crate) fn level_label(value: &str) -> Option<&'static str> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    canonical_level(value)
+}
+
+pub(crate) const LEVEL_COUNT: usize = LEVELS.len();
+
+pub(crate) fn level_name(rank: usize) -> Option<&'static str> {
+    LEVELS.get(rank).copied()
+}
+
+pub(crate) fn level_rank(s: &str) -> Option<usize> {
+    let level = canonical_level(s)?;
+    LEVELS.iter().position(|l| *l == level)
+}
+
+// the severity rank of a whole line: its level field, a syslog priority in front, or the
+// first word that reads like a level
+pub(crate) fn line_level(templates: &Templates, line: &str) -> Option<usize> {
+    lookup(templates, line, "level").and_then(|l| level_rank(&l))
+}
+
+pub(crate) fn is_level_field(name: &str) -> bool {
+    LEVEL_KEYS.contains(&name)
+}
+
+pub(crate) fn is_time_field(name: &str) -> bool {
+    TS_KEYS.contains(&name)
+}
+
+// whether two names are aliases of one well known field, "severity" and "level"
+pub(crate) fn same_field(a: &str, b: &str) -> bool {
+    [LEVEL_KEYS, MSG_KEYS, TS_KEYS].iter().any(|keys| keys.contains(&a) && keys.contains(&b))
+}
+
+pub(crate) fn is_free_text_field(name: &str) -> bool {
+    MSG_KEYS.contains(&name) || TS_KEYS.contains(&name)
+}
+
+// every key/value pair in the line, json or logfmt. nested objects are flattened
+// in the sense that their keys show up too, without any prefix.
+pub(crate) fn pairs<'a>(templates: &Templates, line: &'a str) -> Vec<(&'a str, Cow<'a, str>)> {
+    if let Some(found) = templates.parse(line) {
+        // the format's fields, then pairs inside the last one (the message, usually)
+        let last = found.last().map_or("", |&(_, value)| value);
+        let mut out: Vec<_> = found.iter().map(|&(key, value)| (key, Cow::Borrowed(value))).collect();
+        out.extend(text_pairs(last));
+        return out;
+    }
+    text_pairs(line)
+}
+
+// pairs for text no registered format is about: a line, or what's left of one past a
+// header that isn't key=value (glog's, an access log's)
+pub(crate) fn text_pairs(line: &str) -> Vec<(&str, Cow<'_, str>)> {
+    if let Some(glog) = glog::parse(line) {
+        return glog.pairs();
+    }
+    if let Some(access) = access::parse(line) {
+        return access.pairs();
+    }
+    let mut out = Vec::new();
+    let json = line.trim_start().starts_with('{');
+    let mut pos = 0;
+    while pos < line.len() {
+        let rest = &line[pos..];
+        let (key, after_key) = if json {
+            let Some(open) = rest.find('"') else { break };
+            let body = &rest[open + 1..];
+            let Some(close) = closing_quote(body) else { break };
+            (&body[..close], open + 1 + close + 1)
+        } else {
+            let start = rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len());
+            let len = rest[start..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '@')))
+                .unwrap_or(rest.len() - start);
+            (&rest[start..start + len], start + len)
+        };
+
+        let after = &rest[after_key..];
+        let value_start = if json {
+            let trimmed = after.trim_start();
+            trimmed.strip_prefix(':').map(|v| after.len() - v.len())
+        } else {
+            after.strip_prefix('=').map(|_| 1)
+        };
+        let Some(value_start) = value_start.filter(|_| !key.is_empty()) else {
+            // not a pair. skip the token (logfmt) or the string (json) and keep going
+            pos += if json { after_key } else { after_key + after.find(char::is_whitespace).unwrap_or(after.len()).max(1) };
+            continue;
+        };
+
+        let value_rest = after[value_start..].trim_start();
+        let value_offset = after.len() - value_rest.len();
+        let consumed = if let Some(body) = value_rest.strip_prefix('"') {
+            out.push((key, unescape_quoted(body)));
+            1 + closing_quote(body).map_or(body.len(), |c| c + 1)
+        } else if json && value_rest.starts_with(['{', '[']) {
+            1 // step inside, nested keys get picked up on the next rounds
+        } else {
+            let end = value_rest
+                .find(|c: char| c.is_whitespace() || (json && matches!(c, ',' | '}' | ']')))
+                .unwrap_or(value_rest.len());
+            if end > 0 {
+                out.push((key, Cow::Borrowed(&value_rest[..end])));
+            }
+            end.max(1)
+        };
+        pos += after_key + value_offset + consumed;
+    }
+    out
+}
+
+// seconds since the epoch for "2024-01-01 12:00:00.5", "2024-01-01T12:00:00+02:00", a bare
+// date, or a unix timestamp (seconds or millis). a stamp that gives its offset is brought to
+// UTC with it, so zoned stamps order by the instant they name. one that doesn't is read as
+// UTC.
+pub(crate) fn timestamp_secs(ts: &str) -> Option<f64> {
+    let secs = wall_secs(ts)?;
+    Some(secs - zone::stamp_offset(ts.trim()).unwrap_or(0) as f64)
+}
+
+// timestamp_secs without the offset: the time the stamp reads on its own clock
+pub(crate) fn wall_secs(ts: &str) -> Option<f64> {
+    let ts = ts.trim();
+    if ts.len() >= 9 && ts.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        let n: f64 = ts.parse().ok()?;
+        return Some(if n > 1e11 { n / 1000.0 } else { n });
+    }
+
+    let num = |s: &str| -> Option<i64> {
+        (!s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())).then(|| s.parse().ok())?
+    };
+    let year = num(ts.get(0..4)?)?;
+    let month = num(ts.get(5..7)?)?;
+    let day = num(ts.get(8..10)?)?;
+    if !matches!(ts.as_bytes()[4], b'-' | b'/') || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // days_from_civil, proleptic gregorian
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let mut secs = days as f64 * 86400.0;
+    if let Some(time) = ts.get(11..).filter(|_| matches!(ts.as_bytes().get(10), Some(b'T' | b' '))) {
+        let hour = num(time.get(0..2)?)?;
+        let minute = num(time.get(3..5)?)?;
+        let second: f64 = time
+            .get(6..)
+            .map(|s| &s[..s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len())])
+            .filter(|s| !s.is_empty())
+            .map_or(Some(0.0), |s| s.parse().ok())?;
+        secs += (hour * 3600 + minute * 60) as f64 + second;
+    }
+    Some(secs)
+}
+
+// where a line's severity is written and its rank: (col, len, rank) of the level field's
+// value, the <priority> in front, or the word. None without one, or when the value had to be
+// unescaped and isn't in the line as such.
+pub(crate) fn level_span(templates: &Templates, line: &str) -> Option<(usize, usize, usize)> {
+    let value = lookup(templates, line, "level")?;
+    let rank = level_rank(&value)?;
+    match value {
+        // a borrowed value is a piece of the line itself, that's where it is
+        Cow::Borrowed(found) if line.as_bytes().as_ptr_range().contains(&found.as_ptr()) => {
+            Some((found.as_ptr() as usize - line.as_ptr() as usize, found.len(), rank))
+        }
+        Cow::Borrowed(_) if line.starts_with('<') => Some((0, line.find('>')? + 1, rank)), // syslog_priority's digit
+        Cow::Borrowed(_) if glog::parse(line).is_some() => Some((0, 1, rank)), // the letter in front
+        // an access log line's level comes from its status
+        Cow::Borrowed(_) => {
+            let status = access::parse(line)?.get("status")?;
+            Some((status.as_ptr() as usize - line.as_ptr() as usize, status.len(), rank))
+        }
+        Cow::Owned(_) => None,
+    }
+}
+
+// (path, line) of the code that wrote a line, off a location field: "server.cc:88",
+// "pkg/main.go:42:7". the line is 0 when the field doesn't say. values that don't look like
+// a path ("source":"kafka") don't count.
+pub(crate) fn source_location(templates: &Templates, line: &str) -> Option<(String, u64)> {
+    let number = |s: &str| (!s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())).then(|| s.parse::<u64>().ok()).flatten();
+    LOCATION_KEYS.iter().filter_map(|key| lookup(templates, line, key)).find_map(|value| {
+        let value = value.trim();
+        let (mut path, mut at) = (value, 0);
+        // path:line, or path:line:col
+        if let Some((head, last)) = value.rsplit_once(':').and_then(|(head, last)| Some((head, number(last)?))) {
+            (path, at) = match head.rsplit_once(':').and_then(|(path, line)| Some((path, number(line)?))) {
+                Some(found) => found,
+                None => (head, last),
+            };
+        }
+        let pathlike = path.contains(['.', '/', '\\']) && !path.contains(char::is_whitespace);
+        pathlike.then(|| (path.to_string(), at))
+    })
+}
+
+// "<11>..." (dmesg -r, syslog as it goes over the wire) ->
+// the severity digit, the low three bits of the priority
+fn syslog_priority(line: &str) -> Option<&'static str> {
+    let rest = line.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    let priority: u8 = rest[..end].parse().ok().filter(|_| (1..=3).contains(&end))?;
+    (priority < 192).then(|| ["0", "1", "2", "3", "4", "5", "6", "7"][(priority % 8) as usize])
+}
+
+// first word in the line that reads like a severity
+fn level_token(line: &str) -> Option<&str> {
+    line.split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|word| word.len() >= 3 && canonical_level(word).is_some())
+}
+
+// a line that belongs to the entry above it instead of starting one: traceback frames,
+// wrapped text, "Caused by:". when that entry started with a timestamp, anything without
+// one is taken as part of it too (python tracebacks, multi-line messages).
+pub(crate) fn continues_entry(templates: &Templates, line: &str, head_stamped: bool) -> bool {
+    if line.starts_with([' ', '\t']) || line.starts_with("Caused by:") {
+        return true;
+    }
+    head_stamped && !starts_with_timestamp(templates, line)
+}
+
+// a glog line or one in a registered format starts an entry too, whatever its stamp looks like
+pub(crate) fn starts_with_timestamp(templates: &Templates, line: &str) -> bool {
+    leading_timestamp(line).is_some() || glog::parse(line).is_some() || templates.parse(line).is_some()
+}
+
+// a plain text line past its header: the timestamp in front, then a level word
+// ("2024-01-01 12:00:00 [WARN] disk full" -> "disk full"). what a line says, for grouping
+pub(crate) fn plain_message(line: &str) -> &str {
+    let mut rest = line;
+    if let Some(stamp) = leading_timestamp(line) {
+        let end = stamp.as_ptr() as usize - line.as_ptr() as usize + stamp.len();
+        rest = line[end..].trim_start_matches([']', ' ']);
+    }
+    let word_end = rest.find(' ').unwrap_or(rest.len());
+    let word = rest[..word_end].trim_matches(['[', ']', ':']);
+    if word.len() >= 3 && canonical_level(word).is_some() {
+        rest = rest[word_end..].trim_start();
+    }
+    rest
+}
+
+// "2024-01-01 12:00:00.123 ..." or "[2024-01-01T12:00:00Z] ..." -> the date(+time) prefix
+fn leading_timestamp(line: &str) -> Option<&str> {
+    let rest = line.trim_start_matches(['[', ' ']);
+    let date_end = rest.find([' ', ']']).unwrap_or(rest.len());
+    let date = &rest[..date_end];
+    if date.len() < 6 || !date.starts_with(|c: char| c.is_ascii_digit()) {
+        return None; // "42 bottles" is not a timestamp
+    }
+    let mut end = date_end;
+    // "date time" counts as one timestamp
+    if let Some(after) = rest[date_end..].strip_prefix(' ') {
+        let time = &after[..after.find([' ', ']']).unwrap_or(after.len())];
+        if time.contains(':') && time.starts_with(|c: char| c.is_ascii_digit()) {
+            end = date_end + 1 + time.len();
+        }
+    }
+    Some(&rest[..end])
+}
+
+// "key": "value"  /  "key": 123
+fn json_value<'a>(line: &'a str, key: &str) -> Option<Cow<'a, str>> {
+    if key.is_empty() || !line.trim_start().starts_with('{') {
+        return None;
+    }
+    let mut search_from = 0;
+    while let Some(pos) = line[search_from..].find(key) {
+        let start = search_from + pos;
+        search_from = start + key.len();
+        let Some(rest) = line[search_from..].strip_prefix('"').filter(|_| line[..start].ends_with('"')) else {
+            continue;
+        };
+        let Some(rest) = rest.trim_start().strip_prefix(':') else {
+            continue; // was a value, not a key
+        };
+        let rest = rest.trim_start();
+        if let Some(body) = rest.strip_prefix('"') {
+            return Some(unescape_quoted(body));
+        }
+        let end = rest
+            .find(|c: char| c == ',' || c == '}' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        return Some(Cow::Borrowed(&rest[..end]));
+    }
+    None
+}
+
+// key=value  /  key="quoted value"
+fn kv_value<'a>(line: &'a str, key: &str) -> Option<Cow<'a, str>> {
+    if key.is_empty() {
+        return None;
+    }
+    let mut search_from = 0;
+    while let Some(pos) = line[search_from..].find(key) {
+        let start = search_from + pos;
+        search_from = start + key.len();
+        let Some(rest) = line[search_from..].strip_prefix('=') else {
+            continue;
+        };
+        search_from += 1;
+        // must be a whole key, "xlevel=" is not "level="
+        if line[..start].ends_with(|c: char| !c.is_whitespace()) {
+            continue;
+        }
+        if let Some(body) = rest.strip_prefix('"') {
+            return Some(unescape_quoted(body));
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        return Some(Cow::Borrowed(&rest[..end]));
+    }
+    None
+}
+
+// index of the closing quote in a body that starts right after the opening one
+fn closing_quote(body: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some(i),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+// body starts right after the opening quote
+fn unescape_quoted(body: &str) -> Cow<'_, str> {
+    let Some(end) = closing_quote(body) else {
+        return Cow::Borrowed(body); // unterminated, take what's there
+    };
+    let raw = &body[..end];
+    if !raw.contains('\\') {
+        return Cow::Borrowed(raw);
+    }
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some(other) => out.push(other),
+                None => {}
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    Cow::Owned(out)
+}
@@ -0,0 +1,598 @@
+use crate::delimited::Columns;
+use crate::match_index::{Combine, MatchIndex, PackedBits, PartialIndex};
+use crate::matcher::{Matcher, SearchMode};
+use crate::template::Templates;
+use crate::Piece;
+use std::sync::Arc;
+
+// a virtual file made only of the lines matching a query. nothing gets copied: rows map
+// back to logical lines through the match bitset (rank/select), so filtering a 300M line
+// file costs about the same as searching it once. edits underneath just mark it stale
+// and the row layout is rebuilt on the next call.
+
+// one link of a filter chain: keep (or with `exclude`, drop) the lines matching query.
+// a filter made out of two others (Engine::filter_combine) starts its chain with a step
+// holding both their chains instead, its mode and query unused, so it can be exported and
+// built again like any other.
+#[derive(Clone)]
+pub(crate) struct FilterStep {
+    pub(crate) mode: SearchMode,
+    pub(crate) query: Vec<u8>,
+    pub(crate) exclude: bool,
+    pub(crate) combined: Option<Arc<Combined>>,
+}
+
+pub(crate) struct Combined {
+    pub(crate) op: Combine,
+    pub(crate) parts: [Vec<FilterStep>; 2],
+}
+
+impl FilterStep {
+    pub(crate) fn compile(&self, columns: Option<&Columns>, templates: &Templates, strip: bool) -> Result<Matcher, String> {
+        let matcher = Matcher::compile(self.mode, &self.query, columns, templates, strip)?;
+        Ok(if self.exclude { Matcher::Not(Box::new(matcher)) } else { matcher })
+    }
+
+    // `level>=warn`, `not /timeout/`, `glob conn-*`... for the audit trail
+    pub(crate) fn describe(&self) -> String {
+        if let Some(combined) = &self.combined {
+            let [a, b] = &combined.parts;
+            return format!("({}) {} ({})", describe_chain(a), combined.op.describe(), describe_chain(b));
+        }
+        let query = String::from_utf8_lossy(&self.query);
+        let shown = match self.mode {
+            SearchMode::Literal => format!("\"{}\"", query),
+            SearchMode::Query => query.into_owned(),
+            SearchMode::Glob => format!("glob {}", query),
+            SearchMode::Regex => format!("/{}/", query),
+        };
+        if self.exclude { format!("not {}", shown) } else { shown }
+    }
+}
+
+pub(crate) fn describe_chain(chain: &[FilterStep]) -> String {
+    chain.iter().map(FilterStep::describe).collect::<Vec<_>>().join(" > ")
+}
+
+// everything needed to build a filter again, none of its results: the steps in order, the
+// origin, the context and whether it shows whole records. written as a few lines of ascii
+// so a session plugin can keep it anywhere, bytes that wouldn't survive that are escaped
+// (\\ \t \n \r \xNN).
+//
+//     juanlog-filter 1
+//     origin 1
+//     context 2 2
+//     records
+//     step 0 0 ERROR
+//     step 3 1 health.?check
+//
+// a step is mode (SearchMode numbers), exclude (0/1) and the query, the rest of the line.
+// a combined filter's first step is its two parts' steps, nested (op as in Combine):
+//
+//     combine 0
+//     step 0 0 ERROR
+//     with
+//     step 0 0 db
+//     end
+//     step 2 1 *retry*
+pub(crate) struct FilterSetup {
+    pub(crate) chain: Vec<FilterStep>,
+    pub(crate) origin: Origin,
+    pub(crate) context: Option<(usize, usize)>,
+    pub(crate) records: bool,
+}
+
+const SETUP_HEADER: &str = "juanlog-filter 1";
+
+impl FilterSetup {
+    pub(crate) fn to_text(&self) -> String {
+        let mut out = format!("{}\norigin {}\n", SETUP_HEADER, self.origin as u32);
+        if let Some((before, after)) = self.context {
+            out.push_str(&format!("context {} {}\n", before, after));
+        }
+        if self.records {
+            out.push_str("records\n");
+        }
+        write_chain(&mut out, &self.chain);
+        out
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(SETUP_HEADER) {
+            return Err("not a filter setup".to_string());
+        }
+        let mut setup = FilterSetup {
+            chain: Vec::new(),
+            origin: Origin::Any,
+            context: None,
+            records: false,
+        };
+        // combines still open: the chain they go in front of, their op and first part once
+        // `with` is through. steps always go on `chain`.
+        let mut open: Vec<(Vec<FilterStep>, Combine, Option<Vec<FilterStep>>)> = Vec::new();
+        let mut chain = Vec::new();
+        for line in lines.filter(|l| !l.is_empty()) {
+            let bad = || format!("bad line: {}", line);
+            let mut parts = line.splitn(4, ' ');
+            let number = |part: Option<&str>| part.and_then(|p| p.parse::<u32>().ok()).ok_or_else(bad);
+            match parts.next() {
+                Some("origin") if open.is_empty() => {
+                    setup.origin = Origin::from_raw(number(parts.next())?).ok_or_else(bad)?;
+                }
+                Some("context") if open.is_empty() => {
+                    let before = number(parts.next())? as usize;
+                    setup.context = Some((before, number(parts.next())? as usize));
+                }
+                Some("records") if open.is_empty() => setup.records = true,
+                Some("step") => {
+                    let mode = SearchMode::from_raw(number(parts.next())?).ok_or_else(bad)?;
+                    let exclude = match number(parts.next())? {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(bad()),
+                    };
+                    let query = unescape(parts.next().unwrap_or("")).ok_or_else(bad)?;
+                    chain.push(FilterStep { mode, query, exclude, combined: None });
+                }
+                // only ever first in its chain, that's the one place replay builds it
+                Some("combine") if chain.is_empty() => {
+                    let op = Combine::from_raw(number(parts.next())?).ok_or_else(bad)?;
+                    open.push((Vec::new(), op, None));
+                }
+                Some("with") => match open.last_mut() {
+                    Some((_, _, first @ None)) if !chain.is_empty() => *first = Some(std::mem::take(&mut chain)),
+                    _ => return Err(bad()),
+                },
+                Some("end") => match open.pop() {
+                    Some((outer, op, Some(first))) if !chain.is_empty() => {
+                        let parts = [first, std::mem::replace(&mut chain, outer)];
+                        let combined = Some(Arc::new(Combined { op, parts }));
+                        chain.push(FilterStep { mode: SearchMode::Literal, query: Vec::new(), exclude: false, combined });
+                    }
+                    _ => return Err(bad()),
+                },
+                _ => return Err(bad()),
+            }
+        }
+        if !open.is_empty() {
+            return Err("unfinished combine".to_string());
+        }
+        if chain.is_empty() {
+            return Err("no steps".to_string());
+        }
+        setup.chain = chain;
+        Ok(setup)
+    }
+}
+
+fn write_chain(out: &mut String, chain: &[FilterStep]) {
+    for step in chain {
+        if let Some(combined) = &step.combined {
+            out.push_str(&format!("combine {}\n", combined.op as u32));
+            write_chain(out, &combined.parts[0]);
+            out.push_str("with\n");
+            write_chain(out, &combined.parts[1]);
+            out.push_str("end\n");
+            continue;
+        }
+        out.push_str(&format!("step {} {} ", step.mode as u32, step.exclude as u32));
+        for &b in &step.query {
+            match b {
+                b'\\' => out.push_str("\\\\"),
+                b'\t' => out.push_str("\\t"),
+                b'\n' => out.push_str("\\n"),
+                b'\r' => out.push_str("\\r"),
+                0x20..=0x7e => out.push(b as char),
+                _ => out.push_str(&format!("\\x{:02x}", b)),
+            }
+        }
+        out.push('\n');
+    }
+}
+
+fn unescape(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut bytes = text.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        out.push(match bytes.next()? {
+            b'\\' => b'\\',
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b'x' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?
+            }
+            _ => return None,
+        });
+    }
+    Some(out)
+}
+
+enum Hits {
+    Original { start_line: usize, count: usize },
+    Memory(Vec<usize>), // offsets inside the piece that match
+}
+
+// which lines a filter takes its hits from: the file as it is on disk, the lines edited
+// this session, the lines read back from the rotation target (see Engine::reopen), or all
+// of them. annotations added by hand can keep out of an investigation that way, or be the
+// only thing listed.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Origin {
+    Any = 0,
+    Original = 1,
+    Edited = 2,
+    Rotated = 3,
+}
+
+impl Origin {
+    pub(crate) fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0 => Some(Origin::Any),
+            1 => Some(Origin::Original),
+            2 => Some(Origin::Edited),
+            3 => Some(Origin::Rotated),
+            _ => None,
+        }
+    }
+}
+
+// what a filter row shows
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Row {
+    Line(usize),
+    Separator, // between two runs of context that don't touch, like grep's `--`
+}
+
+// grep -B/-A: every hit brings `before`/`after` neighbouring lines along. overlapping or
+// touching runs merge, the rest get a separator row in between. the hits are walked once
+// per rebuild (after growth only the new ones), which makes this one a Vec entry per run
+// instead of pure rank/select. with
+// `records` a hit is widened to the multi-line entry it's part of before that, see
+// Engine::record_bounds.
+struct Context {
+    before: usize,
+    after: usize,
+    records: bool,
+    runs: Vec<(usize, usize)>, // (first logical line, line count)
+    run_rows: Vec<usize>,      // rows before each run, its separator included
+    total: usize,
+}
+
+// one per piece that has at least one hit
+struct Segment {
+    first_line: usize, // logical line where the piece starts
+    first_row: usize,  // filter rows before it
+    line_count: usize,
+    hits: Hits,
+}
+
+pub(crate) struct FilterView {
+    pub(crate) index: Arc<MatchIndex>,
+    pub(crate) chain: Vec<FilterStep>, // every step it was narrowed by, in order
+    pub(crate) edits: u64, // engine edit counter the layout was built against
+    pub(crate) origin: Origin,
+    segments: Vec<Segment>,
+    total: usize,
+    context: Option<Context>,
+}
+
+impl FilterView {
+    pub(crate) fn new(
+        index: Arc<MatchIndex>,
+        chain: Vec<FilterStep>,
+        pieces: &[Piece],
+        memory: &[String],
+        rotated: usize,
+        edits: u64,
+    ) -> Self {
+        let mut view = FilterView {
+            index,
+            chain,
+            edits,
+            origin: Origin::Any,
+            segments: Vec::new(),
+            total: 0,
+            context: None,
+        };
+        view.rebuild(pieces, memory, rotated, edits);
+        view
+    }
+
+    // `rotated` is the engine's rotated_lines: the first logical lines, in memory but not
+    // edited, that an origin tells apart from the rest
+    pub(crate) fn rebuild(&mut self, pieces: &[Piece], memory: &[String], rotated: usize, edits: u64) {
+        self.rebuild_from(pieces, memory, rotated, edits, 0, |line, _| (line, line));
+    }
+
+    // rebuild when nothing before logical line `from` changed (lines appended on disk): the
+    // context runs up there are kept, only the ones new hits can reach are laid out again.
+    // `record` as in set_grouping, for a filter with records on.
+    pub(crate) fn rebuild_from(
+        &mut self,
+        pieces: &[Piece],
+        memory: &[String],
+        rotated: usize,
+        edits: u64,
+        from: usize,
+        record: impl FnMut(usize, usize) -> (usize, usize),
+    ) {
+        self.segments.clear();
+        self.total = 0;
+        self.edits = edits;
+        let mut first_line = 0;
+        for piece in pieces {
+            let line_count = piece.line_count();
+            let hits = match piece {
+                Piece::Original { .. } if matches!(self.origin, Origin::Edited | Origin::Rotated) => Hits::Memory(Vec::new()),
+                Piece::Memory { .. } if self.origin == Origin::Original => Hits::Memory(Vec::new()),
+                Piece::Original { start_line, line_count } => Hits::Original {
+                    start_line: *start_line,
+                    count: self.index.count_range(*start_line, start_line + line_count),
+                },
+                Piece::Memory { start_idx, line_count } => Hits::Memory(
+                    (0..*line_count)
+                        .filter(|i| match self.origin {
+                            Origin::Edited => first_line + i >= rotated,
+                            Origin::Rotated => first_line + i < rotated,
+                            _ => true,
+                        })
+                        .filter(|i| self.index.matcher.is_match(memory[start_idx + i].as_bytes()))
+                        .collect(),
+                ),
+            };
+            let count = match &hits {
+                Hits::Original { count, .. } => *count,
+                Hits::Memory(offsets) => offsets.len(),
+            };
+            if count > 0 {
+                self.segments.push(Segment {
+                    first_line,
+                    first_row: self.total,
+                    line_count,
+                    hits,
+                });
+                self.total += count;
+            }
+            first_line += line_count;
+        }
+        // records need the lines, after a plain rebuild the engine groups them again
+        if let Some(context) = &self.context {
+            let (before, after, records) = (context.before, context.after, context.records);
+            self.group_from(before, after, records, first_line, from, record);
+        }
+    }
+
+    pub(crate) fn setup(&self) -> FilterSetup {
+        FilterSetup {
+            chain: self.chain.clone(),
+            origin: self.origin,
+            context: self.context(),
+            records: self.records(),
+        }
+    }
+
+    pub(crate) fn set_origin(&mut self, origin: Origin, pieces: &[Piece], memory: &[String], rotated: usize, edits: u64) {
+        if self.origin != origin {
+            self.origin = origin;
+            self.rebuild(pieces, memory, rotated, edits);
+        }
+    }
+
+    // 0/0 goes back to plain hits. `total_lines` is the logical line count, runs stop there.
+    // with records on they stay on, regrouped by the engine after this.
+    pub(crate) fn set_context(&mut self, before: usize, after: usize, total_lines: usize) {
+        self.set_grouping(before, after, self.records(), total_lines, |line, _| (line, line));
+    }
+
+    // set_context with every hit widened first: `record` gets a hit and the first line not
+    // in a run yet, and gives back the (first, last) lines to show for it. with `records`
+    // off it's left alone, 0/0 without records goes back to plain hits.
+    pub(crate) fn set_grouping(
+        &mut self,
+        before: usize,
+        after: usize,
+        records: bool,
+        total_lines: usize,
+        record: impl FnMut(usize, usize) -> (usize, usize),
+    ) {
+        self.group_from(before, after, records, total_lines, 0, record);
+    }
+
+    // set_grouping that keeps the runs no hit at or after `from` can touch, when the
+    // grouping is the same as before: a run ending more than `before` lines above `from`.
+    // only the hits past those are walked.
+    fn group_from(
+        &mut self,
+        before: usize,
+        after: usize,
+        records: bool,
+        total_lines: usize,
+        from: usize,
+        mut record: impl FnMut(usize, usize) -> (usize, usize),
+    ) {
+        let old = self.context.take();
+        if before == 0 && after == 0 && !records {
+            return;
+        }
+        let (mut runs, mut run_rows) = match old {
+            Some(c) if (c.before, c.after, c.records) == (before, after, records) => (c.runs, c.run_rows),
+            _ => (Vec::new(), Vec::new()),
+        };
+        let kept = runs.partition_point(|&(first, count)| first + count < from.saturating_sub(before));
+        runs.truncate(kept);
+        run_rows.truncate(kept);
+        let walked = runs.last().map_or(0, |&(first, count)| self.hit_rows_before(first + count));
+        for row in walked..self.total {
+            let Some(line) = self.hit_line(row) else {
+                break;
+            };
+            let covered = runs.last().map_or(0, |&(first, count)| first + count);
+            if records && line < covered {
+                continue; // a hit further down a record already shown
+            }
+            let (first, last) = if records { record(line, covered) } else { (line, line) };
+            let start = first.saturating_sub(before);
+            let end = last.saturating_add(after).saturating_add(1).min(total_lines);
+            match runs.last_mut() {
+                Some((first, count)) if start <= *first + *count => *count = end.max(*first + *count) - *first,
+                _ => runs.push((start, end - start)),
+            }
+        }
+        let mut total = match kept.checked_sub(1) {
+            Some(last) => run_rows[last] + runs[last].1 + (last > 0) as usize,
+            None => 0,
+        };
+        for (i, (_, count)) in runs.iter().enumerate().skip(kept) {
+            run_rows.push(total);
+            total += count + (i > 0) as usize;
+        }
+        self.context = Some(Context {
+            before,
+            after,
+            records,
+            runs,
+            run_rows,
+            total,
+        });
+    }
+
+    // before/after, None without context lines
+    pub(crate) fn context(&self) -> Option<(usize, usize)> {
+        self.context.as_ref().map(|c| (c.before, c.after)).filter(|&(before, after)| before > 0 || after > 0)
+    }
+
+    pub(crate) fn records(&self) -> bool {
+        self.context.as_ref().is_some_and(|c| c.records)
+    }
+
+    // closed but kept: the rows go, the bits are packed. `total_lines` is how many
+    // original lines they cover, in case the file grows while it's parked.
+    pub(crate) fn park(self, total_lines: usize) -> ParkedFilter {
+        let (context, records) = (self.context(), self.records());
+        ParkedFilter {
+            chain: self.chain,
+            matcher: self.index.matcher.clone(),
+            bits: self.index.pack(),
+            total_lines,
+            origin: self.origin,
+            context,
+            records,
+        }
+    }
+
+    pub(crate) fn total(&self) -> usize {
+        self.context.as_ref().map_or(self.total, |c| c.total)
+    }
+
+    // matching lines, context rows and separators not counted
+    pub(crate) fn hits(&self) -> usize {
+        self.total
+    }
+
+    pub(crate) fn row(&self, row: usize) -> Option<Row> {
+        let Some(context) = &self.context else {
+            return self.hit_line(row).map(Row::Line);
+        };
+        if row >= context.total {
+            return None;
+        }
+        let idx = context.run_rows.partition_point(|&r| r <= row) - 1;
+        let mut offset = row - context.run_rows[idx];
+        if idx > 0 {
+            if offset == 0 {
+                return Some(Row::Separator);
+            }
+            offset -= 1;
+        }
+        Some(Row::Line(context.runs[idx].0 + offset))
+    }
+
+    // filter row -> logical line, None for separators too
+    pub(crate) fn line_at(&self, row: usize) -> Option<usize> {
+        match self.row(row)? {
+            Row::Line(line) => Some(line),
+            Row::Separator => None,
+        }
+    }
+
+    // how many rows sit on logical lines before `line`. also the row of the first
+    // filtered line at or after it.
+    pub(crate) fn rows_before(&self, line: usize) -> usize {
+        let Some(context) = &self.context else {
+            return self.hit_rows_before(line);
+        };
+        let idx = context.runs.partition_point(|&(first, count)| first + count <= line);
+        let Some(&(first, _)) = context.runs.get(idx) else {
+            return context.total;
+        };
+        context.run_rows[idx] + (idx > 0) as usize + line.saturating_sub(first)
+    }
+
+    // n-th hit -> logical line, context left out
+    fn hit_line(&self, row: usize) -> Option<usize> {
+        if row >= self.total {
+            return None;
+        }
+        let seg = &self.segments[self.segments.partition_point(|s| s.first_row <= row) - 1];
+        let k = row - seg.first_row;
+        match &seg.hits {
+            Hits::Original { start_line, .. } => {
+                let hit = self.index.nth_hit(*start_line, k)?;
+                Some(seg.first_line + hit - start_line)
+            }
+            Hits::Memory(offsets) => Some(seg.first_line + offsets[k]),
+        }
+    }
+
+    fn hit_rows_before(&self, line: usize) -> usize {
+        let Some(idx) = self.segments.partition_point(|s| s.first_line <= line).checked_sub(1) else {
+            return 0;
+        };
+        let seg = &self.segments[idx];
+        let offset = (line - seg.first_line).min(seg.line_count);
+        seg.first_row
+            + match &seg.hits {
+                Hits::Original { start_line, .. } => self.index.count_range(*start_line, start_line + offset),
+                Hits::Memory(offsets) => offsets.partition_point(|&o| o < offset),
+            }
+    }
+}
+
+// a filter still being scanned, a slice per log_engine_filter_step. it has its handle
+// already but no rows until the scan is done and it becomes a FilterView.
+pub(crate) struct PendingFilter {
+    pub(crate) chain: Vec<FilterStep>,
+    pub(crate) matcher: Matcher,
+    pub(crate) index: PartialIndex,
+    pub(crate) cache_key: Option<(SearchMode, Vec<u8>)>, // plain searches go in the match cache when done
+    pub(crate) origin: Origin,
+}
+
+// a filter that was switched off. turning it back on is an unpack, no line gets looked at.
+pub(crate) struct ParkedFilter {
+    pub(crate) chain: Vec<FilterStep>,
+    pub(crate) matcher: Matcher,
+    pub(crate) bits: PackedBits,
+    pub(crate) total_lines: usize,
+    pub(crate) origin: Origin,
+    pub(crate) context: Option<(usize, usize)>, // before, after
+    pub(crate) records: bool,
+}
+
+impl ParkedFilter {
+    pub(crate) fn setup(&self) -> FilterSetup {
+        FilterSetup {
+            chain: self.chain.clone(),
+            origin: self.origin,
+            context: self.context,
+            records: self.records,
+        }
+    }
+}
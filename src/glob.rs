@@ -0,0 +1,125 @@
+use memchr::memmem;
+
+// `conn-*-failed`, `user=??? `: * is any run of characters, ? is exactly one.
+// unanchored like every other search here, so the pattern can sit anywhere in the line.
+// \* and \? match the literal characters.
+
+#[derive(Debug, Clone)]
+enum Token {
+    Literal(Vec<u8>),
+    AnyChar,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Glob {
+    // split on '*', each segment has to show up in order
+    segments: Vec<Vec<Token>>,
+}
+
+impl Glob {
+    pub(crate) fn new(pattern: &[u8]) -> Self {
+        let mut segments = vec![Vec::new()];
+        let mut literal = Vec::new();
+        let mut bytes = pattern.iter().copied();
+
+        let flush = |literal: &mut Vec<u8>, segment: &mut Vec<Token>| {
+            if !literal.is_empty() {
+                segment.push(Token::Literal(std::mem::take(literal)));
+            }
+        };
+
+        while let Some(b) = bytes.next() {
+            let segment = segments.last_mut().unwrap();
+            match b {
+                b'\\' => literal.push(bytes.next().unwrap_or(b'\\')),
+                b'*' => {
+                    flush(&mut literal, segment);
+                    segments.push(Vec::new());
+                }
+                b'?' => {
+                    flush(&mut literal, segment);
+                    segment.push(Token::AnyChar);
+                }
+                _ => literal.push(b),
+            }
+        }
+        flush(&mut literal, segments.last_mut().unwrap());
+        segments.retain(|s| !s.is_empty());
+        Glob { segments }
+    }
+
+    // leftmost match as a byte range. leftmost-first per segment is enough since both
+    // ends float: if a later segment can't fit after the earliest spot, it can't fit after
+    // any later one either.
+    pub(crate) fn find(&self, line: &[u8]) -> Option<(usize, usize)> {
+        let mut pos = 0;
+        let mut start = None;
+        for segment in &self.segments {
+            let (seg_start, seg_end) = find_segment(segment, line, pos)?;
+            start.get_or_insert(seg_start);
+            pos = seg_end;
+        }
+        Some((start.unwrap_or(0), pos))
+    }
+
+    pub(crate) fn is_match(&self, line: &[u8]) -> bool {
+        self.find(line).is_some()
+    }
+}
+
+fn find_segment(segment: &[Token], line: &[u8], from: usize) -> Option<(usize, usize)> {
+    // jump between candidates with memmem when the segment opens with a literal
+    let first_literal = match segment.first() {
+        Some(Token::Literal(lit)) => Some(memmem::Finder::new(lit)),
+        _ => None,
+    };
+    let mut candidate = from;
+    while candidate <= line.len() {
+        if let Some(finder) = &first_literal {
+            candidate += finder.find(&line[candidate..])?;
+        }
+        if let Some(end) = match_at(segment, line, candidate) {
+            return Some((candidate, end));
+        }
+        candidate += char_len(line, candidate).max(1);
+    }
+    None
+}
+
+fn match_at(segment: &[Token], line: &[u8], mut pos: usize) -> Option<usize> {
+    for token in segment {
+        match token {
+            Token::Literal(lit) => {
+                if !line[pos..].starts_with(lit) {
+                    return None;
+                }
+                pos += lit.len();
+            }
+            Token::AnyChar => {
+                if pos >= line.len() {
+                    return None;
+                }
+                pos += char_len(line, pos);
+            }
+        }
+    }
+    Some(pos)
+}
+
+// width of the utf8 char starting at pos. garbage bytes count as one char each.
+fn char_len(line: &[u8], pos: usize) -> usize {
+    let Some(&b) = line.get(pos) else {
+        return 0;
+    };
+    let len = match b {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => 1,
+    };
+    if pos + len <= line.len() && line[pos + 1..pos + len].iter().all(|c| c & 0xC0 == 0x80) {
+        len
+    } else {
+        1
+    }
+}
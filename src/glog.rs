@@ -0,0 +1,77 @@
+use crate::fields;
+use std::borrow::Cow;
+
+// google's glog and abseil's logging:
+//
+//   I0321 14:02:11.123456    4242 server.cc:88] listening on :8080
+//
+// the severity's first letter, month and day, the time, the thread id (padded), where in the
+// source it was logged, then the message. no year, like syslog.
+
+pub(crate) struct Glog<'a> {
+    pub(crate) level: &'static str,
+    pub(crate) time: &'a str, // "0321 14:02:11.123456"
+    pub(crate) thread: &'a str,
+    pub(crate) location: &'a str, // "server.cc:88"
+    pub(crate) msg: &'a str,
+}
+
+pub(crate) fn parse(line: &str) -> Option<Glog<'_>> {
+    let level = match line.as_bytes().first()? {
+        b'I' => "info",
+        b'W' => "warn",
+        b'E' => "error",
+        b'F' => "fatal",
+        _ => return None,
+    };
+    let (date, rest) = line[1..].split_once(' ')?;
+    if date.len() != 4 || !date.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (clock, rest) = rest.split_once(' ')?;
+    if clock.len() < 8 || !clock.bytes().all(|b| b.is_ascii_digit() || matches!(b, b':' | b'.')) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let thread_end = rest.find(|c: char| !c.is_ascii_alphanumeric()).filter(|&end| end > 0)?;
+    let (thread, rest) = rest.split_at(thread_end);
+    let (location, msg) = rest.trim_start().split_once("] ").or_else(|| Some((rest.trim_start().strip_suffix(']')?, "")))?;
+    let (_, number) = location.rsplit_once(':')?;
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(Glog {
+        level,
+        time: &line[1..1 + date.len() + 1 + clock.len()],
+        thread,
+        location,
+        msg,
+    })
+}
+
+impl<'a> Glog<'a> {
+    pub(crate) fn get(&self, name: &str) -> Option<&'a str> {
+        let value = match name {
+            "ts" | "time" | "timestamp" => self.time,
+            "thread" | "tid" => self.thread,
+            "location" | "caller" | "source" => self.location,
+            "file" => self.location.rsplit_once(':')?.0,
+            "line" => self.location.rsplit_once(':')?.1,
+            "msg" | "message" => self.msg,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    // every field, the known parts first and then pairs in the message
+    pub(crate) fn pairs(&self) -> Vec<(&'a str, Cow<'a, str>)> {
+        let mut out: Vec<(&'a str, Cow<'a, str>)> = vec![("level", self.level.into())];
+        for name in ["time", "thread", "location", "file", "line", "msg"] {
+            if let Some(value) = self.get(name) {
+                out.push((name, value.into()));
+            }
+        }
+        out.extend(fields::text_pairs(self.msg));
+        out
+    }
+}
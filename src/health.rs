@@ -0,0 +1,82 @@
+use crate::summary::json_str;
+use crate::time::Format;
+use crate::{diag, ops, registry, Engine, Piece, ABI_VERSION};
+
+// what :checkhealth shows for a log buffer, and what to paste into a bug report: how the
+// file is mapped, how far the index got, what the caches hold, what's still running. one
+// JSON object, flat enough for the plugin to print line by line without knowing every key.
+
+pub(crate) fn report(engine: &Engine) -> String {
+    let stats = engine.stats();
+    let madvise = match engine.advised {
+        Some(true) => "random",
+        Some(false) => "failed",
+        None => "none",
+    };
+    // only once something asked for times, the report doesn't build the index itself
+    let time_format = match engine.times.as_ref().map(|times| times.format()) {
+        None => "unindexed",
+        Some(None) => "none",
+        Some(Some(Format::Iso)) => "iso",
+        Some(Some(Format::Epoch)) => "epoch",
+        Some(Some(Format::Syslog)) => "syslog",
+        Some(Some(Format::Glog)) => "glog",
+    };
+    let cached_bytes = engine.match_cache.weight(|index| index.heap_bytes());
+    let memory_bytes: usize = engine
+        .pieces
+        .iter()
+        .filter(|piece| matches!(piece, Piece::Memory { .. }))
+        .map(|piece| engine.piece_bytes(piece))
+        .sum();
+    let threads = engine.options.pool.as_ref().map_or(0, |pool| pool.current_num_threads());
+    let misuse = diag::with_last_misuse(str::to_string);
+
+    let mut out = String::from("{");
+    let mut field = |key: &str, value: String| {
+        if out.len() > 1 {
+            out.push(',');
+        }
+        out.push_str(&format!("{}:{}", json_str(key), value));
+    };
+    field("abi", ABI_VERSION.to_string());
+    field("path", json_str(&engine.watch.path().to_string_lossy()));
+    field("mapped_bytes", engine.mmap.len().to_string());
+    field("file_size", stats.file_size.to_string());
+    field("madvise", json_str(madvise));
+    field("chunks", engine.chunks.len().to_string());
+    field("chunk_size", engine.options.chunk_size.to_string());
+    field("indexed_percent", format!("{:.1}", stats.indexed_percent));
+    field("original_lines", engine.original_total_lines.to_string());
+    field("total_lines", stats.total_lines.to_string());
+    field("pieces", stats.pieces.to_string());
+    field("memory_lines", stats.memory_lines.to_string());
+    field("memory_bytes", memory_bytes.to_string());
+    field("edits", engine.edits.to_string());
+    field("level_index", engine.levels.is_some().to_string());
+    field("time_format", json_str(time_format));
+    field("cached_searches", engine.match_cache.len().to_string());
+    field("cached_bytes", cached_bytes.to_string());
+    field("max_memory", engine.options.max_memory.to_string());
+    field("cache_ttl_secs", engine.cache_ttl.as_secs().to_string());
+    field("filters", engine.filters.len().to_string());
+    field("parked_filters", engine.parked.len().to_string());
+    field("pending_filters", engine.pending.len().to_string());
+    field("snapshots", engine.snapshots.len().to_string());
+    field("threads", threads.to_string());
+    field("native_watch", engine.watch.native().is_some().to_string());
+    field("paused", engine.paused.to_string());
+    field("pending_bytes", engine.pending_bytes().to_string());
+    field("pipe", engine.pipe.is_some().to_string());
+    field("io_retries", engine.retry.retries.to_string());
+    field("read_only", engine.options.read_only.to_string());
+    field("journal", engine.journal.to_string());
+    field("frozen", engine.frozen.is_some().to_string());
+    // the library as a whole, not just this engine
+    field("engines", registry::count().to_string());
+    field("pending_ops", ops::pending().to_string());
+    field("misuses", diag::misuse_count().to_string());
+    field("last_misuse", json_str(&misuse));
+    out.push('}');
+    out
+}
@@ -0,0 +1,53 @@
+use crate::matcher::SearchMode;
+use std::collections::VecDeque;
+
+// most recent first. repeated queries get bumped to the front instead of duplicated.
+const HISTORY_CAPACITY: usize = 64;
+
+pub(crate) struct HistoryEntry {
+    pub(crate) mode: SearchMode,
+    pub(crate) query: Vec<u8>,
+    pub(crate) hits: Option<usize>, // matching lines the last time this query was counted
+}
+
+pub(crate) struct SearchHistory {
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl SearchHistory {
+    pub(crate) fn new() -> Self {
+        SearchHistory {
+            entries: VecDeque::new(),
+        }
+    }
+
+    // None for hits that aren't counted yet, a query already in keeps the count it had
+    pub(crate) fn record(&mut self, mode: SearchMode, query: &[u8], hits: Option<usize>) {
+        let mut before = None;
+        if let Some(pos) = self.entries.iter().position(|e| e.mode == mode && e.query == query) {
+            before = self.entries.remove(pos).and_then(|e| e.hits);
+        }
+        self.entries.push_front(HistoryEntry {
+            mode,
+            query: query.to_vec(),
+            hits: hits.or(before),
+        });
+        self.entries.truncate(HISTORY_CAPACITY);
+    }
+
+    // the count of a query that went in without one, now that there is one. where it is in
+    // the list doesn't change, nobody searched it again.
+    pub(crate) fn counted(&mut self, mode: SearchMode, query: &[u8], hits: usize) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.mode == mode && e.query == query) {
+            entry.hits = Some(hits);
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> Option<&HistoryEntry> {
+        self.entries.get(idx)
+    }
+}
@@ -0,0 +1,127 @@
+use crate::ops;
+use crate::zone;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// `journalctl -o export`: one record per entry, a field per line (`KEY=value`), records
+// separated by an empty line. a value that isn't plain text (line breaks, binary) comes as
+// `KEY\n`, its length as 64-bit little endian, the raw bytes and a line break. that's not
+// something to page through, so the file is rewritten into a line per record:
+//
+//   2024-01-02T03:04:05.123456Z PRIORITY=6 _HOSTNAME=web1 SYSLOG_IDENTIFIER=sshd MESSAGE="..." ...
+//
+// the stamp in front and logfmt after it, so time lookups, fields and queries work on it as
+// on any other log. the rewrite is a copy, the engine on it is a viewer.
+
+// the fields people look at first, in this order, before the rest as they come
+const LEADING: &[&str] = &["PRIORITY", "_HOSTNAME", "SYSLOG_IDENTIFIER", "_SYSTEMD_UNIT", "_PID", "MESSAGE"];
+// too long to be worth reading, and the stamp already says when
+const DROPPED: &[&str] = &["__CURSOR", "__REALTIME_TIMESTAMP"];
+// records between looks at the cancel flag
+const CANCEL_EVERY: usize = 4096;
+
+pub(crate) fn is_export(data: &[u8]) -> bool {
+    data.starts_with(b"__CURSOR=")
+}
+
+// writes a line per record to `out`, stops with ops::cancelled_error once `cancel` is set
+pub(crate) fn convert(data: &[u8], out: &mut impl Write, cancel: &AtomicBool) -> io::Result<()> {
+    let mut pos = 0;
+    let mut fields: Vec<(&str, Vec<u8>)> = Vec::new();
+    let mut records = 0;
+    while pos < data.len() {
+        let line_end = memchr::memchr(b'\n', &data[pos..]).map_or(data.len(), |at| pos + at);
+        let line = &data[pos..line_end];
+        pos = line_end + 1;
+        if line.is_empty() {
+            write_record(&fields, out)?;
+            fields.clear();
+            records += 1;
+            if records % CANCEL_EVERY == 0 && cancel.load(Ordering::Relaxed) {
+                return Err(ops::cancelled_error());
+            }
+            continue;
+        }
+        match memchr::memchr(b'=', line) {
+            Some(eq) => fields.push((key(&line[..eq]), line[eq + 1..].to_vec())),
+            None => {
+                // binary: the length, the bytes, a line break
+                let Some(len) = data.get(pos..pos + 8) else { break };
+                let len = u64::from_le_bytes(len.try_into().unwrap_or_default()) as usize;
+                let value = data.get(pos + 8..).map_or(&[][..], |rest| &rest[..len.min(rest.len())]);
+                fields.push((key(line), value.to_vec()));
+                pos += 8 + value.len() + 1;
+            }
+        }
+    }
+    if !fields.is_empty() {
+        write_record(&fields, out)?;
+    }
+    Ok(())
+}
+
+// field names are uppercase ascii, anything else was never a field
+fn key(bytes: &[u8]) -> &str {
+    std::str::from_utf8(bytes).unwrap_or("?")
+}
+
+fn write_record(fields: &[(&str, Vec<u8>)], out: &mut impl Write) -> io::Result<()> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+    let mut line = String::new();
+    let realtime = fields.iter().find(|(key, _)| *key == "__REALTIME_TIMESTAMP");
+    if let Some(micros) = realtime.and_then(|(_, value)| std::str::from_utf8(value).ok()?.parse::<i64>().ok()) {
+        line.push_str(&stamp(micros));
+    }
+    let leading = LEADING.iter().filter_map(|name| fields.iter().find(|(key, _)| key == name));
+    let rest = fields.iter().filter(|(key, _)| !LEADING.contains(key) && !DROPPED.contains(key));
+    for (key, value) in leading.chain(rest) {
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(key);
+        line.push('=');
+        push_value(&mut line, &String::from_utf8_lossy(value));
+    }
+    line.push('\n');
+    out.write_all(line.as_bytes())
+}
+
+// logfmt: bare when it can be, quoted with \" \\ \n \t escapes when it can't
+fn push_value(line: &mut String, value: &str) {
+    let bare = !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=' || c == '\\');
+    if bare {
+        line.push_str(value);
+        return;
+    }
+    line.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\t' => line.push_str("\\t"),
+            '\r' => {}
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+// microseconds since the epoch -> "2024-01-02T03:04:05.123456Z"
+fn stamp(micros: i64) -> String {
+    let (secs, micros) = (micros.div_euclid(1_000_000), micros.rem_euclid(1_000_000));
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let (year, month, day) = zone::civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+        micros
+    )
+}
@@ -0,0 +1,107 @@
+// json lines (ndjson): one object per line, unreadable once they get long. a line can be
+// expanded into the object pretty-printed over several lines and collapsed back. the
+// pretty lines are ordinary memory lines in the piece table, so search, filters and
+// scrolling see them like any other; what the engine remembers is the line they came
+// from, which is what gets saved and what collapsing puts back.
+
+// lines looked at to tell whether a file is json lines, nine in ten of them must be objects
+const SAMPLE: usize = 20;
+const INDENT: &str = "  ";
+
+// a line shown expanded
+pub(crate) struct Expansion {
+    pub(crate) start: usize, // logical line of the first pretty line
+    pub(crate) count: usize,
+    pub(crate) text: String,            // the line as it was
+    pub(crate) original: Option<usize>, // its line in the file when it was never edited
+}
+
+// whether the first non-empty lines are (nearly) all json objects
+pub(crate) fn looks_like_json_lines<'a>(lines: impl Iterator<Item = &'a str>) -> bool {
+    let (mut seen, mut json) = (0, 0);
+    for line in lines.filter(|line| !line.trim().is_empty()).take(SAMPLE) {
+        seen += 1;
+        json += (line.trim_start().starts_with('{') && pretty(line).is_some()) as usize;
+    }
+    seen > 0 && json * 10 >= seen * 9
+}
+
+// the object or array in `line` pretty-printed, two spaces per level. None when the line
+// isn't one (brackets that don't match, an unterminated string, text around it).
+pub(crate) fn pretty(line: &str) -> Option<Vec<String>> {
+    let line = line.trim();
+    if !line.starts_with(['{', '[']) {
+        return None;
+    }
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut open: Vec<char> = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some((at, c)) = chars.next() {
+        if open.is_empty() && at > 0 {
+            return None; // something after the value
+        }
+        match c {
+            '"' => {
+                current.push(c);
+                let mut escaped = false;
+                loop {
+                    let (_, c) = chars.next()?; // runs out: unterminated string
+                    current.push(c);
+                    match c {
+                        '\\' if !escaped => escaped = true,
+                        '"' if !escaped => break,
+                        _ => escaped = false,
+                    }
+                }
+            }
+            '{' | '[' => {
+                let close = if c == '{' { '}' } else { ']' };
+                while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+                if chars.next_if(|&(_, next)| next == close).is_some() {
+                    current.push(c);
+                    current.push(close); // {} and [] stay on one line
+                    if open.is_empty() {
+                        return chars.next().is_none().then(|| vec![current]);
+                    }
+                    continue;
+                }
+                current.push(c);
+                open.push(close);
+                lines.push(std::mem::replace(&mut current, INDENT.repeat(open.len())));
+            }
+            '}' | ']' => {
+                if open.pop()? != c {
+                    return None;
+                }
+                lines.push(std::mem::replace(&mut current, INDENT.repeat(open.len())));
+                current.push(c);
+            }
+            ',' => {
+                current.push(c);
+                lines.push(std::mem::replace(&mut current, INDENT.repeat(open.len())));
+            }
+            ':' => current.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => current.push(c),
+        }
+    }
+    if !open.is_empty() {
+        return None;
+    }
+    lines.push(current);
+    Some(lines)
+}
+
+// keep expansions where they are across an edit of `deleted` lines at `start` replaced by
+// `added`: ones after it move, ones it touched are plain edited lines from now on
+pub(crate) fn shift(expansions: &mut Vec<Expansion>, start: usize, deleted: usize, added: usize) {
+    expansions.retain_mut(|expansion| {
+        if expansion.start >= start + deleted {
+            expansion.start = expansion.start - deleted + added;
+            true
+        } else {
+            expansion.start + expansion.count <= start
+        }
+    });
+}
@@ -0,0 +1,122 @@
+use crate::delimited::Columns;
+use crate::fields::{self, LEVEL_COUNT};
+use crate::match_index::scan_lines;
+use crate::template::Templates;
+use crate::{pool, ChunkMeta};
+use rayon::prelude::*;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// a line with no severity at all, next to the ranks 0 (trace) .. 5 (fatal)
+pub(crate) const NONE: u8 = u8::MAX;
+
+// lines per rank, trace .. fatal, then lines without one
+pub(crate) type Counts = [u64; LEVEL_COUNT + 1];
+// ranks counted per task when adding them up
+const COUNT_SLICE: usize = 1 << 20;
+
+// severity of every original line, a byte each. built once on the first level filter or
+// level lookup, after that "warn and above" is one pass over the bytes and a block's
+// levels are a copy. a line gets the level the query `level:<that level>` would hit it on.
+// lines a ring buffer evicted (see Engine::enforce_retention) are dropped from the front.
+#[derive(Clone)]
+pub(crate) struct LevelIndex {
+    ranks: Vec<u8>, // from original line `base` on
+    base: usize,
+}
+
+impl LevelIndex {
+    pub(crate) fn build(data: &[u8], chunks: &[ChunkMeta], total_lines: usize, columns: Option<&Columns>, templates: &Templates) -> Self {
+        LevelIndex { ranks: classify_from(data, chunks, total_lines, 0, columns, templates), base: 0 }
+    }
+
+    // after the file grew: `ranks` from classify_from, for the lines from `from` on
+    pub(crate) fn extend(&mut self, from: usize, ranks: Vec<u8>) {
+        self.ranks.truncate(from.saturating_sub(self.base));
+        self.ranks.extend(ranks.into_iter().skip(self.base.saturating_sub(from)));
+    }
+
+    // forget the lines before `first_line`, they're gone for good
+    pub(crate) fn trim(&mut self, first_line: usize) {
+        if first_line > self.base {
+            self.ranks.drain(..(first_line - self.base).min(self.ranks.len()));
+            self.ranks.shrink_to_fit();
+            self.base = first_line;
+        }
+    }
+
+    // the rank of an original line, NONE when it has none (or is past the index)
+    pub(crate) fn get(&self, line: usize) -> u8 {
+        line.checked_sub(self.base).and_then(|at| self.ranks.get(at)).copied().unwrap_or(NONE)
+    }
+
+    // how many of the original lines in `lines` are at every rank, a slice per task
+    pub(crate) fn counts(&self, lines: Range<usize>) -> Counts {
+        let lines = lines.start.saturating_sub(self.base)..lines.end.saturating_sub(self.base);
+        let ranks = self.ranks.get(lines).unwrap_or_default();
+        pool::install(|| {
+            ranks
+                .par_chunks(COUNT_SLICE)
+                .map(|slice| {
+                    let mut counts = Counts::default();
+                    slice.iter().for_each(|&rank| count(&mut counts, rank));
+                    counts
+                })
+                .reduce(Counts::default, |mut a, b| {
+                    a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                    a
+                })
+        })
+    }
+
+    // lines at `min_rank` or more severe, as a bitset
+    pub(crate) fn at_least(&self, min_rank: usize) -> Vec<u64> {
+        let mut bits = vec![0u64; (self.base + self.ranks.len()).div_ceil(64)];
+        for (line, &rank) in (self.base..).zip(&self.ranks) {
+            if rank != NONE && rank as usize >= min_rank {
+                bits[line / 64] |= 1 << (line % 64);
+            }
+        }
+        bits
+    }
+}
+
+// one more line at `rank` (NONE or anything past fatal is the last slot)
+pub(crate) fn count(counts: &mut Counts, rank: u8) {
+    counts[(rank as usize).min(LEVEL_COUNT)] += 1;
+}
+
+// the rank of a line that isn't in the index (typed, or changed this session). a csv/tsv
+// row's comes off its level column
+pub(crate) fn classify(bytes: &[u8], columns: Option<&Columns>, templates: &Templates) -> Option<u8> {
+    let text = String::from_utf8_lossy(bytes);
+    let rank = match columns {
+        Some(columns) => columns.level(&text)?,
+        None => fields::line_level(templates, &text)?,
+    };
+    (rank < LEVEL_COUNT).then_some(rank as u8)
+}
+
+// the ranks of the original lines from `from` on, the part of the index a file that grew
+// needs read (the old last line too, it may have been cut off halfway)
+pub(crate) fn classify_from(
+    data: &[u8],
+    chunks: &[ChunkMeta],
+    total_lines: usize,
+    from: usize,
+    columns: Option<&Columns>,
+    templates: &Templates,
+) -> Vec<u8> {
+    let ranks: Vec<AtomicU8> = (from..total_lines).map(|_| AtomicU8::new(NONE)).collect();
+    // chunks before this one only own lines before `from`
+    let first_chunk = chunks.partition_point(|c| c.start_line < from).saturating_sub(1);
+    scan_lines(data, chunks, first_chunk..chunks.len(), total_lines, |line, bytes| {
+        if line < from {
+            return;
+        }
+        if let Some(rank) = classify(bytes, columns, templates) {
+            ranks[line - from].store(rank, Ordering::Relaxed);
+        }
+    });
+    ranks.into_iter().map(AtomicU8::into_inner).collect()
+}
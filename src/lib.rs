@@ -1,11 +1,118 @@
+// every extern "C" fn below takes raw pointers from LuaJIT and derefs them after a null check.
+// marking them all `unsafe fn` buys nothing on the C side, so silence the lint crate-wide.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+mod access;
+mod ansi;
+pub mod api;
+mod audit;
+mod binary;
+mod cache;
+mod checkpoint;
+mod delimited;
+mod diag;
+mod drain;
+mod events;
+mod fields;
+mod filter;
+mod glob;
+mod glog;
+mod health;
+mod history;
+mod journal;
+mod json;
+mod levels;
+mod match_index;
+mod matcher;
+mod msgpack;
+mod notes;
+mod open;
+mod options;
+mod ops;
+mod pipe;
+mod pool;
+mod query;
+mod registry;
+mod retry;
+mod rotate;
+pub mod rpc;
+mod summary;
+mod template;
+mod throughput;
+mod time;
+mod unwind;
+mod watch;
+mod zone;
+
+use audit::AuditLog;
+use cache::TtlCache;
+use checkpoint::{BlockHasher, Checkpoints};
+use delimited::Columns;
+use drain::Drain;
+use filter::{describe_chain, Combined, FilterSetup, FilterStep, FilterView, Origin, ParkedFilter, PendingFilter, Row};
+use history::SearchHistory;
+use json::Expansion;
+use levels::LevelIndex;
+use match_index::{Combine, Hits, Lazy, MatchIndex, PartialIndex};
+use notes::Notes;
+use pipe::Pipe;
+use options::Options;
+use retry::Retry;
+use summary::Summary;
+use throughput::Throughput;
+use template::Templates;
+use time::TimeIndex;
+use watch::Watch;
+use zone::{Conversion, Zone};
+use matcher::{Matcher, SearchMode};
 use memchr::{memchr2, memchr2_iter, memmem};
 use memmap2::Mmap;
 use rayon::prelude::*;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::CStr;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::io::{BufWriter, Write};
 use std::os::raw::c_char;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::time::{Duration, Instant};
+
+// idle match caches get dropped after this long unless the plugin says otherwise
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+// guards for span listing. "e" on a 10k line window is a few hundred thousand spans,
+// nobody reads that many highlights and the plugin chokes placing the extmarks.
+const DEFAULT_MIN_LIST_QUERY: usize = 2;
+const DEFAULT_MAX_SPANS: usize = 100_000;
+// a line without a stamp of its own takes the one of the nearest line above that has one,
+// this many lines up at most
+const TIME_LOOKBACK: usize = 4096;
+// a record (a line and its stack trace or wrapped text) reaches this many lines up and down
+// from the line asked about at most
+const RECORD_LOOKBACK: usize = 4096;
+// a time histogram has at most this many buckets, a day of seconds at one per second is
+// already more than anything draws
+const MAX_BUCKETS: usize = 100_000;
+// lines per task when a range is gone through in parallel (templates, value_counts)
+const SCAN_SLICE: usize = 100_000;
+// distinct values value_counts keeps track of, across all its slices together: past this new
+// ones stop being counted so a field holding request ids doesn't eat all the memory
+const MAX_VALUES: usize = 1_000_000;
+// lines read at a time by the histogram, so it can stop soon after the end of its range
+const HISTOGRAM_STEP: usize = 65536;
+// a projected column is never padded to more than this, longer values are cut (the last
+// column excepted, it's left whole)
+const MAX_COLUMN: usize = 40;
+// what log_engine_abi_version reports. bump it whenever the exported functions change (one
+// added, a signature or meaning changed, one gone), so the plugin refuses a stale library
+// instead of calling into it with the wrong arguments or into a symbol it lacks. only ever
+// goes up.
+const ABI_VERSION: u32 = 68;
 
 // classic piece table implementation.
 // Original = points to the readonly memory mapped file.
@@ -23,26 +130,335 @@ impl Piece {
             Piece::Memory { line_count, .. } => *line_count,
         }
     }
+
+    // the first `offset` lines and the rest, offset inside the piece
+    fn split(&self, offset: usize) -> (Piece, Piece) {
+        match *self {
+            Piece::Original { start_line, line_count } => (
+                Piece::Original { start_line, line_count: offset },
+                Piece::Original { start_line: start_line + offset, line_count: line_count - offset },
+            ),
+            Piece::Memory { start_idx, line_count } => (
+                Piece::Memory { start_idx, line_count: offset },
+                Piece::Memory { start_idx: start_idx + offset, line_count: line_count - offset },
+            ),
+        }
+    }
+}
+
+// a piece table walked front to back once, lines handed on or dropped as it goes
+struct PieceWalk {
+    pieces: std::vec::IntoIter<Piece>,
+    current: Option<Piece>, // what's left of a piece cut halfway
+}
+
+impl PieceWalk {
+    // the next `count` lines onto `out`, or gone with None
+    fn take(&mut self, mut count: usize, mut out: Option<&mut Vec<Piece>>) {
+        while count > 0 {
+            let Some(piece) = self.current.take().or_else(|| self.pieces.next()) else {
+                return;
+            };
+            let piece = if piece.line_count() > count {
+                let (head, tail) = piece.split(count);
+                self.current = Some(tail);
+                head
+            } else {
+                piece
+            };
+            count -= piece.line_count();
+            if let Some(out) = out.as_deref_mut() {
+                out.push(piece);
+            }
+        }
+    }
+
+    fn rest(self, out: &mut Vec<Piece>) {
+        out.extend(self.current);
+        out.extend(self.pieces);
+    }
+}
+
+// log_engine_refresh_delta results. numbers are part of the C ABI, don't reorder.
+const REFRESH_NOTHING: i32 = 0;
+const REFRESH_GREW: i32 = 1;
+const REFRESH_REOPENED: i32 = 2; // rotated or truncated, every line may be different
+const REFRESH_REPLACED: i32 = 3; // a longer copy of the same content took its place, edits kept
+
+// what log_engine_reopen returns and EVENT_ROTATED carries: whether the edits made it, plus a
+// bit per kind of handle the new content couldn't keep
+const REOPEN_KEPT_EDITS: i32 = 1;
+const REOPEN_CLOSED_FILTERS: i32 = 2; // a chain that no longer compiles
+const REOPEN_CLOSED_SNAPSHOTS: i32 = 4; // of a file truncated in place, their lines are gone
+
+// how much of each end of the old content reopen compares against a replacement. a byte by
+// byte compare of a few GB would hold the lock (and the plugin) for seconds, and a copy
+// that only differs in the middle is not something an editor or logrotate produces.
+const REOPEN_PROBE: usize = 64 * 1024;
+
+// how an overview picks its lines. numbers are part of the C ABI, don't reorder.
+#[derive(Clone, Copy)]
+enum SampleMode {
+    Lines = 0, // every n-th logical line
+    Bytes = 1, // the first line starting in every n bytes of the file, rounded to index chunks
+}
+
+impl SampleMode {
+    fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0 => Some(SampleMode::Lines),
+            1 => Some(SampleMode::Bytes),
+            _ => None,
+        }
+    }
 }
 
+#[derive(Clone)]
 struct ChunkMeta {
     byte_offset: usize,
     start_line: usize,
 }
 
-pub struct LogEngine {
-    mmap: Mmap,
+// the logical content frozen at one point, for reads spanning many calls (an export, a copy
+// of everything) while lines keep arriving. the mapping and memory lines are shared with the
+// engine, which copies the memory lines the first time it changes them. the piece table and
+// chunk list (one entry per MB) are copied up front.
+struct Snapshot {
+    mmap: Arc<Mmap>,
+    chunks: Vec<ChunkMeta>,
+    original_total_lines: usize,
+    pieces: Vec<Piece>,
+    memory_buffer: Arc<Vec<String>>,
+    rotated_lines: usize,
+}
+
+// whether `new` starts with `old`, by its first and last REOPEN_PROBE bytes
+fn same_start(new: &[u8], old: &[u8]) -> bool {
+    if new.len() < old.len() {
+        return false;
+    }
+    let head = old.len().min(REOPEN_PROBE);
+    let tail = old.len().saturating_sub(REOPEN_PROBE).max(head);
+    new[..head] == old[..head] && new[tail..old.len()] == old[tail..]
+}
+
+// counts line terminators treating \r\n as a single one.
+fn count_line_breaks(bytes: &[u8]) -> usize {
+    let mut lines = 0;
+    let mut iter = memchr2_iter(b'\n', b'\r', bytes).peekable();
+    while let Some(p) = iter.next() {
+        lines += 1;
+        if bytes[p] == b'\r' {
+            if let Some(&np) = iter.peek() {
+                if np == p + 1 && bytes[np] == b'\n' {
+                    iter.next();
+                }
+            }
+        }
+    }
+    lines
+}
+
+// the resident pages of the mapping's first `up_to` bytes go back. they're read from the
+// file again if anything ever touches them.
+fn release_pages(mmap: &Mmap, up_to: usize) {
+    #[cfg(unix)]
+    {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as usize;
+        let len = up_to.min(mmap.len()) / page * page;
+        if len > 0 {
+            unsafe { libc::madvise(mmap.as_ptr() as *mut libc::c_void, len, libc::MADV_DONTNEED) };
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (mmap, up_to);
+}
+
+// reads after indexing jump around (a window here, a search hit there), readahead would only
+// pull in pages nobody asked for. None where there's nothing to advise on.
+fn advise_random(mmap: &Mmap) -> Option<bool> {
+    if mmap.is_empty() {
+        return None;
+    }
+    #[cfg(unix)]
+    return Some(unsafe { libc::madvise(mmap.as_ptr() as *mut libc::c_void, mmap.len(), libc::MADV_RANDOM) } == 0);
+    #[cfg(not(unix))]
+    None
+}
+
+// an edit's text as lines, a trailing \n doesn't start one more
+fn edit_lines(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = text.split('\n').map(|s| s.to_string()).collect();
+    if lines.last().is_some_and(|s| s.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn is_line_start(data: &[u8], pos: usize) -> bool {
+    match pos.checked_sub(1).map(|p| data[p]) {
+        None | Some(b'\n') => true,
+        Some(b'\r') => data.get(pos) != Some(&b'\n'),
+        _ => false,
+    }
+}
+
+// pos points at a line terminator (or the end of the data), returns where the next line starts
+fn skip_line_break(data: &[u8], pos: usize) -> usize {
+    if pos >= data.len() {
+        return data.len();
+    }
+    if data[pos] == b'\r' && data.get(pos + 1) == Some(&b'\n') {
+        pos + 2
+    } else {
+        pos + 1
+    }
+}
+
+pub(crate) struct Engine {
+    mmap: Arc<Mmap>,     // shared with snapshots, see Snapshot
     chunks: Vec<ChunkMeta>,
     original_total_lines: usize,
     pieces: Vec<Piece>,
-    memory_buffer: Vec<String>,
+    memory_buffer: Arc<Vec<String>>, // same, copied the first time it changes under one
     last_block: String, // persistent buffer to hand out safe pointers to C
+    match_cache: TtlCache<(SearchMode, Vec<u8>), Arc<MatchIndex>>, // query -> original lines that hit
+    sweeps: TtlCache<(SearchMode, Vec<u8>), PartialIndex>, // the same, still being scanned (see lazy_search)
+    cache_ttl: Duration,
+    history: SearchHistory,
+    last_spans: Vec<u64>, // same deal as last_block, for u64 arrays (spans, checkpoint hashes)
+    last_times: Vec<f64>, // and for time deltas
+    last_levels: Vec<u8>, // and for severities
+    last_bytes: Vec<u8>,  // and for raw file bytes, which needn't be utf-8
+    last_offsets: Vec<u64>, // and for where get_block_lines' lines start
+    edits: u64,           // bumped on every edit so filter views know to rebuild
+    filters: HashMap<u64, FilterView>,
+    parked: HashMap<u64, ParkedFilter>, // switched off filters, same handles, bits packed
+    pending: HashMap<u64, PendingFilter>, // filters still being scanned, see filter_slice
+    filter_slice: usize, // bytes a filter scans per log_engine_filter_step, 0 = all at once
+    next_filter: u64,
+    snapshots: HashMap<u64, Snapshot>,
+    next_snapshot: u64,
+    checkpoints: Option<Checkpoints>, // block hashes for the last block size asked for
+    min_list_query: usize, // shorter queries don't get their spans listed at all
+    max_spans: usize,
+    spans_capped: bool, // whether the last match_spans was cut short by either guard
+    levels: Option<Arc<LevelIndex>>, // severity per original line, built on the first level filter or lookup
+    times: Option<TimeIndex>,   // stamps near every chunk's ends, built on the first time lookup
+    zone: Option<Conversion>,   // the zone stamps are shown in, see zone.rs
+    assume: Zone,               // the zone of stamps that don't give an offset
+    templates: Templates,       // line formats registered on it, see template.rs
+    strip_ansi: bool,           // lines handed out without terminal escapes, see ansi.rs
+    hex_binary: bool,           // binary lines handed out as a hex preview, see binary.rs
+    expansions: Vec<Expansion>, // json lines shown pretty-printed, in line order, see json.rs
+    watch: Watch,               // growth/rotation of the file on disk, polled by the plugin
+    keep_rotated: usize,        // bytes of a rotated out file kept in front of the new one, 0 = none
+    rotated_lines: usize,       // leading lines that came from there, never saved
+    append_open: bool,          // the last appended line had no line break yet, see append
+    pipe: Option<Pipe>,         // the fifo feeding the spill file we map, see pipe.rs
+    retain_lines: usize,        // ring buffer mode: lines kept at most, 0 = no limit
+    retain_bytes: usize,        // same, in bytes
+    evicted: u64,               // lines dropped off the front so far by either limit
+    unchecked_bytes: usize,     // grown/appended since retain_bytes was last measured
+    throughput: Throughput,     // lines/bytes coming in through grow and append, for tailing
+    paused: bool,               // growth and appends wait for resume, see resume
+    held: String,               // appends made while paused
+    retry: Retry,               // backoff for reads failing on a flaky (network) filesystem
+    audit: AuditLog,            // edits, saves and filters made this session
+    options: Options,           // what it was created with, reopening starts over with the same
+    frozen: Option<Arc<AtomicBool>>, // made by freeze: no edits, no following the file. set once the file is truncated in place
+    freezes: Vec<Weak<AtomicBool>>, // the frozen engines made from this one, see freeze
+    journal: bool,              // a journalctl export rewritten a record per line, see journal.rs
+    columns: Option<Columns>,   // read as csv/tsv: the header and what its columns hold, see delimited.rs
+    advised: Option<bool>,      // whether MADV_RANDOM took on the mapping, None if it wasn't tried
+    notes: Option<Notes>,       // notes on lines shared through <log>.notes, read on first use
 }
 
-impl LogEngine {
-    fn new(path: &str) -> Result<Self, std::io::Error> {
-        let file = File::open(path)?;
-        let mmap = unsafe { memmap2::MmapOptions::new().map(&file)? };
+impl Engine {
+    // `cancel` is for open::open giving up on us, counting stops and this fails with
+    // ops::cancelled_error
+    fn new(path: &Path, options: &Options, cancel: &AtomicBool) -> Result<Self, std::io::Error> {
+        let mut retry = options.retry();
+        // stat first: opening a fifo blocks until someone writes to it, a device node maps
+        // as 0 bytes or worse. only plain files get this far.
+        if !retry.run(|| fs::metadata(path))?.is_file() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a regular file"));
+        }
+        let file = retry.run(|| File::open(path))?;
+        Engine::from_file(path, file, retry, options, cancel)
+    }
+
+    // a descriptor somebody else opened (libuv, after neovim's own checks), so possibly one to
+    // a file that's been deleted since. ours is a duplicate, the caller still closes theirs.
+    // there's no path to go with it: growth and truncation are followed on the descriptor,
+    // a rename rotation can't be told apart from the file just being gone, and reopen maps
+    // the same descriptor again instead of whatever took its name.
+    #[cfg(unix)]
+    fn from_fd(fd: i32) -> std::io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+        let dup = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+        if dup < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let file = unsafe { File::from_raw_fd(dup) };
+        let options = Options::default();
+        let mut retry = options.retry();
+        if !retry.run(|| file.metadata())?.is_file() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a regular file"));
+        }
+        Engine::from_file(Path::new(""), file, retry, &options, &AtomicBool::new(false))
+    }
+
+    #[cfg(not(unix))]
+    fn from_fd(_fd: i32) -> std::io::Result<Self> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    // content with no file behind it (a clipboard dump, something decompressed, an unsaved
+    // buffer). it's copied into a scratch file in the temp dir and mapped from there like
+    // any other, so a big one pages out instead of sitting in memory twice. no path either,
+    // same as from_fd. the scratch file goes once nothing maps it, see Pipe::open_scratch.
+    fn from_bytes(bytes: &[u8]) -> std::io::Result<Self> {
+        let scratch = Pipe::spill_file()?;
+        let opened = match fs::write(&scratch, bytes) {
+            Ok(()) => Pipe::open_scratch(&scratch),
+            Err(err) => {
+                let _ = fs::remove_file(&scratch);
+                Err(err)
+            }
+        };
+        let options = Options::default();
+        Engine::from_file(Path::new(""), opened?, options.retry(), &options, &AtomicBool::new(false))
+    }
+
+    // `journalctl -o export` output, rewritten a record per line into a scratch file (see
+    // journal.rs) and mapped from there like from_bytes. it opens as a viewer, saving would
+    // write the rewrite, and isn't followed: the journal's own file never lines up with it.
+    fn from_journal(data: &[u8], options: &Options, cancel: &AtomicBool) -> std::io::Result<Self> {
+        let scratch = Pipe::spill_file()?;
+        let written = File::create(&scratch).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            journal::convert(data, &mut out, cancel)?;
+            out.flush()
+        });
+        let opened = match written {
+            Ok(()) => Pipe::open_scratch(&scratch),
+            Err(err) => {
+                let _ = fs::remove_file(&scratch);
+                Err(err)
+            }
+        };
+        let options = Options { read_only: true, follow: false, ..options.clone() };
+        let mut engine = Engine::from_file(Path::new(""), opened?, options.retry(), &options, cancel)?;
+        engine.journal = true;
+        Ok(engine)
+    }
+
+    fn from_file(path: &Path, file: File, mut retry: Retry, options: &Options, cancel: &AtomicBool) -> std::io::Result<Self> {
+        let mmap = retry.run(|| unsafe { memmap2::MmapOptions::new().map(&file) }).map_err(diag::map_failed)?;
+        if journal::is_export(&mmap) {
+            return Engine::from_journal(&mmap, options, cancel);
+        }
 
         #[cfg(unix)]
         unsafe {
@@ -52,34 +468,43 @@ impl LogEngine {
                 mmap.len(),
                 libc::MADV_SEQUENTIAL,
             );
-            libc::madvise(
-                mmap.as_ptr() as *mut libc::c_void,
-                mmap.len(),
-                libc::MADV_RANDOM,
-            );
         }
 
-        // blast through the file in 1MB chunks to count lines.
-        let chunk_size = 1024 * 1024;
-        let line_counts: Vec<usize> = mmap
-            .par_chunks(chunk_size)
-            .map(|chunk| {
-                let mut count = 0;
-                let mut iter = memchr2_iter(b'\n', b'\r', chunk).peekable();
-                while let Some(pos) = iter.next() {
-                    count += 1;
-                    // the \r\n check here is slightly cursed but prevents overcounting windows line endings.
-                    if chunk[pos] == b'\r' {
-                        if let Some(&next_pos) = iter.peek() {
-                            if next_pos == pos + 1 && chunk[next_pos] == b'\n' {
-                                iter.next();
+        // blast through the file in 1MB chunks (unless the options say otherwise) to count lines.
+        // this is the whole index and it's done before new() returns, there's no background
+        // sweep for get_block/search to wait on. the one sweep there is, a stepped filter's,
+        // lets n/N scan the chunks they need first (see pending_search).
+        let chunk_size = options.chunk_size;
+        let line_counts: Vec<usize> = options.install(|| {
+            mmap
+                .par_chunks(chunk_size)
+                .map(|chunk| {
+                    if cancel.load(Ordering::Relaxed) {
+                        return 0;
+                    }
+                    let mut count = 0;
+                    let mut iter = memchr2_iter(b'\n', b'\r', chunk).peekable();
+                    while let Some(pos) = iter.next() {
+                        count += 1;
+                        // the \r\n check here is slightly cursed but prevents overcounting
+                        // windows line endings.
+                        if chunk[pos] == b'\r' {
+                            if let Some(&next_pos) = iter.peek() {
+                                if next_pos == pos + 1 && chunk[next_pos] == b'\n' {
+                                    iter.next();
+                                }
                             }
                         }
                     }
-                }
-                count
-            })
-            .collect();
+                    count
+                })
+                .collect()
+        });
+        if cancel.load(Ordering::Relaxed) {
+            return Err(ops::cancelled_error());
+        }
+        // the one pass that reads it front to back is over
+        let advised = advise_random(&mmap);
 
         let mut chunks = Vec::with_capacity(line_counts.len());
         let mut current_line = 0;
@@ -110,471 +535,6704 @@ impl LogEngine {
             }
         }
 
+        let watch = Watch::new(path.to_path_buf(), file, &mmap);
+
+        let mut engine = Engine::with_content(Arc::new(mmap), chunks, original_total_lines, watch, retry, options);
+        engine.advised = advised;
+        Ok(engine)
+    }
+
+    // a fresh engine (no edits, caches or filters) on content that's already been indexed
+    fn with_content(
+        mmap: Arc<Mmap>,
+        chunks: Vec<ChunkMeta>,
+        original_total_lines: usize,
+        watch: Watch,
+        retry: Retry,
+        options: &Options,
+    ) -> Engine {
         let pieces = vec![Piece::Original {
             start_line: 0,
             line_count: original_total_lines,
         }];
-
-        Ok(LogEngine {
+        Engine {
             mmap,
             chunks,
             original_total_lines,
             pieces,
-            memory_buffer: Vec::new(),
+            memory_buffer: Arc::new(Vec::new()),
             last_block: String::new(),
-        })
+            match_cache: TtlCache::new(),
+            sweeps: TtlCache::new(),
+            cache_ttl: DEFAULT_CACHE_TTL,
+            history: SearchHistory::new(),
+            last_spans: Vec::new(),
+            last_times: Vec::new(),
+            last_levels: Vec::new(),
+            last_bytes: Vec::new(),
+            last_offsets: Vec::new(),
+            edits: 0,
+            filters: HashMap::new(),
+            next_filter: 1,
+            snapshots: HashMap::new(),
+            next_snapshot: 1,
+            parked: HashMap::new(),
+            pending: HashMap::new(),
+            filter_slice: options.filter_slice,
+            checkpoints: None,
+            min_list_query: DEFAULT_MIN_LIST_QUERY,
+            max_spans: DEFAULT_MAX_SPANS,
+            spans_capped: false,
+            levels: None,
+            times: None,
+            zone: None,
+            assume: Zone::Utc,
+            templates: Templates::default(),
+            strip_ansi: false,
+            hex_binary: true,
+            expansions: Vec::new(),
+            watch,
+            keep_rotated: 0,
+            rotated_lines: 0,
+            append_open: false,
+            pipe: None,
+            retain_lines: 0,
+            retain_bytes: 0,
+            evicted: 0,
+            unchecked_bytes: 0,
+            throughput: Throughput::new(),
+            paused: false,
+            held: String::new(),
+            retry,
+            audit: AuditLog::new(),
+            options: options.clone(),
+            frozen: None,
+            freezes: Vec::new(),
+            journal: false,
+            columns: None,
+            advised: None,
+            notes: None,
+        }
     }
 
-    fn line_to_byte_offset(&self, line: usize) -> usize {
-        if line >= self.original_total_lines {
-            return self.mmap.len();
-        }
-        
-        // find the closest chunk behind our target line (crucial for :LogJump speed)
-        let chunk_idx = match self.chunks.binary_search_by_key(&line, |c| c.start_line) {
-            Ok(idx) => idx,
-            Err(idx) => idx.saturating_sub(1),
-        };
-        
-        let chunk = &self.chunks[chunk_idx];
-        let mut offset = chunk.byte_offset;
-        let mut skip = line - chunk.start_line;
-        
-        // walk the rest of the bytes manually until we hit the exact line
-        while skip > 0 && offset < self.mmap.len() {
-            let slice = &self.mmap[offset..];
-            if let Some(pos) = memchr2(b'\n', b'\r', slice) {
-                offset += pos + 1;
-                if slice[pos] == b'\r' && offset < self.mmap.len() && self.mmap[offset] == b'\n' {
-                    offset += 1; // skip the \n of a \r\n pair
-                }
-                skip -= 1;
-            } else {
-                offset = self.mmap.len();
-                break;
+    // a fifo: the engine maps an empty spill file and a reader thread fills it from the pipe
+    fn from_pipe(path: &Path, options: &Options) -> std::io::Result<Self> {
+        let spill = Pipe::spill_file()?;
+        let opened = Engine::new(&spill, options, &AtomicBool::new(false))
+            .and_then(|engine| Ok((engine, Pipe::start(path, spill.clone())?)));
+        match opened {
+            Ok((mut engine, pipe)) => {
+                engine.pipe = Some(pipe);
+                Ok(engine)
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&spill);
+                Err(err)
             }
         }
-        offset
     }
 
-    fn get_original_bytes(&self, start_line: usize, line_count: usize) -> &[u8] {
-        if line_count == 0 {
-            return &[];
+    // the file on disk got longer: map it again, index only the appended bytes and hang the
+    // new lines off the end of the piece table. every filter gets the new lines (plus the old
+    // last line, if it was still being written) evaluated and appended, nothing is rescanned.
+    // returns how many lines were added, None if the file shrank (that's rotation, not growth).
+    fn grow(&mut self) -> std::io::Result<Option<usize>> {
+        if self.frozen.is_some() {
+            return Ok(Some(0));
+        }
+        let old_len = self.mmap.len();
+        let file = self.watch.file();
+        // follow mode calls this on every write, only map again when there's something new
+        let len = self.retry.run(|| file.metadata())?.len();
+        if len < old_len as u64 {
+            return Ok(None);
+        }
+        if len == old_len as u64 || self.paused {
+            return Ok(Some(0));
+        }
+        let mmap = self.retry.run(|| unsafe { memmap2::MmapOptions::new().map(file) }).map_err(diag::map_failed)?;
+        if mmap.len() < old_len {
+            return Ok(None);
+        }
+        if mmap.len() == old_len {
+            return Ok(Some(0));
         }
-        let start = self.line_to_byte_offset(start_line);
-        let end = self.line_to_byte_offset(start_line + line_count);
-        &self.mmap[start..end]
-    }
 
-    fn total_lines(&self) -> usize {
-        self.pieces.iter().map(|p| p.line_count()).sum()
-    }
+        let old_total = self.original_total_lines;
+        let ends_open = old_len > 0 && !matches!(self.mmap[old_len - 1], b'\n' | b'\r');
+        let first_changed = old_total - ends_open as usize;
+        let data = &mmap[..];
 
-    // returns (piece_index, line_offset_inside_piece)
-    fn find_piece_idx(&self, logical_line: usize) -> (usize, usize) {
-        let mut current = 0;
-        for (i, piece) in self.pieces.iter().enumerate() {
-            let count = piece.line_count();
-            if logical_line < current + count {
-                return (i, logical_line - current);
+        // segments of the new bytes, split on the same grid new() used. each one that
+        // starts on the grid becomes a chunk.
+        let mut starts = vec![old_len];
+        let chunk_size = self.options.chunk_size;
+        starts.extend((self.chunks.len() * chunk_size..data.len()).step_by(chunk_size).filter(|&b| b > old_len));
+        let op = ops::token();
+        let counts: Vec<usize> = self.options.install(|| {
+            starts
+                .par_iter()
+                .enumerate()
+                .map(|(i, &start)| {
+                    if op.cancelled() {
+                        return 0;
+                    }
+                    let end = starts.get(i + 1).copied().unwrap_or(data.len());
+                    let mut count = count_line_breaks(&data[start..end]);
+                    // the \n of a \r\n split across segments was already counted with its \r
+                    if start > 0 && data[start - 1] == b'\r' && data[start] == b'\n' {
+                        count -= 1;
+                    }
+                    count
+                })
+                .collect()
+        });
+        // nothing has changed yet, the next grow starts over from the same place
+        if op.cancelled() {
+            return Err(ops::cancelled_error());
+        }
+
+        let old_chunks = self.chunks.len();
+        let mut current_line = first_changed;
+        for (&start, &count) in starts.iter().zip(&counts) {
+            if start.is_multiple_of(chunk_size) {
+                let straddles = start > 0 && data[start - 1] == b'\r' && data[start] == b'\n';
+                self.chunks.push(ChunkMeta {
+                    byte_offset: start,
+                    start_line: current_line - straddles as usize,
+                });
             }
-            current += count;
+            current_line += count;
         }
-        (self.pieces.len(), 0)
-    }
+        if !matches!(data.last(), Some(b'\n' | b'\r')) {
+            current_line += 1;
+        }
+        let total = current_line;
+        let added = total - old_total;
 
-    fn split_piece_at(&mut self, piece_idx: usize, offset: usize) {
-        if offset == 0 || piece_idx >= self.pieces.len() {
-            return;
+        // filters, cached searches and the severity index get the new lines evaluated the
+        // same way, all of them or none. a filter made from a cached search shares its index.
+        let mut extended: HashMap<*const MatchIndex, Arc<MatchIndex>> = HashMap::new();
+        let indexes = self.match_cache.values().chain(self.filters.values().map(|view| &view.index));
+        for index in indexes {
+            extended.entry(Arc::as_ptr(index)).or_insert_with(|| {
+                let matcher = index.matcher.clone();
+                Arc::new(index.extend(data, &self.chunks, total, first_changed, matcher))
+            });
         }
-        let piece = self.pieces[piece_idx].clone();
-        if offset >= piece.line_count() {
-            return;
+        let levels = self
+            .levels
+            .as_ref()
+            .map(|_| levels::classify_from(data, &self.chunks, total, first_changed, self.columns.as_ref(), &self.templates));
+        if op.cancelled() {
+            self.chunks.truncate(old_chunks);
+            return Err(ops::cancelled_error());
         }
 
-        match piece {
-            Piece::Original { start_line, line_count } => {
-                self.pieces[piece_idx] = Piece::Original { start_line, line_count: offset };
-                self.pieces.insert(piece_idx + 1, Piece::Original {
-                    start_line: start_line + offset,
-                    line_count: line_count - offset,
-                });
+        self.advised = advise_random(&mmap);
+        self.mmap = Arc::new(mmap);
+        self.original_total_lines = total;
+        match self.pieces.last_mut() {
+            Some(Piece::Original { start_line, line_count }) if *start_line + *line_count == old_total => {
+                *line_count += added;
             }
-            Piece::Memory { start_idx, line_count } => {
-                self.pieces[piece_idx] = Piece::Memory { start_idx, line_count: offset };
-                self.pieces.insert(piece_idx + 1, Piece::Memory {
-                    start_idx: start_idx + offset,
-                    line_count: line_count - offset,
-                });
+            _ if added > 0 => self.pieces.push(Piece::Original {
+                start_line: old_total,
+                line_count: added,
+            }),
+            _ => {}
+        }
+
+        let indexes = self.match_cache.values_mut().chain(self.filters.values_mut().map(|view| &mut view.index));
+        for index in indexes {
+            if let Some(grown) = extended.get(&Arc::as_ptr(index)) {
+                *index = grown.clone();
             }
         }
+        for sweep in self.sweeps.values_mut() {
+            sweep.grow(&self.chunks, old_len, total, first_changed);
+        }
+        if let (Some(index), Some(ranks)) = (self.levels.as_mut(), levels) {
+            Arc::make_mut(index).extend(first_changed, ranks);
+        }
+        // a few lines per chunk, nothing to cancel
+        if let Some(times) = self.times.as_mut() {
+            times.extend(&self.mmap, &self.chunks, total);
+        }
+        // row layouts and block hashes go stale the same way they do after an edit. filters
+        // that were laid out only get the tail redone, nothing above the new lines moved.
+        self.edits += 1;
+        let from = self.logical_from_original(first_changed);
+        let mut views = std::mem::take(&mut self.filters);
+        for view in views.values_mut().filter(|view| view.edits + 1 == self.edits) {
+            view.rebuild_from(&self.pieces, &self.memory_buffer, self.rotated_lines, self.edits, from, |line, floor| {
+                self.record_bounds(line, floor).unwrap_or((line, line))
+            });
+        }
+        self.filters = views;
+        self.audit.record("refresh", format!("{} lines appended on disk", added));
+        self.throughput.record(added, self.mmap.len() - old_len);
+        self.unchecked_bytes += self.mmap.len() - old_len;
+        self.enforce_retention();
+        if added > 0 {
+            events::emit(events::EVENT_GREW, self.total_lines() as u64, from as u64);
+        }
+        Ok(Some(added))
     }
 
-    fn apply_edit(&mut self, start_line: usize, num_deleted: usize, new_text: &str) {
-        let (mut piece_idx, offset) = self.find_piece_idx(start_line);
+    // tailing without the churn: while paused, growth of the file is left for later and
+    // appends are held back, so nothing gets reindexed and the caches stay. resuming takes
+    // it all in at once. returns the lines that came in, None if the file shrank meanwhile.
+    fn resume(&mut self) -> std::io::Result<Option<usize>> {
+        if !self.paused {
+            return Ok(Some(0));
+        }
+        self.paused = false;
+        let before = self.total_lines();
+        // the file first, appends land after its lines the way they would have if they came
+        // in later. eviction can make the count smaller than what arrived, never negative.
+        let grown = self.grow();
+        let held = std::mem::take(&mut self.held);
+        self.append(&held);
+        Ok(grown?.map(|_| self.total_lines().saturating_sub(before)))
+    }
 
-        if piece_idx < self.pieces.len() {
-            self.split_piece_at(piece_idx, offset);
-            if offset > 0 {
-                piece_idx += 1;
+    fn stats(&self) -> LogEngineStats {
+        let on_disk = self.watch.file().metadata().map_or(0, |m| m.len());
+        let (mut original_lines, mut memory_lines) = (0, 0);
+        for piece in &self.pieces {
+            match piece {
+                Piece::Original { line_count, .. } => original_lines += *line_count as u64,
+                Piece::Memory { line_count, .. } => memory_lines += *line_count as u64,
             }
         }
+        // the mapping is what's indexed, a file that grew since (paused, not refreshed yet)
+        // isn't all of it
+        let indexed = self.mmap.len() as u64;
+        LogEngineStats {
+            version: STATS_VERSION,
+            file_size: on_disk.max(indexed),
+            total_lines: original_lines + memory_lines,
+            original_lines,
+            memory_lines,
+            pieces: self.pieces.len() as u64,
+            indexed_percent: if on_disk > indexed { indexed as f64 * 100.0 / on_disk as f64 } else { 100.0 },
+        }
+    }
 
-        let mut remaining_delete = num_deleted;
-        
-        // nuke pieces fully contained in the deletion range
-        while remaining_delete > 0 && piece_idx < self.pieces.len() {
-            let count = self.pieces[piece_idx].line_count();
-            if count <= remaining_delete {
-                self.pieces.remove(piece_idx);
-                remaining_delete -= count;
-            } else {
-                // partial overlap, split and drop the front
-                self.split_piece_at(piece_idx, remaining_delete);
-                self.pieces.remove(piece_idx);
-                remaining_delete = 0;
-            }
+    // bytes waiting for resume: held back appends plus what the file grew by
+    fn pending_bytes(&self) -> usize {
+        if !self.paused {
+            return 0;
         }
+        let on_disk = self.watch.file().metadata().map_or(0, |m| m.len() as usize);
+        self.held.len() + on_disk.saturating_sub(self.mmap.len())
+    }
 
-        if !new_text.is_empty() {
-            let mut lines: Vec<String> = new_text.split('\n').map(|s| s.to_string()).collect();
-            // drop the trailing empty string from split if it exists
-            if lines.last().map(|s| s.is_empty()).unwrap_or(false) {
-                lines.pop();
-            }
-            if !lines.is_empty() {
-                let start_idx = self.memory_buffer.len();
-                let line_count = lines.len();
-                self.memory_buffer.extend(lines);
-                self.pieces.insert(piece_idx, Piece::Memory { start_idx, line_count });
+    // poll and grow (or reopen) in one go, for plugins that poll on a timer instead of
+    // watching. returns what happened (REFRESH_*) and the first logical line whose text is
+    // new or different: an unfinished last line that got more text counts.
+    fn refresh_delta(&mut self) -> std::io::Result<(i32, usize)> {
+        if self.frozen.is_some() {
+            return Ok((REFRESH_NOTHING, self.total_lines()));
+        }
+        let changes = self.watch.poll(&mut self.retry)?;
+        let old_len = self.mmap.len();
+        let ends_open = old_len > 0 && !matches!(self.mmap[old_len - 1], b'\n' | b'\r');
+        let first_changed = self.original_total_lines - ends_open as usize;
+        if !changes.rotated && !self.watch.rotated() && self.grow()?.is_some() {
+            if self.mmap.len() == old_len {
+                return Ok((REFRESH_NOTHING, self.total_lines()));
             }
+            return Ok((REFRESH_GREW, self.logical_from_original(first_changed)));
+        }
+        if self.reopen()? & REOPEN_KEPT_EDITS != 0 {
+            Ok((REFRESH_REPLACED, self.logical_from_original(first_changed)))
+        } else {
+            Ok((REFRESH_REOPENED, 0))
         }
     }
 
-    fn get_block(&mut self, start_line: usize, num_lines: usize) -> *const u8 {
-        self.last_block.clear();
-        if num_lines == 0 || start_line >= self.total_lines() {
-            return ptr::null();
+    // ring buffer mode for endless streams: past either limit the oldest lines are dropped
+    // from the front, down to 90% of it so a stream sitting at the limit doesn't evict on
+    // every write. logical line numbers shift down by what was dropped, `evicted` keeps
+    // the count so the plugin can keep its numbering straight.
+    fn enforce_retention(&mut self) {
+        let total = self.total_lines();
+        let mut drop = 0;
+        if self.retain_lines > 0 && total > self.retain_lines {
+            drop = total - (self.retain_lines - self.retain_lines / 10);
+        }
+        // measuring walks every piece, only bother once there's a bit more than last time
+        if self.retain_bytes > 0 && self.unchecked_bytes >= self.retain_bytes / 20 {
+            self.unchecked_bytes = 0;
+            let bytes: usize = self.pieces.iter().map(|p| self.piece_bytes(p)).sum();
+            if bytes > self.retain_bytes {
+                drop = drop.max(self.lines_covering(bytes - (self.retain_bytes - self.retain_bytes / 10)));
+            }
+        }
+        if drop == 0 {
+            return;
         }
 
-        let (mut piece_idx, mut offset) = self.find_piece_idx(start_line);
-        let mut collected = 0;
+        let mut left = drop;
+        while left > 0 && !self.pieces.is_empty() {
+            let count = self.pieces[0].line_count();
+            if count > left {
+                self.split_piece_at(0, left);
+                left = 0;
+            } else {
+                left -= count;
+            }
+            self.pieces.remove(0);
+        }
+        self.evicted += drop as u64;
+        self.rotated_lines = self.rotated_lines.saturating_sub(drop);
+        json::shift(&mut self.expansions, 0, drop, 0);
+        self.compact_memory();
+        self.release_spill();
+        self.trim_evicted();
+        self.edits += 1;
+        self.audit.record("evict", format!("{} oldest lines dropped", drop));
+    }
 
-        // stitch together pieces until we satisfy the requested line count
-        while collected < num_lines && piece_idx < self.pieces.len() {
-            let piece = &self.pieces[piece_idx];
-            let count = piece.line_count() - offset;
-            let take = count.min(num_lines - collected);
+    // the first original line still in the piece table, everything before it was evicted
+    fn first_original(&self) -> usize {
+        let starts = self.pieces.iter().filter_map(|piece| match piece {
+            Piece::Original { start_line, .. } => Some(*start_line),
+            Piece::Memory { .. } => None,
+        });
+        starts.min().unwrap_or(self.original_total_lines)
+    }
 
-            match piece {
-                Piece::Original { start_line: p_start, .. } => {
-                    let start_byte = self.line_to_byte_offset(p_start + offset);
-                    let end_byte = self.line_to_byte_offset(p_start + offset + take);
-                    
-                    let bytes = &self.mmap[start_byte..end_byte];
-                    
-                    // logs are dirty. replace garbage bytes with  instead of failing silently.
-                    let s = String::from_utf8_lossy(bytes);
-                    self.last_block.push_str(&s);
-                    if !self.last_block.ends_with('\n') && !self.last_block.is_empty() {
-                        self.last_block.push('\n');
-                    }
-                }
-                Piece::Memory { start_idx, .. } => {
-                    for i in 0..take {
-                        self.last_block.push_str(&self.memory_buffer[start_idx + offset + i]);
-                        self.last_block.push('\n');
-                    }
-                }
+    // a freshly built index, with nothing kept for lines evicted before it was built
+    fn without_evicted(&self, index: MatchIndex) -> MatchIndex {
+        index.trimmed(self.first_original()).unwrap_or(index)
+    }
+
+    // evicted lines never come back, so what's kept per original line drops them: the
+    // bitsets of cached searches and filters, the severity index, and the mapping's pages
+    // (which only the kernel would otherwise reclaim, and only under pressure)
+    fn trim_evicted(&mut self) {
+        let first = self.first_original();
+        // a filter made from a cached search shares its index, keep it that way
+        let mut trimmed: HashMap<*const MatchIndex, Option<Arc<MatchIndex>>> = HashMap::new();
+        let indexes = self.match_cache.values_mut().chain(self.filters.values_mut().map(|view| &mut view.index));
+        for index in indexes {
+            let smaller = trimmed.entry(Arc::as_ptr(index)).or_insert_with(|| index.trimmed(first).map(Arc::new)).clone();
+            if let Some(smaller) = smaller {
+                *index = smaller;
             }
-            collected += take;
-            offset = 0;
-            piece_idx += 1;
         }
-
-        // C side expects a pointer. this gets overwritten next call, DO NOT keep it around.
-        self.last_block.as_ptr()
+        if let Some(levels) = self.levels.as_mut() {
+            Arc::make_mut(levels).trim(first);
+        }
+        let chunk_idx = self.chunks.partition_point(|c| c.start_line <= first).saturating_sub(1);
+        if let Some(chunk) = self.chunks.get(chunk_idx) {
+            release_pages(&self.mmap, chunk.byte_offset);
+        }
     }
 
-    fn save(&self, path: &str) -> bool {
-        let temp_path = format!("{}.tmp", path);
-        let file = match OpenOptions::new().write(true).create(true).truncate(true).open(&temp_path) {
-            Ok(f) => f,
-            Err(_) => return false,
-        };
-        let mut writer = BufWriter::new(file);
+    fn piece_bytes(&self, piece: &Piece) -> usize {
+        match piece {
+            Piece::Original { start_line, line_count } => {
+                self.line_to_byte_offset(start_line + line_count) - self.line_to_byte_offset(*start_line)
+            }
+            Piece::Memory { start_idx, line_count } => {
+                self.memory_buffer[*start_idx..start_idx + line_count].iter().map(|l| l.len() + 1).sum()
+            }
+        }
+    }
 
+    // how many lines from the front it takes to add up to at least `bytes`
+    fn lines_covering(&self, mut bytes: usize) -> usize {
+        let mut lines = 0;
         for piece in &self.pieces {
-            match piece {
+            let size = self.piece_bytes(piece);
+            if size < bytes {
+                bytes -= size;
+                lines += piece.line_count();
+                continue;
+            }
+            lines += match piece {
+                // binary search for the first line boundary at or past `bytes`
                 Piece::Original { start_line, line_count } => {
-                    let bytes = self.get_original_bytes(*start_line, *line_count);
-                    if writer.write_all(bytes).is_err() {
-                        return false;
-                    }
-                    if !bytes.ends_with(b"\n") && !bytes.is_empty() {
-                        if writer.write_all(b"\n").is_err() {
-                            return false;
-                        }
+                    let base = self.line_to_byte_offset(*start_line);
+                    let (mut lo, mut hi) = (0, *line_count);
+                    while lo < hi {
+                        let mid = (lo + hi) / 2;
+                        if self.line_to_byte_offset(start_line + mid) - base < bytes {
+                            lo = mid + 1;
+                        } else {
+                            hi = mid;
+                        }
                     }
+                    lo
                 }
                 Piece::Memory { start_idx, line_count } => {
-                    for i in 0..*line_count {
-                        if writer.write_all(self.memory_buffer[start_idx + i].as_bytes()).is_err() {
-                            return false;
-                        }
-                        if writer.write_all(b"\n").is_err() {
-                            return false;
-                        }
+                    let mut taken = 0;
+                    while taken < *line_count && bytes > 0 {
+                        bytes = bytes.saturating_sub(self.memory_buffer[start_idx + taken].len() + 1);
+                        taken += 1;
+                    }
+                    taken
+                }
+            };
+            break;
+        }
+        lines
+    }
+
+    fn time_index(&mut self) -> &TimeIndex {
+        self.times.get_or_insert_with(|| TimeIndex::build(&self.mmap, &self.chunks, self.original_total_lines, self.assume, &self.templates))
+    }
+
+    // when a line happened: its own stamp, or the one of the entry it continues (a stack
+    // trace, wrapped text), looking back in ever bigger steps up to TIME_LOOKBACK lines
+    fn line_time(&self, line: usize) -> Option<f64> {
+        if line >= self.total_lines() {
+            return None;
+        }
+        let (mut end, mut step) = (line + 1, 1);
+        while end > 0 && line + 1 - end < TIME_LOOKBACK {
+            let start = end.saturating_sub(step);
+            let mut found = None;
+            self.for_each_line(start, end - start, |_, bytes| {
+                if let Some((secs, _)) = time::parse(bytes, self.assume, &self.templates) {
+                    found = Some(secs);
+                }
+            });
+            if found.is_some() {
+                return found;
+            }
+            end = start;
+            step *= 8;
+        }
+        None
+    }
+
+    // the record a line is part of, (first, last): the line that starts it plus its
+    // continuation lines, the same grouping as fold_ranges. the head is the nearest stamped
+    // line above, or in a file without stamps the nearest one that isn't indented, found in
+    // ever bigger steps. nothing above `floor` is looked at.
+    fn record_bounds(&self, line: usize, floor: usize) -> Option<(usize, usize)> {
+        let total = self.total_lines();
+        if line >= total {
+            return None;
+        }
+        let floor = floor.max(line.saturating_sub(RECORD_LOOKBACK)).min(line);
+        let (mut end, mut step) = (line + 1, 1);
+        let (mut stamped, mut plain) = (None, None);
+        while stamped.is_none() && end > floor {
+            let start = end.saturating_sub(step).max(floor);
+            let mut nearest = None;
+            self.for_each_line(start, end - start, |line, bytes| {
+                let text = String::from_utf8_lossy(bytes);
+                if fields::continues_entry(&self.templates, &text, false) {
+                    return; // indented, never a head
+                }
+                if fields::starts_with_timestamp(&self.templates, &text) {
+                    stamped = Some(line);
+                } else {
+                    nearest = Some(line);
+                }
+            });
+            plain = plain.or(nearest);
+            end = start;
+            step *= 8;
+        }
+        let (first, head_stamped) = match (stamped, plain) {
+            (Some(first), _) => (first, true),
+            (None, Some(first)) => (first, false),
+            (None, None) => (floor, false),
+        };
+        let limit = line.saturating_add(RECORD_LOOKBACK).min(total);
+        let (mut last, mut from, mut step) = (line, line + 1, 1);
+        let mut ended = false;
+        while !ended && from < limit {
+            let take = step.min(limit - from);
+            self.for_each_line(from, take, |line, bytes| {
+                if !ended && fields::continues_entry(&self.templates, &String::from_utf8_lossy(bytes), head_stamped) {
+                    last = line;
+                } else {
+                    ended = true;
+                }
+            });
+            from += take;
+            step *= 8;
+        }
+        Some((first, last))
+    }
+
+    // flat [from previous, from anchor] seconds for every line in a block: how long after the
+    // nearest stamped line above it (in the block or not) the line was written, and how long
+    // after the anchor line. NaN for lines without a stamp of their own, and where there's
+    // nothing to compare with.
+    fn time_deltas(&mut self, start_line: usize, num_lines: usize, anchor: Option<usize>) -> &[f64] {
+        let mut out = std::mem::take(&mut self.last_times);
+        out.clear();
+        let anchor = anchor.and_then(|line| self.line_time(line)).unwrap_or(f64::NAN);
+        let mut previous = start_line.checked_sub(1).and_then(|line| self.line_time(line)).unwrap_or(f64::NAN);
+        self.for_each_line(start_line, num_lines, |_, bytes| match time::parse(bytes, self.assume, &self.templates) {
+            Some((secs, _)) => {
+                out.extend([secs - previous, secs - anchor]);
+                previous = secs;
+            }
+            None => out.extend([f64::NAN, f64::NAN]),
+        });
+        self.last_times = out;
+        &self.last_times
+    }
+
+    // flat (line, millis) pairs, one per stamped line in the block written at least min_secs
+    // after the stamps before it: where the service stalled, died or was restarted. what a
+    // line is measured against is the latest stamp above it (in the block or not), so a line
+    // written out of order doesn't make a gap out of the next one.
+    fn time_gaps(&mut self, start_line: usize, num_lines: usize, min_secs: f64) -> &[u64] {
+        let mut out = std::mem::take(&mut self.last_spans);
+        out.clear();
+        let mut latest = start_line.checked_sub(1).and_then(|line| self.line_time(line)).unwrap_or(f64::NAN);
+        self.for_each_line(start_line, num_lines, |line, bytes| {
+            if let Some((secs, _)) = time::parse(bytes, self.assume, &self.templates) {
+                if secs - latest >= min_secs {
+                    out.extend([line as u64, ((secs - latest) * 1000.0).round() as u64]);
+                }
+                latest = latest.max(secs);
+            }
+        });
+        self.last_spans = out;
+        &self.last_spans
+    }
+
+    // lines per bucket_secs from `from` up to `to`: how busy the log was, for an activity
+    // sparkline. a line counts in the bucket of its stamp, a line without one (a stack
+    // trace) in that of the stamped line above it. starts at the first line stamped at or
+    // after `from` (see seek_time) and stops at the first one stamped at or after `to`.
+    fn time_histogram(&mut self, from: f64, to: f64, bucket_secs: f64) -> &[u64] {
+        let mut out = std::mem::take(&mut self.last_spans);
+        out.clear();
+        let buckets = ((to - from) / bucket_secs).ceil() as usize;
+        out.resize(buckets, 0);
+        let total = self.total_lines();
+        let (mut line, mut current, mut done) = (self.seek_time(from).unwrap_or(total), f64::NAN, false);
+        while !done && line < total {
+            let take = HISTOGRAM_STEP.min(total - line);
+            self.for_each_line(line, take, |_, bytes| {
+                if done {
+                    return;
+                }
+                if let Some((secs, _)) = time::parse(bytes, self.assume, &self.templates) {
+                    if secs >= to {
+                        done = true;
+                        return;
+                    }
+                    current = secs;
+                }
+                // a line written out of order from before `from` is left out
+                if current >= from {
+                    out[(((current - from) / bucket_secs) as usize).min(buckets - 1)] += 1;
+                }
+            });
+            line += take;
+        }
+        self.last_spans = out;
+        &self.last_spans
+    }
+
+    // stamps shown in `target` (None: as written) and read in `assume` when they don't give
+    // an offset. the time index was built reading them in the old one.
+    fn set_time_zone(&mut self, target: Option<Zone>, assume: Zone) {
+        if assume != self.assume {
+            self.times = None;
+        }
+        self.assume = assume;
+        self.zone = target.map(|target| Conversion { target, assume });
+    }
+
+    // one row per line of the block: empty, or "col\tlen\tstamp" with the line's stamp
+    // rewritten in the zone set by set_time_zone. same buffer and lifetime as get_block.
+    fn zoned_times(&mut self, start_line: usize, num_lines: usize) -> &str {
+        let mut block = std::mem::take(&mut self.last_block);
+        block.clear();
+        if let Some(zone) = &self.zone {
+            self.for_each_line(start_line, num_lines, |_, bytes| {
+                if let Some((col, len, stamp)) = zone.convert(&ansi::shown(bytes, self.strip_ansi), &self.templates) {
+                    block.push_str(&format!("{}\t{}\t{}", col, len, stamp));
+                }
+                block.push('\n');
+            });
+        }
+        self.last_block = block;
+        &self.last_block
+    }
+
+    // the first line stamped at or after `secs`. only the file's lines are looked at, for a
+    // line that was deleted it's where it would have been.
+    fn seek_time(&mut self, secs: f64) -> Option<usize> {
+        self.time_index();
+        let times = self.times.as_ref()?;
+        let original = times.seek(&self.mmap, self.original_total_lines, secs)?;
+        Some(self.logical_from_original(original)).filter(|&line| line < self.total_lines())
+    }
+
+    // where a logical line starts counting bytes the way piece_bytes does: the file's own line
+    // breaks, \n after memory lines. past the last line: the size of the whole thing.
+    fn byte_of_line(&self, line: usize) -> usize {
+        let (piece_idx, offset) = self.find_piece_idx(line);
+        let before: usize = self.pieces[..piece_idx].iter().map(|p| self.piece_bytes(p)).sum();
+        before
+            + match self.pieces.get(piece_idx) {
+                Some(Piece::Original { start_line, .. }) => {
+                    self.line_to_byte_offset(start_line + offset) - self.line_to_byte_offset(*start_line)
+                }
+                Some(Piece::Memory { start_idx, .. }) => {
+                    self.memory_buffer[*start_idx..start_idx + offset].iter().map(|l| l.len() + 1).sum()
+                }
+                None => 0,
+            }
+    }
+
+    // the logical line a byte of that content is in, the last line for one past the end
+    fn line_of_byte(&self, offset: usize) -> usize {
+        self.lines_covering(offset.saturating_add(1)).min(self.total_lines()).saturating_sub(1)
+    }
+
+    // evicted memory lines are still in memory_buffer, pieces only point past them. once
+    // they're most of it, copy the live lines over to a fresh one.
+    fn compact_memory(&mut self) {
+        let live: usize = self
+            .pieces
+            .iter()
+            .map(|p| match p {
+                Piece::Memory { line_count, .. } => *line_count,
+                Piece::Original { .. } => 0,
+            })
+            .sum();
+        if live * 2 >= self.memory_buffer.len() {
+            return;
+        }
+        let mut compact = Vec::with_capacity(live);
+        let memory = Arc::make_mut(&mut self.memory_buffer);
+        for piece in self.pieces.iter_mut() {
+            if let Piece::Memory { start_idx, line_count } = piece {
+                let start = compact.len();
+                compact.extend(memory[*start_idx..*start_idx + *line_count].iter_mut().map(std::mem::take));
+                *start_idx = start;
+            }
+        }
+        self.memory_buffer = Arc::new(compact);
+    }
+
+    // a pipe's spill file is ours: give the disk space of evicted lines back. stops a chunk
+    // short of the first line still shown, finding a line scans from its chunk's start
+    // and peeks at the byte before it.
+    fn release_spill(&self) {
+        let Some(pipe) = &self.pipe else {
+            return;
+        };
+        // a snapshot or a frozen engine may still read them
+        if self.shared() {
+            return;
+        }
+        let Some(Piece::Original { start_line, .. }) = self.pieces.first() else {
+            return;
+        };
+        let chunk_idx = self.chunks.partition_point(|c| c.start_line < *start_line);
+        if let Some(chunk) = chunk_idx.checked_sub(2).and_then(|idx| self.chunks.get(idx)) {
+            pipe.release(chunk.byte_offset as u64);
+        }
+    }
+
+    // the file was truncated or the path points at another file now (logrotate), start over
+    // on whatever is at the path. when it's a new file that still starts with everything we
+    // had (an editor saving by rename, say) the piece table is kept, edits included, and the
+    // rest is picked up like growth. otherwise edits can't be placed anywhere and are dropped,
+    // and every filter (open, parked or still scanning) is searched again on the new content
+    // under its old handle. ones that no longer compile are closed, as are snapshots of a
+    // file truncated in place. with keep_rotated set, the tail of wherever
+    // the old file was rotated to stays in front (see rotate.rs). returns REOPEN_* bits.
+    fn reopen(&mut self) -> std::io::Result<i32> {
+        if self.frozen.is_some() {
+            return Err(std::io::Error::other("a frozen snapshot doesn't follow the file"));
+        }
+        let path = self.watch.path().to_path_buf();
+        // the old mapping only reads safely while the file behind it is as long as it was, a
+        // file truncated in place faults past its new end. renamed away it's still whole.
+        let old_whole = self.watch.file().metadata().is_ok_and(|m| m.len() >= self.mmap.len() as u64);
+        if old_whole && self.watch.replaced() {
+            let file = self.retry.run(|| File::open(&path))?;
+            let old_len = self.mmap.len();
+            if self.retry.run(|| file.metadata())?.len() >= old_len as u64 {
+                let mmap = self.retry.run(|| unsafe { memmap2::MmapOptions::new().map(&file) }).map_err(diag::map_failed)?;
+                if mmap.len() >= old_len && same_start(&mmap, &self.mmap) {
+                    self.replace_watch(Watch::new(path.clone(), file, &self.mmap));
+                    self.grow()?;
+                    self.audit.record("reopen", "replaced on disk, same content so far, edits kept".to_string());
+                    events::emit(events::EVENT_ROTATED, self.total_lines() as u64, REOPEN_KEPT_EDITS as u64);
+                    return Ok(REOPEN_KEPT_EDITS);
+                }
+            }
+        }
+
+        // counting the new file is the slow part and can be cancelled, nothing changed yet
+        let op = ops::start();
+        let fresh = if path.as_os_str().is_empty() {
+            // no path to open again (from_fd, from_bytes): start over on what our descriptor
+            // holds now, a file truncated in place and written again is still behind it
+            let file = self.watch.file().try_clone()?;
+            Engine::from_file(&path, file, self.options.retry(), &self.options, &op.flag())?
+        } else {
+            Engine::new(&path, &self.options, &op.flag())?
+        };
+        let rotated = self.rotated_out(&path);
+        let pending = self.pending.drain().map(|(id, p)| {
+            (id, FilterSetup { chain: p.chain, origin: p.origin, context: None, records: false })
+        });
+        let setups: Vec<(u64, FilterSetup)> =
+            self.filters.iter().map(|(&id, view)| (id, view.setup())).chain(pending).collect();
+        let parked: Vec<(u64, FilterSetup)> = self.parked.drain().map(|(id, p)| (id, p.setup())).collect();
+        let dropped_edits = self.pieces.iter().any(|p| matches!(p, Piece::Memory { .. }));
+        self.mmap = fresh.mmap;
+        self.chunks = fresh.chunks;
+        self.original_total_lines = fresh.original_total_lines;
+        self.pieces = fresh.pieces;
+        self.expansions.clear();
+        self.rotated_lines = rotated.len();
+        if !rotated.is_empty() {
+            self.pieces.insert(0, Piece::Memory { start_idx: 0, line_count: rotated.len() });
+        }
+        self.memory_buffer = Arc::new(rotated);
+        self.replace_watch(fresh.watch);
+        self.match_cache.clear();
+        self.sweeps.clear();
+        self.levels = None;
+        self.times = None;
+        self.checkpoints = None;
+        self.edits += 1;
+        self.filters.clear();
+
+        let mut status = 0;
+        // snapshots and frozen engines share the old mapping, which is only safe to keep
+        // reading while it's whole
+        if !old_whole && self.shared() {
+            self.snapshots.clear();
+            for flag in self.freezes.drain(..).filter_map(|flag| flag.upgrade()) {
+                flag.store(true, Ordering::Relaxed);
+            }
+            status |= REOPEN_CLOSED_SNAPSHOTS;
+        }
+        // still scanning ones come back finished, whatever was parked is parked again. the
+        // old content is gone by now, a cancel would only lose the filters.
+        let slice = std::mem::replace(&mut self.filter_slice, 0);
+        let parked_ids: Vec<u64> = parked.iter().map(|&(id, _)| id).collect();
+        for (id, setup) in setups.into_iter().chain(parked) {
+            match ops::shielded(|| self.replay(&setup.chain)) {
+                Ok(Some(rebuilt)) => {
+                    if let Some(view) = self.filters.remove(&rebuilt) {
+                        self.filters.insert(id, view);
+                        self.apply_setup(id, &setup);
+                    }
+                }
+                _ => status |= REOPEN_CLOSED_FILTERS,
+            }
+        }
+        for id in parked_ids {
+            self.filter_park(id);
+        }
+        self.filter_slice = slice;
+        let detail = if dropped_edits { "rotated or truncated, edits dropped" } else { "rotated or truncated" };
+        self.audit.record("reopen", detail.to_string());
+        events::emit(events::EVENT_ROTATED, self.total_lines() as u64, status as u64);
+        Ok(status)
+    }
+
+    // what reopen keeps in front of the new file: the lines kept from earlier rotations plus
+    // the tail of the file that just got rotated out, the oldest dropped past keep_rotated
+    fn rotated_out(&mut self, path: &Path) -> Vec<String> {
+        if self.keep_rotated == 0 {
+            return Vec::new();
+        }
+        let mut lines = Vec::new();
+        self.for_each_line(0, self.rotated_lines, |_, bytes| {
+            lines.push(String::from_utf8_lossy(bytes).into_owned());
+        });
+        if let Some(target) = rotate::rotation_target(path, self.watch.file()) {
+            lines.extend(rotate::tail_lines(&target, self.keep_rotated, &mut self.retry).unwrap_or_default());
+        }
+        let mut bytes: usize = lines.iter().map(|l| l.len() + 1).sum();
+        let mut drop = 0;
+        while bytes > self.keep_rotated && drop < lines.len() {
+            bytes -= lines[drop].len() + 1;
+            drop += 1;
+        }
+        lines.drain(..drop);
+        lines
+    }
+
+    // a native watch (see watch.rs) went with the old file, the new one is watched the same way
+    fn replace_watch(&mut self, mut watch: Watch) {
+        if let Some(native) = self.watch.native() {
+            if let Err(err) = watch.resume_native(native) {
+                diag::io_failure(&err);
+            }
+        }
+        self.watch = watch;
+    }
+
+    fn line_to_byte_offset(&self, line: usize) -> usize {
+        if line >= self.original_total_lines {
+            return self.mmap.len();
+        }
+        
+        // find the closest chunk behind our target line (crucial for :LogJump speed).
+        // a chunk's start_line is the line *containing* its first byte, so a chunk that
+        // starts mid-line only works for lines after that one.
+        let idx = self.chunks.partition_point(|c| c.start_line < line);
+        let chunk_idx = match self.chunks.get(idx) {
+            Some(c) if c.start_line == line && is_line_start(&self.mmap, c.byte_offset) => idx,
+            _ => idx.saturating_sub(1),
+        };
+        
+        let chunk = &self.chunks[chunk_idx];
+        let mut offset = chunk.byte_offset;
+        let mut skip = line - chunk.start_line;
+        
+        // walk the rest of the bytes manually until we hit the exact line
+        while skip > 0 && offset < self.mmap.len() {
+            let slice = &self.mmap[offset..];
+            if let Some(pos) = memchr2(b'\n', b'\r', slice) {
+                offset += pos + 1;
+                if slice[pos] == b'\r' && offset < self.mmap.len() && self.mmap[offset] == b'\n' {
+                    offset += 1; // skip the \n of a \r\n pair
+                }
+                skip -= 1;
+            } else {
+                offset = self.mmap.len();
+                break;
+            }
+        }
+        offset
+    }
+
+    fn get_original_bytes(&self, start_line: usize, line_count: usize) -> &[u8] {
+        if line_count == 0 {
+            return &[];
+        }
+        let start = self.line_to_byte_offset(start_line);
+        let end = self.line_to_byte_offset(start_line + line_count);
+        &self.mmap[start..end]
+    }
+
+    fn total_lines(&self) -> usize {
+        self.pieces.iter().map(|p| p.line_count()).sum()
+    }
+
+    // walks logical lines [start_line, start_line + num_lines) handing out raw bytes, no
+    // terminators
+    fn for_each_line(&self, start_line: usize, num_lines: usize, mut f: impl FnMut(usize, &[u8])) {
+        let end = start_line.saturating_add(num_lines).min(self.total_lines());
+        let (mut piece_idx, mut offset) = self.find_piece_idx(start_line);
+        let mut logical = start_line;
+
+        while logical < end && piece_idx < self.pieces.len() {
+            let piece = &self.pieces[piece_idx];
+            let take = (piece.line_count() - offset).min(end - logical);
+            match piece {
+                Piece::Original { start_line: p_start, .. } => {
+                    let mut pos = self.line_to_byte_offset(p_start + offset);
+                    for _ in 0..take {
+                        let line_end = memchr2(b'\n', b'\r', &self.mmap[pos..])
+                            .map_or(self.mmap.len(), |p| pos + p);
+                        f(logical, &self.mmap[pos..line_end]);
+                        pos = skip_line_break(&self.mmap, line_end);
+                        logical += 1;
+                    }
+                }
+                Piece::Memory { start_idx, .. } => {
+                    for line in &self.memory_buffer[start_idx + offset..start_idx + offset + take] {
+                        f(logical, line.as_bytes());
+                        logical += 1;
                     }
                 }
             }
+            offset = 0;
+            piece_idx += 1;
+        }
+    }
+
+    // whether a logical line lives in memory (typed or changed this session) rather than the file.
+    // lines kept from a rotated out file live there too, but nobody typed them
+    fn line_edited(&self, line: usize) -> bool {
+        if line < self.rotated_lines {
+            return false;
+        }
+        let (piece_idx, _) = self.find_piece_idx(line);
+        matches!(self.pieces.get(piece_idx), Some(Piece::Memory { .. }))
+    }
+
+    // returns (piece_index, line_offset_inside_piece)
+    fn find_piece_idx(&self, logical_line: usize) -> (usize, usize) {
+        let mut current = 0;
+        for (i, piece) in self.pieces.iter().enumerate() {
+            let count = piece.line_count();
+            if logical_line < current + count {
+                return (i, logical_line - current);
+            }
+            current += count;
+        }
+        (self.pieces.len(), 0)
+    }
+
+    fn split_piece_at(&mut self, piece_idx: usize, offset: usize) {
+        if offset == 0 || piece_idx >= self.pieces.len() {
+            return;
+        }
+        if offset >= self.pieces[piece_idx].line_count() {
+            return;
+        }
+        let (head, tail) = self.pieces[piece_idx].split(offset);
+        self.pieces[piece_idx] = head;
+        self.pieces.insert(piece_idx + 1, tail);
+    }
+
+    fn apply_edit(&mut self, start_line: usize, num_deleted: usize, new_text: &str) {
+        let mut lines = edit_lines(new_text);
+        self.keep_escapes(start_line, num_deleted, &mut lines);
+        self.replace_lines(start_line, num_deleted, lines);
+    }
+
+    // with strip_ansi on the plugin only has lines without their escapes, and edits send
+    // them back that way: a line replacing one that had them gets them back (ansi::restore).
+    // lines are paired up in order, ones that come with escapes of their own are left be.
+    fn keep_escapes(&self, start_line: usize, num_deleted: usize, lines: &mut [String]) {
+        if !self.strip_ansi {
+            return;
+        }
+        let paired = num_deleted.min(lines.len());
+        self.for_each_line(start_line, paired, |line, bytes| {
+            let new = &mut lines[line - start_line];
+            if memchr::memchr(0x1b, bytes).is_some() && !new.contains('\x1b') {
+                *new = ansi::restore(&String::from_utf8_lossy(bytes), new);
+            }
+        });
+    }
+
+    fn replace_lines(&mut self, start_line: usize, num_deleted: usize, lines: Vec<String>) {
+        if !self.editable() {
+            return;
+        }
+        self.edits += 1;
+        self.audit.record(
+            "edit",
+            format!(
+                "line {}: {} removed, {} added: {}",
+                start_line,
+                num_deleted,
+                lines.len(),
+                audit::preview(&lines)
+            ),
+        );
+        self.splice(start_line, num_deleted, lines);
+    }
+
+    // a burst of edits (what one :s or undo sends through on_lines) as one: each is against
+    // the content the ones before it left, like separate apply_edit calls, but the filter
+    // views and block cache see a single change and the audit trail a single entry.
+    fn apply_edits(&mut self, mut edits: Vec<(usize, usize, Vec<String>)>) -> bool {
+        if !self.editable() {
+            return false;
+        }
+        if edits.is_empty() {
+            return true;
+        }
+        self.edits += 1;
+        let first = edits.iter().map(|(start, ..)| *start).min().unwrap_or(0);
+        let removed: usize = edits.iter().map(|(_, deleted, _)| deleted).sum();
+        let added: usize = edits.iter().map(|(.., lines)| lines.len()).sum();
+        self.audit.record("edits", format!("{} edits from line {}: {} removed, {} added", edits.len(), first, removed, added));
+        // each one after the lines the one before it left, the usual burst (a :s over a
+        // range, on_lines events of one change) goes in a single walk over the pieces
+        let down_the_file = edits.windows(2).all(|w| w[1].0 >= w[0].0 + w[0].2.len());
+        if down_the_file {
+            // the lines they replace, where they are before any of them
+            let mut shift = 0isize;
+            for (start_line, num_deleted, lines) in &mut edits {
+                self.keep_escapes((*start_line as isize - shift) as usize, *num_deleted, lines);
+                shift += lines.len() as isize - *num_deleted as isize;
+            }
+            self.splice_all(edits);
+        } else {
+            for (start_line, num_deleted, mut lines) in edits {
+                self.keep_escapes(start_line, num_deleted, &mut lines);
+                self.splice(start_line, num_deleted, lines);
+            }
+        }
+        self.merge_pieces();
+        true
+    }
+
+    // apply_edits for edits that go down the file: moved back to where they start in the
+    // content before the batch they don't overlap, so the new table is built in one pass
+    fn splice_all(&mut self, edits: Vec<(usize, usize, Vec<String>)>) {
+        let mut walk = PieceWalk { pieces: std::mem::take(&mut self.pieces).into_iter(), current: None };
+        let mut pieces = Vec::new();
+        let (mut walked, mut shift) = (0, 0isize);
+        for (start_line, num_deleted, lines) in edits {
+            self.shift_with_edit(start_line, num_deleted, lines.len());
+            let before = (start_line as isize - shift) as usize;
+            walk.take(before - walked, Some(&mut pieces));
+            walk.take(num_deleted, None);
+            walked = before + num_deleted;
+            shift += lines.len() as isize - num_deleted as isize;
+            if !lines.is_empty() {
+                let start_idx = self.memory_buffer.len();
+                pieces.push(Piece::Memory { start_idx, line_count: lines.len() });
+                Arc::make_mut(&mut self.memory_buffer).extend(lines);
+            }
+        }
+        walk.rest(&mut pieces);
+        self.pieces = pieces;
+    }
+
+    // what an edit moves besides the pieces
+    fn shift_with_edit(&mut self, start_line: usize, num_deleted: usize, added: usize) {
+        // an edit touching the rotated out lines stays with them, it's not the file's to save
+        if start_line < self.rotated_lines {
+            let kept = self.rotated_lines - num_deleted.min(self.rotated_lines - start_line);
+            self.rotated_lines = kept + added;
+        }
+        json::shift(&mut self.expansions, start_line, num_deleted, added);
+    }
+
+    // the actual piece surgery: drop num_deleted lines at start_line, put `lines` there
+    fn splice(&mut self, start_line: usize, num_deleted: usize, lines: Vec<String>) {
+        self.shift_with_edit(start_line, num_deleted, lines.len());
+        let (mut piece_idx, offset) = self.find_piece_idx(start_line);
+
+        if piece_idx < self.pieces.len() {
+            self.split_piece_at(piece_idx, offset);
+            if offset > 0 {
+                piece_idx += 1;
+            }
+        }
+
+        let mut remaining_delete = num_deleted;
+        
+        // nuke pieces fully contained in the deletion range
+        while remaining_delete > 0 && piece_idx < self.pieces.len() {
+            let count = self.pieces[piece_idx].line_count();
+            if count <= remaining_delete {
+                self.pieces.remove(piece_idx);
+                remaining_delete -= count;
+            } else {
+                // partial overlap, split and drop the front
+                self.split_piece_at(piece_idx, remaining_delete);
+                self.pieces.remove(piece_idx);
+                remaining_delete = 0;
+            }
+        }
+
+        if !lines.is_empty() {
+            let start_idx = self.memory_buffer.len();
+            let line_count = lines.len();
+            Arc::make_mut(&mut self.memory_buffer).extend(lines);
+            self.pieces.insert(piece_idx, Piece::Memory { start_idx, line_count });
+        }
+    }
+
+    // neighbours that continue each other (a line of the file edited over and over, then its
+    // original neighbours again) become one piece, so a burst doesn't leave the table longer
+    // than the edits in it need
+    fn merge_pieces(&mut self) {
+        self.pieces.dedup_by(|next, prev| match (prev, next) {
+            (Piece::Original { start_line, line_count }, Piece::Original { start_line: next_start, line_count: next_count })
+                if *start_line + *line_count == *next_start =>
+            {
+                *line_count += *next_count;
+                true
+            }
+            (Piece::Memory { start_idx, line_count }, Piece::Memory { start_idx: next_start, line_count: next_count })
+                if *start_idx + *line_count == *next_start =>
+            {
+                *line_count += *next_count;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    // lines fed by the plugin instead of read from the file (a job's output, an rpc stream),
+    // hung off the end as memory. text not ending in a line break leaves its last line open
+    // and the next append continues it, so output arriving in arbitrary pieces ends up the
+    // way it would in a file.
+    fn append(&mut self, text: &str) {
+        if text.is_empty() || !self.editable() {
+            return;
+        }
+        if self.paused {
+            self.held.push_str(text);
+            return;
+        }
+        let tail_is_ours = matches!(self.pieces.last(),
+            Some(Piece::Memory { start_idx, line_count }) if start_idx + line_count == self.memory_buffer.len());
+        let mut lines: Vec<String> = text.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l).to_string()).collect();
+        let open = !text.ends_with('\n');
+        if !open {
+            lines.pop(); // split leaves an empty string after the last \n
+        }
+        if self.append_open && tail_is_ours {
+            let first = lines.remove(0);
+            if let Some(last) = Arc::make_mut(&mut self.memory_buffer).last_mut() {
+                last.push_str(&first);
+            }
+        }
+        self.append_open = open;
+
+        let added = lines.len();
+        match self.pieces.last_mut() {
+            Some(Piece::Memory { line_count, .. }) if tail_is_ours => *line_count += added,
+            _ if added > 0 => self.pieces.push(Piece::Memory {
+                start_idx: self.memory_buffer.len(),
+                line_count: added,
+            }),
+            _ => {}
+        }
+        Arc::make_mut(&mut self.memory_buffer).extend(lines);
+        self.edits += 1;
+        self.audit.record("append", format!("{} lines", added));
+        self.throughput.record(added, text.len());
+        self.unchecked_bytes += text.len();
+        self.enforce_retention();
+    }
+
+    // the notes of a log on disk. None for a pipe, bytes or a descriptor: there's no file to
+    // keep them next to, or none anyone else would find.
+    fn notes(&mut self) -> Option<&mut Notes> {
+        if self.pipe.is_some() || self.journal || self.frozen.is_some() || self.watch.path().as_os_str().is_empty() {
+            return None;
+        }
+        let path = self.watch.path().to_path_buf();
+        Some(self.notes.get_or_insert_with(|| Notes::new(&path)))
+    }
+
+    fn snapshot(&mut self) -> u64 {
+        let id = self.next_snapshot;
+        self.next_snapshot += 1;
+        let snapshot = self.capture();
+        self.snapshots.insert(id, snapshot);
+        id
+    }
+
+    fn capture(&self) -> Snapshot {
+        Snapshot {
+            mmap: self.mmap.clone(),
+            chunks: self.chunks.clone(),
+            original_total_lines: self.original_total_lines,
+            pieces: self.pieces.clone(),
+            memory_buffer: self.memory_buffer.clone(),
+            rotated_lines: self.rotated_lines,
+        }
+    }
+
+    // a snapshot as an engine of its own, for work (an export, a search) that runs on another
+    // thread while this one keeps being edited: its own lock, nothing shared that either side
+    // changes. the match cache is copied (it's keyed on the mapped file, which is the same).
+    // it's read-only for good, and closed like a snapshot when the file is truncated in place.
+    fn freeze(&mut self) -> std::io::Result<Engine> {
+        let snapshot = self.capture();
+        let mut frozen = self.freeze_capture(snapshot)?;
+        frozen.match_cache = self.match_cache.clone();
+        Ok(frozen)
+    }
+
+    // freeze on content captured earlier (a snapshot's), with nothing cached: the live
+    // engine's searches may have been made on lines the capture doesn't have
+    fn freeze_capture(&mut self, snapshot: Snapshot) -> std::io::Result<Engine> {
+        let file = self.watch.file().try_clone()?;
+        // no path: nothing to reopen or tell rotation by
+        let watch = Watch::new(PathBuf::new(), file, &snapshot.mmap);
+        let mut frozen =
+            Engine::with_content(snapshot.mmap, snapshot.chunks, snapshot.original_total_lines, watch, self.options.retry(), &self.options);
+        frozen.pieces = snapshot.pieces;
+        frozen.memory_buffer = snapshot.memory_buffer;
+        frozen.rotated_lines = snapshot.rotated_lines;
+        frozen.templates = self.templates.clone();
+        frozen.columns = self.columns.clone();
+        frozen.advised = self.advised;
+        let closed = Arc::new(AtomicBool::new(false));
+        self.freezes.retain(|flag| flag.strong_count() > 0);
+        self.freezes.push(Arc::downgrade(&closed));
+        frozen.frozen = Some(closed);
+        Ok(frozen)
+    }
+
+    // whether a snapshot or a frozen engine still reads the current mapping
+    fn shared(&self) -> bool {
+        !self.snapshots.is_empty() || self.freezes.iter().any(|flag| flag.strong_count() > 0)
+    }
+
+    // false for edits on a frozen engine (a misuse, nobody means to) or one opened read-only
+    // (an error, the user asked for it). nothing goes into memory_buffer either way.
+    fn editable(&self) -> bool {
+        if self.frozen.is_some() {
+            diag::misuse(|| "edit on a frozen snapshot, it's read-only".to_string());
+            return false;
+        }
+        if self.options.read_only {
+            diag::io_failure(&diag::read_only());
+            return false;
+        }
+        true
+    }
+
+    // runs `f` with a snapshot's content in place of the live one. reads only: nothing keyed
+    // by content (caches, filters, the severity index) may be touched in there.
+    fn with_snapshot<R>(&mut self, id: u64, f: impl FnOnce(&mut Self) -> R) -> Option<R> {
+        let mut snapshot = self.snapshots.remove(&id)?;
+        self.swap_content(&mut snapshot);
+        let result = f(self);
+        self.swap_content(&mut snapshot);
+        self.snapshots.insert(id, snapshot);
+        Some(result)
+    }
+
+    fn swap_content(&mut self, snapshot: &mut Snapshot) {
+        std::mem::swap(&mut self.mmap, &mut snapshot.mmap);
+        std::mem::swap(&mut self.chunks, &mut snapshot.chunks);
+        std::mem::swap(&mut self.original_total_lines, &mut snapshot.original_total_lines);
+        std::mem::swap(&mut self.pieces, &mut snapshot.pieces);
+        std::mem::swap(&mut self.memory_buffer, &mut snapshot.memory_buffer);
+        std::mem::swap(&mut self.rotated_lines, &mut snapshot.rotated_lines);
+    }
+
+    // a line's bytes as the text get_block hands out: a binary one as its hex preview,
+    // invalid bytes replaced and escapes stripped on anything else
+    fn shown<'a>(&self, bytes: &'a [u8]) -> Cow<'a, str> {
+        match binary::shown(bytes).filter(|_| self.hex_binary) {
+            Some(preview) => preview,
+            None => ansi::shown(bytes, self.strip_ansi),
+        }
+    }
+
+    // one line as the text get_block would hand out for it
+    fn line_text(&self, line: usize) -> Option<String> {
+        let mut text = None;
+        self.for_each_line(line, 1, |_, bytes| text = Some(self.shown(bytes).into_owned()));
+        text
+    }
+
+    // the path of the code a line was logged from, and its line in the file there (0 when it
+    // doesn't say), see fields::source_location. the path goes in the get_block buffer.
+    fn source_location(&mut self, line: usize) -> Option<(&str, u64)> {
+        let (path, at) = fields::source_location(&self.templates, &self.line_text(line)?)?;
+        self.last_block = path;
+        Some((&self.last_block, at))
+    }
+
+    // "key\tvalue" rows for every field on a line, logfmt or json (see fields::pairs). tabs and
+    // line breaks in a value become spaces, a row is one line. same buffer as get_block.
+    fn line_fields(&mut self, line: usize) -> &str {
+        let mut block = std::mem::take(&mut self.last_block);
+        block.clear();
+        if let Some(text) = self.line_text(line) {
+            for (key, value) in self.pairs(&text) {
+                block.push_str(key);
+                block.push('\t');
+                block.extend(value.chars().map(|c| if matches!(c, '\t' | '\n' | '\r') { ' ' } else { c }));
+                block.push('\n');
+            }
+        }
+        self.last_block = block;
+        &self.last_block
+    }
+
+    // "text\tcount" rows, most common first: the field names used in a range of lines, or with
+    // a key, the values that field takes there. for completion and building filters.
+    fn field_counts(&mut self, start_line: usize, num_lines: usize, key: Option<&str>) -> &str {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        self.for_each_line(start_line, num_lines, |_, bytes| {
+            let text = ansi::shown(bytes, self.strip_ansi);
+            for (name, value) in self.pairs(&text) {
+                match key {
+                    None => *counts.entry(name.to_string()).or_default() += 1,
+                    Some(key) if key == name => *counts.entry(value.replace(['\t', '\n', '\r'], " ")).or_default() += 1,
+                    Some(_) => {}
+                }
+            }
+        });
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut block = std::mem::take(&mut self.last_block);
+        block.clear();
+        for (text, count) in counts {
+            block.push_str(&format!("{}\t{}\n", text, count));
+        }
+        self.last_block = block;
+        &self.last_block
+    }
+
+    // the values a field takes in a range with how often, "value\tcount" rows most common
+    // first, at most `max`: the paths, hosts or status codes that come up the most. with a
+    // regex instead of a field, what its first group caught (the whole match without one).
+    // slices of the range are counted in parallel. same buffer as get_block.
+    fn value_counts(&mut self, start_line: usize, num_lines: usize, field: &str, regex: Option<&regex::Regex>, max: usize) -> &str {
+        let end = start_line.saturating_add(num_lines).min(self.total_lines());
+        let slices: Vec<usize> = (start_line..end).step_by(SCAN_SLICE).collect();
+        let engine = &*self;
+        // entries left to hand out. a value two slices both saw takes one from each, so the
+        // maps never hold more than MAX_VALUES between them and merging them can't grow past it
+        let room = AtomicUsize::new(MAX_VALUES);
+        let counts = pool::install(|| {
+            slices
+                .into_par_iter()
+                .map(|from| {
+                    let mut counts: HashMap<String, u64> = HashMap::new();
+                    engine.for_each_line(from, SCAN_SLICE.min(end - from), |_, bytes| {
+                        if binary::is_binary(bytes) {
+                            return;
+                        }
+                        let text = ansi::shown(bytes, engine.strip_ansi);
+                        let value = match regex {
+                            Some(regex) => regex
+                                .captures(&text)
+                                .and_then(|caught| caught.iter().skip(1).flatten().next().or(caught.get(0)))
+                                .map(|caught| Cow::Borrowed(caught.as_str())),
+                            None => engine.lookup(&text, field),
+                        };
+                        let Some(value) = value else {
+                            return;
+                        };
+                        if let Some(count) = counts.get_mut(value.as_ref()) {
+                            *count += 1;
+                        } else if room.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| left.checked_sub(1)).is_ok() {
+                            counts.insert(value.into_owned(), 1);
+                        }
+                    });
+                    counts
+                })
+                .reduce(HashMap::new, |mut a, mut b| {
+                    if a.len() < b.len() {
+                        std::mem::swap(&mut a, &mut b);
+                    }
+                    for (value, count) in b {
+                        *a.entry(value).or_default() += count;
+                    }
+                    a
+                })
+        });
+        let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(max);
+        let mut block = std::mem::take(&mut self.last_block);
+        block.clear();
+        for (value, count) in counts {
+            block.push_str(&format!("{}\t{}\n", value.replace(['\t', '\n', '\r'], " "), count));
+        }
+        self.last_block = block;
+        &self.last_block
+    }
+
+    // what a line says: its msg field, or for plain text the line past its stamp and level
+    fn message<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self.lookup(text, "msg") {
+            Some(message) if message != text => message,
+            _ => Cow::Borrowed(fields::plain_message(text)),
+        }
+    }
+
+    // what a line is compared on for runs of repeats: the line with its timestamp cut out,
+    // or with `templated` its message as a template (see drain.rs), so "retry 3 failed" and
+    // "retry 4 failed" repeat too
+    fn repeat_key(&self, bytes: &[u8], templated: bool) -> String {
+        let text = ansi::shown(bytes, self.strip_ansi);
+        if templated {
+            return drain::template(&self.message(&text));
+        }
+        match time::find(&text, &self.templates).and_then(|found| found.span) {
+            Some((col, len)) => format!("{}{}", &text[..col], &text[col + len..]),
+            None => text.into_owned(),
+        }
+    }
+
+    // flat (first, last) pairs, one per run of two or more lines in the block that repeat the
+    // one before them (see repeat_key): retry storms, a loop logging the same thing. runs
+    // are cut where the block ends.
+    fn repeat_runs(&mut self, start_line: usize, num_lines: usize, templated: bool) -> &[u64] {
+        let mut out = std::mem::take(&mut self.last_spans);
+        out.clear();
+        let mut previous: Option<String> = None;
+        let mut run: Option<(usize, usize)> = None;
+        self.for_each_line(start_line, num_lines, |line, bytes| {
+            let key = self.repeat_key(bytes, templated);
+            if previous.as_ref() == Some(&key) {
+                run = Some((run.map_or(line - 1, |(first, _)| first), line));
+            } else if let Some((first, last)) = run.take() {
+                out.extend([first as u64, last as u64]);
+            }
+            previous = Some(key);
+        });
+        if let Some((first, last)) = run {
+            out.extend([first as u64, last as u64]);
+        }
+        self.last_spans = out;
+        &self.last_spans
+    }
+
+    // the whole run of repeats `line` is in, (first, last), however far it goes either way.
+    // (line, line) for a line that repeats nothing, None past the end.
+    fn repeat_run(&self, line: usize, templated: bool) -> Option<(usize, usize)> {
+        let total = self.total_lines();
+        if line >= total {
+            return None;
+        }
+        let mut key = String::new();
+        self.for_each_line(line, 1, |_, bytes| key = self.repeat_key(bytes, templated));
+        // up, in ever bigger steps, to the line after the last one that's different
+        let (mut first, mut step) = (line, 64);
+        while first > 0 {
+            let start = first.saturating_sub(step);
+            let mut different = None;
+            self.for_each_line(start, first - start, |line, bytes| {
+                if self.repeat_key(bytes, templated) != key {
+                    different = Some(line);
+                }
+            });
+            match different {
+                Some(different) => {
+                    first = different + 1;
+                    break;
+                }
+                None => first = start,
+            }
+            step *= 2;
+        }
+        let (mut last, mut step, mut ended) = (line, 64, false);
+        while !ended && last + 1 < total {
+            let take = step.min(total - last - 1);
+            self.for_each_line(last + 1, take, |line, bytes| {
+                if !ended && self.repeat_key(bytes, templated) == key {
+                    last = line;
+                } else {
+                    ended = true;
+                }
+            });
+            step *= 2;
+        }
+        Some((first, last))
+    }
+
+    // the messages in a range grouped into templates (see drain.rs), "count\tfirst line\ttemplate"
+    // rows, most common first, at most `max`. a line's message is its msg field, or for plain
+    // text the line past its stamp and level. slices of the range are mined in
+    // parallel. binary lines are left out. same buffer as get_block.
+    fn templates(&mut self, start_line: usize, num_lines: usize, max: usize) -> &str {
+        let end = start_line.saturating_add(num_lines).min(self.total_lines());
+        let slices: Vec<usize> = (start_line..end).step_by(SCAN_SLICE).collect();
+        let engine = &*self;
+        let drain = pool::install(|| {
+            slices
+                .into_par_iter()
+                .map(|from| {
+                    let mut drain = Drain::default();
+                    engine.for_each_line(from, SCAN_SLICE.min(end - from), |line, bytes| {
+                        if binary::is_binary(bytes) {
+                            return;
+                        }
+                        drain.add(line, &engine.message(&ansi::shown(bytes, engine.strip_ansi)));
+                    });
+                    drain
+                })
+                .reduce(Drain::default, Drain::merge)
+        });
+        let mut block = std::mem::take(&mut self.last_block);
+        block.clear();
+        for template in drain.top(max) {
+            block.push_str(&format!("{}\t{}\t{}\n", template.count, template.first_line, template.words.join(" ")));
+        }
+        self.last_block = block;
+        &self.last_block
+    }
+
+    // only some fields of every line in a block, one row per line, lined up in columns: the
+    // de-noised view of a json/logfmt log. fields go through fields::lookup, so "ts", "level"
+    // and "msg" find their usual aliases and the plain text fallbacks. a line with none of
+    // them (a stack frame) is its own row, unchanged. same buffer as get_block.
+    fn project(&mut self, start_line: usize, num_lines: usize, names: &[&str]) -> &str {
+        let mut rows: Vec<Result<Vec<String>, String>> = Vec::new();
+        // a table's columns are as wide as the sample said, so they don't shift while scrolling
+        let mut widths: Vec<usize> = match &self.columns {
+            Some(columns) => names
+                .iter()
+                .map(|name| columns.index(name).map_or(0, |index| columns.widths[index].min(MAX_COLUMN)))
+                .collect(),
+            None => vec![0; names.len()],
+        };
+        self.for_each_line(start_line, num_lines, |_, bytes| {
+            let text = ansi::shown(bytes, self.strip_ansi);
+            let mut values: Vec<Option<Cow<str>>> = names.iter().map(|name| self.lookup(&text, name)).collect();
+            // msg falls back to the whole line, that alone doesn't make it a structured one
+            let whole = |value: &Option<Cow<str>>| value.as_deref() == Some(&*text);
+            if values.iter().all(|value| value.is_none() || whole(value)) {
+                rows.push(Err(text.replace('\t', " ")));
+                return;
+            }
+            // a plain text line's message is what's left after the stamp and level in front
+            for i in 0..values.len() {
+                if whole(&values[i]) {
+                    let mut rest = text.as_ref();
+                    let others: Vec<&str> = values.iter().flatten().map(|v| v.as_ref()).filter(|v| !v.is_empty() && *v != text).collect();
+                    while let Some(after) =
+                        others.iter().find_map(|value| rest.trim_start_matches([' ', '[', ']']).strip_prefix(value))
+                    {
+                        rest = after;
+                    }
+                    values[i] = Some(Cow::Owned(rest.trim_start_matches([' ', '[', ']', ':']).to_string()));
+                }
+            }
+            // bunyan's "level":30 reads as info
+            for (name, value) in names.iter().zip(values.iter_mut()) {
+                if let Some(label) = value.as_deref().filter(|_| fields::is_level_field(name)).and_then(fields::level_label) {
+                    *value = Some(Cow::Borrowed(label));
+                }
+            }
+            let values: Vec<String> =
+                values.into_iter().map(|value| value.unwrap_or_default().replace(['\t', '\n', '\r'], " ")).collect();
+            for (width, value) in widths.iter_mut().zip(&values) {
+                *width = (*width).max(value.chars().count().min(MAX_COLUMN));
+            }
+            rows.push(Ok(values));
+        });
+
+        let mut block = std::mem::take(&mut self.last_block);
+        block.clear();
+        for row in rows {
+            match row {
+                Ok(values) => {
+                    let last = values.len() - 1;
+                    for (i, value) in values.iter().enumerate() {
+                        if i == last {
+                            block.push_str(value);
+                            break;
+                        }
+                        if value.chars().count() > widths[i] {
+                            block.extend(value.chars().take(widths[i] - 1));
+                            block.push('…');
+                        } else {
+                            block.push_str(&format!("{:width$}", value, width = widths[i]));
+                        }
+                        block.push_str("  ");
+                    }
+                }
+                Err(text) => block.push_str(&text),
+            }
+            block.push('\n');
+        }
+        self.last_block = block;
+        &self.last_block
+    }
+
+    // a line's fields: the cells of a row under their column names for a csv/tsv file,
+    // fields::pairs for anything else
+    fn pairs<'a>(&'a self, text: &'a str) -> Vec<(&'a str, Cow<'a, str>)> {
+        match &self.columns {
+            Some(columns) => columns.pairs(text),
+            None => fields::pairs(&self.templates, text),
+        }
+    }
+
+    // one field of a line the same way. in a table only columns are fields, the plain text
+    // fallbacks would make a message out of the whole row
+    fn lookup<'a>(&self, text: &'a str, name: &str) -> Option<Cow<'a, str>> {
+        match &self.columns {
+            Some(columns) => columns.get(text, name),
+            None => fields::lookup(&self.templates, text, name),
+        }
+    }
+
+    // reads the file as csv/tsv from now on (None: as lines again), the first line naming
+    // the columns. delimiter 0 finds out which it is from the first lines. cached searches
+    // and the severity index go, what a field is has changed; filters already open keep
+    // what they matched until the next edit rebuilds them.
+    fn set_delimited(&mut self, delimiter: Option<u8>) -> Result<(), String> {
+        self.columns = match delimiter {
+            Some(delimiter) => {
+                let mut lines = Vec::new();
+                self.for_each_line(0, delimited::SAMPLE + 1, |_, bytes| lines.push(String::from_utf8_lossy(bytes).into_owned()));
+                Some(Columns::detect(&lines, delimiter)?)
+            }
+            None => None,
+        };
+        self.match_cache.clear();
+        self.sweeps.clear();
+        self.levels = None;
+        Ok(())
+    }
+
+    // a line format of this engine's (see template.rs). the same lines may read differently
+    // now: what was worked out from their fields (levels, times, cached searches) goes.
+    fn register_format(&mut self, name: &str, template: &str) -> Result<(), String> {
+        self.templates.register(name, template)?;
+        self.forget_fields();
+        Ok(())
+    }
+
+    fn unregister_format(&mut self, name: &str) -> bool {
+        let known = self.templates.unregister(name);
+        if known {
+            self.forget_fields();
+        }
+        known
+    }
+
+    fn forget_fields(&mut self) {
+        self.levels = None;
+        self.times = None;
+        self.match_cache.clear();
+        self.sweeps.clear();
+    }
+
+    // cached searches matched the lines the other way
+    fn set_strip_ansi(&mut self, strip: bool) {
+        if strip != self.strip_ansi {
+            self.match_cache.clear();
+            self.sweeps.clear();
+        }
+        self.strip_ansi = strip;
+    }
+
+    // "name\tkind\twidth" rows for the columns of a csv/tsv file, in order (see
+    // delimited::Columns). None when it isn't read as one. same buffer as get_block.
+    fn columns(&mut self) -> Option<&str> {
+        let columns = self.columns.as_ref()?;
+        let mut block = std::mem::take(&mut self.last_block);
+        block.clear();
+        for ((name, kind), width) in columns.names.iter().zip(&columns.kinds).zip(&columns.widths) {
+            block.push_str(&format!("{}\t{}\t{}\n", name, kind, width));
+        }
+        self.last_block = block;
+        Some(&self.last_block)
+    }
+
+    // whether this looks like a json lines file, from its first lines
+    fn json_lines(&self) -> bool {
+        let mut lines = Vec::new();
+        self.for_each_line(0, 100, |_, bytes| lines.push(String::from_utf8_lossy(bytes).into_owned()));
+        json::looks_like_json_lines(lines.iter().map(String::as_str))
+    }
+
+    // show a json line pretty-printed over several lines. returns how many it takes up now,
+    // 0 when it isn't json. only the view changes: save still writes it on one line.
+    fn expand_json(&mut self, line: usize) -> usize {
+        if self.frozen.is_some() {
+            diag::misuse(|| "json expand on a frozen snapshot, it's read-only".to_string());
+            return 0;
+        }
+        if let Some(expansion) = self.expansions.iter().find(|e| (e.start..e.start + e.count).contains(&line)) {
+            return expansion.count;
+        }
+        let Some(text) = self.line_text(line) else {
+            return 0;
+        };
+        let Some(pretty) = json::pretty(&text) else {
+            return 0;
+        };
+        let (piece_idx, offset) = self.find_piece_idx(line);
+        let original = match self.pieces[piece_idx] {
+            Piece::Original { start_line, .. } if line >= self.rotated_lines => Some(start_line + offset),
+            _ => None,
+        };
+        let count = pretty.len();
+        self.splice(line, 1, pretty);
+        let at = self.expansions.partition_point(|e| e.start < line);
+        self.expansions.insert(at, Expansion { start: line, count, text, original });
+        self.edits += 1;
+        count
+    }
+
+    // put an expanded json line back on one line. returns the line it's on, None when `line`
+    // isn't part of an expansion (or was edited since, which makes it plain text).
+    fn collapse_json(&mut self, line: usize) -> Option<usize> {
+        let at = self.expansions.iter().position(|e| (e.start..e.start + e.count).contains(&line))?;
+        let expansion = self.expansions.remove(at);
+        match expansion.original {
+            // the file's own line again, not a copy: it searches and saves like it never moved
+            Some(original) => {
+                self.splice(expansion.start, expansion.count, Vec::new());
+                json::shift(&mut self.expansions, expansion.start, 0, 1);
+                let (mut piece_idx, offset) = self.find_piece_idx(expansion.start);
+                if piece_idx < self.pieces.len() {
+                    self.split_piece_at(piece_idx, offset);
+                    piece_idx += (offset > 0) as usize;
+                }
+                self.pieces.insert(piece_idx, Piece::Original { start_line: original, line_count: 1 });
+            }
+            None => self.splice(expansion.start, expansion.count, vec![expansion.text]),
+        }
+        self.merge_pieces();
+        self.edits += 1;
+        Some(expansion.start)
+    }
+
+    // break a line in two at byte column `col`. only that line gets copied into memory,
+    // the rest of the file stays on the mmap.
+    fn split_line(&mut self, line: usize, col: usize) -> bool {
+        if !self.editable() {
+            return false;
+        }
+        let Some(text) = self.line_text(line) else {
+            return false;
+        };
+        let mut col = col.min(text.len());
+        while !text.is_char_boundary(col) {
+            col -= 1;
+        }
+        let (head, tail) = text.split_at(col);
+        let lines = vec![head.to_string(), tail.to_string()];
+        self.replace_lines(line, 1, lines);
+        true
+    }
+
+    // gJ-style: `count` lines starting at `start` become one, glued with `separator`
+    fn join_lines(&mut self, start: usize, count: usize, separator: &str) -> bool {
+        if !self.editable() {
+            return false;
+        }
+        let count = count.min(self.total_lines().saturating_sub(start));
+        if count < 2 {
+            return false;
+        }
+        let mut parts = Vec::with_capacity(count);
+        self.for_each_line(start, count, |_, bytes| parts.push(String::from_utf8_lossy(bytes).into_owned()));
+        self.replace_lines(start, count, vec![parts.join(separator)]);
+        true
+    }
+
+    fn get_block(&mut self, start_line: usize, num_lines: usize) -> *const u8 {
+        self.last_block.clear();
+        if num_lines == 0 || start_line >= self.total_lines() {
+            return ptr::null();
+        }
+
+        let mut block = std::mem::take(&mut self.last_block);
+        self.append_lines(&mut block, start_line, num_lines);
+        self.last_block = block;
+
+        // C side expects a pointer. this gets overwritten next call, DO NOT keep it around.
+        self.last_block.as_ptr()
+    }
+
+    // one line without its line break, for get_line. a valid utf-8 line on the file is
+    // pointed at where it sits in the mmap, nothing copied. the rest (edited lines, invalid
+    // bytes to replace, escapes to strip) goes through last_block. either way it's good until
+    // the next call.
+    fn get_line(&mut self, line: usize) -> Option<(*const u8, usize)> {
+        let mut found = None;
+        self.for_each_line(line, 1, |_, bytes| found = Some((bytes.as_ptr(), bytes.len())));
+        let (ptr, len) = found?;
+        self.last_block.clear();
+        let bytes = unsafe { std::slice::from_raw_parts(ptr, len) };
+        let on_file = self.mmap.as_ptr_range().contains(&ptr);
+        let plain = (!self.strip_ansi || memchr::memchr(0x1b, bytes).is_none()) && !(self.hex_binary && binary::is_binary(bytes));
+        match std::str::from_utf8(bytes) {
+            Ok(_) if on_file && plain => Some((ptr, len)),
+            _ => {
+                let text = self.shown(bytes).into_owned();
+                self.last_block = text;
+                Some((self.last_block.as_ptr(), self.last_block.len()))
+            }
+        }
+    }
+
+    // the given lines in the given order, each ended by \n like get_block, into last_block.
+    // a line past the end comes out empty so the rest still line up. returns how many were.
+    fn get_lines(&mut self, lines: &[u64]) -> usize {
+        let mut block = std::mem::take(&mut self.last_block);
+        block.clear();
+        let mut missing = 0;
+        for &line in lines {
+            let mut found = false;
+            if let Ok(line) = usize::try_from(line) {
+                self.for_each_line(line, 1, |_, bytes| {
+                    block.push_str(&self.shown(bytes));
+                    found = true;
+                });
+            }
+            missing += usize::from(!found);
+            block.push('\n');
+        }
+        self.last_block = block;
+        missing
+    }
+
+    // stitch together pieces until we satisfy the requested line count
+    fn append_lines(&self, out: &mut String, start_line: usize, num_lines: usize) {
+        let (mut piece_idx, mut offset) = self.find_piece_idx(start_line);
+        let mut collected = 0;
+        let from = out.len();
+
+        while collected < num_lines && piece_idx < self.pieces.len() {
+            let piece = &self.pieces[piece_idx];
+            let count = piece.line_count() - offset;
+            let take = count.min(num_lines - collected);
+
+            match piece {
+                Piece::Original { start_line: p_start, .. } => {
+                    let start_byte = self.line_to_byte_offset(p_start + offset);
+                    let end_byte = self.line_to_byte_offset(p_start + offset + take);
+                    
+                    let bytes = &self.mmap[start_byte..end_byte];
+                    
+                    // logs are dirty. replace garbage bytes with  instead of failing silently,
+                    // and show lines that are nothing but garbage as hex
+                    if self.hex_binary {
+                        binary::push_lines(out, bytes);
+                    } else {
+                        out.push_str(&String::from_utf8_lossy(bytes));
+                    }
+                    if !out.ends_with('\n') && !out.is_empty() {
+                        out.push('\n');
+                    }
+                }
+                Piece::Memory { start_idx, .. } => {
+                    for i in 0..take {
+                        out.push_str(&self.memory_buffer[start_idx + offset + i]);
+                        out.push('\n');
+                    }
+                }
+            }
+            collected += take;
+            offset = 0;
+            piece_idx += 1;
+        }
+        if self.strip_ansi {
+            ansi::strip_from(out, from);
+        }
+    }
+
+    // (head lines, hidden lines, first tail line) once head/tail are clamped to the file
+    fn head_tail_split(&self, head: usize, tail: usize) -> (usize, usize, usize) {
+        let total = self.total_lines();
+        let head = head.min(total);
+        let tail = tail.min(total - head);
+        (head, total - head - tail, total - tail)
+    }
+
+    // first `head` lines, one marker line, last `tail` lines. the startup banner and
+    // the crash at the bottom without scrolling through the middle.
+    fn head_tail(&mut self, head: usize, tail: usize) -> &str {
+        let (head, hidden, tail_start) = self.head_tail_split(head, tail);
+        let mut block = std::mem::take(&mut self.last_block);
+        block.clear();
+        self.append_lines(&mut block, 0, head);
+        if hidden > 0 {
+            block.push_str(&format!("··· {} lines hidden ···\n", hidden));
+        }
+        self.append_lines(&mut block, tail_start, self.total_lines() - tail_start);
+        self.last_block = block;
+        &self.last_block
+    }
+
+    // row in the head/tail view -> logical line, None for the marker row
+    fn head_tail_line(&self, head: usize, tail: usize, row: usize) -> Option<usize> {
+        let (head, hidden, tail_start) = self.head_tail_split(head, tail);
+        if row < head {
+            return Some(row);
+        }
+        let tail_row = row - head;
+        let tail_row = match hidden {
+            0 => tail_row,
+            _ => tail_row.checked_sub(1)?, // the marker itself
+        };
+        let line = tail_start + tail_row;
+        (line < self.total_lines()).then_some(line)
+    }
+
+    // (rows, stride) of an overview. stride is in lines or in index chunks, 0 for `step`
+    // picks whatever fits the file into max_rows.
+    fn sample_plan(&self, mode: SampleMode, step: usize, max_rows: usize) -> (usize, usize) {
+        let units = match mode {
+            SampleMode::Lines => self.total_lines(),
+            SampleMode::Bytes => self.chunks.len(),
+        };
+        let stride = match (mode, step) {
+            (_, 0) => units.div_ceil(max_rows.max(1)),
+            (SampleMode::Lines, step) => step,
+            (SampleMode::Bytes, step) => step.div_ceil(self.options.chunk_size),
+        }
+        .max(1);
+        (units.div_ceil(stride).min(max_rows), stride)
+    }
+
+    // overview row -> logical line. byte samples never read more than a few bytes: the index
+    // already knows which line every chunk starts in. lines edited away map to where they were.
+    fn sample_line(&self, mode: SampleMode, step: usize, max_rows: usize, row: usize) -> Option<usize> {
+        let (rows, stride) = self.sample_plan(mode, step, max_rows);
+        if row >= rows {
+            return None;
+        }
+        let line = match mode {
+            SampleMode::Lines => row * stride,
+            SampleMode::Bytes => {
+                let chunk = &self.chunks[row * stride];
+                let original = chunk.start_line + !is_line_start(&self.mmap, chunk.byte_offset) as usize;
+                self.logical_from_original(original)
+            }
+        };
+        (line < self.total_lines()).then_some(line)
+    }
+
+    // where an original line sits in the logical content, or would if it was deleted
+    fn logical_from_original(&self, original: usize) -> usize {
+        let mut logical = 0;
+        for piece in &self.pieces {
+            if let Piece::Original { start_line, line_count } = piece {
+                if original < start_line + line_count {
+                    return logical + original.saturating_sub(*start_line);
+                }
+            }
+            logical += piece.line_count();
+        }
+        logical
+    }
+
+    // a quick overview of the whole file, one line per sample, for minimaps. same buffer
+    // and lifetime as get_block.
+    fn sample(&mut self, mode: SampleMode, step: usize, max_rows: usize) -> &str {
+        let (rows, _) = self.sample_plan(mode, step, max_rows);
+        let mut block = std::mem::take(&mut self.last_block);
+        block.clear();
+        for row in 0..rows {
+            match self.sample_line(mode, step, max_rows, row) {
+                Some(line) => self.append_lines(&mut block, line, 1),
+                None => block.push('\n'),
+            }
+        }
+        self.last_block = block;
+        &self.last_block
+    }
+
+    // builds the match index for a query on first use. after that n/N are just bit scans.
+    fn match_index(&mut self, mode: SearchMode, query: &[u8]) -> Result<Arc<MatchIndex>, String> {
+        let key = (mode, query.to_vec());
+        if let Some(index) = self.match_cache.get(&key) {
+            return Ok(index.clone());
+        }
+        let matcher = Matcher::compile(mode, query, self.columns.as_ref(), &self.templates, self.strip_ansi)?;
+        let index = Arc::new(self.without_evicted(MatchIndex::build(
+            &self.mmap,
+            &self.chunks,
+            self.original_total_lines,
+            matcher,
+        )));
+        // a scan cut short by log_engine_cancel is missing hits, nothing to keep
+        if ops::cancelled() {
+            return Err(ops::cancelled_error().to_string());
+        }
+        self.cache_index(key, index.clone());
+        Ok(index)
+    }
+
+    // into the match cache, dropping the least recently used past the options' max_memory
+    fn cache_index(&mut self, key: (SearchMode, Vec<u8>), index: Arc<MatchIndex>) {
+        self.match_cache.insert(key, index);
+        if self.options.max_memory > 0 {
+            self.match_cache.shrink_to(self.options.max_memory, |index| index.heap_bytes());
+        }
+    }
+
+    // total matching lines across the current logical content
+    fn count_matches(&self, index: &MatchIndex) -> usize {
+        self.pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Original { start_line, line_count } => {
+                    index.count_range(*start_line, start_line + line_count)
+                }
+                Piece::Memory { start_idx, line_count } => self.memory_buffer
+                    [*start_idx..start_idx + line_count]
+                    .iter()
+                    .filter(|line| index.matcher.is_match(line.as_bytes()))
+                    .count(),
+            })
+            .sum()
+    }
+
+    // every search lands in the history. how many lines it hits is counted when its index
+    // gets built, not again on every n/N after that.
+    fn searched(&mut self, mode: SearchMode, query: &[u8]) -> Result<Arc<MatchIndex>, String> {
+        self.expire_idle_caches();
+        let built = !self.match_cache.contains_key(&(mode, query.to_vec()));
+        let index = self.match_index(mode, query)?;
+        let hits = built.then(|| self.count_matches(&index));
+        self.history.record(mode, query, hits);
+        Ok(index)
+    }
+
+    fn search_forward(
+        &mut self,
+        mode: SearchMode,
+        query: &[u8],
+        start_line: usize,
+    ) -> Result<Option<usize>, String> {
+        if let Some(found) = self.lazy_search(mode, query, |engine, hits| engine.next_match(hits, start_line)) {
+            return found;
+        }
+        let index = self.searched(mode, query)?;
+        Ok(self.next_match(&*index, start_line))
+    }
+
+    fn search_backward(
+        &mut self,
+        mode: SearchMode,
+        query: &[u8],
+        start_line: usize,
+    ) -> Result<Option<usize>, String> {
+        if let Some(found) = self.lazy_search(mode, query, |engine, hits| engine.prev_match(hits, start_line)) {
+            return found;
+        }
+        let index = self.searched(mode, query)?;
+        Ok(self.prev_match(&*index, start_line))
+    }
+
+    // n/N on a big file without the query's index cached: rather than build the whole index
+    // first, the search scans the chunks it walks into (and a few past them) and leaves the
+    // rest for later. a stepped filter on the query has a scan going already, that one is
+    // used and its sweep skips what got scanned here. otherwise the scan is kept in `sweeps`
+    // for the next n/N to go on with, and cached like a built index once it's covered every
+    // chunk. the history gets the query right away and its count then.
+    fn lazy_search(
+        &mut self,
+        mode: SearchMode,
+        query: &[u8],
+        search: impl FnOnce(&Self, &Lazy) -> Option<usize>,
+    ) -> Option<Result<Option<usize>, String>> {
+        self.expire_idle_caches();
+        let key = (mode, query.to_vec());
+        if self.match_cache.contains_key(&key) {
+            return None;
+        }
+        // one that started before the file grew doesn't know the appended lines yet
+        let pending = self
+            .pending
+            .values()
+            .find(|p| p.cache_key.as_ref() == Some(&key) && p.index.total_lines == self.original_total_lines);
+        if let Some(pending) = pending {
+            let found = search(self, &pending.index.lazy(&self.mmap, &self.chunks)?);
+            if ops::cancelled() {
+                return Some(Err(ops::cancelled_error().to_string()));
+            }
+            self.history.record(mode, query, None);
+            return Some(Ok(found));
+        }
+
+        if self.sweeps.get(&key).is_none() && !self.sliced() {
+            return None;
+        }
+        if let Err(err) = self.sweep(&key) {
+            return Some(Err(err));
+        }
+        let sweep = self.sweeps.peek(&key)?;
+        let found = search(self, &sweep.lazy(&self.mmap, &self.chunks)?);
+        // what it scanned before the cancel stays scanned, the rest is redone next time
+        if ops::cancelled() {
+            return Some(Err(ops::cancelled_error().to_string()));
+        }
+        self.history.record(mode, query, None);
+        if sweep.done() {
+            self.finish_sweep(key);
+        }
+        Some(Ok(found))
+    }
+
+    // the scan lazy_search goes on with for `key`, started if there's none
+    fn sweep(&mut self, key: &(SearchMode, Vec<u8>)) -> Result<&mut PartialIndex, String> {
+        if self.sweeps.get(key).is_none() {
+            let matcher = Matcher::compile(key.0, &key.1, self.columns.as_ref(), &self.templates, self.strip_ansi)?;
+            let sweep = PartialIndex::new(&self.chunks, self.original_total_lines, matcher, None, false);
+            self.sweeps.insert(key.clone(), sweep);
+        }
+        self.sweeps.get_mut(key).ok_or_else(|| "no scan for the query".to_string())
+    }
+
+    // a scan that got through every chunk becomes a cached index like a built one, and the
+    // history gets its count
+    fn finish_sweep(&mut self, key: (SearchMode, Vec<u8>)) {
+        let Some(sweep) = self.sweeps.remove(&key) else {
+            return;
+        };
+        let index = Arc::new(self.without_evicted(sweep.into_index()));
+        self.history.counted(key.0, &key.1, self.count_matches(&index));
+        self.cache_index(key, index);
+    }
+
+    // a slice of the scan behind n/N on a big file, for the plugin to run between searches
+    // so the index gets complete (and counted) without any n/N waiting for all of it:
+    // (bytes scanned, bytes in the file, done). with a stepped filter on the query it's that
+    // filter's next slice. a query searched in full is done straight away.
+    fn search_step(&mut self, mode: SearchMode, query: &[u8]) -> Result<(usize, usize, bool), String> {
+        let len = self.mmap.len();
+        let key = (mode, query.to_vec());
+        if self.match_cache.contains_key(&key) {
+            return Ok((len, len, true));
+        }
+        let pending = self.pending.iter().find(|(_, p)| p.cache_key.as_ref() == Some(&key)).map(|(&id, _)| id);
+        if let Some((scanned, total, _, done)) = pending.and_then(|id| self.filter_step(id)) {
+            return Ok((scanned, total, done));
+        }
+        if self.sweeps.get(&key).is_none() && !self.sliced() {
+            self.match_index(mode, query)?;
+            return Ok((len, len, true));
+        }
+        let chunk_count = self.slice_chunks();
+        let (mmap, chunks) = (self.mmap.clone(), std::mem::take(&mut self.chunks));
+        let stepped = self.sweep(&key).map(|sweep| {
+            let done = sweep.advance(&mmap, &chunks, chunk_count);
+            (sweep.scanned_bytes(&mmap), done)
+        });
+        self.chunks = chunks;
+        let (scanned, done) = stepped?;
+        if ops::cancelled() {
+            return Err(ops::cancelled_error().to_string());
+        }
+        if done {
+            self.finish_sweep(key);
+        }
+        Ok((scanned, len, done))
+    }
+
+    // first logical line at or after start_line that the index hits
+    fn next_match(&self, index: &impl Hits, start_line: usize) -> Option<usize> {
+        let (mut piece_idx, mut offset) = self.find_piece_idx(start_line);
+        let mut current_logical = start_line;
+
+        while piece_idx < self.pieces.len() {
+            let piece = &self.pieces[piece_idx];
+            match piece {
+                Piece::Original { start_line: p_start, line_count } => {
+                    if let Some(hit) = index.next_hit(p_start + offset, p_start + line_count) {
+                        return Some(current_logical + hit - (p_start + offset));
+                    }
+                }
+                Piece::Memory { start_idx, line_count } => {
+                    for i in offset..*line_count {
+                        if index.matcher().is_match(self.memory_buffer[start_idx + i].as_bytes()) {
+                            return Some(current_logical + i - offset);
+                        }
+                    }
+                }
+            }
+            current_logical += piece.line_count() - offset;
+            offset = 0;
+            piece_idx += 1;
+        }
+        None
+    }
+
+    // last logical line at or before start_line that the index hits
+    fn prev_match(&self, index: &impl Hits, start_line: usize) -> Option<usize> {
+        let total = self.total_lines();
+        if total == 0 {
+            return None;
+        }
+
+        let start_line = start_line.min(total - 1);
+        let (mut piece_idx, mut offset) = self.find_piece_idx(start_line);
+        let mut piece_start = start_line - offset;
+
+        // walking backwards through pieces. same logic as forward search but reversed.
+        loop {
+            let piece = &self.pieces[piece_idx];
+            match piece {
+                Piece::Original { start_line: p_start, line_count } if *line_count > 0 => {
+                    if let Some(hit) = index.prev_hit(*p_start, p_start + offset) {
+                        return Some(piece_start + hit - p_start);
+                    }
+                }
+                Piece::Original { .. } => {}
+                Piece::Memory { start_idx, .. } => {
+                    for i in (0..=offset).rev() {
+                        if index.matcher().is_match(self.memory_buffer[start_idx + i].as_bytes()) {
+                            return Some(piece_start + i);
+                        }
+                    }
+                }
+            }
+
+            if piece_idx == 0 {
+                return None;
+            }
+            piece_idx -= 1;
+            let count = self.pieces[piece_idx].line_count();
+            offset = count.saturating_sub(1);
+            piece_start -= count;
+        }
+    }
+
+    // a filter over the whole file. with `exclude` it's everything the query doesn't hit.
+    fn filter_create(&mut self, mode: SearchMode, query: &[u8], exclude: bool) -> Result<u64, String> {
+        if let (false, Some(rank)) = (exclude, min_level_query(mode, query)) {
+            return self.filter_level(rank);
+        }
+        let step = FilterStep { mode, query: query.to_vec(), exclude, combined: None };
+        let key = (mode, query.to_vec());
+        if self.sliced() && self.match_cache.get(&key).is_none() {
+            // n/N may have scanned some of it already
+            let sweep = if exclude { None } else { self.sweeps.remove(&key) };
+            let index = match sweep {
+                Some(sweep) => sweep,
+                None => PartialIndex::new(
+                    &self.chunks,
+                    self.original_total_lines,
+                    Matcher::compile(mode, query, self.columns.as_ref(), &self.templates, self.strip_ansi)?,
+                    None,
+                    exclude,
+                ),
+            };
+            // an exclude's bits aren't the search's, nothing to cache
+            let cache_key = (!exclude).then_some(key);
+            return Ok(self.add_pending(index, step.compile(self.columns.as_ref(), &self.templates, self.strip_ansi)?, vec![step], cache_key, Origin::Any));
+        }
+        // the history is for searches, replaying an exclude from there would find what it hides
+        let mut index = if exclude { self.match_index(mode, query)? } else { self.searched(mode, query)? };
+        if exclude {
+            index = Arc::new(index.complement(self.original_total_lines, step.compile(self.columns.as_ref(), &self.templates, self.strip_ansi)?));
+        }
+        Ok(self.add_filter(index, vec![step]))
+    }
+
+    // a new filter holding the rows of `parent` that also match (or with `exclude`, don't).
+    // the parent stays around, so closing the refinement is how you step back up the stack.
+    fn filter_refine(
+        &mut self,
+        parent: u64,
+        mode: SearchMode,
+        query: &[u8],
+        exclude: bool,
+    ) -> Result<Option<u64>, String> {
+        let step_matcher = Matcher::compile(mode, query, self.columns.as_ref(), &self.templates, self.strip_ansi)?;
+        let Some(parent) = self.filters.get(&parent) else {
+            return Ok(None);
+        };
+        let step = FilterStep { mode, query: query.to_vec(), exclude, combined: None };
+        let matcher = Matcher::All(vec![parent.index.matcher.clone(), step.compile(self.columns.as_ref(), &self.templates, self.strip_ansi)?]);
+        // a drill-down keeps looking at the same lines as its parent
+        let origin = parent.origin;
+        let mut chain = parent.chain.clone();
+        chain.push(step);
+        if self.sliced() {
+            let index = PartialIndex::new(
+                &self.chunks,
+                self.original_total_lines,
+                step_matcher,
+                Some(parent.index.clone()),
+                exclude,
+            );
+            return Ok(Some(self.add_pending(index, matcher, chain, None, origin)));
+        }
+        let index = Arc::new(MatchIndex::refine(
+            &self.mmap,
+            &self.chunks,
+            self.original_total_lines,
+            &parent.index,
+            &step_matcher,
+            exclude,
+            matcher,
+        ));
+        if ops::cancelled() {
+            return Err(ops::cancelled_error().to_string());
+        }
+        let id = self.add_filter(index, chain);
+        self.set_filter_origin(id, origin);
+        Ok(Some(id))
+    }
+
+    // only lines at min_rank (see fields::LEVELS) or more severe. same result as filtering on
+    // `level>=<name>`, but off the severity index instead of parsing every line per query.
+    fn filter_level(&mut self, min_rank: usize) -> Result<u64, String> {
+        let name = fields::level_name(min_rank).ok_or("unknown level")?;
+        let step = FilterStep {
+            mode: SearchMode::Query,
+            query: format!("level>={}", name).into_bytes(),
+            exclude: false,
+            combined: None,
+        };
+        let levels = self.level_index().map_err(|err| err.to_string())?;
+        let index = MatchIndex::from_bits(step.compile(self.columns.as_ref(), &self.templates, self.strip_ansi)?, levels.at_least(min_rank));
+        let index = Arc::new(self.without_evicted(index));
+        Ok(self.add_filter(index, vec![step]))
+    }
+
+    // lines whose timestamp falls in [from, to], either bound may be left empty. filter 0
+    // starts from the whole file. it's a plain `ts>=from AND ts<=to` query step underneath,
+    // so it chains and survives edits like any other filter. Err when a bound isn't a time.
+    fn filter_time(&mut self, parent: u64, from: &str, to: &str) -> Result<Option<u64>, String> {
+        let mut terms = Vec::new();
+        for (op, bound) in [(">=", from.trim()), ("<=", to.trim())] {
+            if bound.is_empty() {
+                continue;
+            }
+            fields::timestamp_secs(bound).ok_or_else(|| format!("not a timestamp: {}", bound))?;
+            terms.push(format!("ts{}\"{}\"", op, bound.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+        if terms.is_empty() {
+            return Err("empty time range".to_string());
+        }
+        let query = terms.join(" AND ").into_bytes();
+        match parent {
+            0 => self.filter_create(SearchMode::Query, &query, false).map(Some),
+            _ => self.filter_refine(parent, SearchMode::Query, &query, false),
+        }
+    }
+
+    // handles start at 1 so the plugin can treat 0 as "no filter"
+    fn add_filter(&mut self, index: Arc<MatchIndex>, chain: Vec<FilterStep>) -> u64 {
+        let id = self.next_filter;
+        self.next_filter += 1;
+        self.insert_filter(id, index, chain);
+        id
+    }
+
+    fn insert_filter(&mut self, id: u64, index: Arc<MatchIndex>, chain: Vec<FilterStep>) {
+        let view = FilterView::new(index, chain, &self.pieces, &self.memory_buffer, self.rotated_lines, self.edits);
+        self.audit.record("filter", format!("#{} {}", id, describe_chain(&view.chain)));
+        self.filters.insert(id, view);
+    }
+
+    // whether new filters get scanned a slice at a time instead of in one go
+    fn sliced(&self) -> bool {
+        self.filter_slice > 0 && self.mmap.len() > self.filter_slice
+    }
+
+    // chunks a step of a stepped scan gets through
+    fn slice_chunks(&self) -> usize {
+        match self.filter_slice {
+            0 => usize::MAX,
+            slice => slice.div_ceil(self.options.chunk_size),
+        }
+    }
+
+    // same handle numbering as add_filter, the view shows up once the scan is through
+    fn add_pending(
+        &mut self,
+        index: PartialIndex,
+        matcher: Matcher,
+        chain: Vec<FilterStep>,
+        cache_key: Option<(SearchMode, Vec<u8>)>,
+        origin: Origin,
+    ) -> u64 {
+        let id = self.next_filter;
+        self.next_filter += 1;
+        self.pending.insert(id, PendingFilter { chain, matcher, index, cache_key, origin });
+        id
+    }
+
+    // scans the next slice of a pending filter: (bytes scanned, bytes in the file, hits so
+    // far, done). a finished filter reports itself done straight away.
+    fn filter_step(&mut self, id: u64) -> Option<(usize, usize, usize, bool)> {
+        let len = self.mmap.len();
+        if let Some(view) = self.filter(id) {
+            return Some((len, len, view.hits(), true));
+        }
+        let chunk_count = self.slice_chunks();
+        let pending = self.pending.get_mut(&id)?;
+        if !pending.index.advance(&self.mmap, &self.chunks, chunk_count) {
+            let scanned = pending.index.scanned_bytes(&self.mmap);
+            return Some((scanned, len, pending.index.hits(&self.chunks), false));
+        }
+
+        let pending = self.pending.remove(&id)?;
+        let scanned_lines = pending.index.total_lines;
+        let mut index = pending.index.finish(pending.matcher.clone());
+        // the file grew while it was scanning, same catch-up as unpark. the scan is through
+        // and out of `pending`, so this bit isn't left for a cancel to cut short.
+        if scanned_lines < self.original_total_lines {
+            let from = scanned_lines.saturating_sub(1);
+            index = ops::shielded(|| {
+                index.extend(&self.mmap, &self.chunks, self.original_total_lines, from, pending.matcher)
+            });
+        }
+        let index = Arc::new(self.without_evicted(index));
+        if let Some((mode, query)) = pending.cache_key {
+            let hits = self.count_matches(&index);
+            self.history.record(mode, &query, Some(hits));
+            self.cache_index((mode, query), index.clone());
+        }
+        self.insert_filter(id, index, pending.chain);
+        self.set_filter_origin(id, pending.origin);
+        let hits = self.filters[&id].hits();
+        events::emit(events::EVENT_FILTER_DONE, id, hits as u64);
+        Some((len, len, hits, true))
+    }
+
+    fn set_filter_origin(&mut self, id: u64, origin: Origin) -> bool {
+        let Some(view) = self.filters.get_mut(&id) else {
+            return false;
+        };
+        view.set_origin(origin, &self.pieces, &self.memory_buffer, self.rotated_lines, self.edits);
+        self.group_records(id);
+        true
+    }
+
+    // whole records for every hit of a filter, or back to the hit lines. context lines go
+    // around the records.
+    fn set_filter_records(&mut self, id: u64, records: bool) -> bool {
+        let total = self.total_lines();
+        let Some(view) = self.filters.get_mut(&id) else {
+            return false;
+        };
+        let (before, after) = view.context().unwrap_or((0, 0));
+        view.set_grouping(before, after, records, total, |line, _| (line, line));
+        self.group_records(id);
+        true
+    }
+
+    // lays a filter with records on out again with the records as they are now, after
+    // anything that rebuilt its rows. out of the map meanwhile, the records are read off self
+    fn group_records(&mut self, id: u64) {
+        let Some(mut view) = self.filters.remove(&id) else {
+            return;
+        };
+        if view.records() {
+            let (before, after) = view.context().unwrap_or((0, 0));
+            view.set_grouping(before, after, true, self.total_lines(), |line, floor| {
+                self.record_bounds(line, floor).unwrap_or((line, line))
+            });
+        }
+        self.filters.insert(id, view);
+    }
+
+    // switch a filter off without losing it. only the packed bits stay, turning it back on
+    // doesn't scan anything.
+    fn filter_park(&mut self, id: u64) -> bool {
+        let Some(view) = self.filters.remove(&id) else {
+            return false;
+        };
+        self.parked.insert(id, view.park(self.original_total_lines));
+        true
+    }
+
+    // back on, under the same handle. lines appended while it was off get evaluated now
+    // (plus the last one it saw, which may have still been half written).
+    fn filter_unpark(&mut self, id: u64) -> bool {
+        let Some(parked) = self.parked.remove(&id) else {
+            return false;
+        };
+        let mut index = MatchIndex::unpack(parked.matcher.clone(), &parked.bits);
+        if parked.total_lines < self.original_total_lines {
+            let from = parked.total_lines.saturating_sub(1);
+            index = index.extend(&self.mmap, &self.chunks, self.original_total_lines, from, parked.matcher);
+        }
+        let mut view = FilterView::new(Arc::new(index), parked.chain, &self.pieces, &self.memory_buffer, self.rotated_lines, self.edits);
+        view.set_origin(parked.origin, &self.pieces, &self.memory_buffer, self.rotated_lines, self.edits);
+        if let Some((before, after)) = parked.context {
+            view.set_context(before, after, self.total_lines());
+        }
+        self.filters.insert(id, view);
+        if parked.records {
+            self.set_filter_records(id, true);
+        }
+        true
+    }
+
+    // a new filter out of two open ones, straight from their bits. its chain starts with
+    // both of theirs, so refining it, exporting it and reopening the log keep it.
+    fn filter_combine(&mut self, a: u64, b: u64, op: Combine) -> Option<u64> {
+        let (first, second) = (self.filters.get(&a)?, self.filters.get(&b)?);
+        let (ma, mb) = (first.index.matcher.clone(), second.index.matcher.clone());
+        let matcher = match op {
+            Combine::And => Matcher::All(vec![ma, mb]),
+            Combine::Or => Matcher::Any(vec![ma, mb]),
+            Combine::AndNot => Matcher::All(vec![ma, Matcher::Not(Box::new(mb))]),
+        };
+        let index = Arc::new(first.index.combine(&second.index, op, matcher));
+        let parts = [first.chain.clone(), second.chain.clone()];
+        let step = FilterStep {
+            mode: SearchMode::Literal,
+            query: Vec::new(),
+            exclude: false,
+            combined: Some(Arc::new(Combined { op, parts })),
+        };
+        Some(self.add_filter(index, vec![step]))
+    }
+
+    // the setup of an open or parked filter as text
+    fn filter_export(&self, id: u64) -> Option<String> {
+        let setup = match (self.filters.get(&id), self.parked.get(&id)) {
+            (Some(view), _) => view.setup(),
+            (None, Some(parked)) => parked.setup(),
+            (None, None) => return None,
+        };
+        Some(setup.to_text())
+    }
+
+    // a new filter from an exported setup, the steps replayed through create and refine.
+    // always scanned in one go: a restored session wants its filters ready, not stepped.
+    // Ok(None) when a step can't be applied, Err when the setup doesn't parse or compile.
+    fn filter_import(&mut self, text: &str) -> Result<Option<u64>, String> {
+        let setup = FilterSetup::parse(text)?;
+        let slice = std::mem::replace(&mut self.filter_slice, 0);
+        let built = self.replay(&setup.chain);
+        self.filter_slice = slice;
+        let Some(id) = built? else {
+            return Ok(None);
+        };
+        self.apply_setup(id, &setup);
+        self.audit.record("filter", format!("#{} {} (imported)", id, describe_chain(&setup.chain)));
+        Ok(Some(id))
+    }
+
+    // origin and context of an exported setup, onto a filter replayed from its chain
+    fn apply_setup(&mut self, id: u64, setup: &FilterSetup) {
+        self.set_filter_origin(id, setup.origin);
+        if let Some((before, after)) = setup.context {
+            let total = self.total_lines();
+            if let Some(view) = self.filters.get_mut(&id) {
+                view.set_context(before, after, total);
+            }
+        }
+        if setup.records {
+            self.set_filter_records(id, true);
+        }
+    }
+
+    // create (or combine) + one refine per step, dropping every filter on the way but the
+    // last. none of them goes in the audit trail, the caller says what was built.
+    fn replay(&mut self, chain: &[FilterStep]) -> Result<Option<u64>, String> {
+        let muted = self.audit.mute(true);
+        let built = self.replay_chain(chain);
+        self.audit.mute(muted);
+        built
+    }
+
+    fn replay_chain(&mut self, chain: &[FilterStep]) -> Result<Option<u64>, String> {
+        let Some((first, rest)) = chain.split_first() else {
+            return Ok(None);
+        };
+        let mut id = match &first.combined {
+            Some(combined) => {
+                let [a, b] = &combined.parts;
+                let Some(a) = self.replay_chain(a)? else {
+                    return Ok(None);
+                };
+                let b = match self.replay_chain(b) {
+                    Ok(Some(b)) => b,
+                    other => {
+                        self.filters.remove(&a);
+                        return other;
+                    }
+                };
+                let id = self.filter_combine(a, b, combined.op);
+                self.filters.remove(&a);
+                self.filters.remove(&b);
+                match id {
+                    Some(id) => id,
+                    None => return Ok(None),
+                }
+            }
+            None => self.filter_create(first.mode, &first.query, first.exclude)?,
+        };
+        for step in rest {
+            let next = self.filter_refine(id, step.mode, &step.query, step.exclude);
+            self.filters.remove(&id);
+            match next? {
+                Some(next) => id = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(id))
+    }
+
+    // the view with its rows caught up to any edits since it was last used
+    fn filter(&mut self, id: u64) -> Option<&FilterView> {
+        let view = self.filters.get_mut(&id)?;
+        if view.edits != self.edits {
+            view.rebuild(&self.pieces, &self.memory_buffer, self.rotated_lines, self.edits);
+            self.group_records(id);
+        }
+        self.filters.get(&id)
+    }
+
+    // same output as get_block, only the rows come from the filter
+    fn filter_block(&mut self, id: u64, start_row: usize, num_rows: usize) -> *const u8 {
+        self.last_block.clear();
+        let Some(view) = self.filter(id) else {
+            return ptr::null();
+        };
+        let end = start_row.saturating_add(num_rows).min(view.total());
+        let rows: Vec<Row> = (start_row..end).map_while(|row| view.row(row)).collect();
+        if rows.is_empty() {
+            return ptr::null();
+        }
+
+        let mut block = std::mem::take(&mut self.last_block);
+        for row in rows {
+            match row {
+                Row::Line(line) => self.append_lines(&mut block, line, 1),
+                Row::Separator => block.push_str("--\n"),
+            }
+        }
+        self.last_block = block;
+        self.last_block.as_ptr()
+    }
+
+    // search inside a filter, in rows. leapfrogs between the two indexes: the next hit of
+    // the search, then the next filtered line from there, until both land on the same line.
+    fn filter_search(
+        &mut self,
+        id: u64,
+        mode: SearchMode,
+        query: &[u8],
+        start_row: usize,
+        backward: bool,
+    ) -> Result<Option<usize>, String> {
+        let index = self.searched(mode, query)?;
+        let total = match self.filter(id) {
+            Some(view) => view.total(),
+            None => return Ok(None),
+        };
+        if start_row >= total && (!backward || total == 0) {
+            return Ok(None);
+        }
+        let view = &self.filters[&id];
+        let mut row = start_row.min(total - 1);
+        loop {
+            let line = match view.row(row) {
+                Some(Row::Line(line)) => line,
+                // context separator, never the first row
+                Some(Row::Separator) => {
+                    row = if backward { row - 1 } else { row + 1 };
+                    continue;
+                }
+                None => return Ok(None),
+            };
+            let hit = if backward {
+                self.prev_match(&*index, line)
+            } else {
+                self.next_match(&*index, line)
+            };
+            let Some(hit) = hit else {
+                return Ok(None);
+            };
+            if hit == line {
+                return Ok(Some(row));
+            }
+            row = if backward {
+                // last row on a line <= hit
+                match view.rows_before(hit + 1).checked_sub(1) {
+                    Some(r) => r,
+                    None => return Ok(None),
+                }
+            } else {
+                view.rows_before(hit)
+            };
+        }
+    }
+
+    // JSON digest of [start_line, start_line + num_lines), see summary.rs
+    fn summarize(&mut self, start_line: usize, num_lines: usize) -> &str {
+        let mut summary = Summary::new(start_line);
+        self.for_each_line(start_line, num_lines, |line, bytes| {
+            let text = ansi::shown(bytes, self.strip_ansi);
+            summary.add(line, &text, &self.message(&text), &self.templates);
+        });
+        self.last_block = summary.to_json();
+        &self.last_block
+    }
+
+    // hashes of blocks [first_block, first_block + num_blocks) of block_lines lines each,
+    // see checkpoint.rs. computed in parallel on first use and kept until the next edit.
+    fn checkpoints(&mut self, block_lines: usize, first_block: usize, num_blocks: usize) -> &[u64] {
+        let total_blocks = self.total_lines().div_ceil(block_lines);
+        let reuse = self
+            .checkpoints
+            .as_ref()
+            .is_some_and(|c| c.block_lines == block_lines && c.edits == self.edits);
+        let mut cp = match self.checkpoints.take() {
+            Some(cp) if reuse => cp,
+            _ => Checkpoints {
+                block_lines,
+                edits: self.edits,
+                hashes: HashMap::new(),
+            },
+        };
+
+        let first = first_block.min(total_blocks);
+        let end = first.saturating_add(num_blocks).min(total_blocks);
+        let missing: Vec<usize> = (first..end).filter(|block| !cp.hashes.contains_key(block)).collect();
+        let this = &*self;
+        let computed: Vec<(usize, u64)> = self.options.install(|| {
+            missing
+                .par_iter()
+                .map(|&block| {
+                    let mut hasher = BlockHasher::new();
+                    this.for_each_line(block * block_lines, block_lines, |_, bytes| hasher.line(bytes));
+                    (block, hasher.finish())
+                })
+                .collect()
+        });
+        cp.hashes.extend(computed);
+
+        self.last_spans.clear();
+        self.last_spans.extend((first..end).map(|block| cp.hashes[&block]));
+        self.checkpoints = Some(cp);
+        &self.last_spans
+    }
+
+    // every hit inside a block, for extmark highlighting of the viewport. with `tagged` each
+    // one also says 1 when the line was edited this session and 0 when it's straight from the
+    // file. columns are byte offsets into the text get_block hands out (i.e. after lossy utf8
+    // repair).
+    fn match_spans(
+        &mut self,
+        mode: SearchMode,
+        query: &[u8],
+        start_line: usize,
+        num_lines: usize,
+        tagged: bool,
+    ) -> Result<&[u64], String> {
+        let matcher = Matcher::compile(mode, query, self.columns.as_ref(), &self.templates, self.strip_ansi)?;
+        let mut spans = std::mem::take(&mut self.last_spans);
+        spans.clear();
+        self.spans_capped = query.len() < self.min_list_query;
+        if self.spans_capped {
+            self.last_spans = spans;
+            return Ok(&self.last_spans);
+        }
+
+        let max = self.max_spans.saturating_mul(3 + tagged as usize);
+        let mut capped = false;
+        let mut line_spans = Vec::new();
+        self.for_each_line(start_line, num_lines, |line, bytes| {
+            if capped {
+                return;
+            }
+            line_spans.clear();
+            // the plugin shows a hex preview there, nothing in it lines up with the bytes
+            if self.hex_binary && binary::is_binary(bytes) {
+                return;
+            }
+            let text = String::from_utf8_lossy(bytes);
+            matcher.spans(text.as_bytes(), &mut line_spans);
+            let edited = tagged && !line_spans.is_empty() && self.line_edited(line);
+            for &(col, len) in &line_spans {
+                if spans.len() >= max {
+                    capped = true;
+                    break;
+                }
+                if len > 0 {
+                    spans.extend([line as u64, col as u64, len as u64]);
+                    if tagged {
+                        spans.push(edited as u64);
+                    }
+                }
+            }
+        });
+        self.spans_capped = capped;
+        self.last_spans = spans;
+        Ok(&self.last_spans)
+    }
+
+    // flat (line, col) pairs for every literal backslash-n in a block: stack traces that some
+    // pipeline squashed into one line. the plugin draws them expanded, the line itself stays
+    // one line so edits and search don't notice. columns are like match_spans.
+    fn escaped_newlines(&mut self, start_line: usize, num_lines: usize) -> &[u64] {
+        let mut out = std::mem::take(&mut self.last_spans);
+        out.clear();
+        self.for_each_line(start_line, num_lines, |line, bytes| {
+            let text = ansi::shown(bytes, self.strip_ansi);
+            let text = text.as_bytes();
+            for col in memmem::find_iter(text, b"\\n") {
+                // `\\n` is an escaped backslash followed by a plain n
+                if col == 0 || text[col - 1] != b'\\' {
+                    out.extend([line as u64, col as u64]);
+                }
+            }
+        });
+        self.last_spans = out;
+        &self.last_spans
+    }
+
+    // flat (first, last) line pairs, one per run of binary lines in the block (see binary.rs),
+    // single lines too. what's shown for them is a preview whether or not hex_binary is on.
+    fn binary_regions(&mut self, start_line: usize, num_lines: usize) -> &[u64] {
+        let mut out = std::mem::take(&mut self.last_spans);
+        out.clear();
+        let mut run: Option<(usize, usize)> = None;
+        self.for_each_line(start_line, num_lines, |line, bytes| {
+            if binary::is_binary(bytes) {
+                run = Some((run.map_or(line, |(first, _)| first), line));
+            } else if let Some((first, last)) = run.take() {
+                out.extend([first as u64, last as u64]);
+            }
+        });
+        if let Some((first, last)) = run {
+            out.extend([first as u64, last as u64]);
+        }
+        self.last_spans = out;
+        &self.last_spans
+    }
+
+    // lines exactly as they are on file, each with its line break (\n, \r\n or \r, none for a
+    // last line without one): the real bytes behind a hex preview. lines in memory (edited)
+    // get a \n. into last_bytes.
+    fn raw_bytes(&mut self, start_line: usize, num_lines: usize) -> &[u8] {
+        let mut out = std::mem::take(&mut self.last_bytes);
+        out.clear();
+        let file = self.mmap.as_ptr_range();
+        self.for_each_line(start_line, num_lines, |_, bytes| {
+            out.extend_from_slice(bytes);
+            if file.contains(&bytes.as_ptr()) || (bytes.is_empty() && bytes.as_ptr() == file.end) {
+                let end = bytes.as_ptr() as usize - file.start as usize + bytes.len();
+                out.extend_from_slice(&self.mmap[end..skip_line_break(&self.mmap, end)]);
+            } else {
+                out.push(b'\n');
+            }
+        });
+        self.last_bytes = out;
+        &self.last_bytes
+    }
+
+    // flat (first, last) line pairs, one per entry that spans several lines in the block: the
+    // line that starts it plus its continuation lines. single line entries are left out.
+    // an entry cut by the start of the block starts at the block's first line.
+    fn fold_ranges(&mut self, start_line: usize, num_lines: usize) -> &[u64] {
+        let mut out = std::mem::take(&mut self.last_spans);
+        out.clear();
+        let mut head: Option<(usize, bool)> = None; // line, starts with a timestamp
+        let mut last = 0;
+        self.for_each_line(start_line, num_lines, |line, bytes| {
+            let text = String::from_utf8_lossy(bytes);
+            if head.is_some_and(|(_, stamped)| fields::continues_entry(&self.templates, &text, stamped)) {
+                last = line;
+                return;
+            }
+            if let Some((first, _)) = head.filter(|&(first, _)| last > first) {
+                out.extend([first as u64, last as u64]);
+            }
+            head = Some((line, fields::starts_with_timestamp(&self.templates, &text)));
+            last = line;
+        });
+        if let Some((first, _)) = head.filter(|&(first, _)| last > first) {
+            out.extend([first as u64, last as u64]);
+        }
+        self.last_spans = out;
+        &self.last_spans
+    }
+
+    // the severity index, built on first use. a build log_engine_cancel cut short isn't kept.
+    fn level_index(&mut self) -> std::io::Result<Arc<LevelIndex>> {
+        if let Some(levels) = &self.levels {
+            return Ok(levels.clone());
+        }
+        let mut levels = LevelIndex::build(&self.mmap, &self.chunks, self.original_total_lines, self.columns.as_ref(), &self.templates);
+        levels.trim(self.first_original());
+        if ops::cancelled() {
+            return Err(ops::cancelled_error());
+        }
+        Ok(self.levels.insert(Arc::new(levels)).clone())
+    }
+
+    // the severity rank of every line in the block, a byte each (levels::NONE for none).
+    // file lines come off the level index, built here the first time, lines in memory are
+    // classified as they are.
+    fn line_levels(&mut self, start_line: usize, num_lines: usize) -> std::io::Result<&[u8]> {
+        let end = start_line.saturating_add(num_lines).min(self.total_lines());
+        let (mut piece_idx, mut offset) = self.find_piece_idx(start_line);
+        let levels = self.level_index()?;
+        let mut out = std::mem::take(&mut self.last_levels);
+        out.clear();
+        let mut logical = start_line;
+        while logical < end && piece_idx < self.pieces.len() {
+            let piece = &self.pieces[piece_idx];
+            let take = (piece.line_count() - offset).min(end - logical);
+            match piece {
+                Piece::Original { start_line: p_start, .. } => {
+                    out.extend((p_start + offset..p_start + offset + take).map(|line| levels.get(line)));
+                }
+                Piece::Memory { start_idx, .. } => {
+                    let lines = &self.memory_buffer[start_idx + offset..start_idx + offset + take];
+                    out.extend(lines.iter().map(|line| levels::classify(line.as_bytes(), self.columns.as_ref(), &self.templates).unwrap_or(levels::NONE)));
+                }
+            }
+            logical += take;
+            offset = 0;
+            piece_idx += 1;
+        }
+        self.last_levels = out;
+        Ok(&self.last_levels)
+    }
+
+    // lines per severity over the whole thing, see levels::Counts. file lines are counted off
+    // the level index (built here the first time, in parallel), lines in memory classified
+    fn level_counts(&mut self) -> std::io::Result<levels::Counts> {
+        let index = self.level_index()?;
+        let mut counts = levels::Counts::default();
+        for piece in &self.pieces {
+            match *piece {
+                Piece::Original { start_line, line_count } => {
+                    let found = index.counts(start_line..start_line + line_count);
+                    counts.iter_mut().zip(found).for_each(|(a, b)| *a += b);
+                }
+                Piece::Memory { start_idx, line_count } => {
+                    for line in &self.memory_buffer[start_idx..start_idx + line_count] {
+                        let rank = levels::classify(line.as_bytes(), self.columns.as_ref(), &self.templates).unwrap_or(levels::NONE);
+                        levels::count(&mut counts, rank);
+                    }
+                }
+            }
+        }
+        Ok(counts)
+    }
+
+    // flat (line, col, len, fg, bg, attrs) for every run of text the terminal escapes in the
+    // block color (see ansi::Style), columns in the lines as get_block hands them out. for
+    // drawing a captured terminal log the way it looked.
+    fn ansi_colors(&mut self, start_line: usize, num_lines: usize) -> &[u64] {
+        let mut spans = std::mem::take(&mut self.last_spans);
+        spans.clear();
+        let mut runs = Vec::new();
+        self.for_each_line(start_line, num_lines, |line, bytes| {
+            runs.clear();
+            ansi::colors(String::from_utf8_lossy(bytes).as_bytes(), self.strip_ansi, &mut runs);
+            for &(col, len, style) in &runs {
+                spans.extend([line as u64, col as u64, len as u64, style.fg as u64, style.bg as u64, style.attrs as u64]);
+            }
+        });
+        self.last_spans = spans;
+        &self.last_spans
+    }
+
+    // flat (line, col, len, rank) for where every line of the block says its severity, so the
+    // plugin can color levels without matching patterns itself. lines without one are left
+    // out, continuation lines included: they have no level word to color.
+    fn level_spans(&mut self, start_line: usize, num_lines: usize) -> &[u64] {
+        let mut spans = std::mem::take(&mut self.last_spans);
+        spans.clear();
+        self.for_each_line(start_line, num_lines, |line, bytes| {
+            let text = ansi::shown(bytes, self.strip_ansi);
+            let span = match &self.columns {
+                Some(columns) => columns.level_span(&text),
+                None => fields::level_span(&self.templates, &text),
+            };
+            if let Some((col, len, rank)) = span {
+                spans.extend([line as u64, col as u64, len as u64, rank as u64]);
+            }
+        });
+        self.last_spans = spans;
+        &self.last_spans
+    }
+
+    // like fold_ranges, but the pairs are runs of lines below min_rank (debug chatter under
+    // :LogFold warn). severities come off the level index, so only lines without one are
+    // read: those continuing an entry take its severity, the rest break a run, better to
+    // show too much than hide it.
+    fn severity_folds(&mut self, start_line: usize, num_lines: usize, min_rank: usize) -> std::io::Result<&[u64]> {
+        let ranks = self.line_levels(start_line, num_lines)?.to_vec();
+        let mut out = std::mem::take(&mut self.last_spans);
+        out.clear();
+        let mut entry: Option<(bool, u8)> = None; // starts with a timestamp, rank
+        let mut run: Option<(usize, usize)> = None;
+        self.for_each_line(start_line, num_lines, |line, bytes| {
+            let at = line - start_line;
+            let rank = if ranks[at] != levels::NONE {
+                // whether it's stamped only matters to the unranked lines after it
+                let stamped = ranks.get(at + 1) == Some(&levels::NONE)
+                    && fields::starts_with_timestamp(&self.templates, &String::from_utf8_lossy(bytes));
+                entry = Some((stamped, ranks[at]));
+                ranks[at]
+            } else {
+                match entry {
+                    Some((stamped, rank)) if fields::continues_entry(&self.templates, &String::from_utf8_lossy(bytes), stamped) => rank,
+                    _ => {
+                        entry = None;
+                        levels::NONE
+                    }
+                }
+            };
+            if rank != levels::NONE && (rank as usize) < min_rank {
+                run = Some((run.map_or(line, |(first, _)| first), line));
+            } else if let Some((first, last)) = run.take().filter(|&(first, last)| last > first) {
+                out.extend([first as u64, last as u64]);
+            }
+        });
+        if let Some((first, last)) = run.filter(|&(first, last)| last > first) {
+            out.extend([first as u64, last as u64]);
+        }
+        self.last_spans = out;
+        Ok(&self.last_spans)
+    }
+
+    fn expire_idle_caches(&mut self) {
+        self.match_cache.trim(self.cache_ttl, Instant::now());
+        self.sweeps.trim(self.cache_ttl, Instant::now());
+    }
+
+    // explicit trim from the plugin. also gives back whatever the last giant block grew to.
+    fn trim_caches(&mut self, max_idle: Duration) -> usize {
+        let evicted = self.match_cache.trim(max_idle, Instant::now()) + self.sweeps.trim(max_idle, Instant::now());
+        self.last_block.clear();
+        self.last_block.shrink_to_fit();
+        evicted
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        if self.options.read_only {
+            return Err(diag::read_only());
+        }
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let file = OpenOptions::new().write(true).create(true).truncate(true).open(&temp_path)?;
+        let mut writer = BufWriter::new(file);
+        if let Err(err) = self.write_content(&mut writer).and_then(|_| writer.flush()) {
+            drop(writer);
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(err);
+        }
+        // atomic swap
+        std::fs::rename(&temp_path, path)
+    }
+
+    // everything save writes, stopping between chunks once the operation is cancelled.
+    // expanded json lines are written the way they were before, on one line.
+    fn write_content(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let op = ops::token();
+        // lines kept from a rotated out file are already on disk, in that file
+        let mut from = self.rotated_lines;
+        for expansion in &self.expansions {
+            if expansion.start >= from {
+                self.write_lines(writer, &op, from, expansion.start)?;
+                writer.write_all(expansion.text.as_bytes())?;
+                writer.write_all(b"\n")?;
+            }
+            from = from.max(expansion.start + expansion.count);
+        }
+        self.write_lines(writer, &op, from, usize::MAX)
+    }
+
+    // logical lines [from, to) as save writes them
+    fn write_lines(&self, writer: &mut impl Write, op: &ops::Token, from: usize, to: usize) -> std::io::Result<()> {
+        let mut at = 0;
+        for piece in &self.pieces {
+            let count = piece.line_count();
+            let (skipped, end) = (from.saturating_sub(at).min(count), to.saturating_sub(at).min(count));
+            at += count;
+            if skipped >= end {
+                continue;
+            }
+            match piece {
+                Piece::Original { start_line, .. } => {
+                    let bytes = self.get_original_bytes(start_line + skipped, end - skipped);
+                    for chunk in bytes.chunks(self.options.chunk_size) {
+                        if op.cancelled() {
+                            return Err(ops::cancelled_error());
+                        }
+                        writer.write_all(chunk)?;
+                    }
+                    if !bytes.ends_with(b"\n") && !bytes.is_empty() {
+                        writer.write_all(b"\n")?;
+                    }
+                }
+                Piece::Memory { start_idx, .. } => {
+                    if op.cancelled() {
+                        return Err(ops::cancelled_error());
+                    }
+                    for i in skipped..end {
+                        writer.write_all(self.memory_buffer[start_idx + i].as_bytes())?;
+                        writer.write_all(b"\n")?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// `level>=warn` and friends, which the severity index answers without a scan
+fn min_level_query(mode: SearchMode, query: &[u8]) -> Option<usize> {
+    if mode != SearchMode::Query {
+        return None;
+    }
+    match query::parse(&String::from_utf8_lossy(query)).ok()? {
+        query::Expr::Field { name, op: query::Op::Ge, value } if fields::is_level_field(&name) => {
+            fields::level_rank(&value)
+        }
+        _ => None,
+    }
+}
+
+// --- C ABI Boundary ---
+// Trusting the caller from here on out. standard unsafe boilerplate.
+//
+// integer widths: size_t is only for byte lengths of buffers we hand out.
+// anything that counts lines, seconds or matches goes through u64/i64 in new entry points,
+// since usize is 32 bits on some targets and `long` is 32 bits on windows.
+// nothing crosses this boundary as raw multi-byte blobs, so byte order never leaks out.
+
+// what the plugin's number stands for (see registry.rs). calls may come from any thread
+// (the main loop, libuv workers): the ones that only look share the lock, everything else
+// takes it alone and waits its turn. the buffers handed out (last_block, last_spans) are
+// the calling thread's, see Held, so only this thread's next call on the engine can pull
+// them away from under a pointer.
+pub struct LogEngine {
+    state: RwLock<Engine>,
+    generation: AtomicU64, // bumped by every call that could touch last_block/last_spans or the content
+}
+
+// the handle crosses threads, keep it that way
+const _: fn() = || {
+    fn shareable<T: Send + Sync>() {}
+    shareable::<LogEngine>();
+};
+
+impl LogEngine {
+    fn wrap(engine: Engine) -> LogEngine {
+        LogEngine {
+            state: RwLock::new(engine),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    // the number the C ABI knows it by, see registry.rs
+    fn registered(engine: Engine) -> u64 {
+        let (lines, bytes) = (engine.total_lines() as u64, engine.mmap.len() as u64);
+        let follow = engine.options.follow && engine.frozen.is_none();
+        let id = registry::register(LogEngine::wrap(engine));
+        if let Some((engine, _)) = registry::resolve(id).filter(|_| follow) {
+            // best effort, the host can still watch on its own
+            let _ = engine.write().watch.start_native(id);
+        }
+        events::emit(events::EVENT_INDEXED, lines, bytes);
+        events::flush(id);
+        id
+    }
+
+    // a call that panicked (see unwind.rs) poisons the lock on its way out. the engine is
+    // handed out anyway: refusing it forever would be worse than whatever that call left
+    fn write(&self) -> EngineCall<'_> {
+        EngineCall {
+            engine: self.state.write().unwrap_or_else(PoisonError::into_inner),
+            generation: &self.generation,
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Engine> {
+        self.state.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// every entry point goes through here: the number looked up, a bad one ending up in the
+// misuse diagnostics, then the engine to itself until the call returns.
+struct EngineCall<'a> {
+    engine: RwLockWriteGuard<'a, Engine>,
+    generation: &'a AtomicU64,
+}
+
+impl Deref for EngineCall<'_> {
+    type Target = Engine;
+    fn deref(&self) -> &Engine {
+        &self.engine
+    }
+}
+
+// anything reaching the engine mutably might rewrite the buffers handed out or the lines
+// they came from, so that's where pointers go stale. read-only calls leave them alone.
+impl DerefMut for EngineCall<'_> {
+    fn deref_mut(&mut self) -> &mut Engine {
+        self.generation.fetch_add(1, Ordering::Release);
+        &mut self.engine
+    }
+}
+
+impl EngineCall<'_> {
+    // where the generation stands now. nobody else can bump it while the call holds the
+    // engine, so this is the one the pointers it returns belong to.
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+}
+
+// a lock on an engine the registry handed out, keeping it alive until the call is done.
+// the guard borrows from the engine behind the Arc, whose address doesn't move, and is
+// dropped by hand before the Arc can let go. events the call queued go out after that,
+// with the engine free again for the callback to call into. while it's held the engine's
+// thread pool (if it has one) is the one this thread's scans run on, see pool.rs
+// `id` is the number the call came in through, `root` the engine's own (see registry.rs).
+struct Held<G: HandOff> {
+    id: u64,
+    root: u64,
+    guard: ManuallyDrop<G>,
+    _pool: pool::Scope,
+    _engine: Arc<LogEngine>,
+}
+
+impl<G: HandOff + Deref<Target = Engine>> Held<G> {
+    fn new(id: u64, root: u64, guard: G, engine: Arc<LogEngine>) -> Self {
+        let _pool = pool::enter(guard.options.pool.clone());
+        Held { id, root, guard: ManuallyDrop::new(guard), _pool, _engine: engine }
+    }
+}
+
+impl<G: HandOff> Drop for Held<G> {
+    fn drop(&mut self) {
+        self.guard.hand_off(self.root);
+        // still ours: nothing can bump it between here and what the call handed out
+        let generation = self._engine.generation.load(Ordering::Acquire);
+        OUT.with(|out| out.borrow_mut().entry(self.root).or_default().generation = generation);
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        INSIDE.with(|inside| inside.borrow_mut().retain(|&root| root != self.root));
+        events::flush(self.id);
+    }
+}
+
+// the buffers a call hands pointers out of (last_block, last_spans...) belong to the thread
+// that made it, not to the engine: once a call is done, what it filled is traded for what
+// this thread got from the same engine last time. a pointer stays good until the same
+// thread calls into that engine again, whatever other threads do with it meanwhile.
+#[derive(Default)]
+struct OutBuffers {
+    block: String,
+    spans: Vec<u64>,
+    times: Vec<f64>,
+    levels: Vec<u8>,
+    bytes: Vec<u8>,
+    offsets: Vec<u64>,
+    generation: u64, // what the engine's stood at when this thread's last call let go of it
+}
+
+thread_local! {
+    static OUT: RefCell<HashMap<u64, OutBuffers>> = RefCell::new(HashMap::new());
+    // engines this thread is inside a call on, a second one on the same engine is a bug
+    static INSIDE: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+trait HandOff {
+    fn hand_off(&mut self, _id: u64) {}
+}
+
+// reads can't fill anything
+impl HandOff for RwLockReadGuard<'_, Engine> {}
+
+impl HandOff for EngineCall<'_> {
+    fn hand_off(&mut self, id: u64) {
+        // straight through the lock guard: trading buffers doesn't make anything stale
+        let engine = &mut *self.engine;
+        OUT.with(|out| {
+            let mut out = out.borrow_mut();
+            if !out.contains_key(&id) {
+                // what this thread kept of engines freed since goes
+                out.retain(|&root, _| registry::alive(root));
+            }
+            let mine = out.entry(id).or_default();
+            std::mem::swap(&mut engine.last_block, &mut mine.block);
+            std::mem::swap(&mut engine.last_spans, &mut mine.spans);
+            std::mem::swap(&mut engine.last_times, &mut mine.times);
+            std::mem::swap(&mut engine.last_levels, &mut mine.levels);
+            std::mem::swap(&mut engine.last_bytes, &mut mine.bytes);
+            std::mem::swap(&mut engine.last_offsets, &mut mine.offsets);
+        });
+    }
+}
+
+impl<G: HandOff + Deref<Target = Engine>> Deref for Held<G> {
+    type Target = Engine;
+    fn deref(&self) -> &Engine {
+        &self.guard
+    }
+}
+
+impl<G: HandOff + DerefMut<Target = Engine>> DerefMut for Held<G> {
+    fn deref_mut(&mut self) -> &mut Engine {
+        &mut self.guard
+    }
+}
+
+impl Held<RwLockReadGuard<'static, Engine>> {
+    // a read can't fill the engine's buffers, what it builds goes straight into this thread's
+    // block for the engine instead: the same place a write's would have been traded to
+    fn hand_out(&self, block: String) -> (*const u8, usize) {
+        OUT.with(|out| {
+            let mut out = out.borrow_mut();
+            let mine = out.entry(self.root).or_default();
+            mine.block = block;
+            (mine.block.as_ptr(), mine.block.len())
+        })
+    }
+}
+
+impl Held<EngineCall<'static>> {
+    fn generation(&self) -> u64 {
+        self.guard.generation()
+    }
+}
+
+// a call landing on an engine this thread is still inside (a callback calling back in
+// before the events went out, a bug in a binding) would wait on its own lock forever.
+// it's reported and refused instead, like a bad handle.
+fn busy(id: u64, call: &'static str) -> bool {
+    let reentrant = INSIDE.with(|inside| {
+        let mut inside = inside.borrow_mut();
+        let reentrant = inside.contains(&id);
+        if !reentrant {
+            inside.push(id);
+        }
+        reentrant
+    });
+    if reentrant {
+        diag::misuse(|| format!("{}: reentrant call while the engine is busy", call));
+    }
+    reentrant
+}
+
+// the engine behind a number and its root, see registry.rs
+fn handle(engine: u64, call: &'static str) -> Option<(Arc<LogEngine>, u64)> {
+    if engine == 0 {
+        diag::misuse(|| format!("{}: null engine", call));
+        return None;
+    }
+    let found = registry::resolve(engine);
+    if found.is_none() {
+        diag::misuse(|| format!("{}: no engine {} (freed, or never handed out)", call, engine));
+    }
+    found
+}
+
+fn enter(id: u64, call: &'static str) -> Option<Held<EngineCall<'static>>> {
+    let (engine, root) = handle(id, call)?;
+    if busy(root, call) {
+        return None;
+    }
+    events::discard();
+    let guard = unsafe { std::mem::transmute::<EngineCall<'_>, EngineCall<'static>>(engine.write()) };
+    if closed(&guard, id, call) {
+        return None;
+    }
+    Some(Held::new(id, root, guard, engine))
+}
+
+// enter for calls that only read: any number of them run at once
+fn peek(id: u64, call: &'static str) -> Option<Held<RwLockReadGuard<'static, Engine>>> {
+    let (engine, root) = handle(id, call)?;
+    if busy(root, call) {
+        return None;
+    }
+    events::discard();
+    let guard = unsafe { std::mem::transmute::<RwLockReadGuard<'_, Engine>, RwLockReadGuard<'static, Engine>>(engine.read()) };
+    if closed(&guard, id, call) {
+        return None;
+    }
+    Some(Held::new(id, root, guard, engine))
+}
+
+// a frozen engine whose file was truncated in place: its lines are gone, only freeing it works
+fn closed(engine: &Engine, id: u64, call: &'static str) -> bool {
+    let closed = engine.frozen.as_ref().is_some_and(|flag| flag.load(Ordering::Relaxed));
+    if closed {
+        diag::misuse(|| format!("{}: engine {} was frozen from a file truncated since", call, id));
+    }
+    closed
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_new(path: *const c_char) -> u64 {
+    unwind::guard("log_engine_new", || {
+        if path.is_null() {
+            diag::misuse(|| "log_engine_new: null path".to_string());
+            return 0;
+        }
+        if let Ok(engine) = open::open(c_path(path), None, Options::default()) {
+            return LogEngine::registered(engine);
+        }
+        0
+    })
+}
+
+// log_engine_new tuned by a LogEngineOptions (see options.rs), null options for the
+// defaults. 0 on failure, why in last_error: a misuse for options from a newer header.
+#[no_mangle]
+pub extern "C" fn log_engine_new_with_options(path: *const c_char, options: *const options::LogEngineOptions) -> u64 {
+    unwind::guard("log_engine_new_with_options", || {
+        let call = "log_engine_new_with_options";
+        if path.is_null() {
+            diag::misuse(|| format!("{}: null path", call));
+            return 0;
+        }
+        let Some(options) = options_arg(options, call) else {
+            return 0;
+        };
+        match open::open(c_path(path), None, options) {
+            Ok(engine) => LogEngine::registered(engine),
+            Err(_) => 0,
+        }
+    })
+}
+
+// the options a call was handed, the defaults for null. None (the reason in last_error) for
+// ones from a newer header or a pool that can't be started.
+fn options_arg(options: *const options::LogEngineOptions, call: &'static str) -> Option<Options> {
+    let Some(raw) = (unsafe { options.as_ref() }) else {
+        return Some(Options::default());
+    };
+    match Options::from_raw(raw, call)? {
+        Ok(options) => Some(options),
+        Err(err) => {
+            diag::io_failure(&err);
+            None
+        }
+    }
+}
+
+// log_engine_new_with_options with a deadline: gives up after timeout_ms (0 waits forever)
+// instead of hanging the editor on a dead mount or a pathological file. null options for
+// the defaults. null on failure, with out_error saying why: 1 io error (bad options
+// included), 2 not a regular file, 3 timed out, 4 cancelled (0 on success).
+#[no_mangle]
+pub extern "C" fn log_engine_open(
+    path: *const c_char,
+    timeout_ms: u64,
+    options: *const options::LogEngineOptions,
+    out_error: *mut i32,
+) -> u64 {
+    unwind::guard("log_engine_open", || {
+        if path.is_null() {
+            diag::misuse(|| "log_engine_open: null path".to_string());
+            if !out_error.is_null() {
+                unsafe { *out_error = open::OPEN_IO_ERROR };
+            }
+            return 0;
+        }
+        open_path(c_path(path), timeout_ms, options, out_error, "log_engine_open")
+    })
+}
+
+// an engine on a descriptor the caller opened (see Engine::from_fd). the caller's fd stays
+// theirs to close. null on failure, why in last_error. unix only for now.
+#[no_mangle]
+pub extern "C" fn log_engine_new_from_fd(fd: i32) -> u64 {
+    unwind::guard("log_engine_new_from_fd", || {
+        if fd < 0 {
+            diag::misuse(|| format!("log_engine_new_from_fd(fd={}): not a descriptor", fd));
+            return 0;
+        }
+        match Engine::from_fd(fd) {
+            Ok(engine) => LogEngine::registered(engine),
+            Err(err) => {
+                diag::io_failure(&err);
+                0
+            }
+        }
+    })
+}
+
+// an engine on `len` bytes at `data` instead of a file, copied: the caller's memory is
+// free to go as soon as this returns. null on failure, why in last_error.
+#[no_mangle]
+pub extern "C" fn log_engine_new_from_bytes(data: *const u8, len: usize) -> u64 {
+    unwind::guard("log_engine_new_from_bytes", || {
+        let Some(bytes) = byte_arg(data, len, "log_engine_new_from_bytes") else {
+            return 0;
+        };
+        match Engine::from_bytes(bytes) {
+            Ok(engine) => LogEngine::registered(engine),
+            Err(err) => {
+                diag::io_failure(&err);
+                0
+            }
+        }
+    })
+}
+
+// log_engine_open with the path as NUL terminated UTF-16, what windows paths really are: a
+// name the ANSI codepage can't spell, or that isn't valid unicode at all, still opens.
+// elsewhere it's decoded to UTF-8, unpaired surrogates replaced.
+#[no_mangle]
+pub extern "C" fn log_engine_open_w(
+    path: *const u16,
+    timeout_ms: u64,
+    options: *const options::LogEngineOptions,
+    out_error: *mut i32,
+) -> u64 {
+    unwind::guard("log_engine_open_w", || {
+        if path.is_null() {
+            diag::misuse(|| "log_engine_open_w: null path".to_string());
+            if !out_error.is_null() {
+                unsafe { *out_error = open::OPEN_IO_ERROR };
+            }
+            return 0;
+        }
+        let len = (0..).take_while(|&i| unsafe { *path.add(i) } != 0).count();
+        let wide = unsafe { std::slice::from_raw_parts(path, len) };
+        #[cfg(windows)]
+        let path = {
+            use std::os::windows::ffi::OsStringExt;
+            PathBuf::from(std::ffi::OsString::from_wide(wide))
+        };
+        #[cfg(not(windows))]
+        let path = PathBuf::from(String::from_utf16_lossy(wide));
+        open_path(path, timeout_ms, options, out_error, "log_engine_open_w")
+    })
+}
+
+fn open_path(
+    path: PathBuf,
+    timeout_ms: u64,
+    options: *const options::LogEngineOptions,
+    out_error: *mut i32,
+    call: &'static str,
+) -> u64 {
+    let timeout = (timeout_ms > 0).then(|| Duration::from_millis(timeout_ms));
+    let opened = options_arg(options, call).ok_or(open::OPEN_IO_ERROR).and_then(|options| open::open(path, timeout, options));
+    let (engine, code) = match opened {
+        Ok(engine) => (LogEngine::registered(engine), open::OPEN_OK),
+        Err(code) => (0, code),
+    };
+    if !out_error.is_null() {
+        unsafe { *out_error = code };
+    }
+    engine
+}
+
+// a C string path as the OS sees it: the bytes as they are on unix, non-UTF-8 names
+// included. windows wants UTF-8 here (log_engine_open_w takes anything).
+fn c_path(path: *const c_char) -> PathBuf {
+    bytes_path(unsafe { CStr::from_ptr(path) }.to_bytes())
+}
+
+fn bytes_path(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+// the generation the pointers from this thread's last call on the engine (get_block,
+// match_spans, audit, any of them) belong to, as it stood when that call let go of the
+// engine: another thread's call landing in between doesn't change the answer. only ever goes
+// up. check it with log_engine_block_still_valid before reading through a pointer: any edit
+// or buffer-filling call since makes it false, from whichever thread it came. neither waits
+// for a call running on another thread. a thread that hasn't called in yet gets where the
+// engine stands now.
+#[no_mangle]
+pub extern "C" fn log_engine_generation(engine: u64) -> u64 {
+    unwind::guard("log_engine_generation", || {
+        let Some((handle, root)) = handle(engine, "log_engine_generation") else {
+            return 0;
+        };
+        let last = OUT.with(|out| out.borrow().get(&root).map(|mine| mine.generation));
+        last.unwrap_or_else(|| handle.generation.load(Ordering::Acquire))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_block_still_valid(engine: u64, generation: u64) -> bool {
+    unwind::guard("log_engine_block_still_valid", || {
+        let Some((handle, _)) = handle(engine, "log_engine_block_still_valid") else {
+            return false;
+        };
+        handle.generation.load(Ordering::Acquire) == generation
+    })
+}
+
+// where `line` starts as a byte offset into the edited content (the file's bytes with the
+// edits in place, edited lines ending in \n). a line past the end gives the total size.
+#[no_mangle]
+pub extern "C" fn log_engine_line_to_byte(engine: u64, line: u64) -> u64 {
+    unwind::guard("log_engine_line_to_byte", || {
+        let Some(engine) = peek(engine, "log_engine_line_to_byte") else {
+            return 0;
+        };
+        let line = usize::try_from(line).unwrap_or(usize::MAX);
+        engine.byte_of_line(line) as u64
+    })
+}
+
+// the line the byte at `offset` is in (what vim's `go` does), the last line past the end
+#[no_mangle]
+pub extern "C" fn log_engine_byte_to_line(engine: u64, offset: u64) -> u64 {
+    unwind::guard("log_engine_byte_to_line", || {
+        let Some(engine) = peek(engine, "log_engine_byte_to_line") else {
+            return 0;
+        };
+        let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+        engine.line_of_byte(offset) as u64
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_total_lines(engine: u64) -> usize {
+    unwind::guard("log_engine_total_lines", || {
+        // :LogLines. fast because we already paid the price at startup.
+        let Some(engine) = peek(engine, "log_engine_total_lines") else {
+            return 0;
+        };
+        engine.total_lines()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_get_block(
+    engine: u64,
+    start_line: usize,
+    num_lines: usize,
+    out_len: *mut usize,
+) -> *const u8 {
+    unwind::guard("log_engine_get_block", || {
+        // the thing behind :LogJump and scrolling. fetches chunks without loading the whole file.
+        get_block(engine, "log_engine_get_block", start_line, num_lines, out_len, ptr::null_mut())
+    })
+}
+
+// get_block that also says which generation the block belongs to, taken while the engine was
+// still ours: no other thread's call can slip in between like it can before a separate
+// log_engine_generation. hand it to log_engine_block_still_valid before reading.
+#[no_mangle]
+pub extern "C" fn log_engine_get_block_gen(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    out_len: *mut usize,
+    out_generation: *mut u64,
+) -> *const u8 {
+    unwind::guard("log_engine_get_block_gen", || {
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        get_block(engine, "log_engine_get_block_gen", start_line, num_lines, out_len, out_generation)
+    })
+}
+
+// get_block plus where every line starts in it, so the caller can cut the lines out without
+// looking for the breaks again: `out_count` lines, offsets in a buffer of their own with one
+// more at the end (the block's length). line i is [offsets[i], offsets[i + 1]) minus its
+// line break. both pointers live until the next call, like get_block's, and a match_spans
+// in between doesn't touch the offsets.
+#[no_mangle]
+pub extern "C" fn log_engine_get_block_lines(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    out_len: *mut usize,
+    out_offsets: *mut *const u64,
+    out_count: *mut u64,
+) -> *const u8 {
+    unwind::guard("log_engine_get_block_lines", || {
+        let call = "log_engine_get_block_lines";
+        let Some(mut engine) = enter(engine, call) else {
+            return ptr::null();
+        };
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let total = engine.total_lines();
+        if start_line > total {
+            diag::misuse(|| format!("{}(start_line={}, num_lines={}): past the end ({} lines)", call, start_line, num_lines, total));
+        }
+        let block = engine.get_block(start_line, num_lines);
+        let engine = &mut *engine;
+        engine.last_offsets.clear();
+        if !engine.last_block.is_empty() {
+            engine.last_offsets.push(0);
+            let ends = memchr::memchr_iter(b'\n', engine.last_block.as_bytes()).map(|at| at as u64 + 1);
+            engine.last_offsets.extend(ends);
+        }
+        let count = engine.last_offsets.len().saturating_sub(1);
+        if !out_len.is_null() {
+            unsafe { *out_len = engine.last_block.len() };
+        }
+        if !out_offsets.is_null() {
+            unsafe { *out_offsets = engine.last_offsets.as_ptr() };
+        }
+        if !out_count.is_null() {
+            unsafe { *out_count = count as u64 };
+        }
+        block
+    })
+}
+
+fn get_block(
+    engine: u64,
+    call: &'static str,
+    start_line: usize,
+    num_lines: usize,
+    out_len: *mut usize,
+    out_generation: *mut u64,
+) -> *const u8 {
+    let Some(mut engine) = enter(engine, call) else {
+        return ptr::null();
+    };
+    let total = engine.total_lines();
+    if start_line > total {
+        diag::misuse(|| format!("{}(start_line={}, num_lines={}): past the end ({} lines)", call, start_line, num_lines, total));
+    }
+    let ptr = engine.get_block(start_line, num_lines);
+    if !out_len.is_null() {
+        unsafe { *out_len = engine.last_block.len() };
+    }
+    if !out_generation.is_null() {
+        unsafe { *out_generation = engine.generation() };
+    }
+    ptr
+}
+
+// get_block into the caller's memory: `cap` bytes at `buf`. returns the bytes the lines
+// take, written only when that fits in `cap`, otherwise nothing is and the caller retries
+// with a buffer that big (a null `buf` with cap 0 just asks). nothing handed out here
+// depends on the next call, and the engine's own block buffer is left alone.
+#[no_mangle]
+pub extern "C" fn log_engine_get_block_into(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    buf: *mut u8,
+    cap: usize,
+) -> usize {
+    unwind::guard("log_engine_get_block_into", || {
+        let Some(engine) = peek(engine, "log_engine_get_block_into") else {
+            return 0;
+        };
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let total = engine.total_lines();
+        if start_line > total {
+            diag::misuse(|| {
+                format!("log_engine_get_block_into(start_line={}, num_lines={}): past the end ({} lines)", start_line, num_lines, total)
+            });
+        }
+        let mut block = String::new();
+        if num_lines > 0 && start_line < total {
+            engine.append_lines(&mut block, start_line, num_lines);
+        }
+        if block.len() <= cap && !block.is_empty() {
+            if buf.is_null() {
+                diag::misuse(|| format!("log_engine_get_block_into(cap={}): null buffer", cap));
+                return 0;
+            }
+            unsafe { ptr::copy_nonoverlapping(block.as_ptr(), buf, block.len()) };
+        }
+        block.len()
+    })
+}
+
+// a single line, without the line break: hover previews, yanking one line. skips get_block's
+// assembling, a plain line on the file comes straight from the mapping. same lifetime rules
+// as get_block. null past the end.
+#[no_mangle]
+pub extern "C" fn log_engine_get_line(engine: u64, line: u64, out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_get_line", || {
+        let Some(mut engine) = enter(engine, "log_engine_get_line") else {
+            return ptr::null();
+        };
+        let found = usize::try_from(line).ok().and_then(|line| engine.get_line(line));
+        let Some((ptr, len)) = found else {
+            let total = engine.total_lines();
+            diag::misuse(|| format!("log_engine_get_line(line={}): past the end ({} lines)", line, total));
+            return ptr::null();
+        };
+        if !out_len.is_null() {
+            unsafe { *out_len = len };
+        }
+        ptr
+    })
+}
+
+// any set of lines in one call (search results, bookmarks), `count` line numbers in
+// `lines`. comes back like get_block, one line per number in the same order, same buffer and
+// lifetime rules. a number past the end gets an empty line.
+#[no_mangle]
+pub extern "C" fn log_engine_get_lines(
+    engine: u64,
+    lines: *const u64,
+    count: u64,
+    out_len: *mut usize,
+) -> *const u8 {
+    unwind::guard("log_engine_get_lines", || {
+        let Some(mut engine) = enter(engine, "log_engine_get_lines") else {
+            return ptr::null();
+        };
+        let Ok(count) = usize::try_from(count) else {
+            diag::misuse(|| format!("log_engine_get_lines(count={}): more lines than fit in memory", count));
+            return ptr::null();
+        };
+        if lines.is_null() && count > 0 {
+            diag::misuse(|| format!("log_engine_get_lines(count={}): null lines", count));
+            return ptr::null();
+        }
+        let lines = if count == 0 { &[][..] } else { unsafe { std::slice::from_raw_parts(lines, count) } };
+        let missing = engine.get_lines(lines);
+        if missing > 0 {
+            let total = engine.total_lines();
+            diag::misuse(|| format!("log_engine_get_lines(count={}): {} past the end ({} lines)", count, missing, total));
+        }
+        if !out_len.is_null() {
+            unsafe { *out_len = engine.last_block.len() };
+        }
+        engine.last_block.as_ptr()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_head_tail(
+    engine: u64,
+    head_lines: u64,
+    tail_lines: u64,
+    out_len: *mut usize,
+) -> *const u8 {
+    unwind::guard("log_engine_head_tail", || {
+        // same buffer and same lifetime rules as get_block.
+        let Some(mut engine) = enter(engine, "log_engine_head_tail") else {
+            return ptr::null();
+        };
+        let head = usize::try_from(head_lines).unwrap_or(usize::MAX);
+        let tail = usize::try_from(tail_lines).unwrap_or(usize::MAX);
+        let block = engine.head_tail(head, tail);
+        if !out_len.is_null() {
+            unsafe { *out_len = block.len() };
+        }
+        block.as_ptr()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_head_tail_line(
+    engine: u64,
+    head_lines: u64,
+    tail_lines: u64,
+    row: u64,
+) -> i64 {
+    unwind::guard("log_engine_head_tail_line", || {
+        // -1 for the elision marker (or a row past the end)
+        let Some(engine) = peek(engine, "log_engine_head_tail_line") else {
+            return -1;
+        };
+        let head = usize::try_from(head_lines).unwrap_or(usize::MAX);
+        let tail = usize::try_from(tail_lines).unwrap_or(usize::MAX);
+        let Ok(row) = usize::try_from(row) else {
+            return -1;
+        };
+        engine
+            .head_tail_line(head, tail, row)
+            .and_then(|line| i64::try_from(line).ok())
+            .unwrap_or(-1)
+    })
+}
+
+// an overview of the file: mode 0 takes every `step`-th line, mode 1 the first line in every
+// `step` bytes (whole 1MB index chunks, so a 50GB file is sampled without reading it). step 0
+// spreads max_rows samples over the whole file. same buffer and lifetime as get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_sample(
+    engine: u64,
+    mode: u32,
+    step: u64,
+    max_rows: u64,
+    out_len: *mut usize,
+) -> *const u8 {
+    unwind::guard("log_engine_sample", || {
+        let Some(mut engine) = enter(engine, "log_engine_sample") else {
+            return ptr::null();
+        };
+        let Some(mode) = SampleMode::from_raw(mode) else {
+            diag::misuse(|| format!("log_engine_sample(mode={}): unknown mode", mode));
+            return ptr::null();
+        };
+        let step = usize::try_from(step).unwrap_or(usize::MAX);
+        let max_rows = usize::try_from(max_rows).unwrap_or(usize::MAX);
+        let block = engine.sample(mode, step, max_rows);
+        if !out_len.is_null() {
+            unsafe { *out_len = block.len() };
+        }
+        block.as_ptr()
+    })
+}
+
+// row of log_engine_sample (same arguments) -> logical line, -1 past the end
+#[no_mangle]
+pub extern "C" fn log_engine_sample_line(
+    engine: u64,
+    mode: u32,
+    step: u64,
+    max_rows: u64,
+    row: u64,
+) -> i64 {
+    unwind::guard("log_engine_sample_line", || {
+        let Some(engine) = peek(engine, "log_engine_sample_line") else {
+            return -1;
+        };
+        let Some(mode) = SampleMode::from_raw(mode) else {
+            diag::misuse(|| format!("log_engine_sample_line(mode={}): unknown mode", mode));
+            return -1;
+        };
+        let step = usize::try_from(step).unwrap_or(usize::MAX);
+        let max_rows = usize::try_from(max_rows).unwrap_or(usize::MAX);
+        let Ok(row) = usize::try_from(row) else {
+            return -1;
+        };
+        engine
+            .sample_line(mode, step, max_rows, row)
+            .and_then(|line| i64::try_from(line).ok())
+            .unwrap_or(-1)
+    })
+}
+
+// add text at the end, see Engine::append. returns the new total line count.
+#[no_mangle]
+pub extern "C" fn log_engine_append(engine: u64, text: *const c_char) -> u64 {
+    unwind::guard("log_engine_append", || {
+        let Some(mut engine) = enter(engine, "log_engine_append") else {
+            return 0;
+        };
+        if text.is_null() {
+            diag::misuse(|| "log_engine_append: null text".to_string());
+        } else {
+            let text = unsafe { CStr::from_ptr(text) }.to_string_lossy().into_owned();
+            engine.append(&text);
+        }
+        engine.total_lines() as u64
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_apply_edit(
+    engine: u64,
+    start_line: usize,
+    num_deleted: usize,
+    new_text: *const c_char,
+) {
+    unwind::guard("log_engine_apply_edit", || {
+        apply_edit(engine, "log_engine_apply_edit", start_line, num_deleted, cstr_arg(new_text));
+    })
+}
+
+// log_engine_apply_edit with the text as (pointer, length), NUL bytes and all
+#[no_mangle]
+pub extern "C" fn log_engine_apply_edit_n(
+    engine: u64,
+    start_line: u64,
+    num_deleted: u64,
+    new_text: *const u8,
+    new_text_len: usize,
+) {
+    unwind::guard("log_engine_apply_edit_n", || {
+        let call = "log_engine_apply_edit_n";
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_deleted = usize::try_from(num_deleted).unwrap_or(usize::MAX);
+        apply_edit(engine, call, start_line, num_deleted, byte_arg(new_text, new_text_len, call));
+    })
+}
+
+fn apply_edit(engine: u64, call: &'static str, start_line: usize, num_deleted: usize, new_text: Option<&[u8]>) {
+    let Some(mut engine) = enter(engine, call) else {
+        return;
+    };
+    let total = engine.total_lines();
+    if start_line > total || num_deleted > total - start_line.min(total) {
+        // still applied, the piece table clamps. but the buffer and engine disagree somewhere.
+        diag::misuse(|| {
+            format!("{}(start_line={}, num_deleted={}): past the end ({} lines)", call, start_line, num_deleted, total)
+        });
+    }
+    // nvim might send weird stuff, salvage what we can.
+    let text = new_text.map(String::from_utf8_lossy).unwrap_or_default();
+    engine.apply_edit(start_line, num_deleted, &text);
+}
+
+// one edit of a log_engine_apply_edits batch, what log_engine_apply_edit_n takes
+#[repr(C)]
+pub struct LogEngineEdit {
+    pub start_line: u64,
+    pub num_deleted: u64,
+    pub text: *const u8,
+    pub text_len: usize,
+}
+
+// `count` edits in order, each against what the ones before it left, see Engine::apply_edits.
+// all or nothing: unlike a single edit, a batch with one past the end isn't salvaged, it's
+// refused whole (false, and a misuse) and the engine stays as it was.
+#[no_mangle]
+pub extern "C" fn log_engine_apply_edits(engine: u64, edits: *const LogEngineEdit, count: usize) -> bool {
+    unwind::guard("log_engine_apply_edits", || {
+        let call = "log_engine_apply_edits";
+        let Some(mut engine) = enter(engine, call) else {
+            return false;
+        };
+        let edits = if count == 0 {
+            &[][..]
+        } else if edits.is_null() {
+            diag::misuse(|| format!("{}: null edits with count {}", call, count));
+            return false;
+        } else {
+            unsafe { std::slice::from_raw_parts(edits, count) }
+        };
+        let mut total = engine.total_lines();
+        let mut batch = Vec::with_capacity(edits.len());
+        for (i, edit) in edits.iter().enumerate() {
+            let start_line = usize::try_from(edit.start_line).unwrap_or(usize::MAX);
+            let num_deleted = usize::try_from(edit.num_deleted).unwrap_or(usize::MAX);
+            if start_line > total || num_deleted > total - start_line {
+                diag::misuse(|| {
+                    format!("{}: edit {} (start_line={}, num_deleted={}) past the end ({} lines)", call, i, start_line, num_deleted, total)
+                });
+                return false;
+            }
+            let Some(text) = byte_arg(edit.text, edit.text_len, call) else {
+                return false;
+            };
+            let lines = edit_lines(&String::from_utf8_lossy(text));
+            total = total - num_deleted + lines.len();
+            batch.push((start_line, num_deleted, lines));
+        }
+        engine.apply_edits(batch)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_save(engine: u64, path: *const c_char) -> bool {
+    unwind::guard("log_engine_save", || {
+        save(engine, "log_engine_save", cstr_arg(path))
+    })
+}
+
+// log_engine_save with the path as (pointer, length). a path can't hold a NUL, but this way
+// the plugin doesn't have to care how its string ends.
+#[no_mangle]
+pub extern "C" fn log_engine_save_n(engine: u64, path: *const u8, path_len: usize) -> bool {
+    unwind::guard("log_engine_save_n", || {
+        save(engine, "log_engine_save_n", byte_arg(path, path_len, "log_engine_save_n"))
+    })
+}
+
+fn save(engine: u64, call: &'static str, path: Option<&[u8]>) -> bool {
+    let Some(mut engine) = enter(engine, call) else {
+        return false;
+    };
+    let _op = ops::start();
+    let Some(path) = path else {
+        diag::misuse(|| format!("{}: null path", call));
+        return false;
+    };
+    if path.contains(&0) {
+        diag::misuse(|| format!("{}: NUL byte in the path", call));
+        return false;
+    }
+    // paths can be cursed too.
+    let path_str = String::from_utf8_lossy(path);
+    let saved = engine.save(&bytes_path(path)).map_err(|err| diag::io_failure(&err)).is_ok();
+    let detail = if saved { path_str.into_owned() } else { format!("{} (failed)", path_str) };
+    engine.audit.record("save", detail);
+    saved
+}
+
+// the session's audit trail as text, one entry per line: unix seconds, kind (edit, save,
+// filter, refresh), detail, separated by tabs. oldest first. same lifetime rules as get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_audit(engine: u64, out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_audit", || {
+        let Some(mut engine) = enter(engine, "log_engine_audit") else {
+            return ptr::null();
+        };
+        engine.last_block = engine.audit.to_text();
+        if !out_len.is_null() {
+            unsafe { *out_len = engine.last_block.len() };
+        }
+        engine.last_block.as_ptr()
+    })
+}
+
+// a note on `line` by `author`, kept in this session until log_engine_notes_sync shares it
+// through <log>.notes (see notes.rs). an empty text removes the line's note. false for an
+// engine with no file to keep notes next to (a pipe, bytes, a descriptor, a frozen one).
+#[no_mangle]
+pub extern "C" fn log_engine_note_set(engine: u64, line: u64, author: *const c_char, text: *const c_char) -> bool {
+    unwind::guard("log_engine_note_set", || {
+        let call = "log_engine_note_set";
+        let Some(mut engine) = enter(engine, call) else {
+            return false;
+        };
+        if author.is_null() || text.is_null() {
+            diag::misuse(|| format!("{}(line={}): null author or text", call, line));
+            return false;
+        }
+        let author = unsafe { CStr::from_ptr(author) }.to_string_lossy();
+        let text = unsafe { CStr::from_ptr(text) }.to_string_lossy();
+        let Some(notes) = engine.notes() else {
+            diag::misuse(|| format!("{}: no file to keep notes next to", call));
+            return false;
+        };
+        notes.set(line, &author, &text);
+        engine.audit.record("note", format!("line {}", line + 1));
+        true
+    })
+}
+
+// the notes on num_lines lines from start_line, a row each: line, unix seconds, author and
+// text, tab separated, tabs and line breaks in the last two escaped (\t \n \r \\). null
+// when there are none. same lifetime as get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_notes(engine: u64, start_line: u64, num_lines: u64, out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_notes", || {
+        let Some(mut engine) = enter(engine, "log_engine_notes") else {
+            return ptr::null();
+        };
+        let mut block = String::new();
+        if let Some(notes) = engine.notes() {
+            for (line, note) in notes.range(start_line, start_line.saturating_add(num_lines)) {
+                let (author, text) = (notes::escape(&note.author), notes::escape(&note.text));
+                block.push_str(&format!("{}\t{}\t{}\t{}\n", line, note.at, author, text));
+            }
+        }
+        if block.is_empty() {
+            return ptr::null();
+        }
+        engine.last_block = block;
+        if !out_len.is_null() {
+            unsafe { *out_len = engine.last_block.len() };
+        }
+        engine.last_block.as_ptr()
+    })
+}
+
+// this session's notes merged with <log>.notes and written back, under an advisory lock on
+// the file so sessions syncing at the same time take turns. returns how many lines both
+// sides changed since the last sync (the newer note stays, see log_engine_notes_conflicts),
+// -1 on failure: error 13 when another session is writing it right now, try again shortly.
+#[no_mangle]
+pub extern "C" fn log_engine_notes_sync(engine: u64) -> i64 {
+    unwind::guard("log_engine_notes_sync", || {
+        let Some(mut engine) = enter(engine, "log_engine_notes_sync") else {
+            return -1;
+        };
+        let Some(notes) = engine.notes() else {
+            diag::misuse(|| "log_engine_notes_sync: no file to keep notes next to".to_string());
+            return -1;
+        };
+        match notes.sync() {
+            Ok(conflicts) => conflicts as i64,
+            Err(err) => {
+                diag::io_failure(&err);
+                -1
+            }
+        }
+    })
+}
+
+// the lines the last log_engine_notes_sync found changed on both sides, a row each: line,
+// the author and text of the note that stayed, the author and text of the one that lost.
+// tab separated, escaped like log_engine_notes. null when there were none.
+#[no_mangle]
+pub extern "C" fn log_engine_notes_conflicts(engine: u64, out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_notes_conflicts", || {
+        let Some(mut engine) = enter(engine, "log_engine_notes_conflicts") else {
+            return ptr::null();
+        };
+        let mut block = String::new();
+        for conflict in engine.notes.iter().flat_map(|notes| &notes.conflicts) {
+            let (kept, lost) = (&conflict.kept, &conflict.lost);
+            block.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                conflict.line,
+                notes::escape(&kept.author),
+                notes::escape(&kept.text),
+                notes::escape(&lost.author),
+                notes::escape(&lost.text)
+            ));
+        }
+        if block.is_empty() {
+            return ptr::null();
+        }
+        engine.last_block = block;
+        if !out_len.is_null() {
+            unsafe { *out_len = engine.last_block.len() };
+        }
+        engine.last_block.as_ptr()
+    })
+}
+
+// when a line happened, seconds since the epoch (see time.rs): its own timestamp or the one of
+// the entry it's part of. NaN if there's none to be found.
+#[no_mangle]
+pub extern "C" fn log_engine_line_time(engine: u64, line: u64) -> f64 {
+    unwind::guard("log_engine_line_time", || {
+        let Some(engine) = peek(engine, "log_engine_line_time") else {
+            return f64::NAN;
+        };
+        engine.line_time(usize::try_from(line).unwrap_or(usize::MAX)).unwrap_or(f64::NAN)
+    })
+}
+
+// the first and last timestamps in the file (lines edited in don't count) and how they're
+// written: 1 ISO 8601, 2 unix seconds/millis, 3 syslog, 4 glog. 0 when the file has none, the out
+// pointers are left alone then. either may be null.
+#[no_mangle]
+pub extern "C" fn log_engine_time_range(engine: u64, out_first: *mut f64, out_last: *mut f64) -> i32 {
+    unwind::guard("log_engine_time_range", || {
+        let Some(mut engine) = enter(engine, "log_engine_time_range") else {
+            return 0;
+        };
+        let times = engine.time_index();
+        let (Some((first, last)), Some(format)) = (times.range(), times.format()) else {
+            return 0;
+        };
+        if !out_first.is_null() {
+            unsafe { *out_first = first };
+        }
+        if !out_last.is_null() {
+            unsafe { *out_last = last };
+        }
+        format as i32
+    })
+}
+
+// the first line stamped at or after `secs` (seconds since the epoch, as from
+// log_engine_parse_time), -1 when the log ends before that time. a binary search over the
+// time index, then reading a chunk or so, however big the file.
+#[no_mangle]
+pub extern "C" fn log_engine_seek_time(engine: u64, secs: f64) -> i64 {
+    unwind::guard("log_engine_seek_time", || {
+        let Some(mut engine) = enter(engine, "log_engine_seek_time") else {
+            return -1;
+        };
+        if secs.is_nan() {
+            diag::misuse(|| "log_engine_seek_time: the time is NaN".to_string());
+            return -1;
+        }
+        engine.seek_time(secs).and_then(|line| i64::try_from(line).ok()).unwrap_or(-1)
+    })
+}
+
+// flat [from previous, from anchor, ...] seconds per line of the block, see time_deltas.
+// anchor_line < 0 for none. out_count gets the number of lines, the pointer lives until
+// the next call, like match_spans.
+#[no_mangle]
+pub extern "C" fn log_engine_time_deltas(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    anchor_line: i64,
+    out_count: *mut u64,
+) -> *const f64 {
+    unwind::guard("log_engine_time_deltas", || {
+        let Some(mut engine) = enter(engine, "log_engine_time_deltas") else {
+            return ptr::null();
+        };
+        if out_count.is_null() {
+            diag::misuse(|| "log_engine_time_deltas: null out_count".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let anchor = usize::try_from(anchor_line).ok();
+        let deltas = engine.time_deltas(start_line, num_lines, anchor);
+        unsafe { *out_count = (deltas.len() / 2) as u64 };
+        deltas.as_ptr()
+    })
+}
+
+// how many lines there are at every severity, into out_counts[0..7]: trace, debug, info,
+// warn, error, fatal, then lines without one. the first call reads the whole file (in
+// parallel, cancellable) unless a level filter or lookup already did. false for an unknown
+// engine, a null out_counts or a cancelled first call.
+#[no_mangle]
+pub extern "C" fn log_engine_level_counts(engine: u64, out_counts: *mut u64) -> bool {
+    unwind::guard("log_engine_level_counts", || {
+        let Some(mut engine) = enter(engine, "log_engine_level_counts") else {
+            return false;
+        };
+        if out_counts.is_null() {
+            diag::misuse(|| "log_engine_level_counts: null out_counts".to_string());
+            return false;
+        }
+        let _op = ops::start();
+        let counts = match engine.level_counts() {
+            Ok(counts) => counts,
+            Err(err) => {
+                diag::io_failure(&err);
+                return false;
+            }
+        };
+        unsafe { ptr::copy_nonoverlapping(counts.as_ptr(), out_counts, counts.len()) };
+        true
+    })
+}
+
+// lines per bucket_secs between two times (seconds since the epoch, `to` left out), see
+// time_histogram. out_count gets the number of buckets, the pointer lives until the next
+// call, like match_spans. null when to isn't after from, the bucket isn't above 0 or there
+// would be more than MAX_BUCKETS buckets.
+#[no_mangle]
+pub extern "C" fn log_engine_time_histogram(engine: u64, from: f64, to: f64, bucket_secs: f64, out_count: *mut u64) -> *const u64 {
+    unwind::guard("log_engine_time_histogram", || {
+        let Some(mut engine) = enter(engine, "log_engine_time_histogram") else {
+            return ptr::null();
+        };
+        if out_count.is_null() {
+            diag::misuse(|| "log_engine_time_histogram: null out_count".to_string());
+            return ptr::null();
+        }
+        if from.is_nan() || to.is_nan() || to <= from {
+            diag::misuse(|| format!("log_engine_time_histogram: {} to {} isn't a range of time", from, to));
+            return ptr::null();
+        }
+        if bucket_secs.is_nan() || bucket_secs <= 0.0 {
+            diag::misuse(|| format!("log_engine_time_histogram: bucket of {}s", bucket_secs));
+            return ptr::null();
+        }
+        if (to - from) / bucket_secs > MAX_BUCKETS as f64 {
+            diag::misuse(|| format!("log_engine_time_histogram: more than {} buckets of {}s", MAX_BUCKETS, bucket_secs));
+            return ptr::null();
+        }
+        let counts = engine.time_histogram(from, to, bucket_secs);
+        unsafe { *out_count = counts.len() as u64 };
+        counts.as_ptr()
+    })
+}
+
+// flat [line, millis, ...] for the lines of the block written at least min_secs after the
+// stamps before them, see time_gaps. out_count gets the number of gaps, the pointer lives
+// until the next call, like match_spans.
+#[no_mangle]
+pub extern "C" fn log_engine_time_gaps(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    min_secs: f64,
+    out_count: *mut u64,
+) -> *const u64 {
+    unwind::guard("log_engine_time_gaps", || {
+        let Some(mut engine) = enter(engine, "log_engine_time_gaps") else {
+            return ptr::null();
+        };
+        if out_count.is_null() {
+            diag::misuse(|| "log_engine_time_gaps: null out_count".to_string());
+            return ptr::null();
+        }
+        if min_secs.is_nan() || min_secs <= 0.0 {
+            diag::misuse(|| format!("log_engine_time_gaps: min_secs {} isn't above 0", min_secs));
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let gaps = engine.time_gaps(start_line, num_lines, min_secs);
+        unsafe { *out_count = (gaps.len() / 2) as u64 };
+        gaps.as_ptr()
+    })
+}
+
+// one byte per line of the block: its severity, 0 trace .. 5 fatal, 255 for none. out_count
+// gets the number of lines, the pointer lives until the next call, like match_spans.
+#[no_mangle]
+pub extern "C" fn log_engine_line_levels(engine: u64, start_line: u64, num_lines: u64, out_count: *mut u64) -> *const u8 {
+    unwind::guard("log_engine_line_levels", || {
+        let Some(mut engine) = enter(engine, "log_engine_line_levels") else {
+            return ptr::null();
+        };
+        if out_count.is_null() {
+            diag::misuse(|| "log_engine_line_levels: null out_count".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let levels = match engine.line_levels(start_line, num_lines) {
+            Ok(levels) => levels,
+            Err(err) => {
+                diag::io_failure(&err);
+                return ptr::null();
+            }
+        };
+        unsafe { *out_count = levels.len() as u64 };
+        levels.as_ptr()
+    })
+}
+
+// flat (line, col, len, fg, bg, attrs) per colored run of text in the block: a color is 0
+// for the default, 1 + n for palette color n, 0x1000000 | 0xRRGGBB for a true color; attrs
+// bold 1, dim 2, italic 4, underline 8, reverse 16, strikethrough 32. columns are in the
+// lines as get_block hands them out. out_count gets the number of runs, the pointer lives
+// until the next call, like match_spans.
+#[no_mangle]
+pub extern "C" fn log_engine_ansi_colors(engine: u64, start_line: u64, num_lines: u64, out_count: *mut u64) -> *const u64 {
+    unwind::guard("log_engine_ansi_colors", || {
+        let Some(mut engine) = enter(engine, "log_engine_ansi_colors") else {
+            return ptr::null();
+        };
+        if out_count.is_null() {
+            diag::misuse(|| "log_engine_ansi_colors: null out_count".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let spans = engine.ansi_colors(start_line, num_lines);
+        unsafe { *out_count = (spans.len() / 6) as u64 };
+        spans.as_ptr()
+    })
+}
+
+// flat (line, col, len, level) per line of the block that says its severity, level 0 trace
+// .. 5 fatal. out_count gets the number of spans, the pointer lives until the next call,
+// like match_spans.
+#[no_mangle]
+pub extern "C" fn log_engine_level_spans(engine: u64, start_line: u64, num_lines: u64, out_count: *mut u64) -> *const u64 {
+    unwind::guard("log_engine_level_spans", || {
+        let Some(mut engine) = enter(engine, "log_engine_level_spans") else {
+            return ptr::null();
+        };
+        if out_count.is_null() {
+            diag::misuse(|| "log_engine_level_spans: null out_count".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let spans = engine.level_spans(start_line, num_lines);
+        unsafe { *out_count = (spans.len() / 4) as u64 };
+        spans.as_ptr()
+    })
+}
+
+// show stamps in `target` ("utc", "local", "+05:30"), reading those that don't give an
+// offset as in `assume` (same forms, "utc" when null or empty): for seeking, deltas, gaps
+// and everything else that goes by time too. a null or empty target turns the display off,
+// `assume` still holds. false with code 7 for a zone that isn't one.
+#[no_mangle]
+pub extern "C" fn log_engine_set_time_zone(engine: u64, target: *const c_char, assume: *const c_char) -> bool {
+    unwind::guard("log_engine_set_time_zone", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_time_zone") else {
+            return false;
+        };
+        let zone = |spec: &[u8]| Zone::parse(&String::from_utf8_lossy(spec));
+        let target = cstr_arg(target).unwrap_or(&[]);
+        let assume = cstr_arg(assume).unwrap_or(&[]);
+        let target = if target.is_empty() { Some(None) } else { zone(target).map(Some) };
+        let (Some(target), Some(assume)) = (target, if assume.is_empty() { Some(Zone::Utc) } else { zone(assume) }) else {
+            diag::fail(diag::ERROR_BAD_QUERY, "not a time zone, use utc, local or an offset like +05:30".to_string());
+            return false;
+        };
+        engine.set_time_zone(target, assume);
+        true
+    })
+}
+
+// every stamp in the block in the zone set by log_engine_set_time_zone: a row per line,
+// empty or "col\tlen\tstamp" (col and len in bytes of the stamp as written). rows are all
+// empty without a zone set. pointer lives until the next call, like get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_zoned_times(engine: u64, start_line: u64, num_lines: u64, out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_zoned_times", || {
+        let Some(mut engine) = enter(engine, "log_engine_zoned_times") else {
+            return ptr::null();
+        };
+        if out_len.is_null() {
+            diag::misuse(|| "log_engine_zoned_times: null out_len".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let block = engine.zoned_times(start_line, num_lines);
+        unsafe { *out_len = block.len() };
+        block.as_ptr()
+    })
+}
+
+// the fields on a line, json or logfmt key=value: a "key\tvalue" row each, tabs and line
+// breaks in values turned into spaces. pointer lives until the next call, like get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_line_fields(engine: u64, line: u64, out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_line_fields", || {
+        let Some(mut engine) = enter(engine, "log_engine_line_fields") else {
+            return ptr::null();
+        };
+        if out_len.is_null() {
+            diag::misuse(|| "log_engine_line_fields: null out_len".to_string());
+            return ptr::null();
+        }
+        let block = engine.line_fields(usize::try_from(line).unwrap_or(usize::MAX));
+        unsafe { *out_len = block.len() };
+        block.as_ptr()
+    })
+}
+
+// where in the code `line` was logged from: glog's `server.cc:88`, or a caller, location,
+// source or file field. the path (pointer lives until the next call, like get_block) and the
+// line in it in out_line, 0 when the field doesn't say. null when the line names none.
+#[no_mangle]
+pub extern "C" fn log_engine_source_location(engine: u64, line: u64, out_len: *mut usize, out_line: *mut u64) -> *const u8 {
+    unwind::guard("log_engine_source_location", || {
+        let Some(mut engine) = enter(engine, "log_engine_source_location") else {
+            return ptr::null();
+        };
+        if out_len.is_null() {
+            diag::misuse(|| "log_engine_source_location: null out_len".to_string());
+            return ptr::null();
+        }
+        let Some((path, at)) = engine.source_location(usize::try_from(line).unwrap_or(usize::MAX)) else {
+            return ptr::null();
+        };
+        unsafe { *out_len = path.len() };
+        if !out_line.is_null() {
+            unsafe { *out_line = at };
+        }
+        path.as_ptr()
+    })
+}
+
+// read the file as csv/tsv, the first line naming the columns, with `delimiter` between
+// cells (0 to tell from the first lines), or as plain lines again without `enable`. false
+// with the reason in last_error when the first line isn't a header that fits the rows.
+#[no_mangle]
+pub extern "C" fn log_engine_set_delimited(engine: u64, enable: bool, delimiter: u8) -> bool {
+    unwind::guard("log_engine_set_delimited", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_delimited") else {
+            return false;
+        };
+        match engine.set_delimited(enable.then_some(delimiter)) {
+            Ok(()) => true,
+            Err(msg) => {
+                diag::fail(diag::ERROR_BAD_QUERY, msg);
+                false
+            }
+        }
+    })
+}
+
+// the columns of a file read as csv/tsv, "name\tkind\twidth" rows in order: the name
+// queries use, what the sampled cells hold (int, float, time, bool, text) and the widest
+// one in chars. null when it isn't read as one. same lifetime as get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_columns(engine: u64, out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_columns", || {
+        let Some(mut engine) = enter(engine, "log_engine_columns") else {
+            return ptr::null();
+        };
+        if out_len.is_null() {
+            diag::misuse(|| "log_engine_columns: null out_len".to_string());
+            return ptr::null();
+        }
+        let Some(block) = engine.columns() else {
+            return ptr::null();
+        };
+        unsafe { *out_len = block.len() };
+        block.as_ptr()
+    })
+}
+
+// which fields the lines in a range have, "name\tcount" rows most common first. with a key
+// (not null), the values of that field instead. same lifetime as get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_field_counts(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    key: *const c_char,
+    out_len: *mut usize,
+) -> *const u8 {
+    unwind::guard("log_engine_field_counts", || {
+        let Some(mut engine) = enter(engine, "log_engine_field_counts") else {
+            return ptr::null();
+        };
+        if out_len.is_null() {
+            diag::misuse(|| "log_engine_field_counts: null out_len".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let key = cstr_arg(key).map(String::from_utf8_lossy);
+        let block = engine.field_counts(start_line, num_lines, key.as_deref());
+        unsafe { *out_len = block.len() };
+        block.as_ptr()
+    })
+}
+
+// the values a field takes in the lines of a range, "value\tcount" rows most common first,
+// at most max (0 = all): group by path, host, status. with regex, `field` is a pattern and
+// the values are what its first group caught, e.g. "GET (\S+)". counted in parallel. null
+// for a null field or a pattern that doesn't compile (see last_error). same lifetime as
+// get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_value_counts(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    field: *const c_char,
+    regex: bool,
+    max: u64,
+    out_len: *mut usize,
+) -> *const u8 {
+    unwind::guard("log_engine_value_counts", || {
+        let Some(mut engine) = enter(engine, "log_engine_value_counts") else {
+            return ptr::null();
+        };
+        if out_len.is_null() {
+            diag::misuse(|| "log_engine_value_counts: null out_len".to_string());
+            return ptr::null();
+        }
+        let Some(field) = cstr_arg(field).map(String::from_utf8_lossy) else {
+            diag::misuse(|| "log_engine_value_counts: null field".to_string());
+            return ptr::null();
+        };
+        let pattern = match regex.then(|| regex::Regex::new(&field)).transpose() {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                diag::fail(diag::ERROR_BAD_QUERY, err.to_string());
+                return ptr::null();
+            }
+        };
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let max = match usize::try_from(max) {
+            Ok(0) | Err(_) => usize::MAX,
+            Ok(max) => max,
+        };
+        let block = engine.value_counts(start_line, num_lines, &field, pattern.as_ref(), max);
+        unsafe { *out_len = block.len() };
+        block.as_ptr()
+    })
+}
+
+// what the lines in a range mostly say: their messages grouped into templates with the
+// variable words as `*`, "count\tfirst line\ttemplate" rows most common first, at most max
+// of them (0 = all). the range is mined in parallel. same lifetime as get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_templates(engine: u64, start_line: u64, num_lines: u64, max: u64, out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_templates", || {
+        let Some(mut engine) = enter(engine, "log_engine_templates") else {
+            return ptr::null();
+        };
+        if out_len.is_null() {
+            diag::misuse(|| "log_engine_templates: null out_len".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let max = match usize::try_from(max) {
+            Ok(0) | Err(_) => usize::MAX,
+            Ok(max) => max,
+        };
+        let block = engine.templates(start_line, num_lines, max);
+        unsafe { *out_len = block.len() };
+        block.as_ptr()
+    })
+}
+
+// a row per line of the block with only the named fields (space or comma separated, e.g.
+// "ts level msg"), lined up in columns. lines without any of them come through whole. row
+// i is line start_line + i. pointer lives until the next call, like get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_project(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    fields: *const c_char,
+    out_len: *mut usize,
+) -> *const u8 {
+    unwind::guard("log_engine_project", || {
+        let Some(mut engine) = enter(engine, "log_engine_project") else {
+            return ptr::null();
+        };
+        if out_len.is_null() {
+            diag::misuse(|| "log_engine_project: null out_len".to_string());
+            return ptr::null();
+        }
+        let fields = String::from_utf8_lossy(cstr_arg(fields).unwrap_or(&[])).into_owned();
+        let names: Vec<&str> = fields.split([' ', ',']).filter(|name| !name.is_empty()).collect();
+        if names.is_empty() {
+            diag::misuse(|| "log_engine_project: no fields".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let block = engine.project(start_line, num_lines, &names);
+        unsafe { *out_len = block.len() };
+        block.as_ptr()
+    })
+}
+
+// whether the file looks like json lines (ndjson), going by its first lines
+#[no_mangle]
+pub extern "C" fn log_engine_is_json_lines(engine: u64) -> bool {
+    unwind::guard("log_engine_is_json_lines", || {
+        peek(engine, "log_engine_is_json_lines").is_some_and(|engine| engine.json_lines())
+    })
+}
+
+// pretty-print the json on a line over several lines, for reading. returns how many lines it
+// takes up now (the same if it already was), -1 when the line isn't json. saving writes it
+// back on one line, as it was.
+#[no_mangle]
+pub extern "C" fn log_engine_json_expand(engine: u64, line: u64) -> i64 {
+    unwind::guard("log_engine_json_expand", || {
+        let Some(mut engine) = enter(engine, "log_engine_json_expand") else {
+            return -1;
+        };
+        match engine.expand_json(usize::try_from(line).unwrap_or(usize::MAX)) {
+            0 => -1,
+            count => i64::try_from(count).unwrap_or(-1),
+        }
+    })
+}
+
+// undo log_engine_json_expand for the expansion `line` is in. returns the line it's back on,
+// -1 when the line isn't in one (never expanded, or edited since)
+#[no_mangle]
+pub extern "C" fn log_engine_json_collapse(engine: u64, line: u64) -> i64 {
+    unwind::guard("log_engine_json_collapse", || {
+        let Some(mut engine) = enter(engine, "log_engine_json_collapse") else {
+            return -1;
+        };
+        engine
+            .collapse_json(usize::try_from(line).unwrap_or(usize::MAX))
+            .and_then(|line| i64::try_from(line).ok())
+            .unwrap_or(-1)
+    })
+}
+
+// a timestamp typed by the user (or taken from anywhere) read the way the log's are, NaN
+// if it isn't one. one without an offset is taken as UTC, log_engine_read_time reads it in
+// an engine's assumed zone.
+#[no_mangle]
+pub extern "C" fn log_engine_parse_time(text: *const u8, len: usize) -> f64 {
+    unwind::guard("log_engine_parse_time", || {
+        let Some(text) = byte_arg(text, len, "log_engine_parse_time") else {
+            return f64::NAN;
+        };
+        time::parse(text, Zone::Utc, &Templates::default()).map_or(f64::NAN, |(secs, _)| secs)
+    })
+}
+
+// log_engine_parse_time for times to compare with an engine's lines: without an offset it's
+// in the zone log_engine_set_time_zone assumes for them
+#[no_mangle]
+pub extern "C" fn log_engine_read_time(engine: u64, text: *const u8, len: usize) -> f64 {
+    unwind::guard("log_engine_read_time", || {
+        let Some(engine) = peek(engine, "log_engine_read_time") else {
+            return f64::NAN;
+        };
+        let Some(text) = byte_arg(text, len, "log_engine_read_time") else {
+            return f64::NAN;
+        };
+        time::parse(text, engine.assume, &engine.templates).map_or(f64::NAN, |(secs, _)| secs)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_diagnostics(engine: u64, out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_diagnostics", || {
+        let Some(engine) = peek(engine, "log_engine_diagnostics") else {
+            return ptr::null();
+        };
+        let (report, len) = engine.hand_out(health::report(&engine));
+        if !out_len.is_null() {
+            unsafe { *out_len = len };
+        }
+        report
+    })
+}
+
+// a query that didn't compile (or a search log_engine_cancel stopped): -2 for the plugin,
+// the reason goes to last_error
+fn bad_query(err: String) -> i64 {
+    let code = if ops::cancelled() { diag::ERROR_CANCELLED } else { diag::ERROR_BAD_QUERY };
+    diag::fail(code, err);
+    -2
+}
+
+// NUL terminated query -> raw bytes. empty queries are useless, treat them as missing.
+fn query_bytes<'a>(query: *const c_char, call: &'static str) -> Option<&'a [u8]> {
+    non_empty_query(cstr_arg(query), call)
+}
+
+fn non_empty_query<'a>(query: Option<&'a [u8]>, call: &'static str) -> Option<&'a [u8]> {
+    let Some(bytes) = query else {
+        diag::misuse(|| format!("{}: null query", call));
+        return None;
+    };
+    (!bytes.is_empty()).then_some(bytes)
+}
+
+// NUL terminated string -> bytes, None for null
+fn cstr_arg<'a>(ptr: *const c_char) -> Option<&'a [u8]> {
+    (!ptr.is_null()).then(|| unsafe { CStr::from_ptr(ptr) }.to_bytes())
+}
+
+// the _n variants: (pointer, length) -> bytes, NULs included. dirty logs have them and a
+// NUL terminated string stops at the first one. null is fine with length 0 only.
+fn byte_arg<'a>(ptr: *const u8, len: usize, call: &'static str) -> Option<&'a [u8]> {
+    if ptr.is_null() {
+        if len > 0 {
+            diag::misuse(|| format!("{}: null pointer with length {}", call, len));
+        }
+        return (len == 0).then_some(&[]);
+    }
+    Some(unsafe { std::slice::from_raw_parts(ptr, len) })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_search(
+    engine: u64,
+    query: *const c_char,
+    start_line: usize,
+) -> isize {
+    unwind::guard_or("log_engine_search", -2, || {
+        log_engine_search_mode(engine, query, start_line as u64, SearchMode::Literal as u32, false) as isize
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_search_backward(
+    engine: u64,
+    query: *const c_char,
+    start_line: usize,
+) -> isize {
+    unwind::guard_or("log_engine_search_backward", -2, || {
+        log_engine_search_mode(engine, query, start_line as u64, SearchMode::Literal as u32, true) as isize
+    })
+}
+
+// returns the matching line, -1 for no match, -2 if the query doesn't compile in that mode
+// (or the call failed inside, see log_engine_last_error_code).
+#[no_mangle]
+pub extern "C" fn log_engine_search_mode(
+    engine: u64,
+    query: *const c_char,
+    start_line: u64,
+    mode: u32,
+    backward: bool,
+) -> i64 {
+    unwind::guard_or("log_engine_search_mode", -2, || {
+        search_mode(engine, "log_engine_search_mode", cstr_arg(query), start_line, mode, backward)
+    })
+}
+
+// log_engine_search_mode with the query as (pointer, length), so it can hold NUL bytes
+#[no_mangle]
+pub extern "C" fn log_engine_search_mode_n(
+    engine: u64,
+    query: *const u8,
+    query_len: usize,
+    start_line: u64,
+    mode: u32,
+    backward: bool,
+) -> i64 {
+    unwind::guard_or("log_engine_search_mode_n", -2, || {
+        let call = "log_engine_search_mode_n";
+        search_mode(engine, call, byte_arg(query, query_len, call), start_line, mode, backward)
+    })
+}
+
+fn search_mode(
+    engine: u64,
+    call: &'static str,
+    query: Option<&[u8]>,
+    start_line: u64,
+    mode: u32,
+    backward: bool,
+) -> i64 {
+    let Some(mut engine) = enter(engine, call) else {
+        return -1;
+    };
+    let _op = ops::start();
+    let Some(query_bytes) = non_empty_query(query, call) else {
+        return -1;
+    };
+    let Some(mode) = SearchMode::from_raw(mode) else {
+        diag::misuse(|| format!("{}(mode={}): unknown mode", call, mode));
+        return -1;
+    };
+    let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+    let found = if backward {
+        engine.search_backward(mode, query_bytes, start_line)
+    } else {
+        engine.search_forward(mode, query_bytes, start_line)
+    };
+    match found {
+        Ok(Some(line)) => i64::try_from(line).unwrap_or(-1),
+        Ok(None) => -1,
+        Err(err) => bad_query(err),
+    }
+}
+
+// flat [line, col, len, line, col, len, ...] for every hit in the block. out_count gets
+// the number of spans. pointer lives until the next call, like get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_match_spans(
+    engine: u64,
+    query: *const c_char,
+    start_line: u64,
+    num_lines: u64,
+    mode: u32,
+    out_count: *mut u64,
+) -> *const u64 {
+    unwind::guard("log_engine_match_spans", || {
+        spans_call(engine, query, start_line, num_lines, mode, false, out_count)
+    })
+}
+
+// match_spans with a fourth number per span, [line, col, len, edited, ...], edited being 1
+// for lines changed this session and 0 for lines from the file.
+#[no_mangle]
+pub extern "C" fn log_engine_match_spans_tagged(
+    engine: u64,
+    query: *const c_char,
+    start_line: u64,
+    num_lines: u64,
+    mode: u32,
+    out_count: *mut u64,
+) -> *const u64 {
+    unwind::guard("log_engine_match_spans_tagged", || {
+        spans_call(engine, query, start_line, num_lines, mode, true, out_count)
+    })
+}
+
+fn spans_call(
+    engine: u64,
+    query: *const c_char,
+    start_line: u64,
+    num_lines: u64,
+    mode: u32,
+    tagged: bool,
+    out_count: *mut u64,
+) -> *const u64 {
+    let call = if tagged { "log_engine_match_spans_tagged" } else { "log_engine_match_spans" };
+    let Some(mut engine) = enter(engine, call) else {
+        return ptr::null();
+    };
+    if out_count.is_null() {
+        diag::misuse(|| format!("{}: null out_count", call));
+        return ptr::null();
+    }
+    unsafe { *out_count = 0 };
+    let Some(query_bytes) = query_bytes(query, call) else {
+        return ptr::null();
+    };
+    let Some(mode) = SearchMode::from_raw(mode) else {
+        diag::misuse(|| format!("{}(mode={}): unknown mode", call, mode));
+        return ptr::null();
+    };
+    let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+    let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+    match engine.match_spans(mode, query_bytes, start_line, num_lines, tagged) {
+        Ok(spans) => {
+            unsafe { *out_count = (spans.len() / (3 + tagged as usize)) as u64 };
+            spans.as_ptr()
+        }
+        Err(err) => {
+            bad_query(err);
+            ptr::null()
+        }
+    }
+}
+
+// flat [line, col, line, col, ...] of every literal \n in the block. out_count gets the
+// number of pairs. pointer lives until the next call, like match_spans.
+#[no_mangle]
+pub extern "C" fn log_engine_escaped_newlines(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    out_count: *mut u64,
+) -> *const u64 {
+    unwind::guard("log_engine_escaped_newlines", || {
+        let Some(mut engine) = enter(engine, "log_engine_escaped_newlines") else {
+            return ptr::null();
+        };
+        if out_count.is_null() {
+            diag::misuse(|| "log_engine_escaped_newlines: null out_count".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let pairs = engine.escaped_newlines(start_line, num_lines);
+        unsafe { *out_count = (pairs.len() / 2) as u64 };
+        pairs.as_ptr()
+    })
+}
+
+// flat [first, last, first, last, ...] of every multi-line entry (stack traces, wrapped
+// lines) in the block, for folds. out_count gets the number of pairs, pointer lives until
+// the next call like escaped_newlines.
+#[no_mangle]
+pub extern "C" fn log_engine_fold_ranges(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    out_count: *mut u64,
+) -> *const u64 {
+    unwind::guard("log_engine_fold_ranges", || {
+        let Some(mut engine) = enter(engine, "log_engine_fold_ranges") else {
+            return ptr::null();
+        };
+        if out_count.is_null() {
+            diag::misuse(|| "log_engine_fold_ranges: null out_count".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let pairs = engine.fold_ranges(start_line, num_lines);
+        unsafe { *out_count = (pairs.len() / 2) as u64 };
+        pairs.as_ptr()
+    })
+}
+
+// the record `line` is part of: the line starting it (its stamp, or not indented) through
+// its last continuation line, like one fold of fold_ranges. false past the end, the out
+// pointers are left alone then. either may be null.
+#[no_mangle]
+pub extern "C" fn log_engine_record_bounds(engine: u64, line: u64, out_first: *mut u64, out_last: *mut u64) -> bool {
+    unwind::guard("log_engine_record_bounds", || {
+        let Some(engine) = peek(engine, "log_engine_record_bounds") else {
+            return false;
+        };
+        let Some((first, last)) = engine.record_bounds(usize::try_from(line).unwrap_or(usize::MAX), 0) else {
+            return false;
+        };
+        if !out_first.is_null() {
+            unsafe { *out_first = first as u64 };
+        }
+        if !out_last.is_null() {
+            unsafe { *out_last = last as u64 };
+        }
+        true
+    })
+}
+
+// flat [first, last, ...] for the runs of repeated lines in the block (see repeat_runs),
+// the same line over and over give or take its timestamp, or with templated the same
+// message give or take its numbers. out_count gets the number of runs, the pointer lives
+// until the next call, like match_spans.
+#[no_mangle]
+pub extern "C" fn log_engine_repeat_runs(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    templated: bool,
+    out_count: *mut u64,
+) -> *const u64 {
+    unwind::guard("log_engine_repeat_runs", || {
+        let Some(mut engine) = enter(engine, "log_engine_repeat_runs") else {
+            return ptr::null();
+        };
+        if out_count.is_null() {
+            diag::misuse(|| "log_engine_repeat_runs: null out_count".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let runs = engine.repeat_runs(start_line, num_lines, templated);
+        unsafe { *out_count = (runs.len() / 2) as u64 };
+        runs.as_ptr()
+    })
+}
+
+// the whole run of repeats `line` is in, not cut at any block: what a collapsed run stands
+// for. first == last for a line that repeats nothing. false past the end, the out pointers
+// are left alone then. either may be null.
+#[no_mangle]
+pub extern "C" fn log_engine_repeat_run(engine: u64, line: u64, templated: bool, out_first: *mut u64, out_last: *mut u64) -> bool {
+    unwind::guard("log_engine_repeat_run", || {
+        let Some(engine) = peek(engine, "log_engine_repeat_run") else {
+            return false;
+        };
+        let Some((first, last)) = engine.repeat_run(usize::try_from(line).unwrap_or(usize::MAX), templated) else {
+            return false;
+        };
+        if !out_first.is_null() {
+            unsafe { *out_first = first as u64 };
+        }
+        if !out_last.is_null() {
+            unsafe { *out_last = last as u64 };
+        }
+        true
+    })
+}
+
+// fold_ranges for runs of lines below min_level (0 trace .. 5 fatal). null on an unknown
+// level, or when building the level index was cancelled.
+#[no_mangle]
+pub extern "C" fn log_engine_severity_folds(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    min_level: u32,
+    out_count: *mut u64,
+) -> *const u64 {
+    unwind::guard("log_engine_severity_folds", || {
+        let Some(mut engine) = enter(engine, "log_engine_severity_folds") else {
+            return ptr::null();
+        };
+        if out_count.is_null() {
+            diag::misuse(|| "log_engine_severity_folds: null out_count".to_string());
+            return ptr::null();
+        }
+        if fields::level_name(min_level as usize).is_none() {
+            diag::misuse(|| format!("log_engine_severity_folds(min_level={}): unknown level", min_level));
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let pairs = match engine.severity_folds(start_line, num_lines, min_level as usize) {
+            Ok(pairs) => pairs,
+            Err(err) => {
+                diag::io_failure(&err);
+                return ptr::null();
+            }
+        };
+        unsafe { *out_count = (pairs.len() / 2) as u64 };
+        pairs.as_ptr()
+    })
+}
+
+// 0 leaves a limit as it is
+#[no_mangle]
+pub extern "C" fn log_engine_set_match_limits(engine: u64, min_list_query: u64, max_spans: u64) {
+    unwind::guard("log_engine_set_match_limits", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_match_limits") else {
+            return;
+        };
+        if min_list_query > 0 {
+            engine.min_list_query = usize::try_from(min_list_query).unwrap_or(usize::MAX);
+        }
+        if max_spans > 0 {
+            engine.max_spans = usize::try_from(max_spans).unwrap_or(usize::MAX);
+        }
+    })
+}
+
+// true if the last match_spans left hits out: query below the minimum length, or cut at max_spans
+#[no_mangle]
+pub extern "C" fn log_engine_spans_capped(engine: u64) -> bool {
+    unwind::guard("log_engine_spans_capped", || {
+        let Some(engine) = peek(engine, "log_engine_spans_capped") else {
+            return false;
+        };
+        engine.spans_capped
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_set_cache_ttl(engine: u64, ttl_secs: u64) {
+    unwind::guard("log_engine_set_cache_ttl", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_cache_ttl") else {
+            return;
+        };
+        engine.cache_ttl = Duration::from_secs(ttl_secs);
+    })
+}
+
+// reads failing with something transient (EIO/ESTALE from a network mount, timeouts) are
+// retried `attempts` times, waiting base_delay_ms and doubling it each time. 0 attempts
+// gives up on the first error. the open options set the same from the start; this one
+// also carries over to reopen.
+#[no_mangle]
+pub extern "C" fn log_engine_set_io_retries(engine: u64, attempts: u32, base_delay_ms: u64) {
+    unwind::guard("log_engine_set_io_retries", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_io_retries") else {
+            return;
+        };
+        let delay = Duration::from_millis(base_delay_ms);
+        engine.retry.attempts = attempts;
+        engine.retry.base_delay = delay;
+        engine.options.retry_attempts = attempts;
+        engine.options.retry_delay = delay;
+    })
+}
+
+// after a call failed on something transient: how many ms to wait before trying it again,
+// nothing here sleeps on the caller's thread. 0 when there's nothing to retry (the last
+// read worked, or the attempts ran out and the error stands).
+#[no_mangle]
+pub extern "C" fn log_engine_io_retry_after_ms(engine: u64) -> u64 {
+    unwind::guard("log_engine_io_retry_after_ms", || {
+        let Some(engine) = peek(engine, "log_engine_io_retry_after_ms") else {
+            return 0;
+        };
+        engine.retry.retry_after().map_or(0, |after| after.as_millis() as u64)
+    })
+}
+
+// what the read_only open option does, for engines opened some other way (log_engine_new,
+// a fd). only goes one way: a viewer that could be made editable again isn't a guarantee.
+#[no_mangle]
+pub extern "C" fn log_engine_set_read_only(engine: u64) {
+    unwind::guard("log_engine_set_read_only", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_read_only") else {
+            return;
+        };
+        engine.options.read_only = true;
+    })
+}
+
+// binary lines (see binary.rs) handed out as a hex preview, the default, or as text full of
+// replacement characters. like strip_ansi it's only what's shown: search runs on the bytes
+// and a preview line edited is saved the way it was shown.
+#[no_mangle]
+pub extern "C" fn log_engine_set_hex_binary(engine: u64, hex: bool) {
+    unwind::guard("log_engine_set_hex_binary", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_hex_binary") else {
+            return;
+        };
+        engine.hex_binary = hex;
+    })
+}
+
+// flat [first, last, first, last, ...] of every run of binary lines in the block. out_count
+// gets the number of pairs, pointer lives until the next call like fold_ranges.
+#[no_mangle]
+pub extern "C" fn log_engine_binary_regions(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    out_count: *mut u64,
+) -> *const u64 {
+    unwind::guard("log_engine_binary_regions", || {
+        let Some(mut engine) = enter(engine, "log_engine_binary_regions") else {
+            return ptr::null();
+        };
+        if out_count.is_null() {
+            diag::misuse(|| "log_engine_binary_regions: null out_count".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let pairs = engine.binary_regions(start_line, num_lines);
+        unsafe { *out_count = (pairs.len() / 2) as u64 };
+        pairs.as_ptr()
+    })
+}
+
+// the bytes of num_lines lines from start_line as they are on file, line breaks included,
+// nothing replaced: what's behind a hex preview. not utf-8, use the length. lives until the
+// next call.
+#[no_mangle]
+pub extern "C" fn log_engine_raw_bytes(engine: u64, start_line: u64, num_lines: u64, out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_raw_bytes", || {
+        let Some(mut engine) = enter(engine, "log_engine_raw_bytes") else {
+            return ptr::null();
+        };
+        if out_len.is_null() {
+            diag::misuse(|| "log_engine_raw_bytes: null out_len".to_string());
+            return ptr::null();
+        }
+        let start_line = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num_lines = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let bytes = engine.raw_bytes(start_line, num_lines);
+        unsafe { *out_len = bytes.len() };
+        bytes.as_ptr()
+    })
+}
+
+// hand lines out without terminal escapes (colors, erase codes) from now on: get_block and
+// every call that gives text or columns, and searches and filters made from now on match
+// the lines that way. the file keeps them; a line edited while they're stripped gets its
+// escapes back where the text around them stayed the same.
+#[no_mangle]
+pub extern "C" fn log_engine_set_strip_ansi(engine: u64, strip: bool) {
+    unwind::guard("log_engine_set_strip_ansi", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_strip_ansi") else {
+            return;
+        };
+        engine.set_strip_ansi(strip);
+    })
+}
+
+// whether edits and saves are refused: set that way, or opened from something that can't
+// be written back (a journalctl export)
+#[no_mangle]
+pub extern "C" fn log_engine_is_read_only(engine: u64) -> bool {
+    unwind::guard("log_engine_is_read_only", || {
+        peek(engine, "log_engine_is_read_only").is_some_and(|engine| engine.options.read_only)
+    })
+}
+
+// retries the engine needed so far, opening the file included
+#[no_mangle]
+pub extern "C" fn log_engine_io_retries(engine: u64) -> u64 {
+    unwind::guard("log_engine_io_retries", || {
+        let Some(engine) = peek(engine, "log_engine_io_retries") else {
+            return 0;
+        };
+        engine.retry.retries
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_trim_caches(engine: u64, max_idle_secs: u64) -> u64 {
+    unwind::guard("log_engine_trim_caches", || {
+        // 0 drops everything. the plugin calls this from a timer so idle sessions shed memory too.
+        let Some(mut engine) = enter(engine, "log_engine_trim_caches") else {
+            return 0;
+        };
+        engine.trim_caches(Duration::from_secs(max_idle_secs)) as u64
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_history_len(engine: u64) -> usize {
+    unwind::guard("log_engine_history_len", || {
+        let Some(engine) = peek(engine, "log_engine_history_len") else {
+            return 0;
+        };
+        engine.history.len()
+    })
+}
+
+// a query from the history, 0 the most recent. out_hits gets how many lines it hit, or
+// u64::MAX when a search on a big file hasn't got through the whole file yet.
+#[no_mangle]
+pub extern "C" fn log_engine_history_get(
+    engine: u64,
+    idx: usize,
+    out_len: *mut usize,
+    out_hits: *mut u64,
+    out_mode: *mut u32,
+) -> *const u8 {
+    unwind::guard("log_engine_history_get", || {
+        // 0 is the most recent query. same rules as get_block: copy before calling back in.
+        let Some(engine) = peek(engine, "log_engine_history_get") else {
+            return ptr::null();
+        };
+        let Some(entry) = engine.history.get(idx) else {
+            return ptr::null();
+        };
+        if !out_len.is_null() {
+            unsafe { *out_len = entry.query.len() };
+        }
+        if !out_hits.is_null() {
+            unsafe { *out_hits = entry.hits.map_or(u64::MAX, |hits| hits as u64) };
+        }
+        if !out_mode.is_null() {
+            unsafe { *out_mode = entry.mode as u32 };
+        }
+        entry.query.as_ptr()
+    })
+}
+
+// a slice of the scan behind n/N on a file bigger than the filter slice (see
+// log_engine_set_filter_slice), for a plugin to run from its event loop after a search:
+// the next n/N finds the index ready, and the history gets the count. out_scanned/out_total
+// are bytes of the file, either may be null. returns 1 once the index is complete (right
+// away for a small file, or a query searched through before), 0 while there's more, -1 on
+// bad arguments, -2 when the query doesn't compile (or log_engine_cancel stopped it, or the
+// call failed inside).
+#[no_mangle]
+pub extern "C" fn log_engine_search_step(
+    engine: u64,
+    query: *const u8,
+    query_len: usize,
+    mode: u32,
+    out_scanned: *mut u64,
+    out_total: *mut u64,
+) -> i32 {
+    unwind::guard_or("log_engine_search_step", -2, || {
+        let call = "log_engine_search_step";
+        let Some(mut engine) = enter(engine, call) else {
+            return -1;
+        };
+        let _op = ops::start();
+        let Some(query_bytes) = non_empty_query(byte_arg(query, query_len, call), call) else {
+            return -1;
+        };
+        let Some(mode) = SearchMode::from_raw(mode) else {
+            diag::misuse(|| format!("{}(mode={}): unknown mode", call, mode));
+            return -1;
+        };
+        let (scanned, total, done) = match engine.search_step(mode, query_bytes) {
+            Ok(stepped) => stepped,
+            Err(err) => return bad_query(err) as i32,
+        };
+        if !out_scanned.is_null() {
+            unsafe { *out_scanned = scanned as u64 };
+        }
+        if !out_total.is_null() {
+            unsafe { *out_total = total as u64 };
+        }
+        done as i32
+    })
+}
+
+// false (and a diagnostic) if the line doesn't exist
+#[no_mangle]
+pub extern "C" fn log_engine_split_line(engine: u64, line: u64, col: u64) -> bool {
+    unwind::guard("log_engine_split_line", || {
+        let Some(mut engine) = enter(engine, "log_engine_split_line") else {
+            return false;
+        };
+        let line_idx = usize::try_from(line).unwrap_or(usize::MAX);
+        let col = usize::try_from(col).unwrap_or(usize::MAX);
+        if !engine.split_line(line_idx, col) {
+            let total = engine.total_lines();
+            diag::misuse(|| format!("log_engine_split_line(line={}, col={}): past the end ({} lines)", line, col, total));
+            return false;
+        }
+        true
+    })
+}
+
+// false if there aren't at least two lines to join. a null separator joins with nothing.
+#[no_mangle]
+pub extern "C" fn log_engine_join_lines(
+    engine: u64,
+    start_line: u64,
+    count: u64,
+    separator: *const c_char,
+) -> bool {
+    unwind::guard("log_engine_join_lines", || {
+        let Some(mut engine) = enter(engine, "log_engine_join_lines") else {
+            return false;
+        };
+        let separator = if separator.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(separator) }.to_string_lossy().into_owned()
+        };
+        let start = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let count = usize::try_from(count).unwrap_or(usize::MAX);
+        if start >= engine.total_lines() {
+            let total = engine.total_lines();
+            diag::misuse(|| format!("log_engine_join_lines(start_line={}, count={}): past the end ({} lines)", start_line, count, total));
+            return false;
+        }
+        engine.join_lines(start, count, &separator)
+    })
+}
+
+// digest of a range as JSON: time span, level counts, top templates and field values, gaps.
+// same buffer and lifetime rules as get_block.
+#[no_mangle]
+pub extern "C" fn log_engine_summarize(
+    engine: u64,
+    start_line: u64,
+    num_lines: u64,
+    out_len: *mut usize,
+) -> *const u8 {
+    unwind::guard("log_engine_summarize", || {
+        let Some(mut engine) = enter(engine, "log_engine_summarize") else {
+            return ptr::null();
+        };
+        let start = usize::try_from(start_line).unwrap_or(usize::MAX);
+        let num = usize::try_from(num_lines).unwrap_or(usize::MAX);
+        let total = engine.total_lines();
+        if start > total {
+            diag::misuse(|| format!("log_engine_summarize(start_line={}, num_lines={}): past the end ({} lines)", start_line, num_lines, total));
+        }
+        let digest = engine.summarize(start, num);
+        if !out_len.is_null() {
+            unsafe { *out_len = digest.len() };
+        }
+        digest.as_ptr()
+    })
+}
+
+// block hashes for syncing with another copy of the file. block i covers logical lines
+// [i * block_lines, (i + 1) * block_lines). out_count gets how many came back (fewer
+// than asked near the end). pointer lives until the next call, like match_spans.
+#[no_mangle]
+pub extern "C" fn log_engine_checkpoints(
+    engine: u64,
+    block_lines: u64,
+    first_block: u64,
+    num_blocks: u64,
+    out_count: *mut u64,
+) -> *const u64 {
+    unwind::guard("log_engine_checkpoints", || {
+        let Some(mut engine) = enter(engine, "log_engine_checkpoints") else {
+            return ptr::null();
+        };
+        if out_count.is_null() || block_lines == 0 {
+            diag::misuse(|| format!("log_engine_checkpoints(block_lines={}): zero block size or null out_count", block_lines));
+            return ptr::null();
+        }
+        let block_lines = usize::try_from(block_lines).unwrap_or(usize::MAX);
+        let first_block = usize::try_from(first_block).unwrap_or(usize::MAX);
+        let num_blocks = usize::try_from(num_blocks).unwrap_or(usize::MAX);
+        let hashes = engine.checkpoints(block_lines, first_block, num_blocks);
+        unsafe { *out_count = hashes.len() as u64 };
+        hashes.as_ptr()
+    })
+}
+
+// a filter is a virtual file of matching lines living inside the engine.
+// returns its handle (>= 1), -1 on failure, -2 if the query doesn't compile in that mode.
+#[no_mangle]
+pub extern "C" fn log_engine_filter_create(engine: u64, query: *const c_char, mode: u32) -> i64 {
+    unwind::guard("log_engine_filter_create", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_create") else {
+            return -1;
+        };
+        let _op = ops::start();
+        let Some(query_bytes) = query_bytes(query, "log_engine_filter_create") else {
+            return -1;
+        };
+        let Some(mode) = SearchMode::from_raw(mode) else {
+            diag::misuse(|| format!("log_engine_filter_create(mode={}): unknown mode", mode));
+            return -1;
+        };
+        match engine.filter_create(mode, query_bytes, false) {
+            Ok(id) => i64::try_from(id).unwrap_or(-1),
+            Err(err) => bad_query(err),
+        }
+    })
+}
+
+// narrow an existing filter further. same returns as filter_create, the parent stays valid.
+#[no_mangle]
+pub extern "C" fn log_engine_filter_refine(
+    engine: u64,
+    filter: u64,
+    query: *const c_char,
+    mode: u32,
+) -> i64 {
+    unwind::guard("log_engine_filter_refine", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_refine") else {
+            return -1;
+        };
+        let _op = ops::start();
+        if !filter_exists(&mut engine, filter, "log_engine_filter_refine") {
+            return -1;
+        }
+        let Some(query_bytes) = query_bytes(query, "log_engine_filter_refine") else {
+            return -1;
+        };
+        let Some(mode) = SearchMode::from_raw(mode) else {
+            diag::misuse(|| format!("log_engine_filter_refine(mode={}): unknown mode", mode));
+            return -1;
+        };
+        match engine.filter_refine(filter, mode, query_bytes, false) {
+            Ok(Some(id)) => i64::try_from(id).unwrap_or(-1),
+            Ok(None) => -1,
+            Err(err) => bad_query(err),
+        }
+    })
+}
+
+// hide the lines matching query. filter 0 starts from the whole file, anything else
+// narrows that filter. same returns as filter_create.
+#[no_mangle]
+pub extern "C" fn log_engine_filter_exclude(
+    engine: u64,
+    filter: u64,
+    query: *const c_char,
+    mode: u32,
+) -> i64 {
+    unwind::guard("log_engine_filter_exclude", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_exclude") else {
+            return -1;
+        };
+        let _op = ops::start();
+        if filter != 0 && !filter_exists(&mut engine, filter, "log_engine_filter_exclude") {
+            return -1;
+        }
+        let Some(query_bytes) = query_bytes(query, "log_engine_filter_exclude") else {
+            return -1;
+        };
+        let Some(mode) = SearchMode::from_raw(mode) else {
+            diag::misuse(|| format!("log_engine_filter_exclude(mode={}): unknown mode", mode));
+            return -1;
+        };
+        let created = match filter {
+            0 => engine.filter_create(mode, query_bytes, true).map(Some),
+            _ => engine.filter_refine(filter, mode, query_bytes, true),
+        };
+        match created {
+            Ok(Some(id)) => i64::try_from(id).unwrap_or(-1),
+            Ok(None) => -1,
+            Err(err) => bad_query(err),
+        }
+    })
+}
+
+// filter on severity: 0 trace, 1 debug, 2 info, 3 warn, 4 error, 5 fatal and up.
+// returns the filter handle or -1.
+#[no_mangle]
+pub extern "C" fn log_engine_filter_level(engine: u64, min_level: u32) -> i64 {
+    unwind::guard("log_engine_filter_level", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_level") else {
+            return -1;
+        };
+        match engine.filter_level(min_level as usize) {
+            Ok(id) => i64::try_from(id).unwrap_or(-1),
+            Err(_) => {
+                diag::misuse(|| format!("log_engine_filter_level(min_level={}): unknown level", min_level));
+                -1
+            }
+        }
+    })
+}
+
+// lines stamped between from and to (inclusive), parsed like the ts field: ISO dates with
+// or without a time, or epoch seconds/millis. an empty bound leaves that side open.
+// filter 0 starts from the whole file. returns the handle, -1 on bad arguments, -2 when
+// a bound isn't a timestamp (or log_engine_cancel stopped it).
+#[no_mangle]
+pub extern "C" fn log_engine_filter_time(
+    engine: u64,
+    filter: u64,
+    from: *const c_char,
+    to: *const c_char,
+) -> i64 {
+    unwind::guard("log_engine_filter_time", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_time") else {
+            return -1;
+        };
+        let _op = ops::start();
+        if filter != 0 && !filter_exists(&mut engine, filter, "log_engine_filter_time") {
+            return -1;
+        }
+        if from.is_null() || to.is_null() {
+            diag::misuse(|| "log_engine_filter_time: null bound".to_string());
+            return -1;
+        }
+        let from = unsafe { CStr::from_ptr(from) }.to_string_lossy();
+        let to = unsafe { CStr::from_ptr(to) }.to_string_lossy();
+        match engine.filter_time(filter, &from, &to) {
+            Ok(Some(id)) => i64::try_from(id).unwrap_or(-1),
+            Ok(None) => -1,
+            Err(err) => bad_query(err),
+        }
+    })
+}
+
+// what changed on disk since the last poll (or since opening): bytes and finished lines
+// appended, and whether the file was truncated or replaced. true when there's anything
+// to report. this only says the engine is behind, log_engine_refresh catches it up.
+#[no_mangle]
+pub extern "C" fn log_engine_poll_changes(
+    engine: u64,
+    out_bytes: *mut u64,
+    out_lines: *mut u64,
+    out_rotated: *mut bool,
+) -> bool {
+    unwind::guard("log_engine_poll_changes", || {
+        let Some(mut engine) = enter(engine, "log_engine_poll_changes") else {
+            return false;
+        };
+        // a read error now usually means the file is mid-rotation, the next poll will tell
+        let engine = &mut *engine;
+        let Ok(changes) = engine.watch.poll(&mut engine.retry) else {
+            return false;
+        };
+        if !out_bytes.is_null() {
+            unsafe { *out_bytes = changes.bytes_appended };
+        }
+        if !out_lines.is_null() {
+            unsafe { *out_lines = changes.lines_appended };
+        }
+        if !out_rotated.is_null() {
+            unsafe { *out_rotated = changes.rotated };
+        }
+        changes.any()
+    })
+}
+
+// have the engine watch the file itself (inotify, kqueue, or a look at its size a few times
+// a second elsewhere) instead of the host: EVENT_CHANGED comes through log_engine_set_callback
+// or the wakeup mailbox whenever it changed, a = 1 when it looks rotated or truncated, b its
+// size. the host then calls log_engine_poll_changes as it would for its own watcher. the
+// events carry the number this was called with. false when there's no file to watch (a bare
+// descriptor) or the OS refused; each true needs one log_engine_unwatch_native. a reopen
+// moves the watch onto the new file.
+#[no_mangle]
+pub extern "C" fn log_engine_watch_native(engine: u64) -> bool {
+    unwind::guard("log_engine_watch_native", || {
+        let Some(mut engine_ref) = enter(engine, "log_engine_watch_native") else {
+            return false;
+        };
+        engine_ref.watch.start_native(engine).map_err(|err| diag::io_failure(&err)).is_ok()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_unwatch_native(engine: u64) {
+    unwind::guard("log_engine_unwatch_native", || {
+        let Some(mut engine) = enter(engine, "log_engine_unwatch_native") else {
+            return;
+        };
+        engine.watch.stop_native();
+    })
+}
+
+// pick up whatever was appended to the file since it was opened (or last refreshed).
+// open filters are extended with the new lines, handles stay valid. returns the number of
+// lines added, or -1 if the file shrank or can't be mapped again.
+#[no_mangle]
+pub extern "C" fn log_engine_refresh(engine: u64) -> i64 {
+    unwind::guard("log_engine_refresh", || {
+        let Some(mut engine) = enter(engine, "log_engine_refresh") else {
+            return -1;
+        };
+        let _op = ops::start();
+        grown(engine.grow())
+    })
+}
+
+// what refresh and resume hand out: lines added, or -1 with the reason in last_error
+fn grown(result: std::io::Result<Option<usize>>) -> i64 {
+    match result {
+        Ok(Some(added)) => i64::try_from(added).unwrap_or(-1),
+        Ok(None) => {
+            diag::fail(diag::ERROR_TRUNCATED, "the file got shorter, it needs reopening".to_string());
+            -1
+        }
+        Err(err) => {
+            diag::io_failure(&err);
+            -1
+        }
+    }
+}
+
+// after log_engine_poll_changes said the file was rotated or truncated: open whatever is at
+// the path now. returns bits: 1 when edits were kept (same content, just a new file), without
+// it the engine starts over on the new content (filters searched again, same handles). 2 when
+// some filters were closed (a step no longer compiles), 4 when snapshots were (the file was
+// truncated in place, they can't be read anymore). -1 when there's nothing to open yet (mid
+// rotation), the old content stays until then.
+#[no_mangle]
+pub extern "C" fn log_engine_reopen(engine: u64) -> i32 {
+    unwind::guard("log_engine_reopen", || {
+        let Some(mut engine) = enter(engine, "log_engine_reopen") else {
+            return -1;
+        };
+        let _op = ops::start();
+        match engine.reopen() {
+            Ok(status) => status,
+            Err(err) => {
+                diag::io_failure(&err);
+                -1
+            }
+        }
+    })
+}
+
+// how much of a rotated out file log_engine_reopen keeps in front of the new one, as its
+// last max_bytes. 0 (the default) keeps nothing.
+#[no_mangle]
+pub extern "C" fn log_engine_set_keep_rotated(engine: u64, max_bytes: u64) {
+    unwind::guard("log_engine_set_keep_rotated", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_keep_rotated") else {
+            return;
+        };
+        engine.keep_rotated = usize::try_from(max_bytes).unwrap_or(usize::MAX);
+    })
+}
+
+// leading lines that came from rotated out files, the new file starts right after them
+#[no_mangle]
+pub extern "C" fn log_engine_rotated_lines(engine: u64) -> u64 {
+    unwind::guard("log_engine_rotated_lines", || {
+        let Some(engine) = peek(engine, "log_engine_rotated_lines") else {
+            return 0;
+        };
+        engine.rotated_lines as u64
+    })
+}
+
+// ring buffer mode: keep at most max_lines lines / max_bytes bytes (0 = no limit), dropping
+// the oldest once the file or appends go past either. meant for endless streams, a log file
+// has nothing to gain from it. applies from the next growth on.
+#[no_mangle]
+pub extern "C" fn log_engine_set_retention(engine: u64, max_lines: u64, max_bytes: u64) {
+    unwind::guard("log_engine_set_retention", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_retention") else {
+            return;
+        };
+        engine.retain_lines = usize::try_from(max_lines).unwrap_or(usize::MAX);
+        engine.retain_bytes = usize::try_from(max_bytes).unwrap_or(usize::MAX);
+        engine.unchecked_bytes = engine.retain_bytes; // measure on the next growth
+    })
+}
+
+// lines dropped off the front by the retention limits so far. line N now is what was line
+// N + (growth of this since) before: the plugin subtracts the growth from its offsets.
+#[no_mangle]
+pub extern "C" fn log_engine_evicted_lines(engine: u64) -> u64 {
+    unwind::guard("log_engine_evicted_lines", || {
+        let Some(engine) = peek(engine, "log_engine_evicted_lines") else {
+            return 0;
+        };
+        engine.evicted
+    })
+}
+
+// stop taking in new lines: file growth is left alone and appends are held back until
+// log_engine_resume, so scrolling back through a busy tail doesn't reindex or drop caches on
+// every write. log_engine_refresh reports 0 new lines meanwhile, rotation still gets seen.
+#[no_mangle]
+pub extern "C" fn log_engine_pause(engine: u64) {
+    unwind::guard("log_engine_pause", || {
+        let Some(mut engine) = enter(engine, "log_engine_pause") else {
+            return;
+        };
+        engine.paused = true;
+    })
+}
+
+// take in everything missed while paused. returns the lines added, -1 if the file shrank
+// meanwhile (poll and reopen it) or can't be read. resuming an engine that isn't paused is 0.
+#[no_mangle]
+pub extern "C" fn log_engine_resume(engine: u64) -> i64 {
+    unwind::guard("log_engine_resume", || {
+        let Some(mut engine) = enter(engine, "log_engine_resume") else {
+            return -1;
+        };
+        let _op = ops::start();
+        grown(engine.resume())
+    })
+}
+
+// what log_engine_stats fills in, for statuslines. versioned like LogEngineOptions: the
+// caller sets the version its header had, fields only ever get added at the end under a
+// new one and only those the caller's version has are written.
+#[repr(C)]
+pub struct LogEngineStats {
+    pub version: u32,
+    pub file_size: u64,       // bytes on disk now, at least what's mapped
+    pub total_lines: u64,     // logical lines, original_lines + memory_lines
+    pub original_lines: u64,  // lines still read straight from the file
+    pub memory_lines: u64,    // edited or appended lines
+    pub pieces: u64,          // runs in the piece table, how fragmented the edits left it
+    pub indexed_percent: f64, // how much of file_size the line index covers
+}
+
+// the LogEngineStats version this library fills in up to, part of the C ABI
+const STATS_VERSION: u32 = 1;
+
+// false (and `out` untouched) for an unknown engine, a null `out` or one from a newer header
+#[no_mangle]
+pub extern "C" fn log_engine_stats(engine: u64, out: *mut LogEngineStats) -> bool {
+    unwind::guard("log_engine_stats", || {
+        let Some(engine) = peek(engine, "log_engine_stats") else {
+            return false;
+        };
+        if out.is_null() {
+            diag::misuse(|| "log_engine_stats: null out".to_string());
+            return false;
+        }
+        let version = unsafe { (*out).version };
+        if version == 0 || version > STATS_VERSION {
+            diag::misuse(|| format!("log_engine_stats: stats version {}, this library knows up to {}", version, STATS_VERSION));
+            return false;
+        }
+        // every field is version 1's so far
+        unsafe { out.write(engine.stats()) };
+        true
+    })
+}
+
+// bytes waiting for log_engine_resume, 0 when not paused
+#[no_mangle]
+pub extern "C" fn log_engine_pending_bytes(engine: u64) -> u64 {
+    unwind::guard("log_engine_pending_bytes", || {
+        let Some(engine) = peek(engine, "log_engine_pending_bytes") else {
+            return 0;
+        };
+        engine.pending_bytes() as u64
+    })
+}
+
+// how fast lines came in (file growth picked up by refresh, appends, a pipe) over the last
+// throughput window, per second. while the engine is younger than the window it's over
+// its whole life so far. either out pointer may be null.
+#[no_mangle]
+pub extern "C" fn log_engine_throughput(engine: u64, out_lines_per_sec: *mut f64, out_bytes_per_sec: *mut f64) {
+    unwind::guard("log_engine_throughput", || {
+        let Some(engine) = peek(engine, "log_engine_throughput") else {
+            return;
+        };
+        let (lines, bytes) = engine.throughput.rates();
+        if !out_lines_per_sec.is_null() {
+            unsafe { *out_lines_per_sec = lines };
+        }
+        if !out_bytes_per_sec.is_null() {
+            unsafe { *out_bytes_per_sec = bytes };
+        }
+    })
+}
+
+// the window log_engine_throughput averages over, 0 = the default (10s). starts counting over.
+#[no_mangle]
+pub extern "C" fn log_engine_set_throughput_window(engine: u64, window_ms: u64) {
+    unwind::guard("log_engine_set_throughput_window", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_throughput_window") else {
+            return;
+        };
+        engine.throughput.window = match window_ms {
+            0 => throughput::DEFAULT_WINDOW,
+            ms => Duration::from_millis(ms),
+        };
+        engine.throughput.reset();
+    })
+}
+
+// for engines opened on a fifo: 0 still reading, 1 the writer closed the pipe (everything
+// it sent is in), 2 reading failed. -1 when the engine isn't reading a pipe at all.
+// whatever arrived shows up through log_engine_poll_changes/log_engine_refresh like
+// growth of a file.
+#[no_mangle]
+pub extern "C" fn log_engine_pipe_status(engine: u64) -> i32 {
+    unwind::guard("log_engine_pipe_status", || {
+        let Some(engine) = peek(engine, "log_engine_pipe_status") else {
+            return -1;
+        };
+        engine.pipe.as_ref().map_or(-1, Pipe::status)
+    })
+}
+
+// log_engine_poll_changes + log_engine_refresh (or log_engine_reopen after a rotation) in
+// one call, for plugins that poll instead of watching. returns 0 nothing new, 1 the file
+// grew, 2 it was rotated or truncated and got reopened, 3 it was replaced by a longer copy
+// of itself and reopened with the edits kept, -1 error (mid rotation, the next call tries
+// again).
+// out_total gets the line count after, out_first_changed the first line to redraw from (the
+// unfinished last line if it got more text, 0 after 2, the total when nothing changed).
+// lines dropped by the retention limits are in evicted_lines.
+#[no_mangle]
+pub extern "C" fn log_engine_refresh_delta(
+    engine: u64,
+    out_total: *mut u64,
+    out_first_changed: *mut u64,
+) -> i32 {
+    unwind::guard("log_engine_refresh_delta", || {
+        let Some(mut engine) = enter(engine, "log_engine_refresh_delta") else {
+            return -1;
+        };
+        let _op = ops::start();
+        let (result, first_changed) = match engine.refresh_delta() {
+            Ok(refreshed) => refreshed,
+            Err(err) => {
+                diag::io_failure(&err);
+                return -1;
+            }
+        };
+        if !out_total.is_null() {
+            unsafe { *out_total = engine.total_lines() as u64 };
+        }
+        if !out_first_changed.is_null() {
+            unsafe { *out_first_changed = first_changed as u64 };
+        }
+        result
+    })
+}
+
+// the filter, or a diagnostic if the plugin hands us a handle we never gave out
+fn filter_exists(engine: &mut Engine, filter: u64, call: &'static str) -> bool {
+    if engine.filters.contains_key(&filter) || engine.pending.contains_key(&filter) {
+        return true;
+    }
+    diag::misuse(|| format!("{}(filter={}): unknown filter", call, filter));
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_filter_total_lines(engine: u64, filter: u64) -> u64 {
+    unwind::guard("log_engine_filter_total_lines", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_total_lines") else {
+            return 0;
+        };
+        engine.filter(filter).map_or(0, |view| view.total() as u64)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_filter_get_block(
+    engine: u64,
+    filter: u64,
+    start_row: u64,
+    num_rows: u64,
+    out_len: *mut usize,
+) -> *const u8 {
+    unwind::guard("log_engine_filter_get_block", || {
+        // same buffer and same lifetime rules as get_block
+        let Some(mut engine) = enter(engine, "log_engine_filter_get_block") else {
+            return ptr::null();
+        };
+        if !filter_exists(&mut engine, filter, "log_engine_filter_get_block") {
+            return ptr::null();
+        }
+        let start_row = usize::try_from(start_row).unwrap_or(usize::MAX);
+        let num_rows = usize::try_from(num_rows).unwrap_or(usize::MAX);
+        let ptr = engine.filter_block(filter, start_row, num_rows);
+        if !out_len.is_null() {
+            unsafe { *out_len = engine.last_block.len() };
+        }
+        ptr
+    })
+}
+
+// grep -B/-A for a filter: every hit comes with `before` lines above and `after` below,
+// runs that don't touch are split by a `--` row. 0/0 turns it off. rows, totals and
+// everything else about the filter follow, separators map to line -1.
+#[no_mangle]
+pub extern "C" fn log_engine_filter_context(engine: u64, filter: u64, before: u64, after: u64) -> bool {
+    unwind::guard("log_engine_filter_context", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_context") else {
+            return false;
+        };
+        if !filter_exists(&mut engine, filter, "log_engine_filter_context") {
+            return false;
+        }
+        let total = engine.total_lines();
+        let before = usize::try_from(before).unwrap_or(usize::MAX);
+        let after = usize::try_from(after).unwrap_or(usize::MAX);
+        match engine.filter(filter) {
+            Some(_) => {
+                if let Some(view) = engine.filters.get_mut(&filter) {
+                    view.set_context(before, after, total);
+                }
+                engine.group_records(filter);
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+// filter row -> logical line in the full buffer, -1 past the end. filter_row goes the other way.
+#[no_mangle]
+pub extern "C" fn log_engine_filter_line(engine: u64, filter: u64, row: u64) -> i64 {
+    unwind::guard("log_engine_filter_line", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_line") else {
+            return -1;
+        };
+        if !filter_exists(&mut engine, filter, "log_engine_filter_line") {
+            return -1;
+        }
+        let Ok(row) = usize::try_from(row) else {
+            return -1;
+        };
+        engine
+            .filter(filter)
+            .and_then(|view| view.line_at(row))
+            .and_then(|line| i64::try_from(line).ok())
+            .unwrap_or(-1)
+    })
+}
+
+// logical line -> filter row. -1 when the line isn't in the filter, unless `nearest`,
+// in which case it's the first row at or after the line (or the last row).
+#[no_mangle]
+pub extern "C" fn log_engine_filter_row(engine: u64, filter: u64, line: u64, nearest: bool) -> i64 {
+    unwind::guard("log_engine_filter_row", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_row") else {
+            return -1;
+        };
+        if !filter_exists(&mut engine, filter, "log_engine_filter_row") {
+            return -1;
+        }
+        let line = usize::try_from(line).unwrap_or(usize::MAX);
+        let Some(view) = engine.filter(filter) else {
+            return -1;
+        };
+        let row = view.rows_before(line);
+        let row = if view.line_at(row) == Some(line) {
+            Some(row)
+        } else if nearest {
+            view.total().checked_sub(1).map(|last| row.min(last))
+        } else {
+            None
+        };
+        row.and_then(|r| i64::try_from(r).ok()).unwrap_or(-1)
+    })
+}
+
+// like search_mode, but rows in and rows out
+#[no_mangle]
+pub extern "C" fn log_engine_filter_search(
+    engine: u64,
+    filter: u64,
+    query: *const c_char,
+    start_row: u64,
+    mode: u32,
+    backward: bool,
+) -> i64 {
+    unwind::guard_or("log_engine_filter_search", -2, || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_search") else {
+            return -1;
+        };
+        if !filter_exists(&mut engine, filter, "log_engine_filter_search") {
+            return -1;
+        }
+        let Some(query_bytes) = query_bytes(query, "log_engine_filter_search") else {
+            return -1;
+        };
+        let Some(mode) = SearchMode::from_raw(mode) else {
+            diag::misuse(|| format!("log_engine_filter_search(mode={}): unknown mode", mode));
+            return -1;
+        };
+        let start_row = usize::try_from(start_row).unwrap_or(usize::MAX);
+        match engine.filter_search(filter, mode, query_bytes, start_row, backward) {
+            Ok(Some(row)) => i64::try_from(row).unwrap_or(-1),
+            Ok(None) => -1,
+            Err(err) => bad_query(err),
         }
+    })
+}
 
-        if writer.flush().is_err() {
-            return false;
+#[no_mangle]
+pub extern "C" fn log_engine_filter_free(engine: u64, filter: u64) {
+    unwind::guard("log_engine_filter_free", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_free") else {
+            return;
+        };
+        if engine.filters.remove(&filter).is_none()
+            && engine.parked.remove(&filter).is_none()
+            && engine.pending.remove(&filter).is_none()
+        {
+            diag::misuse(|| format!("log_engine_filter_free(filter={}): unknown filter", filter));
         }
-        // atomic swap
-        std::fs::rename(&temp_path, path).is_ok()
-    }
+    })
 }
 
-// --- C ABI Boundary ---
-// Trusting the caller from here on out. standard unsafe boilerplate.
+// freeze the content as it is now: lines arriving, edits and evictions after this don't show
+// up in the snapshot's reads, so a long export or copy sees one consistent file. a rotation
+// or truncation drops every snapshot, calls on them then fail like on freed ones. free them
+// when done, they pin memory lines the engine would otherwise let go of.
+#[no_mangle]
+pub extern "C" fn log_engine_snapshot(engine: u64) -> u64 {
+    unwind::guard("log_engine_snapshot", || {
+        let Some(mut engine) = enter(engine, "log_engine_snapshot") else {
+            return 0;
+        };
+        engine.snapshot()
+    })
+}
 
+// the content as it is now as a new engine number, for background work (an export, a long
+// search) on a thread of its own: it has its own lock, so it neither waits for the editing
+// nor sees any of it. everything that reads works on it, edits are refused as misuse and it
+// doesn't follow the file. free it like any engine. like a snapshot it's closed when the
+// file is truncated in place: calls on it then fail, freeing it still works. 0 on failure.
 #[no_mangle]
-pub extern "C" fn log_engine_new(path: *const c_char) -> *mut LogEngine {
-    if path.is_null() {
-        return ptr::null_mut();
+pub extern "C" fn log_engine_freeze(engine: u64) -> u64 {
+    unwind::guard("log_engine_freeze", || {
+        let Some(mut engine) = enter(engine, "log_engine_freeze") else {
+            return 0;
+        };
+        match engine.freeze() {
+            Ok(frozen) => {
+                drop(engine);
+                LogEngine::registered(frozen)
+            }
+            Err(err) => {
+                diag::io_failure(&err);
+                0
+            }
+        }
+    })
+}
+
+// a snapshot, or a diagnostic for handles we never gave out. freed and dropped ones are
+// just gone, the plugin can't tell a rotation dropped them.
+fn snapshot_exists(engine: &Engine, snapshot: u64, call: &'static str) -> bool {
+    if engine.snapshots.contains_key(&snapshot) {
+        return true;
     }
-    let c_str = unsafe { CStr::from_ptr(path) };
-    // paths can be cursed too on some OSes.
-    let path_str = c_str.to_string_lossy();
-    if let Ok(engine) = LogEngine::new(path_str.as_ref()) {
-        return Box::into_raw(Box::new(engine));
+    if snapshot == 0 || snapshot >= engine.next_snapshot {
+        diag::misuse(|| format!("{}(snapshot={}): unknown snapshot", call, snapshot));
     }
-    ptr::null_mut()
+    false
 }
 
 #[no_mangle]
-pub extern "C" fn log_engine_total_lines(engine: *const LogEngine) -> usize {
-    // :LogLines. fast because we already paid the price at startup.
-    let engine = unsafe {
-        if engine.is_null() {
+pub extern "C" fn log_engine_snapshot_total_lines(engine: u64, snapshot: u64) -> u64 {
+    unwind::guard("log_engine_snapshot_total_lines", || {
+        let Some(mut engine) = enter(engine, "log_engine_snapshot_total_lines") else {
+            return 0;
+        };
+        if !snapshot_exists(&engine, snapshot, "log_engine_snapshot_total_lines") {
             return 0;
         }
-        &*engine
-    };
-    engine.total_lines()
+        engine.with_snapshot(snapshot, |engine| engine.total_lines() as u64).unwrap_or(0)
+    })
 }
 
+// log_engine_get_block on the snapshot's lines, same lifetime rules
 #[no_mangle]
-pub extern "C" fn log_engine_get_block(
-    engine: *mut LogEngine,
-    start_line: usize,
-    num_lines: usize,
+pub extern "C" fn log_engine_snapshot_get_block(
+    engine: u64,
+    snapshot: u64,
+    start_line: u64,
+    num_lines: u64,
     out_len: *mut usize,
 ) -> *const u8 {
-    // the thing behind :LogJump and scrolling. fetches chunks without loading the whole file.
-    let engine = unsafe {
-        if engine.is_null() {
+    unwind::guard("log_engine_snapshot_get_block", || {
+        let Some(mut engine) = enter(engine, "log_engine_snapshot_get_block") else {
             return ptr::null();
+        };
+        if !out_len.is_null() {
+            unsafe { *out_len = 0 };
         }
-        &mut *engine
-    };
-    let ptr = engine.get_block(start_line, num_lines);
-    if !out_len.is_null() {
-        unsafe { *out_len = engine.last_block.len() };
-    }
-    ptr
+        if !snapshot_exists(&engine, snapshot, "log_engine_snapshot_get_block") {
+            return ptr::null();
+        }
+        let (Ok(start_line), Ok(num_lines)) = (usize::try_from(start_line), usize::try_from(num_lines)) else {
+            diag::misuse(|| format!("log_engine_snapshot_get_block(start_line={}, num_lines={}): past what this platform can address", start_line, num_lines));
+            return ptr::null();
+        };
+        let ptr = engine.with_snapshot(snapshot, |engine| engine.get_block(start_line, num_lines)).unwrap_or(ptr::null());
+        if !out_len.is_null() {
+            unsafe { *out_len = engine.last_block.len() };
+        }
+        ptr
+    })
 }
 
+// log_engine_save with the snapshot's content, to any path
 #[no_mangle]
-pub extern "C" fn log_engine_apply_edit(
-    engine: *mut LogEngine,
-    start_line: usize,
-    num_deleted: usize,
-    new_text: *const c_char,
-) {
-    let engine = unsafe {
-        if engine.is_null() {
+pub extern "C" fn log_engine_snapshot_save(engine: u64, snapshot: u64, path: *const c_char) -> bool {
+    unwind::guard("log_engine_snapshot_save", || {
+        let Some(mut engine) = enter(engine, "log_engine_snapshot_save") else {
+            return false;
+        };
+        let _op = ops::start();
+        if path.is_null() {
+            diag::misuse(|| "log_engine_snapshot_save: null path".to_string());
+            return false;
+        }
+        if !snapshot_exists(&engine, snapshot, "log_engine_snapshot_save") {
+            return false;
+        }
+        let path_str = unsafe { CStr::from_ptr(path) }.to_string_lossy();
+        let os_path = c_path(path);
+        let saved = engine
+            .with_snapshot(snapshot, |engine| engine.save(&os_path))
+            .is_some_and(|saved| saved.map_err(|err| diag::io_failure(&err)).is_ok());
+        let detail = format!("{} from snapshot {}{}", path_str, snapshot, if saved { "" } else { " (failed)" });
+        engine.audit.record("save", detail);
+        saved
+    })
+}
+
+// a snapshot as a frozen engine (see log_engine_freeze) for what takes more than reading
+// lines: filters, searches, levels. the snapshot stays, both are freed on their own. 0 on
+// failure.
+#[no_mangle]
+pub extern "C" fn log_engine_snapshot_freeze(engine: u64, snapshot: u64) -> u64 {
+    unwind::guard("log_engine_snapshot_freeze", || {
+        let Some(mut engine) = enter(engine, "log_engine_snapshot_freeze") else {
+            return 0;
+        };
+        if !snapshot_exists(&engine, snapshot, "log_engine_snapshot_freeze") {
+            return 0;
+        }
+        let Some(captured) = engine.with_snapshot(snapshot, |engine| engine.capture()) else {
+            return 0;
+        };
+        match engine.freeze_capture(captured) {
+            Ok(frozen) => {
+                drop(engine);
+                LogEngine::registered(frozen)
+            }
+            Err(err) => {
+                diag::io_failure(&err);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn log_engine_snapshot_free(engine: u64, snapshot: u64) {
+    unwind::guard("log_engine_snapshot_free", || {
+        let Some(mut engine) = enter(engine, "log_engine_snapshot_free") else {
             return;
+        };
+        if snapshot_exists(&engine, snapshot, "log_engine_snapshot_free") {
+            engine.snapshots.remove(&snapshot);
         }
-        &mut *engine
-    };
-    // nvim might send weird stuff, salvage what we can.
-    let text = if new_text.is_null() {
-        String::new()
-    } else {
-        unsafe { CStr::from_ptr(new_text) }.to_string_lossy().into_owned()
-    };
-    engine.apply_edit(start_line, num_deleted, &text);
+    })
 }
 
+// a filter's setup (steps, origin, context) as a few lines of text for a session plugin to
+// keep, see FilterSetup. works on parked and combined filters too. null for unknown
+// handles. same lifetime rules as get_block.
 #[no_mangle]
-pub extern "C" fn log_engine_save(engine: *const LogEngine, path: *const c_char) -> bool {
-    let engine = unsafe {
-        if engine.is_null() {
-            return false;
+pub extern "C" fn log_engine_filter_export(engine: u64, filter: u64, out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_filter_export", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_export") else {
+            return ptr::null();
+        };
+        if !engine.parked.contains_key(&filter) && !filter_exists(&mut engine, filter, "log_engine_filter_export") {
+            return ptr::null();
         }
-        &*engine
-    };
-    if path.is_null() {
-        return false;
-    }
-    // paths can be cursed too.
-    let path_str = unsafe { CStr::from_ptr(path) }.to_string_lossy();
-    return engine.save(path_str.as_ref());
+        let Some(text) = engine.filter_export(filter) else {
+            return ptr::null();
+        };
+        engine.last_block = text;
+        if !out_len.is_null() {
+            unsafe { *out_len = engine.last_block.len() };
+        }
+        engine.last_block.as_ptr()
+    })
 }
 
+// a new filter from log_engine_filter_export's text, rebuilt against the file as it is now.
+// returns the handle, -1 on failure, -2 if the text isn't a setup or a query in it doesn't compile.
 #[no_mangle]
-pub extern "C" fn log_engine_search(
-    engine: *const LogEngine,
-    query: *const c_char,
-    start_line: usize,
-) -> isize {
-    let engine = unsafe {
-        if engine.is_null() {
+pub extern "C" fn log_engine_filter_import(engine: u64, setup: *const c_char) -> i64 {
+    unwind::guard("log_engine_filter_import", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_import") else {
+            return -1;
+        };
+        let _op = ops::start();
+        if setup.is_null() {
+            diag::misuse(|| "log_engine_filter_import: null setup".to_string());
             return -1;
         }
-        &*engine
-    };
-    if query.is_null() {
-        return -1;
-    }
-    let query_bytes = match unsafe { CStr::from_ptr(query) }.to_bytes_with_nul().split_last() {
-        Some((&0, bytes)) => bytes,
-        _ => return -1,
-    };
-    if query_bytes.is_empty() {
-        return -1;
-    }
+        let text = unsafe { CStr::from_ptr(setup) }.to_string_lossy();
+        match engine.filter_import(&text) {
+            Ok(Some(id)) => i64::try_from(id).unwrap_or(-1),
+            Ok(None) => -1,
+            Err(err) => bad_query(err),
+        }
+    })
+}
 
-    let (mut piece_idx, mut offset) = engine.find_piece_idx(start_line);
-    let mut current_logical = start_line;
+// which lines a filter takes its hits from: 0 all of them, 1 only lines from the file on
+// disk, 2 only lines edited this session, 3 only the lines kept from the rotation target
+// (see log_engine_set_keep_rotated). refinements start out with their parent's.
+#[no_mangle]
+pub extern "C" fn log_engine_filter_origin(engine: u64, filter: u64, origin: u32) -> bool {
+    unwind::guard("log_engine_filter_origin", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_origin") else {
+            return false;
+        };
+        if !filter_exists(&mut engine, filter, "log_engine_filter_origin") {
+            return false;
+        }
+        let Some(origin) = Origin::from_raw(origin) else {
+            diag::misuse(|| format!("log_engine_filter_origin(origin={}): unknown origin", origin));
+            return false;
+        };
+        engine.set_filter_origin(filter, origin)
+    })
+}
 
-    while piece_idx < engine.pieces.len() {
-        let piece = &engine.pieces[piece_idx];
-        match piece {
-            Piece::Original { start_line: p_start, line_count } => {
-                let bytes = engine.get_original_bytes(p_start + offset, line_count - offset);
-                if let Some(pos) = memmem::find(bytes, query_bytes) {
-                    
-                    // found the byte offset, now manually count newlines up to this point
-                    // to resolve the actual logical line number. slow but accurate.
-                    let slice_to_match = &bytes[..pos];
-                    let mut lines = 0;
-                    let mut iter = memchr2_iter(b'\n', b'\r', slice_to_match).peekable();
-                    while let Some(p) = iter.next() {
-                        lines += 1;
-                        if slice_to_match[p] == b'\r' {
-                            if let Some(&np) = iter.peek() {
-                                if np == p + 1 && slice_to_match[np] == b'\n' {
-                                    iter.next();
-                                }
-                            }
-                        }
-                    }
-                    return (current_logical + lines) as isize;
-                }
-            }
-            Piece::Memory { start_idx, line_count } => {
-                // query might be cursed too.
-                let q_str = String::from_utf8_lossy(query_bytes);
-                for i in offset..*line_count {
-                    if engine.memory_buffer[start_idx + i].contains(q_str.as_ref()) {
-                        return (current_logical + i - offset) as isize;
-                    }
-                }
-            }
+// whole records for a filter: every hit brings the rest of its record along (the line it
+// continues, the stack trace under it), a record with several hits shows once. context from
+// log_engine_filter_context goes around the records. rows, totals and all follow.
+#[no_mangle]
+pub extern "C" fn log_engine_filter_records(engine: u64, filter: u64, records: bool) -> bool {
+    unwind::guard("log_engine_filter_records", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_records") else {
+            return false;
+        };
+        if !filter_exists(&mut engine, filter, "log_engine_filter_records") {
+            return false;
         }
-        current_logical += piece.line_count() - offset;
-        offset = 0;
-        piece_idx += 1;
-    }
-    -1
+        engine.filter(filter).is_some() && engine.set_filter_records(filter, records)
+    })
 }
 
+// big files: filters over more than `bytes` come back from create/refine/exclude right away,
+// still empty, and get scanned `bytes` at a time by log_engine_filter_step so the plugin can
+// show progress in between. 0 (the default) scans the whole file inside the create call.
 #[no_mangle]
-pub extern "C" fn log_engine_search_backward(
-    engine: *const LogEngine,
-    query: *const c_char,
-    start_line: usize,
-) -> isize {
-    let engine = unsafe {
-        if engine.is_null() {
+pub extern "C" fn log_engine_set_filter_slice(engine: u64, bytes: u64) {
+    unwind::guard("log_engine_set_filter_slice", || {
+        let Some(mut engine) = enter(engine, "log_engine_set_filter_slice") else {
+            return;
+        };
+        engine.filter_slice = usize::try_from(bytes).unwrap_or(usize::MAX);
+    })
+}
+
+// scan the next slice of a filter still being built. out_scanned/out_total are bytes of the
+// file, out_matches the lines it hits so far. returns 1 once the filter is ready (from then on
+// it reads like any other, and stepping it again just reports it), 0 while there's more to
+// scan, -1 for an unknown handle or a step log_engine_cancel stopped (what it got through
+// before that is kept). until it's ready the filter has no rows.
+#[no_mangle]
+pub extern "C" fn log_engine_filter_step(
+    engine: u64,
+    filter: u64,
+    out_scanned: *mut u64,
+    out_total: *mut u64,
+    out_matches: *mut u64,
+) -> i32 {
+    unwind::guard("log_engine_filter_step", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_step") else {
+            return -1;
+        };
+        if !filter_exists(&mut engine, filter, "log_engine_filter_step") {
             return -1;
         }
-        &*engine
-    };
-    if query.is_null() {
-        return -1;
-    }
-    let query_bytes = match unsafe { CStr::from_ptr(query) }.to_bytes_with_nul().split_last() {
-        Some((&0, bytes)) => bytes,
-        _ => return -1,
-    };
-    if query_bytes.is_empty() {
-        return -1;
-    }
+        let _op = ops::start();
+        let Some((scanned, total, matches, done)) = engine.filter_step(filter) else {
+            return -1;
+        };
+        // the chunks it got through count, the next step picks up after them
+        if ops::cancelled() {
+            diag::io_failure(&ops::cancelled_error());
+            return -1;
+        }
+        if !out_scanned.is_null() {
+            unsafe { *out_scanned = scanned as u64 };
+        }
+        if !out_total.is_null() {
+            unsafe { *out_total = total as u64 };
+        }
+        if !out_matches.is_null() {
+            unsafe { *out_matches = matches as u64 };
+        }
+        done as i32
+    })
+}
 
-    let (mut piece_idx, mut offset) = engine.find_piece_idx(start_line);
-    if piece_idx >= engine.pieces.len() {
-        piece_idx = engine.pieces.len().saturating_sub(1);
-        offset = engine.pieces[piece_idx].line_count().saturating_sub(1);
-    }
+// close a filter but keep its results, packed. the handle can't be read from until
+// log_engine_filter_unpark, which brings it back without rescanning. free works on both.
+#[no_mangle]
+pub extern "C" fn log_engine_filter_park(engine: u64, filter: u64) -> bool {
+    unwind::guard("log_engine_filter_park", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_park") else {
+            return false;
+        };
+        if !filter_exists(&mut engine, filter, "log_engine_filter_park") {
+            return false;
+        }
+        engine.filter_park(filter)
+    })
+}
 
-    let mut current_logical = start_line;
+#[no_mangle]
+pub extern "C" fn log_engine_filter_unpark(engine: u64, filter: u64) -> bool {
+    unwind::guard("log_engine_filter_unpark", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_unpark") else {
+            return false;
+        };
+        if !engine.filter_unpark(filter) {
+            diag::misuse(|| format!("log_engine_filter_unpark(filter={}): not a parked filter", filter));
+            return false;
+        }
+        true
+    })
+}
 
-    // walking backwards through pieces. same logic as forward search but reversed.
-    loop {
-        let piece = &engine.pieces[piece_idx];
-        match piece {
-            Piece::Original { start_line: p_start, .. } => {
-                let bytes = engine.get_original_bytes(*p_start, offset + 1);
-                if let Some(pos) = memmem::rfind(bytes, query_bytes) {
-                    let slice_to_match = &bytes[..pos];
-                    let mut lines = 0;
-                    let mut iter = memchr2_iter(b'\n', b'\r', slice_to_match).peekable();
-                    while let Some(p) = iter.next() {
-                        lines += 1;
-                        if slice_to_match[p] == b'\r' {
-                            if let Some(&np) = iter.peek() {
-                                if np == p + 1 && slice_to_match[np] == b'\n' {
-                                    iter.next();
-                                }
-                            }
-                        }
-                    }
-                    return (current_logical - offset + lines) as isize;
-                }
-            }
-            Piece::Memory { start_idx, .. } => {
-                // query might be cursed too.
-                let q_str = String::from_utf8_lossy(query_bytes);
-                for i in (0..=offset).rev() {
-                    if engine.memory_buffer[start_idx + i].contains(q_str.as_ref()) {
-                        return (current_logical - offset + i) as isize;
-                    }
-                }
-            }
+// a new filter from two open ones without touching the file: 0 both, 1 either,
+// 2 in the first but not the second. returns the handle or -1.
+#[no_mangle]
+pub extern "C" fn log_engine_filter_combine(engine: u64, a: u64, b: u64, op: u32) -> i64 {
+    unwind::guard("log_engine_filter_combine", || {
+        let Some(mut engine) = enter(engine, "log_engine_filter_combine") else {
+            return -1;
+        };
+        if !filter_exists(&mut engine, a, "log_engine_filter_combine") || !filter_exists(&mut engine, b, "log_engine_filter_combine") {
+            return -1;
         }
+        let Some(op) = Combine::from_raw(op) else {
+            diag::misuse(|| format!("log_engine_filter_combine(op={}): unknown op", op));
+            return -1;
+        };
+        engine.filter_combine(a, b, op).map_or(-1, |id| i64::try_from(id).unwrap_or(-1))
+    })
+}
 
-        if piece_idx == 0 {
-            break;
+// how many times the plugin has called us wrong since the library was loaded
+#[no_mangle]
+pub extern "C" fn log_engine_misuse_count() -> u64 {
+    unwind::guard("log_engine_misuse_count", || {
+        diag::misuse_count()
+    })
+}
+
+// the C ABI revision this library speaks, see ABI_VERSION. check it before anything else.
+#[no_mangle]
+pub extern "C" fn log_engine_abi_version() -> u32 {
+    unwind::guard("log_engine_abi_version", || {
+        ABI_VERSION
+    })
+}
+
+// a number for the next slow call this thread makes (open, search, filter or a step of
+// one, save, refresh, reopen, level counts), for another thread to hand to
+// log_engine_cancel while it runs, or for log_engine_op_timeout. see ops.rs
+#[no_mangle]
+pub extern "C" fn log_engine_op_reserve() -> u64 {
+    unwind::guard("log_engine_op_reserve", || {
+        ops::reserve()
+    })
+}
+
+// cancels the operation once `ms` have passed, unless it's finished by then: for a caller
+// with no other thread to call log_engine_cancel from. false if it already finished (or
+// the number was never handed out)
+#[no_mangle]
+pub extern "C" fn log_engine_op_timeout(op: u64, ms: u64) -> bool {
+    unwind::guard("log_engine_op_timeout", || {
+        ops::cancel_after(op, Duration::from_millis(ms))
+    })
+}
+
+// stops the operation: it gives up at the next chunk and fails with ERROR_CANCELLED.
+// false if it already finished (or the number was never handed out)
+#[no_mangle]
+pub extern "C" fn log_engine_cancel(op: u64) -> bool {
+    unwind::guard("log_engine_cancel", || {
+        ops::cancel(op)
+    })
+}
+
+// a format for in-house logs, for this engine: `template` is the line with %{name} where a
+// field goes, e.g. "%{ts} [%{level}] %{logger} - %{msg}", and %% for a plain %. lines it
+// matches get those fields in queries, field lists, columns and level colors, ahead of
+// json and logfmt, and each starts a record. registering a name again replaces it. the
+// engine's levels, times and cached searches are worked out again, filters made before
+// keep what they matched. false with the reason in last_error when it doesn't compile.
+#[no_mangle]
+pub extern "C" fn log_engine_register_format(engine: u64, name: *const c_char, template: *const c_char) -> bool {
+    unwind::guard("log_engine_register_format", || {
+        let Some(mut engine) = enter(engine, "log_engine_register_format") else {
+            return false;
+        };
+        let (Some(name), Some(template)) = (cstr_arg(name), cstr_arg(template)) else {
+            diag::misuse(|| "log_engine_register_format: null name or template".to_string());
+            return false;
+        };
+        if let Err(err) = engine.register_format(&String::from_utf8_lossy(name), &String::from_utf8_lossy(template)) {
+            diag::fail(diag::ERROR_BAD_QUERY, err);
+            return false;
         }
-        current_logical = current_logical.saturating_sub(offset + 1);
-        piece_idx -= 1;
-        offset = engine.pieces[piece_idx].line_count().saturating_sub(1);
-    }
-    -1
+        true
+    })
+}
+
+// forget a format registered with log_engine_register_format, false if there was none
+#[no_mangle]
+pub extern "C" fn log_engine_unregister_format(engine: u64, name: *const c_char) -> bool {
+    unwind::guard("log_engine_unregister_format", || {
+        let Some(mut engine) = enter(engine, "log_engine_unregister_format") else {
+            return false;
+        };
+        let Some(name) = cstr_arg(name) else {
+            diag::misuse(|| "log_engine_unregister_format: null name".to_string());
+            return false;
+        };
+        engine.unregister_format(&String::from_utf8_lossy(name))
+    })
+}
+
+// one callback for every engine, called with the engine's number for the events in
+// event_mask (EVENT_* in events.rs) once the call that caused them returns. a null callback
+// or an empty mask turns it off. userdata comes back untouched.
+#[no_mangle]
+pub extern "C" fn log_engine_set_callback(event_mask: u32, callback: Option<events::Callback>, userdata: *mut std::os::raw::c_void) {
+    unwind::guard("log_engine_set_callback", || {
+        events::set(event_mask, callback, userdata);
+    })
 }
 
+// the events in event_mask go in a mailbox instead of to the callback, and `wake(handle)` is
+// called once per call that posted some, from whatever thread made it. meant for
+// uv_async_send and its handle: the loop wakes up on its own thread and takes them out with
+// log_engine_next_event. with a null wake nothing is called, the host polls the mailbox.
+// an empty mask turns it off. works alongside log_engine_set_callback, each gets the
+// events in its own mask.
 #[no_mangle]
-pub extern "C" fn log_engine_free(engine: *mut LogEngine) {
-    if !engine.is_null() {
+pub extern "C" fn log_engine_set_wakeup(event_mask: u32, wake: Option<events::Wake>, handle: *mut std::os::raw::c_void) {
+    unwind::guard("log_engine_set_wakeup", || {
+        events::set_wakeup(event_mask, wake, handle);
+    })
+}
+
+// the oldest event in the wakeup mailbox: the number of the engine it happened on, the event
+// and its two arguments (as the callback gets them). false when it's empty.
+#[no_mangle]
+pub extern "C" fn log_engine_next_event(out_engine: *mut u64, out_event: *mut u32, out_a: *mut u64, out_b: *mut u64) -> bool {
+    unwind::guard("log_engine_next_event", || {
+        if out_engine.is_null() || out_event.is_null() || out_a.is_null() || out_b.is_null() {
+            diag::misuse(|| "log_engine_next_event: null out pointer".to_string());
+            return false;
+        }
+        let Some((engine, event, a, b)) = events::next() else {
+            return false;
+        };
         unsafe {
-            // reclaim ownership and let Rust's drop cleanup the memory
-            let _ = Box::from_raw(engine);
+            *out_engine = engine;
+            *out_event = event;
+            *out_a = a;
+            *out_b = b;
         }
-    }
+        true
+    })
+}
+
+// whether two numbers stand for the same engine (one retained from the other, see
+// log_engine_retain), for telling which holders an event is about
+#[no_mangle]
+pub extern "C" fn log_engine_same(a: u64, b: u64) -> bool {
+    unwind::guard("log_engine_same", || {
+        matches!((registry::resolve(a), registry::resolve(b)), (Some((_, x)), Some((_, y))) if x == y)
+    })
+}
+
+// why the last call on this thread that failed did: 1 not found, 2 permission denied, 3 not
+// a regular file, 4 timed out, 5 mmap failed, 6 other io error, 7 query doesn't compile,
+// 8 the file got shorter (reopen it), 9 misuse (see log_engine_misuse_last), 10 cancelled,
+// 11 a panic inside the library (a bug, please report the message), 12 an edit or save on
+// a read-only engine, 13 a file another session holds locked. 0 if nothing failed yet.
+// successful calls don't reset it, read it right after a failure.
+#[no_mangle]
+pub extern "C" fn log_engine_last_error_code() -> i32 {
+    unwind::guard("log_engine_last_error_code", || {
+        diag::last_error_code()
+    })
+}
+
+// the same failure as text ("No such file or directory (os error 2)", the regex error...).
+// pointer lives until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn log_engine_last_error_message(out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_last_error_message", || {
+        diag::with_last_error(|msg| {
+            if !out_len.is_null() {
+                unsafe { *out_len = msg.len() };
+            }
+            msg.as_ptr()
+        })
+    })
+}
+
+// "fn_name(args): what was wrong" for the latest misuse. empty if there never was one.
+#[no_mangle]
+pub extern "C" fn log_engine_misuse_last(out_len: *mut usize) -> *const u8 {
+    unwind::guard("log_engine_misuse_last", || {
+        diag::with_last_misuse(|msg| {
+            if !out_len.is_null() {
+                unsafe { *out_len = msg.len() };
+            }
+            msg.as_ptr()
+        })
+    })
+}
+
+// another holder for the same engine (a second buffer on the same log, say): same mapping,
+// index, edits and filters, nothing opened again. returns a number of the holder's own, 0
+// if `engine` is gone. every number is let go of with log_engine_release (or free) once,
+// the engine goes with the last.
+#[no_mangle]
+pub extern "C" fn log_engine_retain(engine: u64) -> u64 {
+    unwind::guard("log_engine_retain", || {
+        handle(engine, "log_engine_retain").and_then(|_| registry::retain(engine)).unwrap_or(0)
+    })
+}
+
+// one holder lets go of its number, the engine is freed with the last. true if this was it
+#[no_mangle]
+pub extern "C" fn log_engine_release(engine: u64) -> bool {
+    unwind::guard("log_engine_release", || {
+        release(engine, "log_engine_release")
+    })
+}
+
+// log_engine_release under its older name
+#[no_mangle]
+pub extern "C" fn log_engine_free(engine: u64) {
+    unwind::guard("log_engine_free", || {
+        if engine != 0 {
+            release(engine, "log_engine_free");
+        }
+    })
+}
+
+fn release(engine: u64, call: &'static str) -> bool {
+    // a call still running on another thread holds on to the engine, it goes once that's done
+    registry::release(engine).unwrap_or_else(|| {
+        diag::misuse(|| format!("{}: no engine {} (freed twice?)", call, engine));
+        false
+    })
 }
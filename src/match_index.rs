@@ -0,0 +1,816 @@
+use crate::matcher::Matcher;
+use crate::ops;
+use crate::pool;
+use crate::{count_line_breaks, is_line_start, skip_line_break, ChunkMeta};
+use memchr::{memchr2, memmem};
+use rayon::prelude::*;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// one bit per original line telling whether the query hits it.
+// 1 bit/line keeps even a single-letter query on a 300M line file around ~40MB,
+// which is a lot better than a Vec<usize> of every hit.
+//
+// a ring buffer (see Engine::enforce_retention) evicts lines from the front for good, so
+// the words before `skip` aren't kept: they read as no hits.
+pub(crate) struct MatchIndex {
+    pub(crate) matcher: Matcher, // still needed for lines living in memory pieces
+    bits: Vec<u64>,    // from word `skip` on
+    skip: usize,       // words dropped from the front, a multiple of RANK_WORDS
+    ranks: Vec<usize>, // hits before every RANK_WORDS block of bits (plus the total), for rank/select
+}
+
+// one rank entry per 512 lines is an extra 1/8 on top of the bitset
+const RANK_WORDS: usize = 8;
+
+// how two filters are merged into a third
+#[derive(Clone, Copy)]
+pub(crate) enum Combine {
+    And = 0,
+    Or = 1,
+    AndNot = 2, // in the first, not in the second
+}
+
+impl Combine {
+    pub(crate) fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0 => Some(Combine::And),
+            1 => Some(Combine::Or),
+            2 => Some(Combine::AndNot),
+            _ => None,
+        }
+    }
+
+    // for the audit trail
+    pub(crate) fn describe(self) -> &'static str {
+        match self {
+            Combine::And => "and",
+            Combine::Or => "or",
+            Combine::AndNot => "and not",
+        }
+    }
+}
+
+// the bits of an index that isn't in use, squeezed for keeping around. runs of all-zero
+// or all-one words collapse into one header word, anything else is copied as is: a header
+// holds the fill bit (63), the run length (32..63) and how many literal words follow (0..32).
+// a sparse "ERROR" filter over a chatty log shrinks to a few percent of the bitset.
+pub(crate) struct PackedBits {
+    words: Vec<u64>,
+    len: usize,  // words once unpacked
+    skip: usize, // the index's, see MatchIndex
+}
+
+const MAX_RUN: usize = (1 << 31) - 1;
+const MAX_LITERALS: usize = u32::MAX as usize;
+
+impl PackedBits {
+    fn pack(bits: &[u64], skip: usize) -> Self {
+        let mut words = Vec::new();
+        let mut i = 0;
+        while i < bits.len() {
+            let fill = if bits[i] == !0 { !0 } else { 0 };
+            let mut run = 0;
+            while i < bits.len() && bits[i] == fill && run < MAX_RUN {
+                run += 1;
+                i += 1;
+            }
+            let header = words.len();
+            words.push(0);
+            let mut literals = 0;
+            while i < bits.len() && bits[i] != 0 && bits[i] != !0 && literals < MAX_LITERALS {
+                words.push(bits[i]);
+                literals += 1;
+                i += 1;
+            }
+            words[header] = (fill & 1) << 63 | (run as u64) << 32 | literals as u64;
+        }
+        words.shrink_to_fit();
+        PackedBits { words, len: bits.len(), skip }
+    }
+
+    fn unpack(&self) -> Vec<u64> {
+        let mut bits = Vec::with_capacity(self.len);
+        let mut i = 0;
+        while i < self.words.len() {
+            let header = self.words[i];
+            let fill = if header >> 63 == 1 { !0 } else { 0 };
+            let run = ((header >> 32) & MAX_RUN as u64) as usize;
+            let literals = (header & u32::MAX as u64) as usize;
+            bits.resize(bits.len() + run, fill);
+            bits.extend_from_slice(&self.words[i + 1..i + 1 + literals]);
+            i += 1 + literals;
+        }
+        bits
+    }
+}
+
+impl MatchIndex {
+    // same trick as the line counter in Engine::new: every chunk is scanned in parallel.
+    pub(crate) fn build(
+        data: &[u8],
+        chunks: &[ChunkMeta],
+        total_lines: usize,
+        matcher: Matcher,
+    ) -> Self {
+        let bits = scan(data, chunks, 0..chunks.len(), total_lines, &matcher, |_| true);
+        Self::from_bits(matcher, bits)
+    }
+
+    // a drill-down on top of `parent`: only lines the parent already hits get looked at,
+    // and only with the newest step. with `exclude` the step's hits are dropped instead of kept.
+    // `matcher` is the whole chain, kept for memory pieces.
+    pub(crate) fn refine(
+        data: &[u8],
+        chunks: &[ChunkMeta],
+        total_lines: usize,
+        parent: &MatchIndex,
+        step: &Matcher,
+        exclude: bool,
+        matcher: Matcher,
+    ) -> Self {
+        let words: Vec<AtomicU64> = (0..total_lines.div_ceil(64)).map(|_| AtomicU64::new(0)).collect();
+        scan_hits(&words, data, chunks, 0..chunks.len(), total_lines, step, parent);
+        let mut bits: Vec<u64> = words.into_iter().map(AtomicU64::into_inner).collect();
+        if exclude {
+            for (i, word) in bits.iter_mut().enumerate() {
+                *word = parent.word(i) & !*word;
+            }
+        }
+        // nothing the parent dropped can be a hit
+        bits.drain(..parent.skip.min(bits.len()));
+        Self::from_words(matcher, bits, parent.skip)
+    }
+
+    // every line this one doesn't hit
+    pub(crate) fn complement(&self, total_lines: usize, matcher: Matcher) -> Self {
+        let mut bits: Vec<u64> = self.bits.iter().map(|w| !w).collect();
+        if let Some(last) = bits.last_mut() {
+            if !total_lines.is_multiple_of(64) {
+                *last &= !(!0u64 << (total_lines % 64));
+            }
+        }
+        // evicted lines stay out of it too
+        Self::from_words(matcher, bits, self.skip)
+    }
+
+    // the same index after the file grew to `total_lines`: hits before `from` are kept,
+    // everything from there on is scanned again with `matcher`.
+    pub(crate) fn extend(
+        &self,
+        data: &[u8],
+        chunks: &[ChunkMeta],
+        total_lines: usize,
+        from: usize,
+        matcher: Matcher,
+    ) -> Self {
+        // chunks before this one only own lines before `from`
+        let first_chunk = chunks.partition_point(|c| c.start_line < from).saturating_sub(1);
+        let fresh = scan(data, chunks, first_chunk..chunks.len(), total_lines, &matcher, |line| line >= from);
+
+        let mut bits = self.bits.clone();
+        bits.resize(fresh.len().saturating_sub(self.skip), 0);
+        for (i, word) in bits.iter_mut().enumerate() {
+            let at = self.skip + i;
+            if at == from / 64 {
+                *word &= !(!0u64 << (from % 64));
+            } else if at > from / 64 {
+                *word = 0;
+            }
+            *word |= fresh[at];
+        }
+        Self::from_words(matcher, bits, self.skip)
+    }
+
+    // bitwise merge of two indexes over the same file, `matcher` being the merged test
+    pub(crate) fn combine(&self, other: &MatchIndex, op: Combine, matcher: Matcher) -> Self {
+        // lines either one dropped are gone from the file view, whatever the op
+        let skip = self.skip.max(other.skip);
+        let len = self.words_len().min(other.words_len());
+        let bits = (skip..len.max(skip))
+            .map(|i| (self.word(i), other.word(i)))
+            .map(|(a, b)| match op {
+                Combine::And => a & b,
+                Combine::Or => a | b,
+                Combine::AndNot => a & !b,
+            })
+            .collect();
+        Self::from_words(matcher, bits, skip)
+    }
+
+    pub(crate) fn pack(&self) -> PackedBits {
+        PackedBits::pack(&self.bits, self.skip)
+    }
+
+    pub(crate) fn unpack(matcher: Matcher, packed: &PackedBits) -> Self {
+        Self::from_words(matcher, packed.unpack(), packed.skip)
+    }
+
+    pub(crate) fn from_bits(matcher: Matcher, bits: Vec<u64>) -> Self {
+        Self::from_words(matcher, bits, 0)
+    }
+
+    // `bits` from word `skip` on
+    fn from_words(matcher: Matcher, bits: Vec<u64>, skip: usize) -> Self {
+        let mut ranks = Vec::with_capacity(bits.len().div_ceil(RANK_WORDS) + 1);
+        let mut seen = 0;
+        for block in bits.chunks(RANK_WORDS) {
+            ranks.push(seen);
+            seen += block.iter().map(|w| w.count_ones() as usize).sum::<usize>();
+        }
+        ranks.push(seen);
+
+        MatchIndex { matcher, bits, skip, ranks }
+    }
+
+    // the same index without the words every line before `first_line` is in (evicted from
+    // a ring buffer, see Engine::enforce_retention). None when that frees nothing.
+    pub(crate) fn trimmed(&self, first_line: usize) -> Option<Self> {
+        let skip = first_line / 64 / RANK_WORDS * RANK_WORDS;
+        if skip <= self.skip {
+            return None;
+        }
+        let bits = self.bits.get(skip - self.skip..).unwrap_or_default().to_vec();
+        Some(Self::from_words(self.matcher.clone(), bits, skip))
+    }
+
+    // what it keeps on the heap, for the cache's memory limit
+    pub(crate) fn heap_bytes(&self) -> usize {
+        (self.bits.len() + self.ranks.len()) * 8
+    }
+
+    // words the bitset covers, the dropped ones included
+    fn words_len(&self) -> usize {
+        self.skip + self.bits.len()
+    }
+
+    fn word(&self, i: usize) -> u64 {
+        i.checked_sub(self.skip).and_then(|i| self.bits.get(i)).copied().unwrap_or(0)
+    }
+
+    // number of hits in [0, line)
+    fn rank(&self, line: usize) -> usize {
+        let line = line.min(self.words_len() * 64);
+        let Some(word_idx) = (line / 64).checked_sub(self.skip) else {
+            return 0;
+        };
+        let block = word_idx / RANK_WORDS;
+        let mut count = self.ranks[block];
+        for word in &self.bits[block * RANK_WORDS..word_idx] {
+            count += word.count_ones() as usize;
+        }
+        if let Some(word) = self.bits.get(word_idx) {
+            count += (word & !(!0u64 << (line % 64))).count_ones() as usize;
+        }
+        count
+    }
+
+    // number of hits in [from, to)
+    pub(crate) fn count_range(&self, from: usize, to: usize) -> usize {
+        if from >= to {
+            return 0;
+        }
+        self.rank(to) - self.rank(from)
+    }
+
+    // the n-th hit (0-based) at or after `from`
+    pub(crate) fn nth_hit(&self, from: usize, n: usize) -> Option<usize> {
+        let mut wanted = self.rank(from) + n;
+        if wanted >= *self.ranks.last()? {
+            return None;
+        }
+        let block = self.ranks.partition_point(|&r| r <= wanted).checked_sub(1)?;
+        wanted -= self.ranks[block];
+        for (i, &word) in self.bits[block * RANK_WORDS..].iter().enumerate() {
+            let ones = word.count_ones() as usize;
+            if wanted < ones {
+                // drop the lowest `wanted` set bits, the next one is ours
+                let mut word = word;
+                for _ in 0..wanted {
+                    word &= word - 1;
+                }
+                return Some((self.skip + block * RANK_WORDS + i) * 64 + word.trailing_zeros() as usize);
+            }
+            wanted -= ones;
+        }
+        None
+    }
+
+    // first hit in [from, to)
+    pub(crate) fn next_hit(&self, from: usize, to: usize) -> Option<usize> {
+        if from >= to {
+            return None;
+        }
+        let mut word_idx = from / 64;
+        if word_idx >= self.words_len() {
+            return None;
+        }
+        let mut word = self.word(word_idx) & (!0u64 << (from % 64));
+        loop {
+            if word != 0 {
+                let line = word_idx * 64 + word.trailing_zeros() as usize;
+                return (line < to).then_some(line);
+            }
+            word_idx += 1;
+            if word_idx * 64 >= to || word_idx >= self.words_len() {
+                return None;
+            }
+            word = self.word(word_idx);
+        }
+    }
+
+    // last hit in [from, to]
+    pub(crate) fn prev_hit(&self, from: usize, to: usize) -> Option<usize> {
+        if from > to || self.bits.is_empty() {
+            return None;
+        }
+        let to = to.min(self.words_len() * 64 - 1);
+        let mut word_idx = to / 64;
+        let mut word = self.word(word_idx) & (!0u64 >> (63 - to % 64));
+        loop {
+            if word != 0 {
+                let line = word_idx * 64 + 63 - word.leading_zeros() as usize;
+                return (line >= from).then_some(line);
+            }
+            // nothing before the dropped words either
+            if word_idx <= self.skip || word_idx * 64 <= from {
+                return None;
+            }
+            word_idx -= 1;
+            word = self.word(word_idx);
+        }
+    }
+}
+
+// what next_match/prev_match need from an index: hits among the original lines, and the
+// test for lines that live in memory
+pub(crate) trait Hits {
+    fn next_hit(&self, from: usize, to: usize) -> Option<usize>;
+    fn prev_hit(&self, from: usize, to: usize) -> Option<usize>;
+    fn matcher(&self) -> &Matcher;
+}
+
+impl Hits for MatchIndex {
+    fn next_hit(&self, from: usize, to: usize) -> Option<usize> {
+        MatchIndex::next_hit(self, from, to)
+    }
+
+    fn prev_hit(&self, from: usize, to: usize) -> Option<usize> {
+        MatchIndex::prev_hit(self, from, to)
+    }
+
+    fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+}
+
+// a build done a few chunks at a time, so a filter over a huge file can tell how far it
+// got (and how many hits that turned up) between calls instead of blocking until the end.
+// same result as build/refine (or build + complement for an exclude) once it's finished.
+//
+// the sweep goes through the chunks in order, but a call in the foreground that needs an
+// answer now doesn't wait for it: through `lazy` it scans the chunks it's looking at first
+// (and a few after, in parallel), and the sweep skips whatever got scanned that way.
+pub(crate) struct PartialIndex {
+    step: Matcher,
+    parent: Option<Arc<MatchIndex>>, // refinements only look at the parent's hits
+    exclude: bool,
+    words: Vec<AtomicU64>,
+    scanned: Vec<AtomicBool>,  // per chunk up to end_chunk
+    scanned_count: AtomicUsize,
+    scanned_bytes: AtomicUsize,
+    next_chunk: usize,         // where the sweep goes on, everything before it is scanned
+    end_chunk: usize,          // chunks past this were appended after the scan started
+    pub(crate) total_lines: usize, // original lines when it started, the rest is extend's job
+}
+
+impl PartialIndex {
+    pub(crate) fn new(
+        chunks: &[ChunkMeta],
+        total_lines: usize,
+        step: Matcher,
+        parent: Option<Arc<MatchIndex>>,
+        exclude: bool,
+    ) -> Self {
+        PartialIndex {
+            step,
+            parent,
+            exclude,
+            words: (0..total_lines.div_ceil(64)).map(|_| AtomicU64::new(0)).collect(),
+            scanned: (0..chunks.len()).map(|_| AtomicBool::new(false)).collect(),
+            scanned_count: AtomicUsize::new(0),
+            scanned_bytes: AtomicUsize::new(0),
+            next_chunk: 0,
+            end_chunk: chunks.len(),
+            total_lines,
+        }
+    }
+
+    // sweeps the next `chunk_count` chunks not scanned yet (at least one). true once there's
+    // nothing left.
+    pub(crate) fn advance(&mut self, data: &[u8], chunks: &[ChunkMeta], chunk_count: usize) -> bool {
+        let mut end = self.next_chunk;
+        let mut left = chunk_count.max(1);
+        while end < self.end_chunk && left > 0 {
+            if !self.scanned[end].load(Ordering::Acquire) {
+                left -= 1;
+            }
+            end += 1;
+        }
+        self.scan(data, chunks, self.next_chunk..end);
+        // a sweep cut short by log_engine_cancel goes on from where it was next time
+        if !ops::cancelled() {
+            self.next_chunk = end;
+        }
+        self.done()
+    }
+
+    // scans whatever in `range` isn't yet, a run of chunks at a time
+    fn scan(&self, data: &[u8], chunks: &[ChunkMeta], range: Range<usize>) {
+        let parent = self.parent.as_deref();
+        let mut at = range.start;
+        while at < range.end {
+            if self.scanned[at].load(Ordering::Acquire) {
+                at += 1;
+                continue;
+            }
+            let run_end = (at..range.end).find(|&i| self.scanned[i].load(Ordering::Acquire)).unwrap_or(range.end);
+            match parent {
+                Some(parent) => scan_hits(&self.words, data, chunks, at..run_end, self.total_lines, &self.step, parent),
+                None => scan_into(&self.words, data, chunks, at..run_end, self.total_lines, &self.step, |_| true),
+            }
+            // a chunk skipped by a cancelled scan may be missing hits, it gets scanned again
+            if ops::cancelled() {
+                return;
+            }
+            for i in at..run_end {
+                if !self.scanned[i].swap(true, Ordering::AcqRel) {
+                    self.scanned_count.fetch_add(1, Ordering::Relaxed);
+                    let end = chunks.get(i + 1).map_or(data.len(), |c| c.byte_offset);
+                    self.scanned_bytes.fetch_add(end - chunks[i].byte_offset, Ordering::Relaxed);
+                }
+            }
+            at = run_end;
+        }
+    }
+
+    // makes the bits of `lines` final: every chunk a line in there starts in, or reaches
+    // into, scanned. scans a few chunks more than it needs while it's at it, the way the
+    // search walks, so the pool has something to split and the next window is ready.
+    fn ensure(&self, data: &[u8], chunks: &[ChunkMeta], lines: Range<usize>, forward: bool) {
+        let end = self.end_chunk;
+        if end == 0 {
+            return;
+        }
+        let first = chunks[..end].partition_point(|c| c.start_line < lines.start).saturating_sub(1);
+        let last = chunks[..end].partition_point(|c| c.start_line < lines.end).max(first + 1);
+        if (first..last).all(|i| self.scanned[i].load(Ordering::Acquire)) {
+            return;
+        }
+        let ahead = pool::install(rayon::current_num_threads).max(1);
+        let range = if forward {
+            first..last.max(first + ahead).min(end)
+        } else {
+            first.min(last.saturating_sub(ahead))..last
+        };
+        self.scan(data, chunks, range);
+    }
+
+    pub(crate) fn done(&self) -> bool {
+        self.scanned_count.load(Ordering::Relaxed) >= self.end_chunk
+    }
+
+    // the file grew from `old_len` bytes: the new chunks, and the ones the lines from `from`
+    // on start in (the old last line may have been cut off halfway), need scanning. only for
+    // a plain search, a refinement's parent doesn't know the new lines.
+    pub(crate) fn grow(&mut self, chunks: &[ChunkMeta], old_len: usize, total_lines: usize, from: usize) {
+        let first = chunks[..self.end_chunk].partition_point(|c| c.start_line < from).saturating_sub(1);
+        for i in first..self.end_chunk {
+            if std::mem::take(self.scanned[i].get_mut()) {
+                let end = if i + 1 < self.end_chunk { chunks[i + 1].byte_offset } else { old_len };
+                *self.scanned_count.get_mut() -= 1;
+                *self.scanned_bytes.get_mut() -= end - chunks[i].byte_offset;
+            }
+        }
+        self.words.truncate(from.div_ceil(64));
+        if let Some(word) = self.words.get_mut(from / 64) {
+            *word.get_mut() &= !(!0u64 << (from % 64));
+        }
+        self.words.resize_with(total_lines.div_ceil(64), || AtomicU64::new(0));
+        self.scanned.resize_with(chunks.len(), || AtomicBool::new(false));
+        self.next_chunk = self.next_chunk.min(first);
+        self.end_chunk = chunks.len();
+        self.total_lines = total_lines;
+    }
+
+    pub(crate) fn scanned_bytes(&self, data: &[u8]) -> usize {
+        if self.done() {
+            return data.len();
+        }
+        self.scanned_bytes.load(Ordering::Relaxed)
+    }
+
+    // hits of the finished filter among the lines scanned so far
+    pub(crate) fn hits(&self, chunks: &[ChunkMeta]) -> usize {
+        // bits only ever get set for scanned chunks, whole words can be counted
+        let step_hits: usize = self.words.iter().map(|w| w.load(Ordering::Relaxed).count_ones() as usize).sum();
+        if !self.exclude {
+            return step_hits;
+        }
+        let kept: usize = (0..self.end_chunk)
+            .filter(|&i| self.scanned[i].load(Ordering::Relaxed))
+            .map(|i| {
+                let from = chunks[i].start_line.min(self.total_lines);
+                let to = chunks.get(i + 1).map_or(self.total_lines, |c| c.start_line.min(self.total_lines));
+                self.parent.as_ref().map_or(to - from, |p| p.count_range(from, to))
+            })
+            .sum();
+        kept.saturating_sub(step_hits)
+    }
+
+    // the bits so far as an index a search can walk, scanning what it walks into first. only
+    // for a plain search (no parent, no exclude), where the step's bits are the answer.
+    pub(crate) fn lazy<'a>(&'a self, data: &'a [u8], chunks: &'a [ChunkMeta]) -> Option<Lazy<'a>> {
+        (self.parent.is_none() && !self.exclude).then_some(Lazy { index: self, data, chunks })
+    }
+
+    fn word(&self, idx: usize) -> Option<u64> {
+        self.words.get(idx).map(|w| w.load(Ordering::Relaxed))
+    }
+
+    // a finished plain search as the index build would have made
+    pub(crate) fn into_index(self) -> MatchIndex {
+        let matcher = self.step.clone();
+        self.finish(matcher)
+    }
+
+    // `matcher` is the whole chain, as for build/refine
+    pub(crate) fn finish(self, matcher: Matcher) -> MatchIndex {
+        let mut bits: Vec<u64> = self.words.into_iter().map(AtomicU64::into_inner).collect();
+        if !self.exclude {
+            return MatchIndex::from_bits(matcher, bits);
+        }
+        match &self.parent {
+            Some(parent) => {
+                for (i, word) in bits.iter_mut().enumerate() {
+                    *word = parent.word(i) & !*word;
+                }
+                bits.drain(..parent.skip.min(bits.len()));
+                MatchIndex::from_words(matcher, bits, parent.skip)
+            }
+            None => MatchIndex::from_bits(self.step, bits).complement(self.total_lines, matcher),
+        }
+    }
+}
+
+// a plain search's PartialIndex as a search walks it: each window of lines gets scanned
+// right before it's looked at, a chunk's worth at a time
+pub(crate) struct Lazy<'a> {
+    index: &'a PartialIndex,
+    data: &'a [u8],
+    chunks: &'a [ChunkMeta],
+}
+
+impl Lazy<'_> {
+    // the lines of the chunk `line` is in, as a window to scan and look at
+    fn window(&self, line: usize) -> Range<usize> {
+        let chunks = &self.chunks[..self.index.end_chunk];
+        let idx = chunks.partition_point(|c| c.start_line <= line).saturating_sub(1);
+        let from = chunks.get(idx).map_or(0, |c| c.start_line).min(line);
+        let to = chunks.get(idx + 1).map_or(self.index.total_lines, |c| c.start_line).max(line + 1);
+        from..to
+    }
+}
+
+impl Hits for Lazy<'_> {
+    fn next_hit(&self, from: usize, to: usize) -> Option<usize> {
+        let to = to.min(self.index.total_lines);
+        let mut line = from;
+        while line < to {
+            let end = self.window(line).end.min(to);
+            self.index.ensure(self.data, self.chunks, line..end, true);
+            let mut word_idx = line / 64;
+            let mut word = self.index.word(word_idx)? & (!0u64 << (line % 64));
+            while word_idx * 64 < end {
+                if word != 0 {
+                    let hit = word_idx * 64 + word.trailing_zeros() as usize;
+                    if hit < end {
+                        return Some(hit);
+                    }
+                    break;
+                }
+                word_idx += 1;
+                word = self.index.word(word_idx).unwrap_or(0);
+            }
+            line = end;
+        }
+        None
+    }
+
+    fn prev_hit(&self, from: usize, to: usize) -> Option<usize> {
+        if from > to || self.index.total_lines == 0 {
+            return None;
+        }
+        let mut line = to.min(self.index.total_lines - 1);
+        loop {
+            let start = self.window(line).start.max(from);
+            self.index.ensure(self.data, self.chunks, start..line + 1, false);
+            for at in (start..=line).rev() {
+                if self.index.word(at / 64).is_some_and(|w| w & (1 << (at % 64)) != 0) {
+                    return Some(at);
+                }
+            }
+            if start <= from {
+                return None;
+            }
+            line = start - 1;
+        }
+    }
+
+    fn matcher(&self) -> &Matcher {
+        &self.index.step
+    }
+}
+
+// one bit per line the matcher hits, out of the lines `keep` lets through, for the chunks in
+// `range`
+fn scan(
+    data: &[u8],
+    chunks: &[ChunkMeta],
+    range: Range<usize>,
+    total_lines: usize,
+    matcher: &Matcher,
+    keep: impl Fn(usize) -> bool + Sync,
+) -> Vec<u64> {
+    let words: Vec<AtomicU64> = (0..total_lines.div_ceil(64))
+        .map(|_| AtomicU64::new(0))
+        .collect();
+    scan_into(&words, data, chunks, range, total_lines, matcher, keep);
+    words.into_iter().map(AtomicU64::into_inner).collect()
+}
+
+fn scan_into(
+    words: &[AtomicU64],
+    data: &[u8],
+    chunks: &[ChunkMeta],
+    range: Range<usize>,
+    total_lines: usize,
+    matcher: &Matcher,
+    keep: impl Fn(usize) -> bool + Sync,
+) {
+    let set = |line: usize| {
+        if line < total_lines {
+            words[line / 64].fetch_or(1 << (line % 64), Ordering::Relaxed);
+        }
+    };
+
+    match matcher {
+        Matcher::Literal(query) if query.is_empty() => {}
+        Matcher::Literal(query) => scan_literal(data, chunks, range, query, |line| {
+            if keep(line) {
+                set(line)
+            }
+        }),
+        _ => scan_lines(data, chunks, range, total_lines, |line, bytes| {
+            if keep(line) && matcher.is_match(bytes) {
+                set(line);
+            }
+        }),
+    }
+}
+
+// a refinement only looks at its parent's hits: chunks without any are skipped, and in the
+// others only those lines are matched, a literal included. the hits a drill-down narrows
+// are usually few and far apart, a memmem over everything would cost the whole file.
+fn scan_hits(
+    words: &[AtomicU64],
+    data: &[u8],
+    chunks: &[ChunkMeta],
+    range: Range<usize>,
+    total_lines: usize,
+    matcher: &Matcher,
+    parent: &MatchIndex,
+) {
+    if matches!(matcher, Matcher::Literal(query) if query.is_empty()) {
+        return;
+    }
+    let first = range.start;
+    let op = ops::token();
+    let scan_chunk = |(i, chunk): (usize, &ChunkMeta)| {
+        if op.cancelled() {
+            return;
+        }
+        let i = first + i;
+        let end = chunks.get(i + 1).map_or(data.len(), |c| c.byte_offset);
+        // the next chunk's first line may still start in this one
+        let last = chunks.get(i + 1).map_or(total_lines, |c| c.start_line + 1).min(total_lines);
+        let mut pos = chunk.byte_offset;
+        let mut line = chunk.start_line;
+        if !is_line_start(data, pos) {
+            pos = match memchr2(b'\n', b'\r', &data[pos..]) {
+                Some(p) => skip_line_break(data, pos + p),
+                None => return,
+            };
+            line += 1;
+        }
+        let Some(mut hit) = parent.next_hit(line, last) else {
+            return;
+        };
+        while pos < end && line < total_lines {
+            let line_end = memchr2(b'\n', b'\r', &data[pos..]).map_or(data.len(), |p| pos + p);
+            if line == hit {
+                if matcher.is_match(&data[pos..line_end]) {
+                    words[line / 64].fetch_or(1 << (line % 64), Ordering::Relaxed);
+                }
+                hit = match parent.next_hit(line + 1, last) {
+                    Some(next) => next,
+                    None => return,
+                };
+            }
+            pos = skip_line_break(data, line_end);
+            line += 1;
+        }
+    };
+    pool::install(|| chunks[range].par_iter().enumerate().for_each(scan_chunk));
+}
+
+// memmem over the raw bytes, only counting newlines up to each hit.
+// matches are attributed to the chunk where they *start*, so we peek a few bytes past
+// the chunk end to catch hits straddling the boundary.
+fn scan_literal(
+    data: &[u8],
+    chunks: &[ChunkMeta],
+    range: Range<usize>,
+    query: &[u8],
+    set: impl Fn(usize) + Sync,
+) {
+    let finder = memmem::Finder::new(query);
+    let first = range.start;
+    let op = ops::token();
+    let scan_chunk = |(i, chunk): (usize, &ChunkMeta)| {
+        if op.cancelled() {
+            return;
+        }
+        let i = first + i;
+        let start = chunk.byte_offset;
+        let end = chunks.get(i + 1).map_or(data.len(), |c| c.byte_offset);
+        let scan_end = (end + query.len() - 1).min(data.len());
+        let region = &data[start..scan_end];
+
+        let mut line = chunk.start_line;
+        let mut cursor = 0;
+        let mut from = 0;
+        while let Some(pos) = finder.find(&region[from..]).map(|p| p + from) {
+            if start + pos >= end {
+                break;
+            }
+            line += count_line_breaks(&region[cursor..pos]);
+            cursor = pos;
+            set(line);
+            // one hit per line is enough, skip to the next one
+            from = match memchr2(b'\n', b'\r', &region[pos..]) {
+                Some(p) => pos + p + 1,
+                None => region.len(),
+            };
+        }
+    };
+    pool::install(|| chunks[range].par_iter().enumerate().for_each(scan_chunk));
+}
+
+// anything smarter than a substring has to look at whole lines.
+// a chunk owns every line that *starts* inside it, the one straddling its start
+// belongs to the previous chunk.
+pub(crate) fn scan_lines(
+    data: &[u8],
+    chunks: &[ChunkMeta],
+    range: Range<usize>,
+    total_lines: usize,
+    visit: impl Fn(usize, &[u8]) + Sync,
+) {
+    let first = range.start;
+    let op = ops::token();
+    let scan_chunk = |(i, chunk): (usize, &ChunkMeta)| {
+        if op.cancelled() {
+            return;
+        }
+        let i = first + i;
+        let end = chunks.get(i + 1).map_or(data.len(), |c| c.byte_offset);
+        let mut pos = chunk.byte_offset;
+        let mut line = chunk.start_line;
+        if !is_line_start(data, pos) {
+            pos = match memchr2(b'\n', b'\r', &data[pos..]) {
+                Some(p) => skip_line_break(data, pos + p),
+                None => return,
+            };
+            line += 1;
+        }
+        while pos < end && line < total_lines {
+            let line_end = memchr2(b'\n', b'\r', &data[pos..]).map_or(data.len(), |p| pos + p);
+            visit(line, &data[pos..line_end]);
+            pos = skip_line_break(data, line_end);
+            line += 1;
+        }
+    };
+    pool::install(|| chunks[range].par_iter().enumerate().for_each(scan_chunk));
+}
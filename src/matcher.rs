@@ -0,0 +1,168 @@
+use crate::ansi;
+use crate::delimited::Columns;
+use crate::glob::Glob;
+use crate::query::{self, Expr};
+use crate::template::Templates;
+use memchr::memmem;
+use regex::bytes::{Regex, RegexBuilder};
+
+// how the plugin wants a query interpreted. numbers are part of the C ABI, don't reorder.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SearchMode {
+    Literal = 0,
+    Query = 1,
+    Glob = 2,
+    Regex = 3,
+}
+
+impl SearchMode {
+    pub(crate) fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            0 => Some(SearchMode::Literal),
+            1 => Some(SearchMode::Query),
+            2 => Some(SearchMode::Glob),
+            3 => Some(SearchMode::Regex),
+            _ => None,
+        }
+    }
+}
+
+// a compiled query that answers "does this line match?"
+#[derive(Clone)]
+pub(crate) enum Matcher {
+    Literal(Vec<u8>),
+    Query(Expr, Templates), // with the engine's formats, for its fields
+    Glob(Glob),
+    Regex(Regex), // bytes regex, log lines aren't always valid utf-8
+    All(Vec<Matcher>), // chained filters, every step has to hit
+    Any(Vec<Matcher>), // filters OR-ed together
+    Not(Box<Matcher>), // exclusion step of a filter
+    Stripped(Box<Matcher>), // strip_ansi on: runs on the line without its escapes
+}
+
+impl Matcher {
+    // with the columns of a delimited file, query fields that are columns read those cells.
+    // with `strip`, lines are matched the way they're shown with strip_ansi on.
+    pub(crate) fn compile(
+        mode: SearchMode,
+        query: &[u8],
+        columns: Option<&Columns>,
+        templates: &Templates,
+        strip: bool,
+    ) -> Result<Self, String> {
+        let matcher = Matcher::compile_plain(mode, query, columns, templates)?;
+        Ok(if strip { Matcher::Stripped(Box::new(matcher)) } else { matcher })
+    }
+
+    fn compile_plain(mode: SearchMode, query: &[u8], columns: Option<&Columns>, templates: &Templates) -> Result<Self, String> {
+        match mode {
+            SearchMode::Literal => Ok(Matcher::Literal(query.to_vec())),
+            SearchMode::Query => {
+                let expr = query::parse(&String::from_utf8_lossy(query))?;
+                let expr = match columns {
+                    Some(columns) => expr.bind(columns),
+                    None => expr,
+                };
+                Ok(Matcher::Query(expr, templates.clone()))
+            }
+            SearchMode::Glob => Ok(Matcher::Glob(Glob::new(query))),
+            SearchMode::Regex => compile_regex(query).map(Matcher::Regex).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub(crate) fn is_match(&self, line: &[u8]) -> bool {
+        match self {
+            Matcher::Literal(needle) => memmem::find(line, needle).is_some(),
+            Matcher::Query(expr, templates) => expr.matches(line, templates),
+            Matcher::Glob(glob) => glob.is_match(line),
+            Matcher::Regex(re) => re.is_match(line),
+            Matcher::All(steps) => steps.iter().all(|m| m.is_match(line)),
+            Matcher::Any(steps) => steps.iter().any(|m| m.is_match(line)),
+            Matcher::Not(inner) => !inner.is_match(line),
+            Matcher::Stripped(inner) => inner.is_match(&ansi::strip_bytes(line)),
+        }
+    }
+
+    // (col, len) byte spans to highlight inside a line. a query without any literal
+    // terms (say `level:error`) lights up the whole line instead.
+    pub(crate) fn spans(&self, line: &[u8], out: &mut Vec<(usize, usize)>) {
+        let terms = match self {
+            Matcher::Glob(glob) => {
+                let mut from = 0;
+                while let Some((start, end)) = glob.find(&line[from..]) {
+                    if end == start {
+                        break; // a bare `*` matches nothing worth painting
+                    }
+                    out.push((from + start, end - start));
+                    from += end;
+                }
+                return;
+            }
+            Matcher::Regex(re) => {
+                out.extend(re.find_iter(line).filter(|m| !m.is_empty()).map(|m| (m.start(), m.len())));
+                return;
+            }
+            Matcher::Not(_) => return, // whatever it matched isn't in the line
+            // columns in the line as it's shown
+            Matcher::Stripped(inner) => {
+                inner.spans(&ansi::strip_bytes(line), out);
+                return;
+            }
+            Matcher::All(steps) => {
+                if !self.is_match(line) {
+                    return;
+                }
+                let start = out.len();
+                for step in steps {
+                    step.spans(line, out);
+                }
+                out[start..].sort_unstable();
+                return;
+            }
+            // every branch only paints what it actually hit
+            Matcher::Any(steps) => {
+                let start = out.len();
+                for step in steps {
+                    step.spans(line, out);
+                }
+                out[start..].sort_unstable();
+                return;
+            }
+            Matcher::Literal(needle) => vec![needle.as_slice()],
+            Matcher::Query(expr, templates) => {
+                if !expr.matches(line, templates) {
+                    return;
+                }
+                let mut terms = Vec::new();
+                expr.highlight_terms(&mut terms);
+                if terms.is_empty() {
+                    out.push((0, line.len()));
+                    return;
+                }
+                terms
+            }
+        };
+        let start = out.len();
+        for term in terms.into_iter().filter(|t| !t.is_empty()) {
+            out.extend(memmem::find_iter(line, term).map(|col| (col, term.len())));
+        }
+        out[start..].sort_unstable();
+    }
+}
+
+// a pattern that isn't valid utf-8 (pasted from a dirty log) is matched byte for byte: its
+// stray bytes become \xHH escapes, which mean the raw byte with unicode off. lossy decoding
+// would turn them into U+FFFD and the pattern would never find the line it came from.
+fn compile_regex(query: &[u8]) -> Result<Regex, regex::Error> {
+    if let Ok(pattern) = std::str::from_utf8(query) {
+        return Regex::new(pattern);
+    }
+    let mut pattern = String::with_capacity(query.len() + 8);
+    for chunk in query.utf8_chunks() {
+        pattern.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            pattern.push_str(&format!("\\x{:02X}", byte));
+        }
+    }
+    RegexBuilder::new(&pattern).unicode(false).build()
+}
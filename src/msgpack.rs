@@ -0,0 +1,271 @@
+use std::io::{self, Read, Write};
+
+// just enough msgpack for msgpack-rpc (the protocol neovim speaks on its channels): every
+// type can be read, since the other end picks the encoding, but only what our replies use
+// gets written. strings stay bytes, log lines aren't always utf-8.
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(Vec<u8>),
+    Bin(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Ext(i8, Vec<u8>), // neovim sends its buffer/window handles as these
+}
+
+impl Value {
+    pub(crate) fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::UInt(n) => Some(n),
+            Value::Int(n) => u64::try_from(n).ok(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    // str or bin, lua strings come over as either depending on the sender
+    pub(crate) fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Str(bytes) | Value::Bin(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.as_bytes().to_vec())
+    }
+}
+
+// a message claiming to be bigger than this is garbage, not a reason to allocate it
+const MAX_LEN: usize = 1 << 30;
+// arrays and maps inside each other past this are refused: reading recurses, and a stack
+// overflow aborts the server where an error only ends the one connection
+const MAX_DEPTH: usize = 64;
+
+pub(crate) fn read(r: &mut impl Read) -> io::Result<Value> {
+    read_nested(r, 0)
+}
+
+// `depth` arrays and maps around the value being read
+fn read_nested(r: &mut impl Read, depth: usize) -> io::Result<Value> {
+    let marker = read_u8(r)?;
+    if matches!(marker, 0x80..=0x9f | 0xdc..=0xdf) && depth >= MAX_DEPTH {
+        return Err(invalid("nested too deep"));
+    }
+    Ok(match marker {
+        0x00..=0x7f => Value::UInt(marker as u64),
+        0x80..=0x8f => read_map(r, (marker & 0x0f) as usize, depth)?,
+        0x90..=0x9f => read_array(r, (marker & 0x0f) as usize, depth)?,
+        0xa0..=0xbf => Value::Str(read_bytes(r, (marker & 0x1f) as usize)?),
+        0xc0 => Value::Nil,
+        0xc2 => Value::Bool(false),
+        0xc3 => Value::Bool(true),
+        0xc4 => Value::Bin(sized(r, 1)?),
+        0xc5 => Value::Bin(sized(r, 2)?),
+        0xc6 => Value::Bin(sized(r, 4)?),
+        0xc7..=0xc9 => {
+            let len = read_len(r, 1 << (marker - 0xc7))?;
+            let kind = read_u8(r)? as i8;
+            Value::Ext(kind, read_bytes(r, len)?)
+        }
+        0xca => Value::Float(f32::from_bits(read_uint(r, 4)? as u32) as f64),
+        0xcb => Value::Float(f64::from_bits(read_uint(r, 8)?)),
+        0xcc => Value::UInt(read_uint(r, 1)?),
+        0xcd => Value::UInt(read_uint(r, 2)?),
+        0xce => Value::UInt(read_uint(r, 4)?),
+        0xcf => Value::UInt(read_uint(r, 8)?),
+        0xd0 => Value::Int(read_uint(r, 1)? as u8 as i8 as i64),
+        0xd1 => Value::Int(read_uint(r, 2)? as u16 as i16 as i64),
+        0xd2 => Value::Int(read_uint(r, 4)? as u32 as i32 as i64),
+        0xd3 => Value::Int(read_uint(r, 8)? as i64),
+        0xd4..=0xd8 => {
+            let kind = read_u8(r)? as i8;
+            Value::Ext(kind, read_bytes(r, 1 << (marker - 0xd4))?)
+        }
+        0xd9 => Value::Str(sized(r, 1)?),
+        0xda => Value::Str(sized(r, 2)?),
+        0xdb => Value::Str(sized(r, 4)?),
+        0xdc => {
+            let len = read_len(r, 2)?;
+            read_array(r, len, depth)?
+        }
+        0xdd => {
+            let len = read_len(r, 4)?;
+            read_array(r, len, depth)?
+        }
+        0xde => {
+            let len = read_len(r, 2)?;
+            read_map(r, len, depth)?
+        }
+        0xdf => {
+            let len = read_len(r, 4)?;
+            read_map(r, len, depth)?
+        }
+        0xe0..=0xff => Value::Int(marker as i8 as i64),
+        0xc1 => return Err(invalid("0xc1 is never used")),
+    })
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0u8; 1];
+    r.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+// big endian, `width` bytes
+fn read_uint(r: &mut impl Read, width: usize) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf[8 - width..])?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+fn read_len(r: &mut impl Read, width: usize) -> io::Result<usize> {
+    let len = read_uint(r, width)? as usize;
+    if len > MAX_LEN {
+        return Err(invalid("length past the limit"));
+    }
+    Ok(len)
+}
+
+// grows with what actually arrives, the length alone allocates nothing
+fn read_bytes(r: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "msgpack: message cut short"));
+    }
+    Ok(bytes)
+}
+
+fn sized(r: &mut impl Read, width: usize) -> io::Result<Vec<u8>> {
+    let len = read_len(r, width)?;
+    read_bytes(r, len)
+}
+
+fn read_array(r: &mut impl Read, len: usize, depth: usize) -> io::Result<Value> {
+    // no with_capacity, the length is the sender's word
+    let mut items = Vec::new();
+    for _ in 0..len {
+        items.push(read_nested(r, depth + 1)?);
+    }
+    Ok(Value::Array(items))
+}
+
+fn read_map(r: &mut impl Read, len: usize, depth: usize) -> io::Result<Value> {
+    let mut entries = Vec::new();
+    for _ in 0..len {
+        entries.push((read_nested(r, depth + 1)?, read_nested(r, depth + 1)?));
+    }
+    Ok(Value::Map(entries))
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("msgpack: {}", what))
+}
+
+pub(crate) fn write(w: &mut impl Write, value: &Value) -> io::Result<()> {
+    match value {
+        Value::Nil => w.write_all(&[0xc0]),
+        Value::Bool(b) => w.write_all(&[if *b { 0xc3 } else { 0xc2 }]),
+        Value::UInt(n) => write_uint(w, *n),
+        Value::Int(n) if *n >= 0 => write_uint(w, *n as u64),
+        Value::Int(n) if *n >= -32 => w.write_all(&[*n as i8 as u8]),
+        Value::Int(n) => {
+            w.write_all(&[0xd3])?;
+            w.write_all(&n.to_be_bytes())
+        }
+        Value::Float(f) => {
+            w.write_all(&[0xcb])?;
+            w.write_all(&f.to_bits().to_be_bytes())
+        }
+        Value::Str(bytes) => {
+            match bytes.len() {
+                len @ 0..=31 => w.write_all(&[0xa0 | len as u8])?,
+                len => write_len(w, [0xd9, 0xda, 0xdb], len)?,
+            }
+            w.write_all(bytes)
+        }
+        Value::Bin(bytes) => {
+            write_len(w, [0xc4, 0xc5, 0xc6], bytes.len())?;
+            w.write_all(bytes)
+        }
+        Value::Array(items) => {
+            match items.len() {
+                len @ 0..=15 => w.write_all(&[0x90 | len as u8])?,
+                len => write_count(w, [0xdc, 0xdd], len)?,
+            }
+            items.iter().try_for_each(|item| write(w, item))
+        }
+        Value::Map(entries) => {
+            match entries.len() {
+                len @ 0..=15 => w.write_all(&[0x80 | len as u8])?,
+                len => write_count(w, [0xde, 0xdf], len)?,
+            }
+            entries.iter().try_for_each(|(k, v)| {
+                write(w, k)?;
+                write(w, v)
+            })
+        }
+        Value::Ext(kind, data) => {
+            write_len(w, [0xc7, 0xc8, 0xc9], data.len())?;
+            w.write_all(&[*kind as u8])?;
+            w.write_all(data)
+        }
+    }
+}
+
+fn write_uint(w: &mut impl Write, n: u64) -> io::Result<()> {
+    match n {
+        0..=0x7f => w.write_all(&[n as u8]),
+        0x80..=0xff => w.write_all(&[0xcc, n as u8]),
+        0x100..=0xffff => {
+            w.write_all(&[0xcd])?;
+            w.write_all(&(n as u16).to_be_bytes())
+        }
+        0x1_0000..=0xffff_ffff => {
+            w.write_all(&[0xce])?;
+            w.write_all(&(n as u32).to_be_bytes())
+        }
+        _ => {
+            w.write_all(&[0xcf])?;
+            w.write_all(&n.to_be_bytes())
+        }
+    }
+}
+
+// arrays and maps only come in 16/32 bit counts past the fix forms
+fn write_count(w: &mut impl Write, markers: [u8; 2], len: usize) -> io::Result<()> {
+    if len <= 0xffff {
+        w.write_all(&[markers[0]])?;
+        w.write_all(&(len as u16).to_be_bytes())
+    } else {
+        w.write_all(&[markers[1]])?;
+        w.write_all(&(len as u32).to_be_bytes())
+    }
+}
+
+// the 8/16/32 bit length forms, markers in that order
+fn write_len(w: &mut impl Write, markers: [u8; 3], len: usize) -> io::Result<()> {
+    if len <= 0xff {
+        w.write_all(&[markers[0], len as u8])
+    } else if len <= 0xffff {
+        w.write_all(&[markers[1]])?;
+        w.write_all(&(len as u16).to_be_bytes())
+    } else {
+        w.write_all(&[markers[2]])?;
+        w.write_all(&(len as u32).to_be_bytes())
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// notes on a log's lines, kept next to it in <log>.notes so a team looking at the same
+// incident log (over a network drive, say) sees each other's. every sync takes an advisory
+// lock on the file, reads what's there, merges it with what this session changed and writes
+// the result back before letting go, so two people syncing at once take turns instead of
+// one clobbering the other.
+//
+// merging is per line, against what the file said when this session last read it: a line
+// only one side touched takes that side's note, a line both sides changed differently keeps
+// the newer note and the other one goes on the conflict list for the plugin to show.
+// a removed note stays in the file as an empty one, so the removal merges like any change.
+//
+//     juanlog-notes 1
+//     1523\t1717000000\tana\tthis is where the pool ran dry
+//
+// a note is line, unix seconds, author and text. tabs, line breaks and backslashes in the
+// author and text are escaped (\t \n \r \\).
+
+const HEADER: &str = "juanlog-notes 1";
+
+#[derive(Clone, PartialEq)]
+pub(crate) struct Note {
+    pub(crate) at: i64,
+    pub(crate) author: String,
+    pub(crate) text: String, // empty: removed
+}
+
+// a line both sides changed: the note that stayed and the one that lost
+pub(crate) struct Conflict {
+    pub(crate) line: u64,
+    pub(crate) kept: Note,
+    pub(crate) lost: Note,
+}
+
+pub(crate) struct Notes {
+    path: PathBuf,
+    current: BTreeMap<u64, Note>, // as this session sees them
+    base: BTreeMap<u64, Note>,    // as the file had them at the last sync
+    pub(crate) conflicts: Vec<Conflict>, // from the last sync
+}
+
+impl Notes {
+    // the sidecar of `log`. nothing is read until the first sync.
+    pub(crate) fn new(log: &Path) -> Self {
+        let mut path = log.as_os_str().to_owned();
+        path.push(".notes");
+        Notes {
+            path: PathBuf::from(path),
+            current: BTreeMap::new(),
+            base: BTreeMap::new(),
+            conflicts: Vec::new(),
+        }
+    }
+
+    // an empty text removes the line's note
+    pub(crate) fn set(&mut self, line: u64, author: &str, text: &str) {
+        let at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+        let note = Note { at, author: author.to_string(), text: text.to_string() };
+        self.current.insert(line, note);
+    }
+
+    // the notes on lines in [from, to), removed ones left out
+    pub(crate) fn range(&self, from: u64, to: u64) -> impl Iterator<Item = (u64, &Note)> {
+        self.current.range(from..to.max(from)).filter(|(_, note)| !note.text.is_empty()).map(|(&line, note)| (line, note))
+    }
+
+    // merge with the file and write the result back, under its lock. WouldBlock when another
+    // session holds it right now: nothing changed, try again in a moment. returns how many
+    // conflicts the merge turned up, listed in `conflicts` until the next sync.
+    pub(crate) fn sync(&mut self) -> io::Result<usize> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&self.path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "another session is writing the notes"));
+            }
+            Err(TryLockError::Error(err)) => return Err(err),
+        }
+        let theirs = read(&mut file)?;
+        let (merged, conflicts) = merge(&self.base, &self.current, &theirs);
+        // in place, not through a rename: the lock belongs to this file
+        file.seek(SeekFrom::Start(0))?;
+        file.set_len(0)?;
+        file.write_all(to_text(&merged).as_bytes())?;
+        file.sync_all()?;
+        self.base = merged.clone();
+        self.current = merged;
+        self.conflicts = conflicts;
+        Ok(self.conflicts.len())
+    }
+}
+
+fn read(file: &mut File) -> io::Result<BTreeMap<u64, Note>> {
+    let mut text = String::new();
+    file.read_to_string(&mut text)?;
+    let mut lines = text.lines();
+    match lines.next() {
+        None => return Ok(BTreeMap::new()),
+        Some(HEADER) => {}
+        Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "not a notes file")),
+    }
+    let mut notes = BTreeMap::new();
+    for row in lines.filter(|l| !l.is_empty()) {
+        let mut parts = row.splitn(4, '\t');
+        let parsed = (|| {
+            let line = parts.next()?.parse().ok()?;
+            let at = parts.next()?.parse().ok()?;
+            let author = unescape(parts.next()?);
+            Some((line, Note { at, author, text: unescape(parts.next().unwrap_or("")) }))
+        })();
+        let (line, note) = parsed.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("bad note: {}", row)))?;
+        notes.insert(line, note);
+    }
+    Ok(notes)
+}
+
+fn to_text(notes: &BTreeMap<u64, Note>) -> String {
+    let mut out = format!("{}\n", HEADER);
+    for (line, note) in notes {
+        out.push_str(&format!("{}\t{}\t{}\t{}\n", line, note.at, escape(&note.author), escape(&note.text)));
+    }
+    out
+}
+
+// per line: whoever changed it since `base` wins, the newer note when both did (the author
+// breaking a tie, so every session merges the same way)
+fn merge(
+    base: &BTreeMap<u64, Note>,
+    ours: &BTreeMap<u64, Note>,
+    theirs: &BTreeMap<u64, Note>,
+) -> (BTreeMap<u64, Note>, Vec<Conflict>) {
+    let lines: BTreeSet<u64> = base.keys().chain(ours.keys()).chain(theirs.keys()).copied().collect();
+    let mut merged = BTreeMap::new();
+    let mut conflicts = Vec::new();
+    for line in lines {
+        let (was, mine, other) = (base.get(&line), ours.get(&line), theirs.get(&line));
+        let kept = match (mine != was, other != was) {
+            (true, true) if mine != other => match (mine, other) {
+                (Some(a), Some(b)) => {
+                    let (kept, lost) = if (a.at, &a.author) >= (b.at, &b.author) { (a, b) } else { (b, a) };
+                    conflicts.push(Conflict { line, kept: kept.clone(), lost: lost.clone() });
+                    Some(kept)
+                }
+                // a note only goes missing from the file if someone edited it by hand
+                (note, None) | (None, note) => note,
+            },
+            (true, _) => mine,
+            _ => other,
+        };
+        if let Some(note) = kept {
+            merged.insert(line, note.clone());
+        }
+    }
+    (merged, conflicts)
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
@@ -0,0 +1,93 @@
+use crate::diag;
+use crate::ops;
+use crate::options::Options;
+use crate::Engine;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+// Engine::new can take forever: open/stat on a dead network mount block in the kernel,
+// an enormous sparse file takes ages to count. with a deadline it runs on a helper thread
+// and the caller stops waiting on time. the helper gets told to stop counting and drops
+// whatever it ends up with. a syscall stuck on a dead mount only keeps that one thread parked.
+
+// log_engine_open's out_error. numbers are part of the C ABI, don't reorder.
+pub(crate) const OPEN_OK: i32 = 0;
+pub(crate) const OPEN_IO_ERROR: i32 = 1; // missing, no permission, can't be mapped
+pub(crate) const OPEN_NOT_A_FILE: i32 = 2; // directory, device node, socket
+pub(crate) const OPEN_TIMED_OUT: i32 = 3;
+pub(crate) const OPEN_CANCELLED: i32 = 4;
+
+// no timeout opens right here on the calling thread, like it always did. the deadline and
+// log_engine_cancel stop the counting through the same flag
+pub(crate) fn open(path: PathBuf, timeout: Option<Duration>, options: Options) -> Result<Engine, i32> {
+    let op = ops::start();
+    let cancel = op.flag();
+    if is_fifo(&path) {
+        // nothing to count up front, the pipe is read in the background
+        return Engine::from_pipe(&path, &options).map_err(|err| error_code(&err));
+    }
+    let Some(timeout) = timeout else {
+        return Engine::new(&path, &options, &cancel).map_err(|err| error_code(&err));
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let flag = cancel.clone();
+    let spawned = thread::Builder::new()
+        .name("juanlog-open".to_string())
+        .spawn(move || {
+            // our own thread, so opening may wait between retries. the engine it makes
+            // goes back to handing that to the caller (see retry.rs)
+            let opened = Engine::new(&path, &Options { retry_wait: true, ..options }, &flag).map(|mut engine| {
+                engine.retry.wait = false;
+                engine.options.retry_wait = false;
+                engine
+            });
+            // nobody listening anymore means we timed out, the engine just gets dropped
+            let _ = tx.send(opened);
+        });
+    if let Err(err) = spawned {
+        return Err(error_code(&err));
+    }
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result.map_err(|err| error_code(&err)),
+        Err(RecvTimeoutError::Timeout) => {
+            cancel.store(true, Ordering::Relaxed);
+            diag::fail(diag::ERROR_TIMED_OUT, format!("gave up opening after {}ms", timeout.as_millis()));
+            Err(OPEN_TIMED_OUT)
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            diag::fail(diag::ERROR_IO, "opening the file panicked".to_string());
+            Err(OPEN_IO_ERROR)
+        }
+    }
+}
+
+fn is_fifo(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        std::fs::metadata(path).is_ok_and(|meta| meta.file_type().is_fifo())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+// also the reason for log_engine_last_error, we're back on the caller's thread here
+fn error_code(err: &io::Error) -> i32 {
+    diag::io_failure(err);
+    if diag::is_cancelled(err) {
+        return OPEN_CANCELLED;
+    }
+    match err.kind() {
+        io::ErrorKind::InvalidInput => OPEN_NOT_A_FILE,
+        io::ErrorKind::TimedOut => OPEN_TIMED_OUT,
+        _ => OPEN_IO_ERROR,
+    }
+}
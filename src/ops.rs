@@ -0,0 +1,176 @@
+use crate::diag;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Once, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// the slow calls (opening, searching, building a filter, saving) run as operations that
+// another thread can cancel with log_engine_cancel. they block until they're done, so the
+// number can't come back from the call itself: the thread asks for one first with
+// log_engine_op_reserve and the next slow call it makes runs under it. one it doesn't ask
+// for gets a number nobody knows, which is the same as not cancellable. a cancelled call
+// stops at the next chunk, keeps nothing it built halfway and fails with ERROR_CANCELLED.
+// a caller without another thread (the plugin) gives the number a deadline instead, with
+// log_engine_op_timeout: one thread here waits for all of them and cancels what's late.
+// that goes for growing and reopening, building the severity index and each step of a
+// stepped filter too (the next step goes on from where the cancelled one stopped).
+
+// running or reserved, by number. a number is never handed out twice.
+static OPS: Mutex<BTreeMap<u64, Arc<AtomicBool>>> = Mutex::new(BTreeMap::new());
+static NEXT: AtomicU64 = AtomicU64::new(1);
+// when to cancel what, for the deadline thread. gone with the op.
+static DEADLINES: Mutex<BTreeMap<u64, Instant>> = Mutex::new(BTreeMap::new());
+static DEADLINE_SET: Condvar = Condvar::new();
+static DEADLINE_THREAD: Once = Once::new();
+
+thread_local! {
+    static RESERVED: RefCell<Option<(u64, Arc<AtomicBool>)>> = const { RefCell::new(None) };
+    static CURRENT: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+fn ops<R>(f: impl FnOnce(&mut BTreeMap<u64, Arc<AtomicBool>>) -> R) -> R {
+    f(&mut OPS.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+fn deadlines() -> std::sync::MutexGuard<'static, BTreeMap<u64, Instant>> {
+    DEADLINES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// done with, or never going to run
+fn forget(id: u64) {
+    ops(|ops| ops.remove(&id));
+    deadlines().remove(&id);
+}
+
+fn new_op() -> (u64, Arc<AtomicBool>) {
+    let id = NEXT.fetch_add(1, Ordering::Relaxed);
+    let flag = Arc::new(AtomicBool::new(false));
+    ops(|ops| ops.insert(id, flag.clone()));
+    (id, flag)
+}
+
+// running or reserved right now, across every thread
+pub(crate) fn pending() -> usize {
+    ops(|ops| ops.len())
+}
+
+// a number for the next slow call on this thread. reserving again drops the one before
+pub(crate) fn reserve() -> u64 {
+    let (id, flag) = new_op();
+    if let Some((old, _)) = RESERVED.with(|r| r.borrow_mut().replace((id, flag))) {
+        forget(old);
+    }
+    id
+}
+
+// false for a number that already finished, or never was
+pub(crate) fn cancel(id: u64) -> bool {
+    ops(|ops| ops.get(&id).map(|flag| flag.store(true, Ordering::Relaxed))).is_some()
+}
+
+// cancels `id` once `after` has passed, unless it's finished by then. false if it already
+// finished (or the number was never handed out)
+pub(crate) fn cancel_after(id: u64, after: Duration) -> bool {
+    if !ops(|ops| ops.contains_key(&id)) {
+        return false;
+    }
+    // so far off it never comes
+    let Some(at) = Instant::now().checked_add(after) else {
+        return true;
+    };
+    DEADLINE_THREAD.call_once(|| {
+        // without the thread nothing times out, same as a number nobody cancels
+        let _ = thread::Builder::new().name("juanlog-deadlines".to_string()).spawn(wait_for_deadlines);
+    });
+    deadlines().insert(id, at);
+    DEADLINE_SET.notify_one();
+    true
+}
+
+fn wait_for_deadlines() {
+    let mut pending = deadlines();
+    loop {
+        let now = Instant::now();
+        // nothing takes DEADLINES while holding OPS, so this can't deadlock
+        pending.retain(|&id, &mut at| {
+            if at > now {
+                return true;
+            }
+            cancel(id);
+            false
+        });
+        pending = match pending.values().min().copied() {
+            Some(at) => {
+                DEADLINE_SET.wait_timeout(pending, at - now).map_or_else(|err| err.into_inner().0, |(guard, _)| guard)
+            }
+            None => DEADLINE_SET.wait(pending).unwrap_or_else(PoisonError::into_inner),
+        };
+    }
+}
+
+// the operation a slow call runs as, until it's dropped. a slow call made from inside
+// another one (a filter searching, say) is part of the outer one.
+pub(crate) struct Op {
+    id: Option<u64>,
+    flag: Arc<AtomicBool>,
+}
+
+pub(crate) fn start() -> Op {
+    if let Some(flag) = CURRENT.with(|c| c.borrow().clone()) {
+        return Op { id: None, flag };
+    }
+    let (id, flag) = RESERVED.with(|r| r.borrow_mut().take()).unwrap_or_else(new_op);
+    CURRENT.with(|c| *c.borrow_mut() = Some(flag.clone()));
+    Op { id: Some(id), flag }
+}
+
+impl Op {
+    // for work handed to another thread, which can't see this one's current op
+    pub(crate) fn flag(&self) -> Arc<AtomicBool> {
+        self.flag.clone()
+    }
+}
+
+impl Drop for Op {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            forget(id);
+            CURRENT.with(|c| *c.borrow_mut() = None);
+        }
+    }
+}
+
+// what this thread's operation looks like from the rayon workers scanning for it
+#[derive(Clone)]
+pub(crate) struct Token(Option<Arc<AtomicBool>>);
+
+pub(crate) fn token() -> Token {
+    Token(CURRENT.with(|c| c.borrow().clone()))
+}
+
+impl Token {
+    pub(crate) fn cancelled(&self) -> bool {
+        self.0.as_ref().is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+}
+
+pub(crate) fn cancelled() -> bool {
+    token().cancelled()
+}
+
+// what a cancelled call fails with, diag::io_failure makes it ERROR_CANCELLED
+pub(crate) fn cancelled_error() -> io::Error {
+    diag::cancelled()
+}
+
+// runs `f` as if no operation were current, for the part of a call that has to finish once
+// it's committed (reopen searching the new file again for filters it already took down)
+pub(crate) fn shielded<R>(f: impl FnOnce() -> R) -> R {
+    let current = CURRENT.with(|c| c.borrow_mut().take());
+    let result = f();
+    CURRENT.with(|c| *c.borrow_mut() = current);
+    result
+}
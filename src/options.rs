@@ -0,0 +1,138 @@
+use crate::diag;
+use crate::pool;
+use crate::retry::{self, Retry};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+// what log_engine_new_with_options can tune before the file is indexed, instead of the
+// 1MB chunks and the global rayon pool everything used to get. the struct is versioned:
+// the caller fills in the version its header had, fields only ever get added at the end
+// under a new version, so an older plugin's struct still reads right.
+
+// the one this library knows up to, part of the C ABI
+pub(crate) const OPTIONS_VERSION: u32 = 3;
+
+// filters scan the whole file inside the create call, or come back empty and get scanned
+// by log_engine_filter_step (see filter_slice), STEPPED_CHUNKS chunks at a time
+pub(crate) const INDEX_FULL: u32 = 0;
+pub(crate) const INDEX_STEPPED: u32 = 1;
+const STEPPED_CHUNKS: usize = 64;
+
+// the line index keeps one ChunkMeta per this many bytes of the file
+pub(crate) const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+// small enough to take a lot of entries, big enough that a match never spans three chunks
+const MIN_CHUNK_SIZE: usize = 64 * 1024;
+const MAX_CHUNK_SIZE: usize = 1024 * 1024 * 1024;
+
+// the C side's struct, 0 anywhere means the default
+#[repr(C)]
+pub struct LogEngineOptions {
+    pub version: u32,
+    pub threads: u32,     // 0 = the shared rayon pool, 1 = one thread, n = a pool of n for this engine
+    pub chunk_size: u64,  // bytes per index chunk, clamped to 64KB..1GB
+    pub max_memory: u64,  // bytes the cached search indexes may take before the oldest go
+    pub index: u32,       // INDEX_*
+    pub follow: bool,     // start the native watch right away, see log_engine_watch_native
+    // version 2
+    pub read_only: bool, // a pure viewer: edits, appends and saves fail with ERROR_READ_ONLY
+    // version 3
+    pub io_retries: u32,        // times a read failing transiently is retried, see retry.rs
+    pub io_retry_delay_ms: u64, // before the first retry, doubled for each one after
+}
+
+#[derive(Clone)]
+pub(crate) struct Options {
+    pub(crate) chunk_size: usize,
+    pub(crate) pool: Option<Arc<ThreadPool>>,
+    pub(crate) max_memory: usize, // 0 = no limit
+    pub(crate) filter_slice: usize,
+    pub(crate) follow: bool,
+    pub(crate) read_only: bool,
+    pub(crate) retry_attempts: u32,
+    pub(crate) retry_delay: Duration,
+    pub(crate) retry_wait: bool, // on a thread of our own, retries may sleep (see retry.rs)
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            pool: None,
+            max_memory: 0,
+            filter_slice: 0,
+            follow: false,
+            read_only: false,
+            retry_attempts: retry::DEFAULT_ATTEMPTS,
+            retry_delay: retry::DEFAULT_BASE_DELAY,
+            retry_wait: false,
+        }
+    }
+}
+
+impl Options {
+    // None (with a misuse) for a struct from a newer header or an unknown strategy, Err if
+    // the pool can't be started
+    pub(crate) fn from_raw(raw: &LogEngineOptions, call: &'static str) -> Option<io::Result<Self>> {
+        if raw.version == 0 || raw.version > OPTIONS_VERSION {
+            diag::misuse(|| format!("{}: options version {}, this library knows up to {}", call, raw.version, OPTIONS_VERSION));
+            return None;
+        }
+        let chunk_size = match raw.chunk_size {
+            0 => DEFAULT_CHUNK_SIZE,
+            size => usize::try_from(size).unwrap_or(MAX_CHUNK_SIZE).clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE),
+        };
+        let filter_slice = match raw.index {
+            INDEX_FULL => 0,
+            INDEX_STEPPED => chunk_size * STEPPED_CHUNKS,
+            index => {
+                diag::misuse(|| format!("{}: unknown index strategy {}", call, index));
+                return None;
+            }
+        };
+        let pool = match raw.threads {
+            0 => Ok(None),
+            threads => thread_pool(threads as usize).map(Some),
+        };
+        Some(pool.map(|pool| Options {
+            chunk_size,
+            pool,
+            max_memory: usize::try_from(raw.max_memory).unwrap_or(usize::MAX),
+            filter_slice,
+            follow: raw.follow,
+            // past the end of a version 1 struct
+            read_only: raw.version >= 2 && raw.read_only,
+            retry_attempts: match raw.io_retries {
+                attempts if raw.version >= 3 && attempts > 0 => attempts,
+                _ => retry::DEFAULT_ATTEMPTS,
+            },
+            retry_delay: match raw.io_retry_delay_ms {
+                delay if raw.version >= 3 && delay > 0 => Duration::from_millis(delay),
+                _ => retry::DEFAULT_BASE_DELAY,
+            },
+            retry_wait: false,
+        }))
+    }
+
+    pub(crate) fn retry(&self) -> Retry {
+        let mut retry = Retry::new(self.retry_attempts, self.retry_delay);
+        retry.wait = self.retry_wait;
+        retry
+    }
+
+    // the parallel part of a call that has the options at hand, on the engine's pool
+    pub(crate) fn install<R: Send>(&self, f: impl FnOnce() -> R + Send) -> R {
+        let _pool = pool::enter(self.pool.clone());
+        pool::install(f)
+    }
+}
+
+pub(crate) fn thread_pool(threads: usize) -> io::Result<Arc<ThreadPool>> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|i| format!("juanlog-{}", i))
+        .build()
+        .map(Arc::new)
+        .map_err(io::Error::other)
+}
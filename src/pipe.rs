@@ -0,0 +1,139 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+// fifos (mkfifo, `cmd | nvim` style plumbing) can't be mapped: a reader thread copies
+// whatever comes out of the pipe into a spill file, and the engine maps that one instead.
+// from then on it's an ordinary growing file: watch/refresh index the new bytes as they land.
+
+// pipe_status numbers are part of the C ABI, don't reorder
+pub(crate) const PIPE_STREAMING: i32 = 0;
+pub(crate) const PIPE_FINISHED: i32 = 1; // the writer closed its end
+pub(crate) const PIPE_FAILED: i32 = 2; // reading the pipe or writing the spill went wrong
+
+const READ_BUF: usize = 64 * 1024;
+
+pub(crate) struct Pipe {
+    source: PathBuf,
+    spill: PathBuf,
+    status: Arc<AtomicI32>,
+    stop: Arc<AtomicBool>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Pipe {
+    // an empty spill file for the engine to open, nothing is read until start()
+    pub(crate) fn spill_file() -> io::Result<PathBuf> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let name = format!("juanlog-{}-{}.spill", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let spill = std::env::temp_dir().join(name);
+        OpenOptions::new().write(true).create_new(true).open(&spill)?;
+        Ok(spill)
+    }
+
+    // a finished scratch file (spill_file, filled) opened for mapping, gone from the temp dir
+    // once nothing has it open: unix unlinks it right away, windows can't delete an open file
+    // and is asked to when the last handle closes instead
+    pub(crate) fn open_scratch(scratch: &Path) -> io::Result<File> {
+        #[cfg(windows)]
+        let opened = {
+            use std::os::windows::fs::OpenOptionsExt;
+            const GENERIC_READ: u32 = 0x8000_0000;
+            const DELETE: u32 = 0x0001_0000;
+            const FILE_SHARE_ALL: u32 = 0x7; // read, write and delete
+            const FILE_FLAG_DELETE_ON_CLOSE: u32 = 0x0400_0000;
+            OpenOptions::new()
+                .access_mode(GENERIC_READ | DELETE)
+                .share_mode(FILE_SHARE_ALL)
+                .custom_flags(FILE_FLAG_DELETE_ON_CLOSE)
+                .open(scratch)
+        };
+        #[cfg(not(windows))]
+        let opened = File::open(scratch);
+        if cfg!(not(windows)) || opened.is_err() {
+            let _ = fs::remove_file(scratch);
+        }
+        opened
+    }
+
+    // opening a fifo for reading blocks until someone opens the other end, so that happens
+    // on the reader thread too
+    pub(crate) fn start(source: &Path, spill: PathBuf) -> io::Result<Self> {
+        let mut out = OpenOptions::new().append(true).open(&spill)?;
+        let status = Arc::new(AtomicI32::new(PIPE_STREAMING));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (thread_source, thread_status, thread_stop) = (source.to_path_buf(), status.clone(), stop.clone());
+        let reader = thread::Builder::new().name("juanlog-pipe".to_string()).spawn(move || {
+            let result = File::open(&thread_source).and_then(|mut input| {
+                let mut buf = vec![0u8; READ_BUF];
+                loop {
+                    let read = match input.read(&mut buf) {
+                        Ok(0) => return Ok(()),
+                        Ok(read) => read,
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(err) => return Err(err),
+                    };
+                    // woken up by Drop, what it wrote to get us here isn't the log's
+                    if thread_stop.load(Ordering::Relaxed) {
+                        return Ok(());
+                    }
+                    out.write_all(&buf[..read])?;
+                }
+            });
+            let done = if result.is_ok() { PIPE_FINISHED } else { PIPE_FAILED };
+            thread_status.store(done, Ordering::Release);
+        })?;
+        Ok(Pipe { source: source.to_path_buf(), spill, status, stop, reader: Some(reader) })
+    }
+
+    pub(crate) fn status(&self) -> i32 {
+        self.status.load(Ordering::Acquire)
+    }
+
+    // hand the disk space of the spill's first `up_to` bytes back, they read as zeros after.
+    // best effort, filesystems without hole punching just keep it.
+    pub(crate) fn release(&self, up_to: u64) {
+        #[cfg(target_os = "linux")]
+        if let Ok(file) = OpenOptions::new().write(true).open(&self.spill) {
+            use std::os::unix::io::AsRawFd;
+            let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+            unsafe { libc::fallocate(file.as_raw_fd(), mode, 0, up_to as libc::off_t) };
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = up_to;
+    }
+
+    // a reader blocked on the fifo (no writer yet, or one that's gone quiet) only looks at
+    // `stop` once open or read returns. opening the other end without blocking gets it out
+    // of open, a byte written gets it out of read. no reader left makes that open fail,
+    // and anything that's taken the fifo's name meanwhile is left alone.
+    #[cfg(unix)]
+    fn wake(&self) {
+        use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+        let Ok(mut fifo) = OpenOptions::new().write(true).custom_flags(libc::O_NONBLOCK).open(&self.source) else {
+            return;
+        };
+        if fifo.metadata().is_ok_and(|meta| meta.file_type().is_fifo()) {
+            let _ = fifo.write(b"\n");
+        }
+    }
+}
+
+// the reader is stopped and waited for, it has the spill file open for writing. the spill
+// file goes right after.
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(reader) = self.reader.take() {
+            #[cfg(unix)]
+            if !reader.is_finished() {
+                self.wake();
+            }
+            let _ = reader.join();
+        }
+        let _ = fs::remove_file(&self.spill);
+    }
+}
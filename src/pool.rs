@@ -0,0 +1,34 @@
+use rayon::ThreadPool;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+// which rayon pool the parallel scans run on. an engine with a pool of its own (see
+// options.rs) makes it this thread's for as long as a call holds the engine, the scans
+// deep inside ask for it here instead of every one of them being handed it. nobody
+// holding one means the shared global pool, like it always was.
+
+thread_local! {
+    static CURRENT: RefCell<Option<Arc<ThreadPool>>> = const { RefCell::new(None) };
+}
+
+// `pool` is this thread's until the scope is dropped, then the one before it is again
+pub(crate) struct Scope(Option<Arc<ThreadPool>>);
+
+pub(crate) fn enter(pool: Option<Arc<ThreadPool>>) -> Scope {
+    Scope(CURRENT.with(|current| std::mem::replace(&mut *current.borrow_mut(), pool)))
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let outer = self.0.take();
+        CURRENT.with(|current| *current.borrow_mut() = outer);
+    }
+}
+
+// runs `f` (the par_iter part, nothing that needs this thread's locals) on the current pool
+pub(crate) fn install<R: Send>(f: impl FnOnce() -> R + Send) -> R {
+    match CURRENT.with(|current| current.borrow().clone()) {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
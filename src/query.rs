@@ -0,0 +1,359 @@
+use crate::delimited::{self, Columns};
+use crate::fields;
+use crate::template::Templates;
+use memchr::memmem;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+// tiny query language evaluated per line:
+//   level:error AND msg~"timeout" AND ts>2024-01-01
+//   (status>=500 OR "connection reset") NOT healthcheck
+// bare words and "quoted strings" are plain substring matches on the whole line.
+// adjacent terms without an operator are ANDed. a field name starts with a letter, _ or @,
+// so 12:30:05 and http://host stay words.
+
+// parens and NOTs nested deeper than this are refused instead of recursing on
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Op {
+    Eq,       // field:value, case insensitive
+    NotEq,    // field!=value
+    Contains, // field~value
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Expr {
+    Text(Vec<u8>),
+    Field { name: String, op: Op, value: String },
+    // a field that's a column of a csv/tsv file, see bind. never true on the header line
+    Column { index: usize, delimiter: u8, header: Arc<str>, name: String, op: Op, value: String },
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Text(String),
+    Field(String, Op, String),
+}
+
+pub(crate) fn parse(input: &str) -> Result<Expr, String> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0, depth: 0 };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some(tok) => Err(format!("unexpected {:?}", tok)),
+    }
+}
+
+impl Expr {
+    // fields are looked up with the engine's registered formats first
+    pub(crate) fn matches(&self, line: &[u8], templates: &Templates) -> bool {
+        match self {
+            Expr::Text(needle) => memmem::find(line, needle).is_some(),
+            Expr::Field { name, op, value } => {
+                let line = String::from_utf8_lossy(line);
+                match fields::lookup(templates, &line, name) {
+                    Some(found) => compare(name, &found, *op, value),
+                    None => *op == Op::NotEq,
+                }
+            }
+            Expr::Column { index, delimiter, header, name, op, value } => {
+                if line == header.as_bytes() {
+                    return false;
+                }
+                let line = String::from_utf8_lossy(line);
+                match delimited::cells(&line, *delimiter).nth(*index) {
+                    Some(found) => compare(name, &found, *op, value),
+                    None => *op == Op::NotEq,
+                }
+            }
+            Expr::Not(inner) => !inner.matches(line, templates),
+            Expr::And(a, b) => a.matches(line, templates) && b.matches(line, templates),
+            Expr::Or(a, b) => a.matches(line, templates) || b.matches(line, templates),
+        }
+    }
+
+    // the literal bits worth highlighting in a matching line. anything under NOT is skipped,
+    // it can't be in the line anyway.
+    pub(crate) fn highlight_terms<'a>(&'a self, out: &mut Vec<&'a [u8]>) {
+        match self {
+            Expr::Text(needle) => out.push(needle),
+            Expr::Field { op: Op::Contains, value, .. } | Expr::Column { op: Op::Contains, value, .. } => out.push(value.as_bytes()),
+            Expr::Field { .. } | Expr::Column { .. } | Expr::Not(_) => {}
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.highlight_terms(out);
+                b.highlight_terms(out);
+            }
+        }
+    }
+
+    // field terms naming a column of a delimited file made to read that cell of a row
+    // instead, by position: what's in a row has no names of its own
+    pub(crate) fn bind(self, columns: &Columns) -> Expr {
+        match self {
+            Expr::Field { name, op, value } => match columns.index(&name) {
+                Some(index) => Expr::Column { index, delimiter: columns.delimiter, header: columns.header.clone(), name, op, value },
+                None => Expr::Field { name, op, value },
+            },
+            Expr::Not(inner) => Expr::Not(Box::new(inner.bind(columns))),
+            Expr::And(a, b) => Expr::And(Box::new(a.bind(columns)), Box::new(b.bind(columns))),
+            Expr::Or(a, b) => Expr::Or(Box::new(a.bind(columns)), Box::new(b.bind(columns))),
+            other => other,
+        }
+    }
+}
+
+fn compare(name: &str, found: &str, op: Op, wanted: &str) -> bool {
+    if op == Op::Contains {
+        return found.contains(wanted);
+    }
+    // status:5xx, any digit where there's an x
+    if matches!(op, Op::Eq | Op::NotEq) && is_digit_pattern(wanted) {
+        let hit = found.len() == wanted.len()
+            && found.bytes().zip(wanted.bytes()).all(|(f, w)| f == w || (w.eq_ignore_ascii_case(&b'x') && f.is_ascii_digit()));
+        return hit == (op == Op::Eq);
+    }
+
+    // levels compare by severity so level>=warn does the obvious thing, timestamps by the
+    // instant they name so "2024-01-01 12:00" and "2024-01-01T12:00:30Z" line up
+    let times = match op {
+        Op::Gt | Op::Ge | Op::Lt | Op::Le if fields::is_time_field(name) => {
+            fields::timestamp_secs(found).zip(fields::timestamp_secs(wanted))
+        }
+        _ => None,
+    };
+    let ord = match (fields::level_rank(found), fields::level_rank(wanted), times) {
+        (Some(a), Some(b), _) if fields::is_level_field(name) => a.cmp(&b),
+        (_, _, Some((a, b))) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => match (found.parse::<f64>(), wanted.parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            _ if matches!(op, Op::Eq | Op::NotEq) => {
+                if found.eq_ignore_ascii_case(wanted) {
+                    Ordering::Equal
+                } else {
+                    Ordering::Less
+                }
+            }
+            // ISO-ish timestamps sort lexicographically, which is exactly what we want
+            _ => found.cmp(wanted),
+        },
+    };
+
+    match op {
+        Op::Eq => ord == Ordering::Equal,
+        Op::NotEq => ord != Ordering::Equal,
+        Op::Gt => ord == Ordering::Greater,
+        Op::Ge => ord != Ordering::Less,
+        Op::Lt => ord == Ordering::Less,
+        Op::Le => ord != Ordering::Greater,
+        Op::Contains => unreachable!(),
+    }
+}
+
+fn is_digit_pattern(value: &str) -> bool {
+    value.bytes().any(|b| b.eq_ignore_ascii_case(&b'x')) && value.bytes().all(|b| b.is_ascii_digit() || b.eq_ignore_ascii_case(&b'x'))
+}
+
+fn is_field_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '@')
+}
+
+// an identifier (level, http.status, @timestamp), and for `:` not the scheme of a url
+fn is_field(name: &str, op: Op, rest: &[char]) -> bool {
+    let starts_right = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '@');
+    let ends_right = !name.ends_with(['.', '-']);
+    let url = op == Op::Eq && rest.starts_with(&[':', '/', '/']);
+    starts_right && ends_right && !url
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        match c {
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            '"' => {
+                let (text, next) = read_quoted(&chars, i + 1)?;
+                tokens.push(Token::Text(text));
+                i = next;
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() && is_field_char(chars[i]) {
+                    i += 1;
+                }
+                let name: String = chars[start..i].iter().collect();
+                if let Some((op, op_len)) = read_op(&chars[i..]).filter(|&(op, _)| is_field(&name, op, &chars[i..])) {
+                    i += op_len;
+                    let (value, next) = read_value(&chars, i)?;
+                    tokens.push(Token::Field(name.to_ascii_lowercase(), op, value));
+                    i = next;
+                    continue;
+                }
+                // not a field, keep eating until whitespace or a paren
+                while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '(' && chars[i] != ')' {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.as_str() {
+                    "AND" | "&&" => Token::And,
+                    "OR" | "||" => Token::Or,
+                    "NOT" | "!" => Token::Not,
+                    _ => Token::Text(word),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn read_op(chars: &[char]) -> Option<(Op, usize)> {
+    match chars {
+        ['>', '=', ..] => Some((Op::Ge, 2)),
+        ['<', '=', ..] => Some((Op::Le, 2)),
+        ['!', '=', ..] => Some((Op::NotEq, 2)),
+        [':', ..] | ['=', ..] => Some((Op::Eq, 1)),
+        ['~', ..] => Some((Op::Contains, 1)),
+        ['>', ..] => Some((Op::Gt, 1)),
+        ['<', ..] => Some((Op::Lt, 1)),
+        _ => None,
+    }
+}
+
+fn read_value(chars: &[char], i: usize) -> Result<(String, usize), String> {
+    if chars.get(i) == Some(&'"') {
+        return read_quoted(chars, i + 1);
+    }
+    let mut end = i;
+    while end < chars.len() && !chars[end].is_whitespace() && chars[end] != ')' {
+        end += 1;
+    }
+    if end == i {
+        return Err("missing value after operator".to_string());
+    }
+    Ok((chars[i..end].iter().collect(), end))
+}
+
+// i points right after the opening quote
+fn read_quoted(chars: &[char], mut i: usize) -> Result<(String, usize), String> {
+    let mut out = String::new();
+    while i < chars.len() {
+        match chars[i] {
+            '"' => return Ok((out, i + 1)),
+            '\\' if i + 1 < chars.len() => {
+                out.push(chars[i + 1]);
+                i += 2;
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err("unterminated quote".to_string())
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize, // parens and NOTs we're inside of
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let right = self.and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.pos += 1,
+                // implicit AND
+                Some(Token::Not | Token::LParen | Token::Text(_) | Token::Field(..)) => {}
+                _ => break,
+            }
+            let right = self.unary()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            let inner = self.nested(Parser::unary)?;
+            return Ok(Expr::Not(Box::new(inner)));
+        }
+        self.primary()
+    }
+
+    fn nested(&mut self, parse: fn(&mut Parser) -> Result<Expr, String>) -> Result<Expr, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!("nested more than {} deep", MAX_DEPTH));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let tok = self.tokens.get(self.pos).ok_or("unexpected end of query")?;
+        self.pos += 1;
+        match tok {
+            Token::LParen => {
+                let inner = self.nested(Parser::or)?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err("missing )".to_string());
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Token::Text(text) => Ok(Expr::Text(text.clone().into_bytes())),
+            Token::Field(name, op, value) => Ok(Expr::Field {
+                name: name.clone(),
+                op: *op,
+                value: value.clone(),
+            }),
+            other => Err(format!("unexpected {:?}", other)),
+        }
+    }
+}
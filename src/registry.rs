@@ -0,0 +1,66 @@
+use crate::LogEngine;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+
+// the C ABI hands out numbers, not pointers. every call looks its number up here, so one
+// that's been freed (or was never handed out) is a misuse error instead of the editor
+// reading freed memory. numbers aren't reused, a stale one can't land on a newer engine.
+// a call in flight keeps its engine alive, freeing it only takes the number away.
+//
+// an engine can have several holders (log_engine_retain), each with a number of its own
+// that it releases once: a holder letting go twice gets a misuse error instead of taking
+// the engine away from the others. the engine goes with the last number. the first number
+// handed out for it is its root, what per-engine state (out buffers, reentrancy) is keyed
+// by whichever number a call came in through.
+
+struct Entry {
+    engine: Arc<LogEngine>,
+    root: u64,
+}
+
+static ENGINES: RwLock<BTreeMap<u64, Entry>> = RwLock::new(BTreeMap::new());
+// 0 stays free, it's the "no engine" every constructor returns on failure
+static NEXT: AtomicU64 = AtomicU64::new(1);
+
+pub(crate) fn register(engine: LogEngine) -> u64 {
+    let id = NEXT.fetch_add(1, Ordering::Relaxed);
+    let entry = Entry { engine: Arc::new(engine), root: id };
+    ENGINES.write().unwrap_or_else(PoisonError::into_inner).insert(id, entry);
+    id
+}
+
+// the engine behind a number, and its root
+pub(crate) fn resolve(id: u64) -> Option<(Arc<LogEngine>, u64)> {
+    ENGINES.read().unwrap_or_else(PoisonError::into_inner).get(&id).map(|entry| (entry.engine.clone(), entry.root))
+}
+
+// whether any number still stands for the engine with this root
+pub(crate) fn alive(root: u64) -> bool {
+    ENGINES.read().unwrap_or_else(PoisonError::into_inner).values().any(|entry| entry.root == root)
+}
+
+// engines with at least one number handed out
+pub(crate) fn count() -> usize {
+    let engines = ENGINES.read().unwrap_or_else(PoisonError::into_inner);
+    engines.values().map(|entry| entry.root).collect::<BTreeSet<_>>().len()
+}
+
+// a number of its own for one more holder of the engine behind `id`, None if that isn't
+// (or no longer) handed out
+pub(crate) fn retain(id: u64) -> Option<u64> {
+    let mut engines = ENGINES.write().unwrap_or_else(PoisonError::into_inner);
+    let entry = engines.get(&id)?;
+    let entry = Entry { engine: entry.engine.clone(), root: entry.root };
+    let holder = NEXT.fetch_add(1, Ordering::Relaxed);
+    engines.insert(holder, entry);
+    Some(holder)
+}
+
+// a holder lets go of its number: Some(true) if no other number stands for the engine now,
+// None if it wasn't handed out. the engine itself goes when the last call on it is done too.
+pub(crate) fn release(id: u64) -> Option<bool> {
+    let mut engines = ENGINES.write().unwrap_or_else(PoisonError::into_inner);
+    let root = engines.remove(&id)?.root;
+    Some(!engines.values().any(|entry| entry.root == root))
+}
@@ -0,0 +1,79 @@
+use crate::ops;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+// network filesystems hiccup. a read that fails with something that looks transient is
+// tried again a few times, waiting twice as long each time, before the error goes up.
+// this only covers reads that return errors (open, map, stat, read). a page of the mmap
+// going bad under get_block is a SIGBUS, nothing to retry there.
+//
+// the waiting only happens on a thread of our own (opening with a deadline, see open.rs).
+// on the caller's thread, the editor's main loop as often as not, a failure goes straight
+// up and retry_after says when to call again: the host waits on its own timer instead.
+
+pub(crate) const DEFAULT_ATTEMPTS: u32 = 3;
+pub(crate) const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(50);
+
+pub(crate) struct Retry {
+    pub(crate) attempts: u32, // retries after the first try, 0 fails right away
+    pub(crate) base_delay: Duration,
+    pub(crate) retries: u64, // how many retries were needed so far, for stats
+    pub(crate) wait: bool,   // sleep between tries here, see above
+    failed: u32,             // transient failures in a row handed to the caller
+}
+
+impl Retry {
+    pub(crate) fn new(attempts: u32, base_delay: Duration) -> Self {
+        Retry { attempts, base_delay, retries: 0, wait: false, failed: 0 }
+    }
+
+    pub(crate) fn run<T>(&mut self, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut delay = self.base_delay;
+        let mut left = self.attempts;
+        loop {
+            match op() {
+                Err(err) if self.wait && left > 0 && is_transient(&err) && !ops::cancelled() => {
+                    left -= 1;
+                    self.retries += 1;
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+                Err(err) if is_transient(&err) => {
+                    self.failed = self.failed.saturating_add(1);
+                    if self.failed <= self.attempts {
+                        self.retries += 1;
+                    }
+                    return Err(err);
+                }
+                result => {
+                    if result.is_ok() {
+                        self.failed = 0;
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+
+    // how long the caller should give it before calling again after a transient failure,
+    // doubling with each one in a row. None once a try worked or the attempts are used up.
+    pub(crate) fn retry_after(&self) -> Option<Duration> {
+        (self.failed > 0 && self.failed <= self.attempts).then(|| self.base_delay.saturating_mul(1 << (self.failed - 1).min(20)))
+    }
+}
+
+fn is_transient(err: &io::Error) -> bool {
+    if matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    ) {
+        return true;
+    }
+    // EIO and ESTALE are what NFS/SMB hand out while the server is away
+    #[cfg(unix)]
+    if let Some(code) = err.raw_os_error() {
+        return matches!(code, libc::EIO | libc::ESTALE | libc::EAGAIN | libc::ETIMEDOUT);
+    }
+    false
+}
@@ -0,0 +1,97 @@
+use crate::retry::Retry;
+use memchr::{memchr, memchr2};
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// rotated file sets: when app.log gets rotated mid-session the lines we were showing now
+// live in app.log.1 (or app.log-20240101, app.log.2024-01-01...). Engine::reopen keeps
+// the tail of that file in front of the new one so they don't vanish from the buffer.
+
+// rotation suffixes ending in one of these are compressed, nothing we can show
+const COMPRESSED: &[&str] = &[".gz", ".bz2", ".xz", ".zst", ".lz4", ".z"];
+
+// where the file we had open went. a sibling that is the very same file wins (rename
+// rotation), otherwise the newest one (copytruncate copies first, then truncates ours).
+// only suffixes starting with a digit after the separator count, so our own .tmp and
+// .audit files never show up here.
+pub(crate) fn rotation_target(path: &Path, ours: &File) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let ours = ours.metadata().ok();
+    let mut newest: Option<(SystemTime, PathBuf)> = None;
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let file_name = entry.file_name();
+        let Some(suffix) = file_name.to_str().and_then(|other| other.strip_prefix(name)) else {
+            continue;
+        };
+        let mut rest = suffix.chars();
+        if !matches!(rest.next(), Some('.' | '-' | '_')) || !rest.next().is_some_and(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        if COMPRESSED.iter().any(|ext| suffix.to_ascii_lowercase().ends_with(ext)) {
+            continue;
+        }
+        let Ok(meta) = fs::metadata(entry.path()) else {
+            continue;
+        };
+        if !meta.is_file() {
+            continue;
+        }
+        if ours.as_ref().is_some_and(|ours| same_file(ours, &meta)) {
+            return Some(entry.path());
+        }
+        let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+        if newest.as_ref().is_none_or(|(seen, _)| modified > *seen) {
+            newest = Some((modified, entry.path()));
+        }
+    }
+    newest.map(|(_, path)| path)
+}
+
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        a.dev() == b.dev() && a.ino() == b.ino()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (a, b);
+        false
+    }
+}
+
+// the last `max_bytes` of a file as lines, starting on a line boundary so the first one
+// isn't cut in half. line breaks are the engine's: \n, \r\n or a lone \r.
+pub(crate) fn tail_lines(path: &Path, max_bytes: usize, retry: &mut Retry) -> io::Result<Vec<String>> {
+    let mut file = retry.run(|| File::open(path))?;
+    let len = retry.run(|| file.metadata())?.len();
+    let start = len.saturating_sub(max_bytes as u64);
+    let mut buf = Vec::new();
+    retry.run(|| {
+        buf.clear();
+        file.seek(SeekFrom::Start(start))?;
+        file.read_to_end(&mut buf)
+    })?;
+
+    let mut text = &buf[..];
+    if start > 0 {
+        match memchr(b'\n', text) {
+            Some(pos) => text = &text[pos + 1..],
+            None => return Ok(Vec::new()), // one line longer than the whole budget
+        }
+    }
+    let mut lines = Vec::new();
+    while !text.is_empty() {
+        let end = memchr2(b'\n', b'\r', text).unwrap_or(text.len());
+        lines.push(String::from_utf8_lossy(&text[..end]).into_owned());
+        let mut next = end + 1;
+        if text.get(end) == Some(&b'\r') && text.get(end + 1) == Some(&b'\n') {
+            next += 1;
+        }
+        text = &text[next.min(text.len())..];
+    }
+    Ok(lines)
+}
@@ -0,0 +1,641 @@
+use crate::msgpack::{self, Value};
+use crate::*;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::thread;
+
+// the engine in a process of its own (juanlog-server), speaking msgpack-rpc on stdio or a
+// socket. a crash or an mmap eating all memory takes that process down instead of the
+// editor. from neovim:
+// jobstart({ "juanlog-server" }, { rpc = true }) and rpcrequest(chan, "open", path, 0).
+//
+// requests are the C ABI's calls on handles this side hands out (per connection, freed when
+// it closes), with the same meanings and returns. failures come back as the rpc error, with
+// the last_error message. lines go both ways as lists of strings.
+
+const USAGE: &str = "usage: juanlog-server [--listen <socket path | 127.0.0.1:port>]";
+
+// the binary's main: msgpack-rpc on stdio until the other end closes it, or a listener
+// taking any number of connections. returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let result = match args {
+        [] => serve(io::stdin().lock(), io::stdout().lock()),
+        [flag, addr] if flag == "--listen" => listen(addr),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("juanlog-server: {}", err);
+            1
+        }
+    }
+}
+
+fn listen(addr: &str) -> io::Result<()> {
+    #[cfg(unix)]
+    if !addr.contains(':') {
+        use std::os::unix::fs::PermissionsExt;
+        let listener = std::os::unix::net::UnixListener::bind(addr)?;
+        // only the user running it gets to connect
+        std::fs::set_permissions(addr, std::fs::Permissions::from_mode(0o600))?;
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let output = stream.try_clone()?;
+            thread::spawn(move || serve(stream, output));
+        }
+        return Ok(());
+    }
+    // anyone who can connect can read and write any file this process can, so tcp only
+    // listens on loopback. reaching it from another machine is an ssh tunnel's job.
+    let addrs: Vec<_> = addr.to_socket_addrs()?.collect();
+    if addrs.is_empty() || addrs.iter().any(|a| !a.ip().is_loopback()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a loopback address, only unix sockets and 127.0.0.1/::1 are served", addr),
+        ));
+    }
+    let listener = TcpListener::bind(&addrs[..])?;
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let output = stream.try_clone()?;
+        thread::spawn(move || serve(stream, output));
+    }
+    Ok(())
+}
+
+// one connection: requests answered in order, notifications run and answered with nothing
+fn serve(input: impl Read, output: impl Write) -> io::Result<()> {
+    let mut input = BufReader::new(input);
+    let mut output = BufWriter::new(output);
+    let mut session = Session::default();
+    loop {
+        let message = match msgpack::read(&mut input) {
+            Ok(message) => message,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        // [0, msgid, method, params] is a request, [2, method, params] a notification
+        let Value::Array(parts) = message else {
+            continue;
+        };
+        match parts.as_slice() {
+            [kind, id, method, Value::Array(params)] if kind.as_u64() == Some(0) => {
+                let method = method.as_bytes().unwrap_or_default();
+                let (error, result) = match session.call(method, params) {
+                    Ok(result) => (Value::Nil, result),
+                    Err(err) => (Value::from(err.as_str()), Value::Nil),
+                };
+                let response = Value::Array(vec![Value::UInt(1), id.clone(), error, result]);
+                msgpack::write(&mut output, &response)?;
+                output.flush()?;
+            }
+            [kind, method, Value::Array(params)] if kind.as_u64() == Some(2) => {
+                let _ = session.call(method.as_bytes().unwrap_or_default(), params);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Default)]
+struct Session {
+    engines: HashMap<u64, u64>, // this connection's numbers -> the C ABI's
+    next: u64,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        for (_, engine) in self.engines.drain() {
+            log_engine_free(engine);
+        }
+    }
+}
+
+impl Session {
+    fn call(&mut self, method: &[u8], params: &[Value]) -> Result<Value, String> {
+        let method = String::from_utf8_lossy(method);
+        if method == "open" {
+            let path = c_string(bytes(params, 0)?)?;
+            let timeout_ms = params.get(1).and_then(Value::as_u64).unwrap_or(0);
+            let engine = log_engine_open(path.as_ptr(), timeout_ms, ptr::null(), ptr::null_mut());
+            if engine == 0 {
+                return Err(last_error());
+            }
+            self.next += 1;
+            self.engines.insert(self.next, engine);
+            return Ok(Value::UInt(self.next));
+        }
+        let handle = uint(params, 0)?;
+        let Some(&engine) = self.engines.get(&handle) else {
+            return Err(format!("{}: unknown handle {}", method, handle));
+        };
+        match method.as_ref() {
+            "free" => {
+                self.engines.remove(&handle);
+                log_engine_free(engine);
+                Ok(Value::Nil)
+            }
+            "total_lines" => Ok(Value::UInt(log_engine_total_lines(engine) as u64)),
+            "is_read_only" => Ok(Value::Bool(log_engine_is_read_only(engine))),
+            "diagnostics" => {
+                let mut len = 0;
+                let report = log_engine_diagnostics(engine, &mut len);
+                if report.is_null() {
+                    return Err(last_error());
+                }
+                Ok(Value::Str(unsafe { std::slice::from_raw_parts(report, len) }.to_vec()))
+            }
+            "stats" => {
+                let mut stats = LogEngineStats {
+                    version: 1,
+                    file_size: 0,
+                    total_lines: 0,
+                    original_lines: 0,
+                    memory_lines: 0,
+                    pieces: 0,
+                    indexed_percent: 0.0,
+                };
+                if !log_engine_stats(engine, &mut stats) {
+                    return Err(last_error());
+                }
+                Ok(Value::Array(vec![
+                    Value::UInt(stats.file_size),
+                    Value::UInt(stats.total_lines),
+                    Value::UInt(stats.original_lines),
+                    Value::UInt(stats.memory_lines),
+                    Value::UInt(stats.pieces),
+                    Value::Float(stats.indexed_percent),
+                ]))
+            }
+            "line_to_byte" => Ok(Value::UInt(log_engine_line_to_byte(engine, uint(params, 1)?))),
+            "byte_to_line" => Ok(Value::UInt(log_engine_byte_to_line(engine, uint(params, 1)?))),
+            "line_time" => {
+                let secs = log_engine_line_time(engine, uint(params, 1)?);
+                Ok(if secs.is_nan() { Value::Nil } else { Value::Float(secs) })
+            }
+            "record_bounds" => {
+                let (mut first, mut last) = (0, 0);
+                Ok(if log_engine_record_bounds(engine, uint(params, 1)?, &mut first, &mut last) {
+                    Value::Array(vec![Value::UInt(first), Value::UInt(last)])
+                } else {
+                    Value::Nil
+                })
+            }
+            "time_range" => {
+                let (mut first, mut last) = (0.0, 0.0);
+                Ok(match log_engine_time_range(engine, &mut first, &mut last) {
+                    0 => Value::Nil,
+                    _ => Value::Array(vec![Value::Float(first), Value::Float(last)]),
+                })
+            }
+            "time_deltas" => {
+                let anchor = params.get(3).and_then(Value::as_u64).map_or(-1, |line| line as i64);
+                let mut count = 0;
+                let deltas = log_engine_time_deltas(engine, uint(params, 1)?, uint(params, 2)?, anchor, &mut count);
+                if deltas.is_null() {
+                    return Err(last_error());
+                }
+                let deltas = unsafe { std::slice::from_raw_parts(deltas, count as usize * 2) };
+                let known = |secs: f64| if secs.is_nan() { Value::Nil } else { Value::Float(secs) };
+                Ok(Value::Array(deltas.chunks(2).map(|pair| Value::Array(vec![known(pair[0]), known(pair[1])])).collect()))
+            }
+            "time_gaps" => {
+                let min_secs = float(params, 3)?;
+                if min_secs.is_nan() || min_secs <= 0.0 {
+                    return Err("time_gaps: min_secs has to be above 0".to_string());
+                }
+                let mut count = 0;
+                let gaps = log_engine_time_gaps(engine, uint(params, 1)?, uint(params, 2)?, min_secs, &mut count);
+                if gaps.is_null() {
+                    return Err(last_error());
+                }
+                let gaps = unsafe { std::slice::from_raw_parts(gaps, count as usize * 2) };
+                Ok(Value::Array(
+                    gaps.chunks(2).map(|pair| Value::Array(vec![Value::UInt(pair[0]), Value::Float(pair[1] as f64 / 1000.0)])).collect(),
+                ))
+            }
+            "time_histogram" => {
+                let mut count = 0;
+                let counts = log_engine_time_histogram(engine, float(params, 1)?, float(params, 2)?, float(params, 3)?, &mut count);
+                if counts.is_null() {
+                    return Err(format!("time_histogram: needs from < to and a bucket above 0, at most {} buckets", MAX_BUCKETS));
+                }
+                let counts = unsafe { std::slice::from_raw_parts(counts, count as usize) };
+                Ok(Value::Array(counts.iter().map(|&n| Value::UInt(n)).collect()))
+            }
+            "line_levels" => {
+                let mut count = 0;
+                let levels = log_engine_line_levels(engine, uint(params, 1)?, uint(params, 2)?, &mut count);
+                if levels.is_null() {
+                    return Err(last_error());
+                }
+                let levels = unsafe { std::slice::from_raw_parts(levels, count as usize) };
+                let name = |&rank: &u8| fields::level_name(rank as usize).map_or(Value::Nil, |name| Value::Str(name.into()));
+                Ok(Value::Array(levels.iter().map(name).collect()))
+            }
+            "level_counts" => {
+                let mut counts = levels::Counts::default();
+                if !log_engine_level_counts(engine, counts.as_mut_ptr()) {
+                    return Err(last_error());
+                }
+                let name = |rank: usize| Value::Str(fields::level_name(rank).unwrap_or("none").into());
+                Ok(Value::Map(counts.iter().enumerate().map(|(rank, &n)| (name(rank), Value::UInt(n))).collect()))
+            }
+            "level_spans" => {
+                let mut count = 0;
+                let spans = log_engine_level_spans(engine, uint(params, 1)?, uint(params, 2)?, &mut count);
+                if spans.is_null() {
+                    return Err(last_error());
+                }
+                let spans = unsafe { std::slice::from_raw_parts(spans, count as usize * 4) };
+                let span = |span: &[u64]| {
+                    let name = fields::level_name(span[3] as usize).unwrap_or_default();
+                    Value::Array(vec![Value::UInt(span[0]), Value::UInt(span[1]), Value::UInt(span[2]), Value::Str(name.into())])
+                };
+                Ok(Value::Array(spans.chunks(4).map(span).collect()))
+            }
+            "ansi_colors" => {
+                let mut count = 0;
+                let spans = log_engine_ansi_colors(engine, uint(params, 1)?, uint(params, 2)?, &mut count);
+                if spans.is_null() {
+                    return Err(last_error());
+                }
+                let spans = unsafe { std::slice::from_raw_parts(spans, count as usize * 6) };
+                Ok(Value::Array(spans.chunks(6).map(|span| Value::Array(span.iter().map(|&v| Value::UInt(v)).collect())).collect()))
+            }
+            "set_strip_ansi" => {
+                log_engine_set_strip_ansi(engine, params.get(1).and_then(Value::as_bool).unwrap_or(true));
+                Ok(Value::Nil)
+            }
+            "set_hex_binary" => {
+                log_engine_set_hex_binary(engine, params.get(1).and_then(Value::as_bool).unwrap_or(true));
+                Ok(Value::Nil)
+            }
+            "binary_regions" => {
+                let mut count = 0;
+                let pairs = log_engine_binary_regions(engine, uint(params, 1)?, uint(params, 2)?, &mut count);
+                if pairs.is_null() {
+                    return Err(last_error());
+                }
+                let pairs = unsafe { std::slice::from_raw_parts(pairs, count as usize * 2) };
+                Ok(Value::Array(pairs.chunks(2).map(|pair| Value::Array(vec![Value::UInt(pair[0]), Value::UInt(pair[1])])).collect()))
+            }
+            "repeat_runs" => {
+                let templated = params.get(3).and_then(Value::as_bool).unwrap_or(false);
+                let mut count = 0;
+                let pairs = log_engine_repeat_runs(engine, uint(params, 1)?, uint(params, 2)?, templated, &mut count);
+                if pairs.is_null() {
+                    return Err(last_error());
+                }
+                let pairs = unsafe { std::slice::from_raw_parts(pairs, count as usize * 2) };
+                Ok(Value::Array(pairs.chunks(2).map(|pair| Value::Array(vec![Value::UInt(pair[0]), Value::UInt(pair[1])])).collect()))
+            }
+            "repeat_run" => {
+                let templated = params.get(2).and_then(Value::as_bool).unwrap_or(false);
+                let (mut first, mut last) = (0, 0);
+                Ok(if log_engine_repeat_run(engine, uint(params, 1)?, templated, &mut first, &mut last) {
+                    Value::Array(vec![Value::UInt(first), Value::UInt(last)])
+                } else {
+                    Value::Nil
+                })
+            }
+            "raw_bytes" => {
+                let mut len = 0;
+                let bytes = log_engine_raw_bytes(engine, uint(params, 1)?, uint(params, 2)?, &mut len);
+                if bytes.is_null() {
+                    return Err(last_error());
+                }
+                Ok(Value::Bin(unsafe { std::slice::from_raw_parts(bytes, len) }.to_vec()))
+            }
+            "register_format" => {
+                let (name, template) = (c_string(bytes(params, 1)?)?, c_string(bytes(params, 2)?)?);
+                if !log_engine_register_format(engine, name.as_ptr(), template.as_ptr()) {
+                    return Err(last_error());
+                }
+                Ok(Value::Nil)
+            }
+            "unregister_format" => {
+                let name = c_string(bytes(params, 1)?)?;
+                Ok(Value::Bool(log_engine_unregister_format(engine, name.as_ptr())))
+            }
+            "set_time_zone" => {
+                let target = c_string(params.get(1).and_then(Value::as_bytes).unwrap_or_default())?;
+                let assume = c_string(params.get(2).and_then(Value::as_bytes).unwrap_or_default())?;
+                if !log_engine_set_time_zone(engine, target.as_ptr(), assume.as_ptr()) {
+                    return Err(last_error());
+                }
+                Ok(Value::Nil)
+            }
+            "zoned_times" => {
+                let mut len = 0;
+                let block = log_engine_zoned_times(engine, uint(params, 1)?, uint(params, 2)?, &mut len);
+                if block.is_null() {
+                    return Err(last_error());
+                }
+                Ok(lines(block, len))
+            }
+            "line_fields" => {
+                let mut len = 0;
+                let block = log_engine_line_fields(engine, uint(params, 1)?, &mut len);
+                if block.is_null() {
+                    return Err(last_error());
+                }
+                Ok(tab_rows(block, len))
+            }
+            "source_location" => {
+                let (mut len, mut at) = (0, 0);
+                let path = log_engine_source_location(engine, uint(params, 1)?, &mut len, &mut at);
+                if path.is_null() {
+                    return Ok(Value::Nil);
+                }
+                let path = unsafe { std::slice::from_raw_parts(path, len) }.to_vec();
+                Ok(Value::Array(vec![Value::Str(path), Value::UInt(at)]))
+            }
+            "set_delimited" => {
+                let enable = params.get(1).and_then(Value::as_bool).unwrap_or(true);
+                let delimiter = params.get(2).and_then(Value::as_bytes).and_then(|d| d.first().copied()).unwrap_or(0);
+                if !log_engine_set_delimited(engine, enable, delimiter) {
+                    return Err(last_error());
+                }
+                Ok(Value::Nil)
+            }
+            "columns" => {
+                let mut len = 0;
+                let block = log_engine_columns(engine, &mut len);
+                if block.is_null() {
+                    return Ok(Value::Nil);
+                }
+                let text = String::from_utf8_lossy(unsafe { std::slice::from_raw_parts(block, len) }).into_owned();
+                let columns = text
+                    .lines()
+                    .filter_map(|row| {
+                        let mut parts = row.split('\t');
+                        let (name, kind, width) = (parts.next()?, parts.next()?, parts.next()?);
+                        Some(Value::Array(vec![
+                            Value::Str(name.as_bytes().to_vec()),
+                            Value::Str(kind.as_bytes().to_vec()),
+                            Value::UInt(width.parse().unwrap_or(0)),
+                        ]))
+                    })
+                    .collect();
+                Ok(Value::Array(columns))
+            }
+            "field_counts" => {
+                let key = params.get(3).and_then(Value::as_bytes).map(c_string).transpose()?;
+                let key = key.as_ref().map_or(ptr::null(), |key| key.as_ptr());
+                let mut len = 0;
+                let block = log_engine_field_counts(engine, uint(params, 1)?, uint(params, 2)?, key, &mut len);
+                if block.is_null() {
+                    return Err(last_error());
+                }
+                Ok(tab_rows(block, len))
+            }
+            "value_counts" => {
+                let field = c_string(bytes(params, 3)?)?;
+                let regex = params.get(4).and_then(Value::as_bool).unwrap_or(false);
+                let max = params.get(5).and_then(Value::as_u64).unwrap_or(0);
+                let mut len = 0;
+                let block = log_engine_value_counts(engine, uint(params, 1)?, uint(params, 2)?, field.as_ptr(), regex, max, &mut len);
+                if block.is_null() {
+                    return Err(last_error());
+                }
+                Ok(tab_rows(block, len))
+            }
+            "templates" => {
+                let mut len = 0;
+                let max = params.get(3).and_then(Value::as_u64).unwrap_or(0);
+                let block = log_engine_templates(engine, uint(params, 1)?, uint(params, 2)?, max, &mut len);
+                if block.is_null() {
+                    return Err(last_error());
+                }
+                let block = unsafe { std::slice::from_raw_parts(block, len) };
+                let rows = String::from_utf8_lossy(block);
+                Ok(Value::Array(
+                    rows.lines()
+                        .filter_map(|row| {
+                            let mut cells = row.splitn(3, '\t');
+                            let count = cells.next()?.parse().ok()?;
+                            let first_line = cells.next()?.parse().ok()?;
+                            Some(Value::Array(vec![Value::from(cells.next()?), Value::UInt(count), Value::UInt(first_line)]))
+                        })
+                        .collect(),
+                ))
+            }
+            "project" => {
+                // "ts level msg" or ["ts", "level", "msg"]
+                let mut names = joined(params, 3)?;
+                names.iter_mut().filter(|b| **b == b'\n').for_each(|b| *b = b' ');
+                let names = c_string(&names)?;
+                let mut len = 0;
+                let block = log_engine_project(engine, uint(params, 1)?, uint(params, 2)?, names.as_ptr(), &mut len);
+                if block.is_null() {
+                    return Err(last_error());
+                }
+                Ok(lines(block, len))
+            }
+            "is_json_lines" => Ok(Value::Bool(log_engine_is_json_lines(engine))),
+            "json_expand" => Ok(match log_engine_json_expand(engine, uint(params, 1)?) {
+                count if count < 0 => Value::Nil,
+                count => Value::UInt(count as u64),
+            }),
+            "json_collapse" => Ok(match log_engine_json_collapse(engine, uint(params, 1)?) {
+                line if line < 0 => Value::Nil,
+                line => Value::UInt(line as u64),
+            }),
+            "seek_time" => {
+                let secs = match params.get(1) {
+                    Some(Value::Float(secs)) => *secs,
+                    _ => uint(params, 1)? as f64,
+                };
+                Ok(match log_engine_seek_time(engine, secs) {
+                    line if line < 0 => Value::Nil,
+                    line => Value::UInt(line as u64),
+                })
+            }
+            "get_block" => {
+                let mut len = 0;
+                let block = log_engine_get_block(engine, uint(params, 1)? as usize, uint(params, 2)? as usize, &mut len);
+                Ok(lines(block, len))
+            }
+            "get_line" => {
+                let mut len = 0;
+                let line = log_engine_get_line(engine, uint(params, 1)?, &mut len);
+                if line.is_null() {
+                    return Ok(Value::Nil);
+                }
+                Ok(Value::Str(unsafe { std::slice::from_raw_parts(line, len) }.to_vec()))
+            }
+            "get_lines" => {
+                let Some(Value::Array(numbers)) = params.get(1) else {
+                    return Err("argument 2 should be a list of numbers".to_string());
+                };
+                let numbers: Vec<u64> = numbers.iter().map(|n| n.as_u64().unwrap_or(u64::MAX)).collect();
+                let mut len = 0;
+                let block = log_engine_get_lines(engine, numbers.as_ptr(), numbers.len() as u64, &mut len);
+                Ok(lines(block, len))
+            }
+            "search" => {
+                let query = bytes(params, 1)?;
+                let backward = params.get(4).and_then(Value::as_bool).unwrap_or(false);
+                let mode = params.get(3).and_then(Value::as_u64).unwrap_or(0) as u32;
+                match log_engine_search_mode_n(engine, query.as_ptr(), query.len(), uint(params, 2)?, mode, backward) {
+                    -2 => Err(last_error()),
+                    line if line < 0 => Ok(Value::Nil),
+                    line => Ok(Value::Int(line)),
+                }
+            }
+            "apply_edit" => {
+                let text = joined(params, 3)?;
+                log_engine_apply_edit_n(engine, uint(params, 1)?, uint(params, 2)?, text.as_ptr(), text.len());
+                Ok(Value::UInt(log_engine_total_lines(engine) as u64))
+            }
+            "apply_edits" => {
+                let Some(Value::Array(items)) = params.get(1) else {
+                    return Err("argument 2 should be a list of [start, deleted, lines]".to_string());
+                };
+                let mut texts = Vec::with_capacity(items.len());
+                let mut numbers = Vec::with_capacity(items.len());
+                for item in items {
+                    let Value::Array(edit) = item else {
+                        return Err("argument 2 should be a list of [start, deleted, lines]".to_string());
+                    };
+                    numbers.push((uint(edit, 0)?, uint(edit, 1)?));
+                    texts.push(joined(edit, 2)?);
+                }
+                let edits: Vec<LogEngineEdit> = numbers
+                    .iter()
+                    .zip(&texts)
+                    .map(|(&(start_line, num_deleted), text)| LogEngineEdit {
+                        start_line,
+                        num_deleted,
+                        text: text.as_ptr(),
+                        text_len: text.len(),
+                    })
+                    .collect();
+                match log_engine_apply_edits(engine, edits.as_ptr(), edits.len()) {
+                    true => Ok(Value::UInt(log_engine_total_lines(engine) as u64)),
+                    false => Err(last_error()),
+                }
+            }
+            "append" => {
+                let text = c_string(&joined(params, 1)?)?;
+                Ok(Value::UInt(log_engine_append(engine, text.as_ptr())))
+            }
+            "save" => {
+                let path = bytes(params, 1)?;
+                match log_engine_save_n(engine, path.as_ptr(), path.len()) {
+                    true => Ok(Value::Bool(true)),
+                    false => Err(last_error()),
+                }
+            }
+            "refresh" => {
+                let (mut total, mut first_changed) = (0, 0);
+                match log_engine_refresh_delta(engine, &mut total, &mut first_changed) {
+                    -1 => Err(last_error()),
+                    result => Ok(Value::Array(vec![Value::Int(result as i64), Value::UInt(total), Value::UInt(first_changed)])),
+                }
+            }
+            "poll_changes" => {
+                let (mut bytes, mut lines, mut rotated) = (0, 0, false);
+                log_engine_poll_changes(engine, &mut bytes, &mut lines, &mut rotated);
+                Ok(Value::Array(vec![Value::UInt(bytes), Value::UInt(lines), Value::Bool(rotated)]))
+            }
+            "reopen" => match log_engine_reopen(engine) {
+                -1 => Err(last_error()),
+                status => Ok(Value::UInt(status as u64)),
+            },
+            "filter_create" => {
+                let query = c_string(bytes(params, 1)?)?;
+                let mode = params.get(2).and_then(Value::as_u64).unwrap_or(0) as u32;
+                match log_engine_filter_create(engine, query.as_ptr(), mode) {
+                    filter if filter < 0 => Err(last_error()),
+                    filter => Ok(Value::Int(filter)),
+                }
+            }
+            "filter_total_lines" => Ok(Value::UInt(log_engine_filter_total_lines(engine, uint(params, 1)?))),
+            "filter_get_block" => {
+                let mut len = 0;
+                let block = log_engine_filter_get_block(engine, uint(params, 1)?, uint(params, 2)?, uint(params, 3)?, &mut len);
+                Ok(lines(block, len))
+            }
+            "filter_line" => match log_engine_filter_line(engine, uint(params, 1)?, uint(params, 2)?) {
+                line if line < 0 => Ok(Value::Nil),
+                line => Ok(Value::Int(line)),
+            },
+            "filter_free" => {
+                log_engine_filter_free(engine, uint(params, 1)?);
+                Ok(Value::Nil)
+            }
+            _ => Err(format!("unknown method {}", method)),
+        }
+    }
+}
+
+fn uint(params: &[Value], idx: usize) -> Result<u64, String> {
+    params.get(idx).and_then(Value::as_u64).ok_or_else(|| format!("argument {} should be a number", idx + 1))
+}
+
+// seconds and such, which msgpack sends as an integer when they're whole
+fn float(params: &[Value], idx: usize) -> Result<f64, String> {
+    match params.get(idx) {
+        Some(Value::Float(value)) => Ok(*value),
+        _ => Ok(uint(params, idx)? as f64),
+    }
+}
+
+fn bytes(params: &[Value], idx: usize) -> Result<&[u8], String> {
+    params.get(idx).and_then(Value::as_bytes).ok_or_else(|| format!("argument {} should be a string", idx + 1))
+}
+
+// a string, or a list of lines the way nvim_buf_get_lines hands them out
+fn joined(params: &[Value], idx: usize) -> Result<Vec<u8>, String> {
+    match params.get(idx) {
+        Some(Value::Array(items)) => {
+            let lines: Option<Vec<&[u8]>> = items.iter().map(Value::as_bytes).collect();
+            lines.map(|lines| lines.join(&b'\n')).ok_or_else(|| format!("argument {} should be a list of strings", idx + 1))
+        }
+        _ => bytes(params, idx).map(<[u8]>::to_vec),
+    }
+}
+
+fn c_string(bytes: &[u8]) -> Result<CString, String> {
+    CString::new(bytes).map_err(|_| "NUL byte in the argument".to_string())
+}
+
+// a get_block style buffer as the list of lines in it
+fn lines(block: *const u8, len: usize) -> Value {
+    if block.is_null() || len == 0 {
+        return Value::Array(Vec::new());
+    }
+    let text = unsafe { std::slice::from_raw_parts(block, len) };
+    let text = text.strip_suffix(b"\n").unwrap_or(text);
+    Value::Array(text.split(|&b| b == b'\n').map(|line| Value::Str(line.to_vec())).collect())
+}
+
+// a block of "a\tb" rows as a list of [a, b] pairs
+fn tab_rows(block: *const u8, len: usize) -> Value {
+    let Value::Array(rows) = lines(block, len) else {
+        return Value::Array(Vec::new());
+    };
+    Value::Array(
+        rows.into_iter()
+            .filter_map(|row| match row {
+                Value::Str(row) => {
+                    let tab = row.iter().rposition(|&b| b == b'\t')?;
+                    Some(Value::Array(vec![Value::Str(row[..tab].to_vec()), Value::Str(row[tab + 1..].to_vec())]))
+                }
+                _ => None,
+            })
+            .collect(),
+    )
+}
+
+fn last_error() -> String {
+    diag::with_last_error(str::to_string)
+}
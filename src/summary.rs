@@ -0,0 +1,194 @@
+use crate::drain;
+use crate::fields;
+use crate::template::Templates;
+use std::collections::HashMap;
+
+// digest of a range of lines: what time it covers, how loud it was, what it kept saying
+// and where it went quiet. the plugin renders it, the engine only does the counting.
+// out as JSON since that's the one format lua can decode for free.
+
+const TOP_TEMPLATES: usize = 10;
+const TOP_FIELDS: usize = 10;
+const TOP_VALUES: usize = 5;
+const TOP_GAPS: usize = 5;
+// past this many distinct templates / field values new ones stop being tracked,
+// otherwise a range full of unique ids eats memory for nothing
+const MAX_DISTINCT: usize = 10_000;
+// a gap is notable when it's this many times longer than the average spacing
+const GAP_FACTOR: f64 = 10.0;
+
+struct Gap {
+    line: usize, // first line after the silence
+    secs: f64,
+    from: String,
+    to: String,
+}
+
+#[derive(Default)]
+pub(crate) struct Summary {
+    start_line: usize,
+    lines: usize,
+    first_ts: Option<(f64, String)>,
+    last_ts: Option<(f64, String)>,
+    levels: HashMap<&'static str, usize>,
+    templates: HashMap<String, usize>,
+    values: HashMap<String, HashMap<String, usize>>,
+    gaps: Vec<Gap>, // largest first, at most TOP_GAPS
+}
+
+impl Summary {
+    pub(crate) fn new(start_line: usize) -> Self {
+        Summary {
+            start_line,
+            ..Default::default()
+        }
+    }
+
+    // `message` is what the engine says the line says (Engine::message), so the templates
+    // here group the same way log_engine_templates does
+    pub(crate) fn add(&mut self, line_idx: usize, line: &str, message: &str, formats: &Templates) {
+        self.lines += 1;
+
+        let level = fields::lookup(formats, line, "level").and_then(|l| fields::canonical_level(&l));
+        *self.levels.entry(level.unwrap_or("none")).or_default() += 1;
+
+        if let Some(ts) = fields::lookup(formats, line, "ts") {
+            if let Some(secs) = fields::timestamp_secs(&ts) {
+                self.add_timestamp(line_idx, secs, &ts);
+            }
+        }
+
+        let template = drain::template(message);
+        if self.templates.len() < MAX_DISTINCT || self.templates.contains_key(&template) {
+            *self.templates.entry(template).or_default() += 1;
+        }
+
+        for (key, value) in fields::pairs(formats, line) {
+            // levels have their own section, messages and timestamps are unique per line
+            if fields::is_level_field(key) || fields::is_free_text_field(key) {
+                continue;
+            }
+            let values = self.values.entry(key.to_string()).or_default();
+            if values.len() < MAX_DISTINCT || values.contains_key(value.as_ref()) {
+                *values.entry(value.into_owned()).or_default() += 1;
+            }
+        }
+    }
+
+    fn add_timestamp(&mut self, line_idx: usize, secs: f64, ts: &str) {
+        if let Some((prev, prev_ts)) = &self.last_ts {
+            let gap = secs - prev;
+            if gap > 0.0 && (self.gaps.len() < TOP_GAPS || gap > self.gaps[TOP_GAPS - 1].secs) {
+                let at = self.gaps.partition_point(|g| g.secs >= gap);
+                self.gaps.insert(at, Gap {
+                    line: line_idx,
+                    secs: gap,
+                    from: prev_ts.clone(),
+                    to: ts.to_string(),
+                });
+                self.gaps.truncate(TOP_GAPS);
+            }
+        }
+        if self.first_ts.is_none() {
+            self.first_ts = Some((secs, ts.to_string()));
+        }
+        self.last_ts = Some((secs, ts.to_string()));
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("{{\"start_line\":{},\"lines\":{},", self.start_line, self.lines));
+
+        out.push_str("\"time\":");
+        match (&self.first_ts, &self.last_ts) {
+            (Some((first, first_ts)), Some((last, last_ts))) => out.push_str(&format!(
+                "{{\"first\":{},\"last\":{},\"span_secs\":{}}}",
+                json_str(first_ts),
+                json_str(last_ts),
+                last - first
+            )),
+            _ => out.push_str("null"),
+        }
+
+        out.push_str(",\"levels\":{");
+        let levels: Vec<String> = top(&self.levels, usize::MAX)
+            .into_iter()
+            .map(|(level, count)| format!("{}:{}", json_str(level), count))
+            .collect();
+        out.push_str(&levels.join(","));
+
+        out.push_str("},\"templates\":[");
+        let templates: Vec<String> = top(&self.templates, TOP_TEMPLATES)
+            .into_iter()
+            .map(|(t, count)| format!("{{\"template\":{},\"count\":{}}}", json_str(t), count))
+            .collect();
+        out.push_str(&templates.join(","));
+
+        // the fields showing up on the most lines, each with its most common values
+        out.push_str("],\"fields\":{");
+        let mut keys: Vec<(&String, usize)> =
+            self.values.iter().map(|(k, v)| (k, v.values().sum())).collect();
+        keys.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        let fields: Vec<String> = keys
+            .into_iter()
+            .take(TOP_FIELDS)
+            .map(|(key, _)| {
+                let values: Vec<String> = top(&self.values[key], TOP_VALUES)
+                    .into_iter()
+                    .map(|(v, count)| format!("{{\"value\":{},\"count\":{}}}", json_str(v), count))
+                    .collect();
+                format!("{}:[{}]", json_str(key), values.join(","))
+            })
+            .collect();
+        out.push_str(&fields.join(","));
+
+        out.push_str("},\"gaps\":[");
+        let average = match (&self.first_ts, &self.last_ts) {
+            (Some((first, _)), Some((last, _))) if self.lines > 1 => (last - first) / (self.lines - 1) as f64,
+            _ => 0.0,
+        };
+        let gaps: Vec<String> = self
+            .gaps
+            .iter()
+            .filter(|g| g.secs > average * GAP_FACTOR)
+            .map(|g| {
+                format!(
+                    "{{\"line\":{},\"secs\":{},\"from\":{},\"to\":{}}}",
+                    g.line,
+                    g.secs,
+                    json_str(&g.from),
+                    json_str(&g.to)
+                )
+            })
+            .collect();
+        out.push_str(&gaps.join(","));
+        out.push_str("]}");
+        out
+    }
+}
+
+// most common first, ties broken by name so the output is stable
+fn top<K: AsRef<str> + Ord>(counts: &HashMap<K, usize>, n: usize) -> Vec<(&K, usize)> {
+    let mut all: Vec<(&K, usize)> = counts.iter().map(|(k, c)| (k, *c)).collect();
+    all.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    all.truncate(n);
+    all
+}
+
+pub(crate) fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
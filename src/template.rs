@@ -0,0 +1,142 @@
+use memchr::memmem::Finder;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex, PoisonError};
+
+// formats registered by the caller for logs nobody else writes, e.g.
+//
+//   %{ts} [%{level}] %{logger} - %{msg}
+//
+// compiled once into the text between the fields, searched for with a prebuilt finder. a line
+// matches when the parts come in order: text at the start has to be at the start, a field
+// takes everything up to the first place the text after it shows up, a field at the end the
+// rest of the line. fields can't be empty. every engine has its own (two logs open side by
+// side can be in different formats that happen to look alike), the first one registered
+// that matches a line wins.
+
+#[derive(Clone)]
+enum Part {
+    Text(Box<Finder<'static>>),
+    Field(&'static str),
+}
+
+#[derive(Clone)]
+struct Template {
+    name: String,
+    parts: Vec<Part>,
+}
+
+// an engine's formats. shared with what was built from them (a compiled query, the time
+// index), registering copies them first.
+#[derive(Clone, Default)]
+pub(crate) struct Templates(Arc<Vec<Template>>);
+
+// field names live as long as the library, each one once however often it's registered
+static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+fn intern(name: &str) -> &'static str {
+    let mut names = NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(&known) = names.get(name) {
+        return known;
+    }
+    let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+    names.insert(name);
+    name
+}
+
+// `%{name}` for a field, `%%` for a plain %. names are what logfmt keys can be
+fn compile(template: &str) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(at) = rest.find('%') {
+        text.push_str(&rest[..at]);
+        let after = &rest[at + 1..];
+        if let Some(after) = after.strip_prefix('%') {
+            text.push('%');
+            rest = after;
+            continue;
+        }
+        let col = template.len() - rest.len() + at;
+        let body = after.strip_prefix('{').ok_or_else(|| format!("the % at {} is not %{{name}} or %%", col))?;
+        let end = body.find('}').ok_or("a %{ without its }")?;
+        let name = &body[..end];
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '@')) {
+            return Err(format!("not a field name: {:?}", name));
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(Box::new(Finder::new(&text).into_owned())));
+            text.clear();
+        } else if matches!(parts.last(), Some(Part::Field(_))) {
+            return Err(format!("nothing between the field before %{{{}}} and it, no telling where one ends", name));
+        }
+        parts.push(Part::Field(intern(name)));
+        rest = &body[end + 1..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        parts.push(Part::Text(Box::new(Finder::new(&text).into_owned())));
+    }
+    if !parts.iter().any(|part| matches!(part, Part::Field(_))) {
+        return Err("no %{field} in it".to_string());
+    }
+    Ok(parts)
+}
+
+impl Template {
+    fn fields<'a>(&self, line: &'a str) -> Option<Vec<(&'static str, &'a str)>> {
+        let bytes = line.as_bytes();
+        let mut out = Vec::new();
+        let mut pos = 0;
+        let mut field = None; // waiting for the text after it to know where it ends
+        for part in &self.parts {
+            match part {
+                Part::Field(name) => field = Some(*name),
+                Part::Text(finder) => {
+                    let at = match field.take() {
+                        Some(name) => {
+                            let at = pos + finder.find(&bytes[pos..]).filter(|&len| len > 0)?;
+                            out.push((name, &line[pos..at]));
+                            at
+                        }
+                        None if bytes[pos..].starts_with(finder.needle()) => pos,
+                        None => return None,
+                    };
+                    pos = at + finder.needle().len();
+                }
+            }
+        }
+        match field {
+            Some(name) if pos < line.len() => out.push((name, &line[pos..])),
+            Some(_) => return None,
+            None if pos < line.len() => return None,
+            None => {}
+        }
+        Some(out)
+    }
+}
+
+impl Templates {
+    // a name registered again is replaced, in the same place
+    pub(crate) fn register(&mut self, name: &str, template: &str) -> Result<(), String> {
+        let parts = compile(template)?;
+        let templates = Arc::make_mut(&mut self.0);
+        match templates.iter_mut().find(|known| known.name == name) {
+            Some(known) => known.parts = parts,
+            None => templates.push(Template { name: name.to_string(), parts }),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn unregister(&mut self, name: &str) -> bool {
+        let before = self.0.len();
+        if self.0.iter().any(|known| known.name == name) {
+            Arc::make_mut(&mut self.0).retain(|known| known.name != name);
+        }
+        self.0.len() < before
+    }
+
+    // (field, value) in template order for the first format that matches the line
+    pub(crate) fn parse<'a>(&self, line: &'a str) -> Option<Vec<(&'static str, &'a str)>> {
+        self.0.iter().find_map(|template| template.fields(line))
+    }
+}
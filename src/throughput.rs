@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// how fast lines are coming in while tailing: whatever grow/append picked up, summed over
+// the last `window`. the plugin shows it in the statusline and warns when a producer writes
+// faster than following can redraw.
+pub(crate) const DEFAULT_WINDOW: Duration = Duration::from_secs(10);
+// arrivals closer together than this share one sample, a chatty append loop stays cheap
+const BUCKET: Duration = Duration::from_millis(100);
+
+pub(crate) struct Throughput {
+    samples: VecDeque<(Instant, u64, u64)>, // bucket start, lines, bytes
+    since: Instant,                         // when counting started, short sessions divide by this
+    pub(crate) window: Duration,
+}
+
+impl Throughput {
+    pub(crate) fn new() -> Self {
+        Throughput {
+            samples: VecDeque::new(),
+            since: Instant::now(),
+            window: DEFAULT_WINDOW,
+        }
+    }
+
+    pub(crate) fn record(&mut self, lines: usize, bytes: usize) {
+        let now = Instant::now();
+        self.expire(now);
+        match self.samples.back_mut() {
+            Some((at, l, b)) if now.duration_since(*at) < BUCKET => {
+                *l += lines as u64;
+                *b += bytes as u64;
+            }
+            _ => self.samples.push_back((now, lines as u64, bytes as u64)),
+        }
+    }
+
+    // (lines, bytes) per second over the window, or over the time since we started counting
+    // when that's shorter. read only, samples past the window just get skipped here.
+    pub(crate) fn rates(&self) -> (f64, f64) {
+        let now = Instant::now();
+        let span = now.duration_since(self.since).min(self.window).as_secs_f64();
+        if span <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let (lines, bytes) = self
+            .samples
+            .iter()
+            .filter(|s| now.duration_since(s.0) <= self.window)
+            .fold((0, 0), |(l, b), s| (l + s.1, b + s.2));
+        (lines as f64 / span, bytes as f64 / span)
+    }
+
+    // counting starts over, for a changed window
+    pub(crate) fn reset(&mut self) {
+        self.samples.clear();
+        self.since = Instant::now();
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self.samples.front().is_some_and(|s| now.duration_since(s.0) > self.window) {
+            self.samples.pop_front();
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use crate::template::Templates;
+use crate::zone::{self, Zone};
+use crate::{fields, glog};
+use crate::{is_line_start, pool, skip_line_break, ChunkMeta};
+use memchr::{memchr2, memrchr2};
+use rayon::prelude::*;
+use std::borrow::Cow;
+
+// when lines happened. every line is looked at on its own: an ISO 8601 date (leading, or a
+// ts/time/@timestamp field in json and logfmt), a unix timestamp in seconds or millis, a
+// syslog "Jan  2 15:04:05" or glog's "I0102 15:04:05.123456", whichever it has. the time index
+// over the original lines only reads the first and last stamped lines of every chunk, so
+// building it costs next to nothing on a huge file; finding anything finer reads one chunk.
+
+// how many lines at either end of a chunk are tried for a stamp before giving up on it,
+// so a file without any costs a few lines per chunk and not a full parse
+const TRIES: usize = 32;
+
+// how a stamp was written, numbers are part of the C ABI
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Format {
+    Iso = 1,
+    Epoch = 2,
+    Syslog = 3,
+    Glog = 4,
+}
+
+// seconds since the epoch and how it was written. a stamp that gives its offset is brought
+// to UTC with it, so "Z" and "+02:00" lines compare right; one that doesn't is taken to be
+// in `assume`. fields of the engine's registered formats count, see fields::lookup.
+pub(crate) fn parse(line: &[u8], assume: Zone, templates: &Templates) -> Option<(f64, Format)> {
+    let found = find(&String::from_utf8_lossy(line), templates)?;
+    let offset = found.offset.unwrap_or_else(|| assume.offset_at(found.secs));
+    Some((found.secs - offset as f64, found.format))
+}
+
+// a stamp in a line as written: `secs` is the wall time it reads (fields::wall_secs),
+// `offset` the one it ends with if any
+pub(crate) struct Found {
+    pub(crate) span: Option<(usize, usize)>, // (col, len) in the line, None when it had to be unescaped
+    pub(crate) secs: f64,
+    pub(crate) format: Format,
+    pub(crate) offset: Option<i64>,
+}
+
+pub(crate) fn find(text: &str, templates: &Templates) -> Option<Found> {
+    let rest = text.trim_start_matches(['[', ' ']);
+    if let Some((secs, len)) = syslog_secs(rest) {
+        let span = Some((text.len() - rest.len(), len));
+        return Some(Found { span, secs, format: Format::Syslog, offset: None });
+    }
+    if let Some((stamp, secs)) = glog::parse(text).and_then(|glog| Some((glog.time, glog_secs(glog.time)?))) {
+        let span = Some((stamp.as_ptr() as usize - text.as_ptr() as usize, stamp.len()));
+        return Some(Found { span, secs, format: Format::Glog, offset: None });
+    }
+    let ts = fields::lookup(templates, text, "ts")?;
+    let secs = fields::wall_secs(&ts)?;
+    let stamp = ts.trim();
+    let span = match &ts {
+        Cow::Borrowed(_) => Some((stamp.as_ptr() as usize - text.as_ptr() as usize, stamp.len())),
+        Cow::Owned(_) => None,
+    };
+    if stamp.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return Some(Found { span, secs, format: Format::Epoch, offset: Some(0) });
+    }
+    Some(Found { span, secs, format: Format::Iso, offset: zone::stamp_offset(stamp) })
+}
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+const MONTH_DAYS: [i64; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+
+// "Jan  2 15:04:05" (and "Jan 02 15:04:05.123") -> (secs, length of it). syslog doesn't say
+// the year, these are all taken as 1970: times in one file compare fine, and a time typed
+// the same way lines up.
+fn syslog_secs(text: &str) -> Option<(f64, usize)> {
+    let month = MONTHS.iter().position(|m| text.starts_with(m))?;
+    let rest = text.get(3..)?.strip_prefix(' ')?.trim_start();
+    let day_end = rest.find(' ')?;
+    let day: i64 = rest[..day_end].parse().ok().filter(|d| (1..=31).contains(d))?;
+    let time = &rest[day_end + 1..];
+    let clock = &time[..time.find(' ').unwrap_or(time.len())];
+    let days = MONTH_DAYS[month] + day - 1;
+    let len = text.len() - time.len() + clock.len();
+    Some((days as f64 * 86400.0 + clock_secs(clock)?, len))
+}
+
+// glog's "0102 15:04:05.123456", month and day, also taken as 1970 like syslog's
+fn glog_secs(stamp: &str) -> Option<f64> {
+    let (date, clock) = stamp.split_once(' ')?;
+    let month: usize = date.get(0..2)?.parse().ok().filter(|m| (1..=12).contains(m))?;
+    let day: i64 = date.get(2..4)?.parse().ok().filter(|d| (1..=31).contains(d))?;
+    let days = MONTH_DAYS[month - 1] + day - 1;
+    Some(days as f64 * 86400.0 + clock_secs(clock)?)
+}
+
+// "15:04:05" or "15:04:05.123" -> seconds into the day
+fn clock_secs(clock: &str) -> Option<f64> {
+    let mut parts = clock.split(':');
+    let hour: i64 = parts.next()?.parse().ok()?;
+    let minute: i64 = parts.next()?.parse().ok()?;
+    let second: f64 = parts.next()?.parse().ok()?;
+    Some((hour * 3600 + minute * 60) as f64 + second)
+}
+
+// a stamped original line: (line, byte offset, secs, format)
+type Stamp = (usize, usize, f64, Format);
+
+// a stamp at an original line. `secs` only ever goes up from one mark to the next (the
+// latest seen so far), so the marks can be binary searched however out of order the
+// writers were.
+#[derive(Clone, Copy)]
+struct Mark {
+    line: usize,
+    pos: usize,
+    secs: f64,
+}
+
+pub(crate) struct TimeIndex {
+    marks: Vec<Mark>, // one per chunk with a stamp near its start, in line order
+    lasts: Vec<Option<f64>>, // per chunk read, the stamp near its end
+    first: Option<f64>,
+    format: Option<Format>, // of the first stamp in the file
+    assume: Zone,           // stamps without an offset are read in it, see parse
+    templates: Templates,   // the engine's formats when it was built
+}
+
+impl TimeIndex {
+    pub(crate) fn build(data: &[u8], chunks: &[ChunkMeta], total_lines: usize, assume: Zone, templates: &Templates) -> Self {
+        let templates = templates.clone();
+        let mut index = TimeIndex { marks: Vec::new(), lasts: Vec::new(), first: None, format: None, assume, templates };
+        index.extend(data, chunks, total_lines);
+        index
+    }
+
+    // after the file grew: the chunk that was last (it may have more lines now) and the new
+    // ones are read, the marks of the ones before stay
+    pub(crate) fn extend(&mut self, data: &[u8], chunks: &[ChunkMeta], total_lines: usize) {
+        let from = self.lasts.len().saturating_sub(1);
+        let start = chunks.get(from).map_or(data.len(), |c| line_start_at(data, c.byte_offset));
+        self.marks.truncate(self.marks.partition_point(|mark| mark.pos < start));
+        self.lasts.truncate(from);
+        let ends: Vec<(Option<Stamp>, Option<f64>)> =
+            pool::install(|| (from..chunks.len()).into_par_iter().map(|i| chunk_ends(data, chunks, i, total_lines, self.assume, &self.templates)).collect());
+        let mut latest = self.marks.last().map_or(f64::NEG_INFINITY, |mark| mark.secs);
+        for (line, pos, secs, format) in ends.iter().filter_map(|(first, _)| *first) {
+            self.first.get_or_insert(secs);
+            self.format.get_or_insert(format);
+            latest = latest.max(secs);
+            self.marks.push(Mark { line, pos, secs: latest });
+        }
+        self.lasts.extend(ends.iter().map(|(_, last)| *last));
+    }
+
+    // the first and last stamps in the file, None without any
+    pub(crate) fn range(&self) -> Option<(f64, f64)> {
+        self.first.zip(self.lasts.iter().rev().find_map(|last| *last))
+    }
+
+    pub(crate) fn format(&self) -> Option<Format> {
+        self.format
+    }
+
+    // the first original line stamped at or after `secs`, None if the file ends first. lines
+    // out of order count by the latest stamp up to them, so a line written late doesn't
+    // hide the ones after it. the marks narrow it down to a chunk or so, which is read.
+    pub(crate) fn seek(&self, data: &[u8], total_lines: usize, secs: f64) -> Option<usize> {
+        let after = self.marks.partition_point(|mark| mark.secs < secs);
+        let start = Mark { line: 0, pos: 0, secs: f64::NEG_INFINITY };
+        let from = after.checked_sub(1).map_or(start, |i| self.marks[i]);
+        let (mut pos, mut latest) = (from.pos, from.secs);
+        for line in from.line..total_lines {
+            if pos >= data.len() {
+                break;
+            }
+            let line_end = memchr2(b'\n', b'\r', &data[pos..]).map_or(data.len(), |p| pos + p);
+            if let Some((stamp, _)) = parse(&data[pos..line_end], self.assume, &self.templates) {
+                latest = latest.max(stamp);
+                if latest >= secs {
+                    return Some(line);
+                }
+            }
+            pos = skip_line_break(data, line_end);
+        }
+        None
+    }
+}
+
+// the first stamped line among a chunk's first TRIES lines (line, secs, format) and the
+// stamp of the last among its last TRIES. a chunk owns the lines starting inside it.
+fn chunk_ends(
+    data: &[u8],
+    chunks: &[ChunkMeta],
+    i: usize,
+    total_lines: usize,
+    assume: Zone,
+    templates: &Templates,
+) -> (Option<Stamp>, Option<f64>) {
+    let start = line_start_at(data, chunks[i].byte_offset);
+    let end = chunks.get(i + 1).map_or(data.len(), |c| line_start_at(data, c.byte_offset));
+    let first_line = chunks[i].start_line + !is_line_start(data, chunks[i].byte_offset) as usize;
+
+    let mut first = None;
+    let mut pos = start;
+    for line in (first_line..).take(TRIES) {
+        if pos >= end || line >= total_lines {
+            break;
+        }
+        let line_end = memchr2(b'\n', b'\r', &data[pos..]).map_or(data.len(), |p| pos + p);
+        if let Some((secs, format)) = parse(&data[pos..line_end], assume, templates) {
+            first = Some((line, pos, secs, format));
+            break;
+        }
+        pos = skip_line_break(data, line_end);
+    }
+    if first.is_none() {
+        return (None, None); // nothing near the start, the end isn't likelier to have one
+    }
+
+    // backwards from the last line break
+    let mut rest = strip_break(&data[start..end]);
+    let mut last = None;
+    for _ in 0..TRIES {
+        let line_start = memrchr2(b'\n', b'\r', rest).map_or(0, |p| p + 1);
+        if let Some((secs, _)) = parse(&rest[line_start..], assume, templates) {
+            last = Some(secs);
+            break;
+        }
+        if line_start == 0 {
+            break;
+        }
+        rest = strip_break(&rest[..line_start]);
+    }
+    (first, last)
+}
+
+// where the first line starting at or after `pos` starts
+fn line_start_at(data: &[u8], pos: usize) -> usize {
+    if is_line_start(data, pos) {
+        return pos;
+    }
+    memchr2(b'\n', b'\r', &data[pos..]).map_or(data.len(), |p| skip_line_break(data, pos + p))
+}
+
+fn strip_break(bytes: &[u8]) -> &[u8] {
+    bytes.strip_suffix(b"\r\n").or_else(|| bytes.strip_suffix(b"\n")).or_else(|| bytes.strip_suffix(b"\r")).unwrap_or(bytes)
+}
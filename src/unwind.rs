@@ -0,0 +1,98 @@
+use crate::diag;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Once;
+
+// every extern "C" fn runs its body in guard: a panic anywhere under it (an index off by one,
+// a rayon worker's) stops there instead of unwinding into neovim, which aborts the whole
+// editor. the call returns its failure value instead, with ERROR_PANIC and the panic's
+// message in last_error. the engine it was working on stays usable as far as the locks go,
+// but what the call was in the middle of may be half done: reopening it is the safe bet.
+
+thread_local! {
+    static GUARDED: Cell<u32> = const { Cell::new(0) };
+    static LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static HOOK: Once = Once::new();
+
+// what a call returns when it panicked: its usual failure value
+pub(crate) trait Fallback {
+    fn fallback() -> Self;
+}
+
+impl Fallback for () {
+    fn fallback() {}
+}
+
+impl Fallback for bool {
+    fn fallback() -> Self {
+        false
+    }
+}
+
+macro_rules! fallback {
+    ($value:expr => $($ty:ty),*) => {
+        $(impl Fallback for $ty {
+            fn fallback() -> Self {
+                $value
+            }
+        })*
+    };
+}
+
+fallback!(0 => u32, u64, usize);
+fallback!(-1 => i32, i64, isize);
+fallback!(f64::NAN => f64);
+
+impl<T> Fallback for *const T {
+    fn fallback() -> Self {
+        ptr::null()
+    }
+}
+
+pub(crate) fn guard<R: Fallback>(call: &'static str, f: impl FnOnce() -> R) -> R {
+    guard_or(call, R::fallback(), f)
+}
+
+// for calls whose usual failure value is also an answer (a search's -1, no match): a panic
+// returns `panicked` instead, so it isn't taken for one
+pub(crate) fn guard_or<R>(call: &'static str, panicked: R, f: impl FnOnce() -> R) -> R {
+    HOOK.call_once(install_hook);
+    GUARDED.with(|guarded| guarded.set(guarded.get() + 1));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    GUARDED.with(|guarded| guarded.set(guarded.get() - 1));
+    result.unwrap_or_else(|payload| {
+        let at = LOCATION.with(|at| at.borrow_mut().take()).map(|at| format!(" at {}", at)).unwrap_or_default();
+        diag::fail(diag::ERROR_PANIC, format!("{}: internal error: {}{}", call, message(&*payload), at));
+        panicked
+    })
+}
+
+// the default hook prints to stderr, right over neovim's screen. ours keeps quiet for panics
+// inside a guarded call (and on our own pool's threads, whose panics end up in one) and only
+// remembers where it happened. anything else goes to whatever hook was there before.
+fn install_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let ours = GUARDED.with(Cell::get) > 0 || std::thread::current().name().is_some_and(|name| name.starts_with("juanlog"));
+        if !ours {
+            return previous(info);
+        }
+        if let Some(at) = info.location() {
+            LOCATION.with(|location| *location.borrow_mut() = Some(at.to_string()));
+        }
+    }));
+}
+
+fn message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(text) = payload.downcast_ref::<&str>() {
+        text
+    } else if let Some(text) = payload.downcast_ref::<String>() {
+        text
+    } else {
+        "panic"
+    }
+}
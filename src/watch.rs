@@ -0,0 +1,430 @@
+use crate::count_line_breaks;
+use crate::events;
+use crate::retry::Retry;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// what happened to the file on disk since the last poll, rolled into one summary however
+// many writes landed in between. the plugin only polls from a debounced fs watcher, so a
+// log taking thousands of writes a second still costs one call (and one notification) a tick.
+// nothing here touches the mmap, pulling the new lines in is Engine::grow's job.
+// hearing about the writes can be left to the engine too: a Native watch asks the OS on a
+// thread of its own (inotify, kqueue, or a look at the file's size every
+// Notifier::POLL_EVERY where there's neither) and sends EVENT_CHANGED, for the host to poll on.
+
+#[derive(Default)]
+pub(crate) struct Changes {
+    pub(crate) bytes_appended: u64,
+    pub(crate) lines_appended: u64, // line breaks in the new bytes, so only finished lines
+    pub(crate) rotated: bool,       // truncated, or the path now points at another file
+}
+
+impl Changes {
+    pub(crate) fn any(&self) -> bool {
+        self.bytes_appended > 0 || self.rotated
+    }
+}
+
+pub(crate) struct Watch {
+    path: PathBuf,
+    file: File,     // the file we opened, to tell it apart from whatever sits at `path` later
+    seen_len: u64,  // bytes already reported (or mapped, before the first poll)
+    ends_cr: bool,  // last seen byte was \r, so a leading \n in the next batch isn't a new line
+    rotated: bool,  // reported once, after that there's nothing more to say about this file
+    native: Option<Native>,
+    native_holders: usize, // log_engine_watch_native calls not undone yet, see stop_native
+}
+
+// appended bytes are counted in pieces this big instead of reading them all in at once
+const READ_CHUNK: usize = 1024 * 1024;
+
+impl Watch {
+    pub(crate) fn new(path: PathBuf, file: File, seen: &[u8]) -> Self {
+        Watch {
+            path,
+            file,
+            seen_len: seen.len() as u64,
+            ends_cr: seen.last() == Some(&b'\r'),
+            rotated: false,
+            native: None,
+            native_holders: 0,
+        }
+    }
+
+    // have the OS tell the host (through events sent as `engine`) when the file changes.
+    // every start needs a stop_native, several views on one engine each start their own.
+    pub(crate) fn start_native(&mut self, engine: u64) -> io::Result<()> {
+        if self.native.is_none() {
+            self.native = Some(Native::start(&self.path, &self.file, self.seen_len, engine)?);
+        }
+        self.native_holders += 1;
+        Ok(())
+    }
+
+    pub(crate) fn stop_native(&mut self) {
+        self.native_holders = self.native_holders.saturating_sub(1);
+        if self.native_holders == 0 {
+            self.native = None;
+        }
+    }
+
+    // what a fresh Watch on the file that replaced this one needs to carry on the same way
+    pub(crate) fn native(&self) -> Option<(u64, usize)> {
+        self.native.as_ref().map(|native| (native.engine, self.native_holders))
+    }
+
+    // the same, after a reopen: the old watch is tied to the old file
+    pub(crate) fn resume_native(&mut self, (engine, holders): (u64, usize)) -> io::Result<()> {
+        self.native = Some(Native::start(&self.path, &self.file, self.seen_len, engine)?);
+        self.native_holders = holders;
+        Ok(())
+    }
+
+    pub(crate) fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // a poll already said so, the file behind `file` is done
+    pub(crate) fn rotated(&self) -> bool {
+        self.rotated
+    }
+
+    pub(crate) fn poll(&mut self, retry: &mut Retry) -> io::Result<Changes> {
+        let mut changes = Changes::default();
+        if self.rotated {
+            return Ok(changes);
+        }
+
+        let len = retry.run(|| self.file.metadata())?.len();
+        if len < self.seen_len || self.replaced() {
+            self.rotated = true;
+            changes.rotated = true;
+            return Ok(changes);
+        }
+
+        let seen_len = self.seen_len;
+        retry.run(|| self.file.seek(SeekFrom::Start(seen_len)))?;
+        let mut buf = vec![0u8; READ_CHUNK];
+        let mut remaining = len - self.seen_len;
+        while remaining > 0 {
+            let want = remaining.min(READ_CHUNK as u64) as usize;
+            let read = retry.run(|| self.file.read(&mut buf[..want]))?;
+            if read == 0 {
+                break; // shrank under us, the next poll sees the truncation
+            }
+            let bytes = &buf[..read];
+            let mut lines = count_line_breaks(bytes) as u64;
+            if self.ends_cr && bytes[0] == b'\n' {
+                lines -= 1;
+            }
+            self.ends_cr = bytes[read - 1] == b'\r';
+            changes.lines_appended += lines;
+            changes.bytes_appended += read as u64;
+            remaining -= read as u64;
+        }
+        self.seen_len += changes.bytes_appended;
+        Ok(changes)
+    }
+
+    pub(crate) fn replaced(&self) -> bool {
+        replaced(&self.path, &self.file)
+    }
+}
+
+// rename rotation: the path is gone or is another inode now. an engine on a bare
+// descriptor has no path, only its own file to go by
+fn replaced(path: &Path, file: &File) -> bool {
+    if path.as_os_str().is_empty() {
+        return false;
+    }
+    let Ok(on_disk) = fs::metadata(path) else {
+        return true;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let Ok(ours) = file.metadata() {
+            return ours.dev() != on_disk.dev() || ours.ino() != on_disk.ino();
+        }
+    }
+    let _ = (on_disk, file);
+    false
+}
+
+// a chatty log wakes the OS watch on every write, one report per this long is plenty
+const REPORT_GAP: Duration = Duration::from_millis(50);
+
+// the engine's own watch on the file: a thread waiting on the OS, sending EVENT_CHANGED
+// (a = 1 once the file looks rotated or truncated, b = its size) whenever the size moved.
+// it doesn't touch the engine, log_engine_poll_changes still does the work. it stops after
+// a rotation, whoever reopens the file watches the new one.
+pub(crate) struct Native {
+    engine: u64,
+    stop: Arc<Stop>,
+}
+
+impl Native {
+    fn start(path: &Path, file: &File, seen_len: u64, engine: u64) -> io::Result<Native> {
+        if path.as_os_str().is_empty() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "no path to watch"));
+        }
+        let file = file.try_clone()?;
+        let stop = Arc::new(Stop::new()?);
+        let notifier = Notifier::new(path, &file, &stop)?;
+        let (path, thread_stop) = (path.to_path_buf(), stop.clone());
+        thread::Builder::new()
+            .name("juanlog-watch".to_string())
+            .spawn(move || watch_native(path, file, notifier, thread_stop, seen_len, engine))?;
+        Ok(Native { engine, stop })
+    }
+}
+
+// not joined: the thread may be inside the host's callback, calling into the engine that's
+// dropping this. it sees the stop at its next wake and sends nothing after it.
+impl Drop for Native {
+    fn drop(&mut self) {
+        self.stop.signal();
+    }
+}
+
+fn watch_native(path: PathBuf, file: File, notifier: Notifier, stop: Arc<Stop>, mut seen_len: u64, engine: u64) {
+    while notifier.wait(&stop) {
+        // a flaky mount, try again on the next change
+        let Ok(len) = file.metadata().map(|m| m.len()) else {
+            continue;
+        };
+        let rotated = len < seen_len || replaced(&path, &file);
+        if (rotated || len != seen_len) && !stop.stopped() {
+            seen_len = len;
+            events::emit(events::EVENT_CHANGED, rotated as u64, len);
+            events::flush(engine);
+            if rotated {
+                return;
+            }
+        }
+        if !stop.pause(REPORT_GAP) {
+            return;
+        }
+    }
+}
+
+// how the owner tells the thread to go: a pipe the thread can wait on along with the OS
+#[cfg(unix)]
+struct Stop {
+    read: libc::c_int,
+    write: libc::c_int,
+}
+
+#[cfg(unix)]
+impl Stop {
+    fn new() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        for fd in fds {
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        }
+        Ok(Stop { read: fds[0], write: fds[1] })
+    }
+
+    fn signal(&self) {
+        unsafe { libc::write(self.write, b"x".as_ptr() as *const libc::c_void, 1) };
+    }
+
+    fn stopped(&self) -> bool {
+        !self.pause(Duration::ZERO)
+    }
+
+    // false once signalled, true when `wait` went by
+    fn pause(&self, wait: Duration) -> bool {
+        let mut fd = libc::pollfd { fd: self.read, events: libc::POLLIN, revents: 0 };
+        let ms = wait.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        let ready = unsafe { libc::poll(&mut fd, 1, ms) };
+        ready <= 0
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Stop {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct Stop {
+    stopped: std::sync::Mutex<bool>,
+    signal: std::sync::Condvar,
+}
+
+#[cfg(not(unix))]
+impl Stop {
+    fn new() -> io::Result<Self> {
+        Ok(Stop { stopped: std::sync::Mutex::new(false), signal: std::sync::Condvar::new() })
+    }
+
+    fn signal(&self) {
+        *self.stopped.lock().unwrap_or_else(std::sync::PoisonError::into_inner) = true;
+        self.signal.notify_all();
+    }
+
+    fn stopped(&self) -> bool {
+        *self.stopped.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn pause(&self, wait: Duration) -> bool {
+        let stopped = self.stopped.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let (stopped, _) = self
+            .signal
+            .wait_timeout_while(stopped, wait, |stopped| !*stopped)
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        !*stopped
+    }
+}
+
+// inotify on the file itself: writes, truncation, and the file being moved or deleted
+// (rename rotation). the events aren't read for what they say, the size is looked at.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+struct Notifier {
+    fd: libc::c_int,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Notifier {
+    fn new(path: &Path, _file: &File, _stop: &Stop) -> io::Result<Self> {
+        use std::os::unix::ffi::OsStrExt;
+        let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let notifier = Notifier { fd };
+        let mask = libc::IN_MODIFY | libc::IN_ATTRIB | libc::IN_CLOSE_WRITE | libc::IN_MOVE_SELF | libc::IN_DELETE_SELF;
+        if unsafe { libc::inotify_add_watch(fd, path.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error()); // drop closes the fd
+        }
+        Ok(notifier)
+    }
+
+    // until something happened to the file (true) or the stop came (false)
+    fn wait(&self, stop: &Stop) -> bool {
+        let mut fds = [
+            libc::pollfd { fd: self.fd, events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: stop.read, events: libc::POLLIN, revents: 0 },
+        ];
+        loop {
+            if unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } < 0 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return false;
+            }
+            if fds[1].revents != 0 {
+                return false;
+            }
+            let mut buf = [0u8; 4096];
+            while unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {}
+            return true;
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+// kqueue: a vnode filter on our descriptor (writes, growth, the file renamed or deleted)
+// next to a read filter on the stop pipe
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "dragonfly"))]
+struct Notifier {
+    kq: libc::c_int,
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "dragonfly"))]
+impl Notifier {
+    fn new(_path: &Path, file: &File, stop: &Stop) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+        let kq = unsafe { libc::kqueue() };
+        if kq < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let notifier = Notifier { kq };
+        let mut changes: [libc::kevent; 2] = unsafe { std::mem::zeroed() };
+        changes[0].ident = file.as_raw_fd() as _;
+        changes[0].filter = libc::EVFILT_VNODE as _;
+        changes[0].flags = (libc::EV_ADD | libc::EV_CLEAR) as _;
+        changes[0].fflags = (libc::NOTE_WRITE | libc::NOTE_EXTEND | libc::NOTE_ATTRIB | libc::NOTE_RENAME | libc::NOTE_DELETE) as _;
+        changes[1].ident = stop.read as _;
+        changes[1].filter = libc::EVFILT_READ as _;
+        changes[1].flags = libc::EV_ADD as _;
+        if unsafe { libc::kevent(kq, changes.as_ptr(), 2, std::ptr::null_mut(), 0, std::ptr::null()) } < 0 {
+            return Err(io::Error::last_os_error()); // drop closes the queue
+        }
+        Ok(notifier)
+    }
+
+    fn wait(&self, stop: &Stop) -> bool {
+        let mut event: libc::kevent = unsafe { std::mem::zeroed() };
+        loop {
+            let n = unsafe { libc::kevent(self.kq, std::ptr::null(), 0, &mut event, 1, std::ptr::null()) };
+            if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return n > 0 && event.ident != stop.read as usize;
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "dragonfly"))]
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.kq) };
+    }
+}
+
+// nothing to ask (windows, the other unixes): the size is looked at every POLL_EVERY
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+)))]
+struct Notifier;
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+)))]
+impl Notifier {
+    const POLL_EVERY: Duration = Duration::from_millis(250);
+
+    fn new(_path: &Path, _file: &File, _stop: &Stop) -> io::Result<Self> {
+        Ok(Notifier)
+    }
+
+    fn wait(&self, stop: &Stop) -> bool {
+        stop.pause(Self::POLL_EVERY)
+    }
+}
@@ -0,0 +1,170 @@
+use crate::template::Templates;
+use crate::time::{self, Format};
+
+// showing stamps in another zone. logs mix UTC and local times: a stamp that says its offset
+// ("Z", "+02:00") is read with it, one that doesn't is taken to be in the `assume` zone.
+// either way it's rewritten as the same instant in the `target` zone, in the style it was
+// written in. the line itself never changes, the plugin draws the rewritten stamp over it.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Zone {
+    Utc,
+    Local, // the machine's, daylight saving included
+    Fixed(i64), // seconds east of UTC
+}
+
+impl Zone {
+    // "utc" / "z", "local", or an offset like "+05:30", "-0800", "+2"
+    pub(crate) fn parse(spec: &str) -> Option<Zone> {
+        let spec = spec.trim();
+        match spec.to_ascii_lowercase().as_str() {
+            "utc" | "gmt" | "z" => return Some(Zone::Utc),
+            "local" => return Some(Zone::Local),
+            _ => {}
+        }
+        offset_secs(spec).map(|secs| if secs == 0 { Zone::Utc } else { Zone::Fixed(secs) })
+    }
+
+    // seconds east of UTC at that instant
+    pub(crate) fn offset_at(self, utc_secs: f64) -> i64 {
+        match self {
+            Zone::Utc => 0,
+            Zone::Fixed(secs) => secs,
+            Zone::Local => local_offset(utc_secs),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn local_offset(utc_secs: f64) -> i64 {
+    let time = utc_secs.floor() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff as i64
+}
+
+#[cfg(not(unix))]
+fn local_offset(_utc_secs: f64) -> i64 {
+    0 // no zone database to ask, local reads as UTC
+}
+
+// "+05:30", "-0800", "+2" -> seconds east of UTC
+fn offset_secs(text: &str) -> Option<i64> {
+    let sign = match text.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits: String = text[1..].chars().filter(|&c| c != ':').collect();
+    if digits.is_empty() || digits.len() > 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = if digits.len() <= 2 { (&digits[..], "0") } else { digits.split_at(digits.len() - 2) };
+    let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours <= 14 && minutes < 60).then_some(sign * (hours * 3600 + minutes * 60))
+}
+
+// the offset an ISO stamp ends with, None when it doesn't say
+pub(crate) fn stamp_offset(stamp: &str) -> Option<i64> {
+    let time = stamp.get(11..)?;
+    let suffix = time.trim_start_matches(|c: char| c.is_ascii_digit() || c == ':' || c == '.');
+    if suffix.eq_ignore_ascii_case("z") {
+        return Some(0);
+    }
+    offset_secs(suffix)
+}
+
+pub(crate) struct Conversion {
+    pub(crate) target: Zone,
+    pub(crate) assume: Zone,
+}
+
+impl Conversion {
+    // (col, len, rewritten) for the stamp in a line. dates without a time and syslog and glog
+    // stamps (no year to know the offset by) are left alone.
+    pub(crate) fn convert(&self, line: &str, templates: &Templates) -> Option<(usize, usize, String)> {
+        let found = time::find(line, templates)?;
+        let (col, len) = found.span?;
+        let stamp = &line[col..col + len];
+        // whole seconds in integers and the fractional digits as written: an f64 this far
+        // from 1970 only keeps about 7 of them, and offsets never touch them
+        let (utc, fraction, sep) = match found.format {
+            Format::Syslog | Format::Glog => return None,
+            Format::Epoch => {
+                let (whole, decimals) = stamp.split_once('.').unwrap_or((stamp, ""));
+                let whole: i64 = whole.parse().ok()?;
+                // milliseconds past this, like fields::wall_secs takes them
+                if whole > 100_000_000_000 {
+                    let millis = match whole % 1000 {
+                        0 if decimals.is_empty() => String::new(),
+                        millis => format!("{:03}{}", millis, decimals),
+                    };
+                    (whole / 1000, millis, 'T')
+                } else {
+                    (whole, decimals.to_string(), 'T')
+                }
+            }
+            Format::Iso => {
+                let sep = *stamp.as_bytes().get(10)? as char;
+                if !matches!(sep, 'T' | 't' | ' ') {
+                    return None;
+                }
+                let fraction = match stamp.get(19..) {
+                    Some(rest) if rest.starts_with('.') => {
+                        &rest[1..1 + rest[1..].bytes().take_while(u8::is_ascii_digit).count()]
+                    }
+                    _ => "",
+                };
+                let part: f64 = format!("0.{}", fraction).parse().ok()?;
+                let wall = (found.secs - part).round() as i64;
+                // found.secs is the wall time written; without an offset it's in `assume`
+                let offset = found.offset.unwrap_or_else(|| self.assume.offset_at(found.secs));
+                (wall - offset, fraction.to_string(), sep)
+            }
+        };
+        let offset = self.target.offset_at(utc as f64);
+        Some((col, len, render(utc + offset, &fraction, sep, offset, self.target)))
+    }
+}
+
+// "2024-01-02T05:04:05.123+02:00", fractional digits and date/time separator as given
+fn render(wall: i64, fraction: &str, sep: char, offset: i64, target: Zone) -> String {
+    let (days, secs) = (wall.div_euclid(86400), wall.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    let mut out = format!(
+        "{:04}-{:02}-{:02}{}{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        sep,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    );
+    if !fraction.is_empty() {
+        out.push('.');
+        out.push_str(fraction);
+    }
+    if target == Zone::Utc {
+        out.push('Z');
+    } else {
+        let sign = if offset < 0 { '-' } else { '+' };
+        out.push_str(&format!("{}{:02}:{:02}", sign, offset.abs() / 3600, offset.abs() % 3600 / 60));
+    }
+    out
+}
+
+// days since 1970-01-01 -> (year, month, day), the inverse of fields::wall_secs's
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + (month <= 2) as i64, month, day)
+}
@@ -0,0 +1,160 @@
+// a file through the engine and back out: open, index, search, filter, edit, save. the
+// big-endian CI job runs these too, so nothing here may depend on the host's byte order.
+
+use juanlog::api::{Refresh, SearchMode};
+use juanlog::*;
+use std::ffi::CString;
+use std::io::Write;
+use std::path::PathBuf;
+
+const LINES: usize = 100_000;
+
+// a file of its own per test, the tests run side by side
+fn scratch(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("juanlog-test-{}-{}", std::process::id(), name))
+}
+
+fn line(i: usize) -> String {
+    let level = if i.is_multiple_of(97) { "ERROR" } else { "INFO" };
+    format!("2024-01-01T00:{:02}:{:02}Z {} request {} done", i / 60 % 60, i % 60, level, i)
+}
+
+// spans several index chunks, so the line starts are pieced together across them
+fn write_log(name: &str) -> PathBuf {
+    let path = scratch(name);
+    let mut text = String::new();
+    for i in 0..LINES {
+        text.push_str(&line(i));
+        text.push('\n');
+    }
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn opens_and_indexes_every_line() {
+    let path = write_log("index");
+    let engine = LogEngine::open(&path).unwrap();
+    assert_eq!(engine.total_lines(), LINES as u64);
+    assert_eq!(engine.line(0).as_deref(), Some(line(0).as_str()));
+    assert_eq!(engine.line(LINES as u64 - 1).as_deref(), Some(line(LINES - 1).as_str()));
+    assert_eq!(engine.line(LINES as u64), None);
+    assert_eq!(engine.lines(500, 3), vec![line(500), line(501), line(502)]);
+    let offset = engine.line_to_byte(1234);
+    assert_eq!(offset, (0..1234).map(|i| line(i).len() as u64 + 1).sum::<u64>());
+    assert_eq!(engine.byte_to_line(offset), 1234);
+    assert_eq!(engine.stats().file_size, std::fs::metadata(&path).unwrap().len());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn searches_both_ways_in_every_mode() {
+    let path = write_log("search");
+    let engine = LogEngine::open(&path).unwrap();
+    assert_eq!(engine.search("ERROR", SearchMode::Literal, 1, false), Ok(Some(97)));
+    assert_eq!(engine.search("ERROR", SearchMode::Literal, 96, true), Ok(Some(0)));
+    assert_eq!(engine.search("request 4321 ", SearchMode::Literal, 0, false), Ok(Some(4321)));
+    assert_eq!(engine.search(r"request 9999\d ", SearchMode::Regex, 0, false), Ok(Some(99990)));
+    assert_eq!(engine.search("*request 5000? done", SearchMode::Glob, 0, false), Ok(Some(50000)));
+    assert_eq!(engine.search("not in the file", SearchMode::Literal, 0, false), Ok(None));
+    assert!(engine.search("(", SearchMode::Regex, 0, false).is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn filters_keep_the_matching_lines() {
+    let path = write_log("filter");
+    let c_path = CString::new(path.to_str().unwrap()).unwrap();
+    let engine = log_engine_new(c_path.as_ptr());
+    assert_ne!(engine, 0);
+    let query = CString::new("ERROR").unwrap();
+    let filter = log_engine_filter_create(engine, query.as_ptr(), 0);
+    assert!(filter >= 0);
+    let filter = filter as u64;
+    let expected: Vec<i64> = (0..LINES).filter(|i| i.is_multiple_of(97)).map(|i| i as i64).collect();
+    assert_eq!(log_engine_filter_total_lines(engine, filter), expected.len() as u64);
+    for (row, &line) in expected.iter().enumerate() {
+        assert_eq!(log_engine_filter_line(engine, filter, row as u64), line);
+    }
+    assert_eq!(log_engine_filter_line(engine, filter, expected.len() as u64), -1);
+    log_engine_filter_free(engine, filter);
+    log_engine_free(engine);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn edits_survive_a_save_and_reopen() {
+    let path = write_log("edit");
+    let saved = scratch("edit-saved");
+    let engine = LogEngine::open(&path).unwrap();
+    engine.replace_lines(0, 1, &["first, edited"]);
+    engine.apply_edits(&[(10, 2, &["ten and eleven"][..]), (20, 0, &["new at 20", "new at 21"][..])]);
+    engine.append("appended\n");
+    assert_eq!(engine.total_lines(), LINES as u64 + 2);
+    assert!(engine.pieces().iter().any(|piece| piece.in_memory));
+    engine.save(&saved).unwrap();
+
+    let mut expected: Vec<String> = (0..LINES).map(line).collect();
+    expected[0] = "first, edited".to_string();
+    expected.splice(10..12, ["ten and eleven".to_string()]);
+    expected.splice(20..20, ["new at 20".to_string(), "new at 21".to_string()]);
+    expected.push("appended".to_string());
+    let text = std::fs::read_to_string(&saved).unwrap();
+    assert_eq!(text.lines().collect::<Vec<_>>(), expected);
+
+    let reopened = LogEngine::open(&saved).unwrap();
+    assert_eq!(reopened.total_lines(), expected.len() as u64);
+    assert_eq!(reopened.lines(0, 25), expected[..25]);
+    assert_eq!(reopened.search("ten and eleven", SearchMode::Literal, 0, false), Ok(Some(10)));
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(saved).unwrap();
+}
+
+#[test]
+fn refresh_picks_up_appended_lines() {
+    let path = write_log("refresh");
+    let engine = LogEngine::open(&path).unwrap();
+    assert_eq!(engine.refresh().unwrap(), Refresh::Unchanged);
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    writeln!(file, "{}", line(LINES)).unwrap();
+    drop(file);
+    assert_eq!(engine.refresh().unwrap(), Refresh::Grew { first_changed: LINES as u64 });
+    assert_eq!(engine.total_lines(), LINES as u64 + 1);
+    assert_eq!(engine.line(LINES as u64).as_deref(), Some(line(LINES).as_str()));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn summary_groups_messages_like_templates() {
+    let text = "2024-01-01T00:00:00Z ERROR boom timeout 17\n\
+                2024-01-01T00:00:01Z ERROR boom timeout 18\n\
+                {\"ts\":\"2024-01-01T00:00:02Z\",\"level\":\"info\",\"msg\":\"user 5 in\"}\n";
+    let engine = LogEngine::from_bytes(text.as_bytes()).unwrap();
+    let summary = engine.summary(0, 3);
+    assert!(summary.contains(r#"{"template":"boom timeout *","count":2}"#), "{}", summary);
+    assert!(summary.contains(r#"{"template":"user * in","count":1}"#), "{}", summary);
+    let templates: Vec<_> = engine.templates(0, 3, 10).into_iter().map(|(template, count, _)| (template, count)).collect();
+    assert_eq!(templates, vec![("boom timeout *".to_string(), 2), ("user * in".to_string(), 1)]);
+}
+
+#[test]
+fn zone_conversion_keeps_the_written_fraction() {
+    let text = "2024-01-01T23:59:59.987654321Z one\n\
+                2024-01-01T10:00:00.999999999999+02:00 two\n\
+                2024-06-30 12:00:00 three\n\
+                1704067200.123456789 four\n\
+                1704067200123 five\n";
+    let engine = LogEngine::from_bytes(text.as_bytes()).unwrap();
+    assert!(engine.set_time_zone(Some("+05:30"), None));
+    let shown: Vec<_> = engine.zoned_times(0, 5).into_iter().map(|zoned| zoned.map(|(_, _, stamp)| stamp)).collect();
+    assert_eq!(
+        shown,
+        vec![
+            Some("2024-01-02T05:29:59.987654321+05:30".to_string()),
+            Some("2024-01-01T13:30:00.999999999999+05:30".to_string()),
+            Some("2024-06-30 17:30:00+05:30".to_string()),
+            Some("2024-01-01T05:30:00.123456789+05:30".to_string()),
+            Some("2024-01-01T05:30:00.123+05:30".to_string()),
+        ]
+    );
+}
@@ -0,0 +1,316 @@
+// juanlog-server over stdio. requests are encoded and replies taken apart here by hand, from
+// the msgpack spec rather than the crate's codec, so a byte order mixup on either side shows
+// up on the big-endian CI job instead of agreeing with itself.
+
+use std::io::{BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(Vec<u8>),
+    Bin(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+}
+
+fn str(s: &str) -> Value {
+    Value::Str(s.as_bytes().to_vec())
+}
+
+fn int(n: u64) -> Value {
+    Value::Int(n as i64)
+}
+
+fn encode(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Nil => out.push(0xc0),
+        Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Int(n @ 0..=0x7f) => out.push(*n as u8),
+        Value::Int(n) if *n >= 0 => {
+            out.push(0xcf);
+            out.extend_from_slice(&(*n as u64).to_be_bytes());
+        }
+        Value::Int(n) => {
+            out.push(0xd3);
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+        Value::Float(f) => {
+            out.push(0xcb);
+            out.extend_from_slice(&f.to_bits().to_be_bytes());
+        }
+        Value::Str(bytes) => {
+            out.push(0xdb);
+            out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            out.extend_from_slice(bytes);
+        }
+        Value::Bin(bytes) => {
+            out.push(0xc6);
+            out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            out.extend_from_slice(bytes);
+        }
+        Value::Array(items) => {
+            out.push(0xdd);
+            out.extend_from_slice(&(items.len() as u32).to_be_bytes());
+            items.iter().for_each(|item| encode(out, item));
+        }
+        Value::Map(entries) => {
+            out.push(0xdf);
+            out.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+            for (k, v) in entries {
+                encode(out, k);
+                encode(out, v);
+            }
+        }
+    }
+}
+
+fn take(r: &mut impl Read, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    r.read_exact(&mut bytes).unwrap();
+    bytes
+}
+
+fn be(r: &mut impl Read, width: usize) -> u64 {
+    take(r, width).iter().fold(0, |n, &b| n << 8 | b as u64)
+}
+
+fn decode(r: &mut impl Read) -> Value {
+    let marker = take(r, 1)[0];
+    match marker {
+        0x00..=0x7f => Value::Int(marker as i64),
+        0x80..=0x8f => map(r, (marker & 0x0f) as usize),
+        0x90..=0x9f => array(r, (marker & 0x0f) as usize),
+        0xa0..=0xbf => Value::Str(take(r, (marker & 0x1f) as usize)),
+        0xc0 => Value::Nil,
+        0xc2 => Value::Bool(false),
+        0xc3 => Value::Bool(true),
+        0xc4..=0xc6 => {
+            let len = be(r, 1 << (marker - 0xc4)) as usize;
+            Value::Bin(take(r, len))
+        }
+        0xcb => Value::Float(f64::from_bits(be(r, 8))),
+        0xcc..=0xcf => Value::Int(be(r, 1 << (marker - 0xcc)) as i64),
+        0xd3 => Value::Int(be(r, 8) as i64),
+        0xd9..=0xdb => {
+            let len = be(r, 1 << (marker - 0xd9)) as usize;
+            Value::Str(take(r, len))
+        }
+        0xdc | 0xdd => {
+            let len = be(r, 2 << (marker - 0xdc)) as usize;
+            array(r, len)
+        }
+        0xde | 0xdf => {
+            let len = be(r, 2 << (marker - 0xde)) as usize;
+            map(r, len)
+        }
+        0xe0..=0xff => Value::Int(marker as i8 as i64),
+        _ => panic!("the server doesn't write 0x{:02x}", marker),
+    }
+}
+
+fn array(r: &mut impl Read, len: usize) -> Value {
+    Value::Array((0..len).map(|_| decode(r)).collect())
+}
+
+fn map(r: &mut impl Read, len: usize) -> Value {
+    Value::Map((0..len).map(|_| (decode(r), decode(r))).collect())
+}
+
+fn scratch(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("juanlog-rpc-test-{}-{}", std::process::id(), name))
+}
+
+struct Server {
+    child: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Server {
+    fn start() -> Server {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_juanlog-server"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+        Server { child, input, output, next_id: 0 }
+    }
+
+    // the raw bytes of a message, then the reply
+    fn send(&mut self, message: &[u8]) -> Value {
+        self.input.write_all(message).unwrap();
+        self.input.flush().unwrap();
+        decode(&mut self.output)
+    }
+
+    fn call(&mut self, method: &str, params: Vec<Value>) -> Result<Value, Value> {
+        self.next_id += 1;
+        let mut message = Vec::new();
+        encode(&mut message, &Value::Array(vec![int(0), int(self.next_id), str(method), Value::Array(params)]));
+        let Value::Array(reply) = self.send(&message) else {
+            panic!("{}: the reply isn't an array", method);
+        };
+        assert_eq!(reply.len(), 4);
+        assert_eq!(reply[0], int(1));
+        assert_eq!(reply[1], int(self.next_id));
+        match (&reply[2], &reply[3]) {
+            (Value::Nil, result) => Ok(result.clone()),
+            (error, _) => Err(error.clone()),
+        }
+    }
+
+    // closing stdin ends the session, the exit code says whether it ended cleanly
+    fn finish(mut self) -> i32 {
+        drop(self.input);
+        self.child.wait().unwrap().code().expect("the server was killed by a signal")
+    }
+}
+
+fn line(i: usize) -> String {
+    let level = if i.is_multiple_of(50) { "ERROR" } else { "INFO" };
+    format!("2024-01-01T00:{:02}:{:02}Z {} request {}", i / 60 % 60, i % 60, level, i)
+}
+
+fn write_log(name: &str, lines: usize) -> PathBuf {
+    let path = scratch(name);
+    let text: String = (0..lines).map(|i| line(i) + "\n").collect();
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+fn path_value(path: &std::path::Path) -> Value {
+    str(path.to_str().unwrap())
+}
+
+#[test]
+fn open_search_filter_edit_save() {
+    let path = write_log("session", 3000);
+    let saved = scratch("session-saved");
+    let mut server = Server::start();
+    let handle = server.call("open", vec![path_value(&path), int(0)]).unwrap();
+    assert_eq!(server.call("total_lines", vec![handle.clone()]), Ok(int(3000)));
+    assert_eq!(server.call("get_line", vec![handle.clone(), int(2999)]), Ok(str(&line(2999))));
+    assert_eq!(server.call("search", vec![handle.clone(), str("ERROR"), int(1)]), Ok(int(50)));
+    assert_eq!(server.call("search", vec![handle.clone(), str("request 12\\d\\d$"), int(0), int(3)]), Ok(int(1200)));
+    assert_eq!(server.call("search", vec![handle.clone(), str("missing"), int(0)]), Ok(Value::Nil));
+    assert!(server.call("search", vec![handle.clone(), str("("), int(0), int(3)]).is_err());
+
+    let filter = server.call("filter_create", vec![handle.clone(), str("ERROR")]).unwrap();
+    assert_eq!(server.call("filter_total_lines", vec![handle.clone(), filter.clone()]), Ok(int(60)));
+    assert_eq!(server.call("filter_line", vec![handle.clone(), filter.clone(), int(59)]), Ok(int(2950)));
+    let rows = server.call("filter_get_block", vec![handle.clone(), filter.clone(), int(0), int(2)]).unwrap();
+    assert_eq!(rows, Value::Array(vec![str(&line(0)), str(&line(50))]));
+    assert_eq!(server.call("filter_free", vec![handle.clone(), filter]), Ok(Value::Nil));
+
+    let edits = Value::Array(vec![
+        Value::Array(vec![int(0), int(1), Value::Array(vec![str("edited")])]),
+        Value::Array(vec![int(100), int(0), Value::Array(vec![str("inserted"), str("twice")])]),
+    ]);
+    assert_eq!(server.call("apply_edits", vec![handle.clone(), edits]), Ok(int(3002)));
+    let shown = server.call("get_lines", vec![handle.clone(), Value::Array(vec![int(0), int(100), int(101), int(102)])]);
+    assert_eq!(shown, Ok(Value::Array(vec![str("edited"), str("inserted"), str("twice"), str(&line(100))])));
+    assert_eq!(server.call("save", vec![handle.clone(), path_value(&saved)]), Ok(Value::Bool(true)));
+    assert_eq!(server.call("free", vec![handle]), Ok(Value::Nil));
+
+    let reopened = server.call("open", vec![path_value(&saved)]).unwrap();
+    assert_eq!(server.call("total_lines", vec![reopened.clone()]), Ok(int(3002)));
+    assert_eq!(server.call("get_block", vec![reopened, int(99), int(3)]), Ok(Value::Array(vec![str(&line(99)), str("inserted"), str("twice")])));
+    assert_eq!(server.finish(), 0);
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(saved).unwrap();
+}
+
+#[test]
+fn numbers_decode_the_same_in_every_width() {
+    let path = write_log("widths", 3000);
+    let mut server = Server::start();
+    let handle = server.call("open", vec![path_value(&path)]).unwrap();
+    let expected = server.call("line_to_byte", vec![handle.clone(), int(2500)]).unwrap();
+    // past 0xffff, so the reply is a 32 bit uint
+    let Value::Int(offset) = expected else { panic!("line_to_byte: {:?}", expected) };
+    assert!(offset > 0xffff);
+
+    // 2500 as uint 16, uint 32, uint 64 and int 64, after [0, id, "line_to_byte", [1, ...]]
+    let widths: [&[u8]; 4] = [
+        &[0xcd, 0x09, 0xc4],
+        &[0xce, 0x00, 0x00, 0x09, 0xc4],
+        &[0xcf, 0, 0, 0, 0, 0, 0, 0x09, 0xc4],
+        &[0xd3, 0, 0, 0, 0, 0, 0, 0x09, 0xc4],
+    ];
+    for (id, number) in widths.iter().enumerate() {
+        let mut message = vec![0x94, 0x00, 0xcc, 0xf0 + id as u8, 0xac];
+        message.extend_from_slice(b"line_to_byte");
+        message.extend_from_slice(&[0x92, 0x01]);
+        message.extend_from_slice(number);
+        let mut reply = vec![0x94, 0x01, 0xcc, 0xf0 + id as u8, 0xc0, 0xce];
+        reply.extend_from_slice(&(offset as u32).to_be_bytes());
+        server.input.write_all(&message).unwrap();
+        server.input.flush().unwrap();
+        let mut got = vec![0u8; reply.len()];
+        server.output.read_exact(&mut got).unwrap();
+        assert_eq!(got, reply, "2500 sent as {:02x?}", number);
+    }
+    assert_eq!(server.finish(), 0);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn floats_strings_and_bins_round_trip() {
+    let path = write_log("floats", 100);
+    let mut server = Server::start();
+    let handle = server.call("open", vec![path_value(&path)]).unwrap();
+    // 2024-01-01T00:01:01Z
+    assert_eq!(server.call("line_time", vec![handle.clone(), int(61)]), Ok(Value::Float(1704067261.0)));
+    let range = server.call("time_range", vec![handle.clone()]).unwrap();
+    assert_eq!(range, Value::Array(vec![Value::Float(1704067200.0), Value::Float(1704067299.0)]));
+    // a second between every line, none of them a gap of 1.5
+    let gaps = server.call("time_gaps", vec![handle.clone(), int(0), int(100), Value::Float(1.5)]).unwrap();
+    assert_eq!(gaps, Value::Array(Vec::new()));
+    let raw = server.call("raw_bytes", vec![handle.clone(), int(3), int(2)]).unwrap();
+    assert_eq!(raw, Value::Bin(format!("{}\n{}\n", line(3), line(4)).into_bytes()));
+
+    // a line longer than 0xffff bytes goes out as a str 32
+    let long = "x".repeat(70_000);
+    assert_eq!(server.call("apply_edit", vec![handle.clone(), int(0), int(0), Value::Bin(long.clone().into_bytes())]), Ok(int(101)));
+    assert_eq!(server.call("get_line", vec![handle.clone(), int(0)]), Ok(str(&long)));
+    let counts = server.call("level_counts", vec![handle]).unwrap();
+    let Value::Map(counts) = counts else { panic!("level_counts: {:?}", counts) };
+    assert!(counts.contains(&(str("error"), int(2))));
+    assert_eq!(server.finish(), 0);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn unknown_handles_and_methods_come_back_as_errors() {
+    let mut server = Server::start();
+    assert!(matches!(server.call("total_lines", vec![int(42)]), Err(Value::Str(_))));
+    assert!(matches!(server.call("open", vec![str("/nonexistent/juanlog")]), Err(Value::Str(_))));
+    assert!(matches!(server.call("no_such_method", vec![int(1)]), Err(Value::Str(_))));
+    assert_eq!(server.finish(), 0);
+}
+
+#[test]
+fn deep_nesting_ends_the_connection_not_the_process() {
+    let mut server = Server::start();
+    assert!(server.call("total_lines", vec![int(1)]).is_err());
+    // a million arrays one inside the other, far past what recursing could take
+    let _ = server.input.write_all(&vec![0x91; 1 << 20]);
+    assert_eq!(server.finish(), 1);
+}
+
+#[test]
+fn a_length_without_the_bytes_allocates_nothing() {
+    let mut server = Server::start();
+    // a 1GB string announced, a few bytes sent, then the end of the input
+    server.input.write_all(&[0x94, 0x00, 0x01, 0xdb, 0x3f, 0xff, 0xff, 0xff, b'o', b'p']).unwrap();
+    assert_eq!(server.finish(), 0);
+}